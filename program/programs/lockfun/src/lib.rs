@@ -1,214 +1,1615 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::memo::{self, BuildMemo, Memo};
+use anchor_spl::token_interface::{
+    self, Burn, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
+use solana_program::ed25519_program;
+use solana_program::hash::{hash, hashv};
+use solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
 
 declare_id!("57MA23vJ2yS9FV2oL4bz5GcKoXWXGhc25R61PU8dgefD");
 
+// The mock clock lets the authority rewrite time, so it must never reach a deployable build:
+// it only compiles with debug assertions (the dev and `test-clock` profiles), never in `release`.
+#[cfg(all(feature = "test-clock", not(debug_assertions)))]
+compile_error!("the `test-clock` feature requires debug assertions and must never be deployed");
+
 /// Seeds for PDA derivation
 pub const GLOBAL_STATE_SEED: &[u8] = b"global_state";
 pub const LOCK_SEED: &[u8] = b"lock";
 pub const VAULT_SEED: &[u8] = b"vault";
+pub const MILESTONE_SEED: &[u8] = b"milestones";
+pub const EXPIRY_SEED: &[u8] = b"expiry";
+pub const CREATION_SEED: &[u8] = b"created";
+pub const DENYLIST_SEED: &[u8] = b"denylist";
+pub const IDEMPOTENCY_SEED: &[u8] = b"idempotency";
+pub const FEE_OVERRIDE_SEED: &[u8] = b"fee_override";
+pub const TEMPLATE_SEED: &[u8] = b"template";
+pub const MINT_STATUS_SEED: &[u8] = b"mint_status";
+pub const HEIRS_SEED: &[u8] = b"heirs";
+pub const RECOVERY_VAULT_SEED: &[u8] = b"recovery_vault";
+pub const MINT_STATS_SEED: &[u8] = b"mint_stats";
+pub const MIGRATION_SEED: &[u8] = b"migration";
+pub const PROGRAM_STATS_SEED: &[u8] = b"program_stats";
+pub const OWNER_MINT_STATS_SEED: &[u8] = b"owner_mint_stats";
+pub const RECEIPT_SEED: &[u8] = b"receipt";
+
+/// Maximum number of entries in a milestone schedule
+pub const MAX_MILESTONES: usize = 12;
+
+/// Byte offsets of the `Lock` fields indexers filter on (memcmp), a committed ABI:
+/// `Lock` fields are only ever appended, so these never move
+pub const LOCK_OWNER_OFFSET: usize = 16;
+pub const LOCK_MINT_OFFSET: usize = 48;
+pub const LOCK_IS_UNLOCKED_OFFSET: usize = 105;
+
+/// Version of the account layouts, bumped whenever a layout changes (returned by `get_config`)
+pub const SCHEMA_VERSION: u8 = 1;
+
+/// Size (discriminator included) of the GlobalState of the first deployment, before fields
+/// were appended; `migrate_global_state` grows it to the current layout
+pub const LEGACY_GLOBAL_STATE_LEN: usize = 8 + 40;
+
+/// Size (discriminator included) of the locks of the first deployment, up to `is_unlocked`;
+/// `migrate_lock` grows them to the current layout
+pub const LEGACY_LOCK_LEN: usize = 8 + 98;
+
+/// Vault schemes of a lock (`Lock.vault_scheme`). Either way the vault's authority is the
+/// PDA `[VAULT_SEED, lock_id]`: it is the vault itself, or owns the vault ATA or the salted
+/// vault `[VAULT_SEED, lock_id, lock.vault_salt]`.
+pub const VAULT_SCHEME_PDA: u8 = 0;
+pub const VAULT_SCHEME_ATA: u8 = 1;
+pub const VAULT_SCHEME_SALTED: u8 = 2;
+
+/// Vesting curves of a lock (`LockOptions.vesting_curve`), over `[created_at, unlock_timestamp]`
+/// with `p` the elapsed fraction: none (all at maturity), `p`, `1 - (1 - p)^2` and `p^2`
+pub const VESTING_NONE: u8 = 0;
+pub const VESTING_LINEAR: u8 = 1;
+pub const VESTING_FRONT_LOADED: u8 = 2;
+pub const VESTING_BACK_LOADED: u8 = 3;
+
+/// Bits of `GlobalState.disabled_instructions`, each turning off one family of instructions
+/// - Lock creation, every variant
+pub const DISABLE_LOCK: u8 = 1 << 0;
+/// - `top_up` and `extend_with_top_up`
+pub const DISABLE_TOP_UP: u8 = 1 << 1;
+/// - `extend` and its variants, `batch_extend` and `extend_with_top_up`
+pub const DISABLE_EXTEND: u8 = 1 << 2;
+/// - Owner withdrawals: `unlock` and its variants, partial unlocks, `reduce`, `claim_vested`,
+///   `claim_milestone` and `cancel`
+pub const DISABLE_UNLOCK: u8 = 1 << 3;
+/// Every known `disabled_instructions` bit
+pub const DISABLE_ALL: u8 = DISABLE_LOCK | DISABLE_TOP_UP | DISABLE_EXTEND | DISABLE_UNLOCK;
+
+/// Returned by `next_unlock_time` once nothing is left to release
+pub const NO_NEXT_UNLOCK: i64 = 0;
+
+/// Status codes returned by `lock_status`
+pub const LOCK_STATUS_ACTIVE: u8 = 0;
+pub const LOCK_STATUS_MATURED: u8 = 1;
+pub const LOCK_STATUS_UNLOCKED: u8 = 2;
+pub const LOCK_STATUS_FROZEN: u8 = 3;
+
+/// Smallest millisecond timestamp `lock_ms` accepts (March 1973). Any current timestamp in
+/// seconds is far below it, so seconds passed by mistake are rejected instead of locking for ages.
+pub const MIN_TIMESTAMP_MS: i64 = 100_000_000_000;
+
+/// Minimum time between maturity and the authority's emergency recovery of a lock (one year)
+pub const MIN_RECOVERY_DELAY: i64 = 365 * SECONDS_PER_DAY;
+
+/// Maximum number of heirs of a lock
+pub const MAX_HEIRS: usize = 4;
+
+/// Width of an expiry index bucket (one day)
+pub const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Maximum number of lock ids stored per expiry index bucket
+pub const MAX_EXPIRY_INDEX_ENTRIES: usize = 64;
 
-/// Fee amount in lamports (0.03 SOL = 30,000,000 lamports)
+/// Maximum number of lock ids stored per creation index bucket
+pub const MAX_CREATION_INDEX_ENTRIES: usize = 64;
+
+/// Default delay between requesting and confirming a beneficiary change (two days)
+pub const DEFAULT_BENEFICIARY_CHANGE_DELAY: i64 = 2 * SECONDS_PER_DAY;
+
+/// Largest `GlobalState.clock_tolerance_seconds` (five minutes)
+pub const MAX_CLOCK_TOLERANCE: u32 = 300;
+
+/// Granularity of unlock timestamps when `GlobalState.require_aligned_timestamps` is set (one minute)
+pub const TIMESTAMP_ALIGNMENT: i64 = 60;
+
+/// Remaining accounts per lock in `batch_unlock`: lock, vault, mint, destination, mint stats,
+/// owner mint stats
+pub const BATCH_UNLOCK_ACCOUNTS: usize = 6;
+
+/// Default `GlobalState.max_batch_size`
+pub const DEFAULT_MAX_BATCH_SIZE: u8 = 16;
+
+/// Number of remaining accounts per lock in `batch_extend`
+pub const BATCH_EXTEND_ACCOUNTS: usize = 2;
+
+/// Maximum page size of `list_active_locks`, so a full page of `LockSummary` fits in the
+/// 1024 bytes of return data
+pub const MAX_LIST_LIMIT: u8 = 10;
+
+/// Maximum length in bytes of an unlock memo
+pub const MAX_MEMO_LEN: usize = 128;
+
+/// Basis points in 100%
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Maximum number of owners on the denylist
+pub const MAX_DENYLIST_ENTRIES: usize = 64;
+
+/// Default fee amount in lamports (0.03 SOL = 30,000,000 lamports), see `GlobalState.fee_amount`
 pub const FEE_AMOUNT: u64 = 30_000_000;
 
-/// Fee recipient address
-pub const FEE_RECIPIENT: Pubkey = ::solana_program::pubkey!("CsJ1qQSA7hsxAH27cqENqhTy7vBUcdMdVQXAMubJniPo");
+/// Default fee recipient address, see `GlobalState.fee_recipient`
+pub const FEE_RECIPIENT: Pubkey =
+    ::solana_program::pubkey!("CsJ1qQSA7hsxAH27cqENqhTy7vBUcdMdVQXAMubJniPo");
 
 #[program]
 pub mod lockfun {
     use super::*;
 
     /// Initialize the program with global state
+    /// - Fails with `AlreadyInitialized` once it was initialized (here or by the first lock)
     pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
-        let global_state = &mut ctx.accounts.global_state;
-        global_state.authority = ctx.accounts.authority.key();
-        global_state.lock_counter = 0;
+        require!(
+            ctx.accounts.global_state.authority == Pubkey::default(),
+            ErrorCode::AlreadyInitialized
+        );
+        let authority = ctx.accounts.authority.key();
+        ctx.accounts
+            .global_state
+            .init(authority, ctx.bumps.global_state);
+        ctx.accounts.program_stats.bump = ctx.bumps.program_stats;
         msg!("Lockfun initialized!");
         Ok(())
     }
 
+    /// Grow a GlobalState of the first deployment (`authority` and `lock_counter` only) to the
+    /// current layout, which every other instruction needs to deserialize it
+    /// - Stored authority only, who pays the extra rent
+    /// - Keeps the authority and lock counter; every appended setting gets the default of
+    ///   `initialize`
+    /// - `NotLegacyAccount` for a GlobalState already in the current layout
+    pub fn migrate_global_state(ctx: Context<MigrateGlobalState>) -> Result<()> {
+        let info = ctx.accounts.global_state.to_account_info();
+        let legacy: LegacyGlobalState =
+            read_legacy_account(&info, LEGACY_GLOBAL_STATE_LEN, GlobalState::DISCRIMINATOR)?;
+        require_keys_eq!(
+            ctx.accounts.authority.key(),
+            legacy.authority,
+            ErrorCode::NotAuthority
+        );
+
+        let space = 8 + GlobalState::INIT_SPACE;
+        grow_account(
+            &info,
+            &ctx.accounts.authority.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            space,
+        )?;
+        let mut global_state = GlobalState::deserialize(&mut &vec![0; space - 8][..])?;
+        global_state.init(legacy.authority, ctx.bumps.global_state);
+        global_state.lock_counter = legacy.lock_counter;
+        global_state.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+        msg!(
+            "GlobalState migrated, {} locks so far",
+            global_state.lock_counter
+        );
+        Ok(())
+    }
+
+    /// Grow a lock of the first deployment (fields up to `is_unlocked`) to the current layout,
+    /// so it can be read and unlocked again
+    /// - Lock owner or authority, who pays the extra rent; GlobalState must be migrated first
+    ///   (`migrate_global_state`)
+    /// - Keeps the original fields; the appended ones get the values of a plain `lock`: the owner
+    ///   is the beneficiary, partial unlocks are allowed, the vault is the PDA vault, and the
+    ///   token program is the owner of the lock's `mint`
+    /// - `NotLegacyAccount` for a lock already in the current layout
+    pub fn migrate_lock(ctx: Context<MigrateLock>) -> Result<()> {
+        let info = ctx.accounts.lock.to_account_info();
+        let legacy: LegacyLock = read_legacy_account(&info, LEGACY_LOCK_LEN, Lock::DISCRIMINATOR)?;
+        let (expected_lock, _) =
+            Pubkey::find_program_address(&[LOCK_SEED, &legacy.id.to_le_bytes()], &ID);
+        require_keys_eq!(info.key(), expected_lock, ErrorCode::LockPdaMismatch);
+        let payer = ctx.accounts.payer.key();
+        require!(
+            payer == legacy.owner || payer == ctx.accounts.global_state.authority,
+            ErrorCode::Unauthorized
+        );
+        require_keys_eq!(ctx.accounts.mint.key(), legacy.mint, ErrorCode::InvalidMint);
+
+        let space = 8 + Lock::INIT_SPACE;
+        grow_account(
+            &info,
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            space,
+        )?;
+        // Zeroes are the plain-lock value of every appended field not set below
+        let mut lock = Lock::deserialize(&mut &vec![0; space - 8][..])?;
+        lock.id = legacy.id;
+        lock.owner = legacy.owner;
+        lock.mint = legacy.mint;
+        lock.amount = legacy.amount;
+        lock.unlock_timestamp = legacy.unlock_timestamp;
+        lock.created_at = legacy.created_at;
+        lock.vault_bump = legacy.vault_bump;
+        lock.is_unlocked = legacy.is_unlocked;
+        lock.beneficiary = legacy.owner;
+        lock.token_program = *ctx.accounts.mint.to_account_info().owner;
+        lock.is_token_2022 = lock.token_program == anchor_spl::token_2022::ID;
+        lock.allow_partial = LockOptions::default().allow_partial;
+        lock.vault_scheme = VAULT_SCHEME_PDA;
+        lock.vesting_curve = VESTING_NONE;
+        lock.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+
+        msg!("Lock #{} migrated", lock.id);
+        Ok(())
+    }
+
+    /// `initialize` and `lock` in one transaction, for integrators deploying their own instance
+    /// - On a fresh deployment, initializes GlobalState with the signer as authority and creates
+    ///   ProgramStats, like `initialize`. Only the program's upgrade authority may do this (pass
+    ///   `program_data`), so nobody can front-run the deployer and claim the authority; other
+    ///   callers get `NotInitialized`
+    /// - Once initialized, the initialization is skipped and this is a plain `lock`: the
+    ///   signer never becomes the authority
+    pub fn initialize_and_lock(
+        ctx: Context<LockTokens>,
+        amount: u64,
+        unlock_timestamp: i64,
+    ) -> Result<()> {
+        let accounts = ctx.accounts;
+        if accounts.global_state.authority == Pubkey::default() {
+            accounts.bootstrap_global_state()?;
+            if accounts.program_stats.data_is_empty() {
+                create_program_stats(
+                    &accounts.program_stats,
+                    &accounts.owner,
+                    &accounts.system_program,
+                    ctx.bumps.program_stats,
+                )?;
+            }
+        } else {
+            msg!("Lockfun already initialized, locking only");
+        }
+
+        accounts.create_lock(
+            ctx.bumps.vault,
+            amount,
+            unlock_timestamp,
+            LockOptions::default(),
+        )?;
+        Ok(())
+    }
+
+    /// Override the timestamp used by every instruction (test builds only)
+    /// - Authority only
+    /// - Setting it back to 0 restores the real clock
+    #[cfg(feature = "test-clock")]
+    pub fn set_mock_timestamp(ctx: Context<UpdateGlobalState>, mock_timestamp: i64) -> Result<()> {
+        ctx.accounts.global_state.mock_timestamp = mock_timestamp;
+        msg!("Mock timestamp set to {}", mock_timestamp);
+        Ok(())
+    }
+
+    /// Overwrite a lock's stored vault bump, to exercise the canonical bump guard (test builds only)
+    /// - Authority only
+    #[cfg(feature = "test-clock")]
+    pub fn set_mock_vault_bump(ctx: Context<SetMockVaultBump>, vault_bump: u8) -> Result<()> {
+        ctx.accounts.lock.vault_bump = vault_bump;
+        msg!(
+            "Lock #{} vault bump set to {}",
+            ctx.accounts.lock.id,
+            vault_bump
+        );
+        Ok(())
+    }
+
     /// Lock tokens until a specific timestamp
     /// - Creates a Lock account with unique id
     /// - Transfers tokens to a vault PDA
     /// - Only the owner can unlock after the timestamp
-    pub fn lock(ctx: Context<LockTokens>, amount: u64, unlock_timestamp: i64) -> Result<()> {
-        require!(amount > 0, ErrorCode::AmountZero);
+    /// - An unexpired `fee_voucher` of the voucher signer waives the lock fee (see `FeeVoucher`)
+    pub fn lock(
+        ctx: Context<LockTokens>,
+        amount: u64,
+        unlock_timestamp: i64,
+        fee_voucher: Option<FeeVoucher>,
+    ) -> Result<()> {
+        let vault_bump = ctx.bumps.vault;
+        let vault = ctx.accounts.vault.to_account_info();
+        ctx.accounts.create_lock_into(
+            vault,
+            vault_bump,
+            amount,
+            unlock_timestamp,
+            LockOptions::default(),
+            fee_voucher,
+        )?;
+        Ok(())
+    }
 
-        let current_ts = Clock::get()?.unix_timestamp;
-        require!(unlock_timestamp > current_ts, ErrorCode::TimestampInPast);
+    /// Same as `lock`, with the unlock timestamp in Unix milliseconds as most frontends produce
+    /// - Rounded up to the next whole second, so the lock never matures early
+    /// - Values below `MIN_TIMESTAMP_MS` are seconds passed by mistake and are rejected
+    pub fn lock_ms(ctx: Context<LockTokens>, amount: u64, unlock_timestamp_ms: i64) -> Result<()> {
+        require!(
+            unlock_timestamp_ms >= MIN_TIMESTAMP_MS,
+            ErrorCode::TimestampNotMilliseconds
+        );
+        let unlock_timestamp =
+            unlock_timestamp_ms / 1000 + (unlock_timestamp_ms % 1000 != 0) as i64;
 
-        let global_state = &mut ctx.accounts.global_state;
-        // Assign sequential ID to this lock (represents which lock this is: 1st, 2nd, 3rd, etc.)
-        let lock_id = global_state.lock_counter;
+        lock(ctx, amount, unlock_timestamp, None)
+    }
+
+    /// Lock tokens on behalf of a controlling program, e.g. a DAO governance PDA
+    /// - The signer funds the lock (tokens, fee and rent) but `controller` becomes its owner and
+    ///   beneficiary, so only `controller` can sign `unlock` (and every other owner instruction)
+    /// - For a PDA, the controlling program must CPI into `unlock` with `invoke_signed` using the
+    ///   PDA's seeds, and the destination token account must be owned by the PDA
+    /// - Irreversible for the funder, who keeps no rights over the lock
+    pub fn lock_to_program(
+        ctx: Context<LockTokens>,
+        amount: u64,
+        unlock_timestamp: i64,
+        controller: Pubkey,
+    ) -> Result<()> {
+        require!(
+            controller != Pubkey::default(),
+            ErrorCode::InvalidController
+        );
+
+        let vault_bump = ctx.bumps.vault;
+        let lock_id = ctx.accounts.create_lock(
+            vault_bump,
+            amount,
+            unlock_timestamp,
+            LockOptions::default(),
+        )?;
 
-        // Populate lock account
         let lock = &mut ctx.accounts.lock;
-        lock.id = lock_id; // Store the sequential number in the lock account
-        lock.owner = ctx.accounts.owner.key();
-        lock.mint = ctx.accounts.mint.key();
-        lock.amount = amount;
-        lock.unlock_timestamp = unlock_timestamp;
-        lock.created_at = current_ts;
-        lock.vault_bump = ctx.bumps.vault;
-        lock.is_unlocked = false;
+        lock.owner = controller;
+        lock.beneficiary = controller;
 
-        // Get decimals for transfer
-        let decimals = ctx.accounts.mint.decimals;
+        msg!("Lock #{} controlled by {}", lock_id, controller);
+        Ok(())
+    }
 
-        // Transfer tokens from owner to vault
-        token_interface::transfer_checked(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                TransferChecked {
-                    from: ctx.accounts.owner_token_account.to_account_info(),
-                    mint: ctx.accounts.mint.to_account_info(),
-                    to: ctx.accounts.vault.to_account_info(),
-                    authority: ctx.accounts.owner.to_account_info(),
-                },
-            ),
+    /// Same as `lock`, with the tokens held in the associated token account of the vault PDA
+    /// rather than in the vault PDA itself, for tooling that expects ATAs
+    /// - The PDA token account created by `LockTokens` stays empty and is closed right away,
+    ///   refunding its rent to the owner
+    /// - Unlocked with `unlock_ata_vault`; instructions working on the PDA vault don't apply
+    pub fn lock_with_ata_vault(
+        ctx: Context<LockWithAtaVault>,
+        amount: u64,
+        unlock_timestamp: i64,
+    ) -> Result<()> {
+        let vault_bump = ctx.bumps.lock_tokens.vault;
+        let ata_vault = ctx.accounts.ata_vault.to_account_info();
+        let lock_tokens = &mut ctx.accounts.lock_tokens;
+        let lock_id = lock_tokens.create_lock_into(
+            ata_vault,
+            vault_bump,
             amount,
-            decimals,
+            unlock_timestamp,
+            LockOptions::default(),
+            None,
         )?;
+        lock_tokens.lock.vault_scheme = VAULT_SCHEME_ATA;
 
-        // Transfer fee (0.03 SOL) to fee recipient
-        anchor_lang::system_program::transfer(
-            CpiContext::new(
-                ctx.accounts.system_program.to_account_info(),
-                anchor_lang::system_program::Transfer {
-                    from: ctx.accounts.owner.to_account_info(),
-                    to: ctx.accounts.fee_recipient.to_account_info(),
-                },
-            ),
-            FEE_AMOUNT,
+        close_vault(
+            &lock_tokens.token_program,
+            &lock_tokens.vault,
+            lock_tokens.owner.to_account_info(),
+            &lock_tokens.lock,
         )?;
 
-        // Increment the global counter for the next lock
-        // This allows easy fetching of total lock count and recent locks
-        global_state.lock_counter = global_state.lock_counter.checked_add(1).unwrap();
+        msg!(
+            "Lock #{} held in vault ATA {}",
+            lock_id,
+            ctx.accounts.ata_vault.key()
+        );
+        Ok(())
+    }
+
+    /// Unlock a `lock_with_ata_vault` lock after the timestamp has passed
+    /// - Same rules as `unlock`, from the vault ATA to the beneficiary's token account
+    pub fn unlock_ata_vault(ctx: Context<UnlockAtaVault>) -> Result<()> {
+        require!(
+            ctx.accounts.lock.vault_scheme == VAULT_SCHEME_ATA,
+            ErrorCode::WrongVaultScheme
+        );
+        require_canonical_vault_bump(&ctx.accounts.lock, ctx.bumps.vault_authority)?;
+
+        let now = current_timestamp(&ctx.accounts.global_state)?;
+        let amount = begin_unlock(
+            &mut ctx.accounts.lock,
+            &ctx.accounts.global_state,
+            &ctx.accounts.denylist,
+            now,
+            false,
+        )?;
+        transfer_signed_by_vault_pda(
+            &ctx.accounts.token_program,
+            ctx.accounts.vault.to_account_info(),
+            ctx.accounts.vault_authority.to_account_info(),
+            &ctx.accounts.mint,
+            ctx.accounts.owner_token_account.to_account_info(),
+            &ctx.accounts.lock,
+            amount,
+        )?;
+        record_withdrawal(&ctx.accounts.mint_stats, amount, true)?;
+        record_owner_withdrawal(&ctx.accounts.owner_mint_stats, amount)?;
+        record_lock_closed(&ctx.accounts.program_stats)?;
 
         msg!(
-            "Locked {} tokens of mint {} until timestamp {} (lock #{})",
+            "Unlocked {} tokens from the vault ATA of lock #{}",
             amount,
-            lock.mint,
-            unlock_timestamp,
-            lock_id
+            ctx.accounts.lock.id
         );
+        Ok(())
+    }
+
+    /// Same as `lock`, with the tokens held in a vault salted by the owner, for privacy
+    /// - The vault is the token account `[VAULT_SEED, lock_id, salt]`, owned by the vault PDA:
+    ///   its address can't be predicted from the lock id, so an owner's vaults aren't linkable
+    /// - The salt is stored on the lock to find the vault again at unlock time
+    /// - The unused PDA token account is closed, refunding its rent to the owner
+    pub fn lock_with_salted_vault(
+        ctx: Context<LockWithSaltedVault>,
+        amount: u64,
+        unlock_timestamp: i64,
+        salt: [u8; 32],
+    ) -> Result<()> {
+        let vault_bump = ctx.bumps.lock_tokens.vault;
+        let salted_vault = ctx.accounts.salted_vault.to_account_info();
+        let lock_tokens = &mut ctx.accounts.lock_tokens;
+        let lock_id = lock_tokens.create_lock_into(
+            salted_vault,
+            vault_bump,
+            amount,
+            unlock_timestamp,
+            LockOptions::default(),
+            None,
+        )?;
+        lock_tokens.lock.vault_scheme = VAULT_SCHEME_SALTED;
+        lock_tokens.lock.vault_salt = salt;
+
+        close_vault(
+            &lock_tokens.token_program,
+            &lock_tokens.vault,
+            lock_tokens.owner.to_account_info(),
+            &lock_tokens.lock,
+        )?;
 
+        msg!("Lock #{} held in a salted vault", lock_id);
         Ok(())
     }
 
-    /// Unlock tokens after the timestamp has passed
-    /// - Only the original owner can unlock
-    /// - Transfers tokens from vault back to owner
-    pub fn unlock(ctx: Context<UnlockTokens>) -> Result<()> {
-        // Prevent duplicate mutable accounts attack
+    /// Unlock a `lock_with_salted_vault` lock after the timestamp has passed
+    /// - Same rules as `unlock`, from the vault re-derived with the stored salt
+    pub fn unlock_salted_vault(ctx: Context<UnlockSaltedVault>) -> Result<()> {
         require!(
-            ctx.accounts.vault.key() != ctx.accounts.owner_token_account.key(),
-            ErrorCode::DuplicateAccounts
+            ctx.accounts.lock.vault_scheme == VAULT_SCHEME_SALTED,
+            ErrorCode::WrongVaultScheme
         );
+        require_canonical_vault_bump(&ctx.accounts.lock, ctx.bumps.vault_authority)?;
 
-        let lock = &ctx.accounts.lock;
-
-        require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+        let now = current_timestamp(&ctx.accounts.global_state)?;
+        let amount = begin_unlock(
+            &mut ctx.accounts.lock,
+            &ctx.accounts.global_state,
+            &ctx.accounts.denylist,
+            now,
+            false,
+        )?;
+        transfer_signed_by_vault_pda(
+            &ctx.accounts.token_program,
+            ctx.accounts.vault.to_account_info(),
+            ctx.accounts.vault_authority.to_account_info(),
+            &ctx.accounts.mint,
+            ctx.accounts.owner_token_account.to_account_info(),
+            &ctx.accounts.lock,
+            amount,
+        )?;
+        record_withdrawal(&ctx.accounts.mint_stats, amount, true)?;
+        record_owner_withdrawal(&ctx.accounts.owner_mint_stats, amount)?;
+        record_lock_closed(&ctx.accounts.program_stats)?;
 
-        let current_ts = Clock::get()?.unix_timestamp;
-        require!(current_ts >= lock.unlock_timestamp, ErrorCode::TooEarly);
+        msg!(
+            "Unlocked {} tokens from the salted vault of lock #{}",
+            amount,
+            ctx.accounts.lock.id
+        );
+        Ok(())
+    }
 
-        let amount = lock.amount;
-        let lock_id_bytes = lock.id.to_le_bytes();
-        let decimals = ctx.accounts.mint.decimals;
+    /// Close a fully unlocked lock and its vault, returning their rent to the owner
+    /// - Only the lock owner, only once `is_unlocked` is set, so active locks can't be closed
+    /// - Any dust left in the vault (e.g. tokens sent to it directly) is swept to the owner
+    ///   first, so it can't keep the vault from closing
+    /// - Burns the receipt NFT when the optional receipt accounts are passed, for locks released
+    ///   by a path the receipt holder didn't sign
+    pub fn force_close(ctx: Context<ForceClose>) -> Result<()> {
+        require!(ctx.accounts.lock.is_unlocked, ErrorCode::LockStillActive);
+        require_canonical_vault_bump(&ctx.accounts.lock, ctx.bumps.vault)?;
 
-        // Transfer tokens from vault back to owner using PDA signer
-        let seeds = &[VAULT_SEED, lock_id_bytes.as_ref(), &[lock.vault_bump]];
-        let signer_seeds = &[&seeds[..]];
+        if ctx.accounts.receipt_token_account.is_some() {
+            burn_receipt(
+                &ctx.accounts.lock,
+                ctx.accounts.receipt_mint.as_ref(),
+                ctx.accounts.receipt_token_account.as_ref(),
+                &ctx.accounts.owner,
+                &ctx.accounts.token_program,
+            )?;
+        }
 
-        token_interface::transfer_checked(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                TransferChecked {
-                    from: ctx.accounts.vault.to_account_info(),
-                    mint: ctx.accounts.mint.to_account_info(),
-                    to: ctx.accounts.owner_token_account.to_account_info(),
-                    authority: ctx.accounts.vault.to_account_info(),
-                },
-                signer_seeds,
-            ),
-            amount,
-            decimals,
+        let dust = ctx.accounts.vault.amount;
+        if dust > 0 {
+            transfer_from_vault(
+                &ctx.accounts.token_program,
+                &ctx.accounts.vault,
+                &ctx.accounts.mint,
+                ctx.accounts.owner_token_account.to_account_info(),
+                &ctx.accounts.lock,
+                dust,
+            )?;
+        }
+        close_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault,
+            ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.lock,
         )?;
 
-        // Mark as unlocked
-        let lock = &mut ctx.accounts.lock;
-        lock.is_unlocked = true;
+        msg!(
+            "Closed lock #{} (swept {} dust tokens)",
+            ctx.accounts.lock.id,
+            dust
+        );
+        Ok(())
+    }
+
+    /// Lock tokens for a calendar duration: `months` calendar months then `days` days from now
+    /// - Month lengths follow the Gregorian calendar (UTC); a day past the end of the target
+    ///   month is clamped to its last day (Jan 31 + 1 month = Feb 28, or 29 in leap years)
+    /// - The time of day is kept (rounded up to a whole minute when aligned timestamps are
+    ///   required)
+    /// - Fails with `InvalidCalendarDuration` when both `months` and `days` are zero
+    pub fn lock_calendar(
+        ctx: Context<LockTokens>,
+        amount: u64,
+        months: u8,
+        days: u16,
+    ) -> Result<()> {
+        require!(months > 0 || days > 0, ErrorCode::InvalidCalendarDuration);
 
-        msg!("Unlocked {} tokens from lock #{}", amount, lock.id);
+        let global_state = &ctx.accounts.global_state;
+        let now = current_timestamp(global_state)?;
+        let unlock_timestamp =
+            add_calendar_duration(now, months, days).ok_or(ErrorCode::InvalidCalendarDuration)?;
+        let unlock_timestamp = round_up_to_alignment(global_state, unlock_timestamp);
+        require!(unlock_timestamp > now, ErrorCode::InvalidCalendarDuration);
 
+        let vault_bump = ctx.bumps.vault;
+        ctx.accounts
+            .create_lock(vault_bump, amount, unlock_timestamp, LockOptions::default())?;
         Ok(())
     }
 
-    /// Add more tokens to an existing lock
-    /// - Only the lock owner can add tokens
-    /// - Lock must not be unlocked
-    /// - Mint must match the existing lock
-    pub fn top_up(ctx: Context<TopUpLock>, additional_amount: u64) -> Result<()> {
-        // Prevent duplicate mutable accounts attack
+    /// Lock the owner's whole token account balance, read on-chain
+    /// - Avoids racing incoming transfers between a client-side balance read and `lock`
+    /// - Fails with `AmountZero` on an empty account
+    pub fn lock_all(ctx: Context<LockTokens>, unlock_timestamp: i64) -> Result<()> {
+        let vault_bump = ctx.bumps.vault;
+        let amount = ctx.accounts.owner_token_account.amount;
+        ctx.accounts
+            .create_lock(vault_bump, amount, unlock_timestamp, LockOptions::default())?;
+        Ok(())
+    }
+
+    /// Lock a share of the owner's token account balance, read on-chain
+    /// - Locks `balance * bps / 10000`, rounded down; `bps` must be between 1 and 10000 (100%)
+    /// - Fails with `AmountZero` when the share rounds down to nothing
+    pub fn lock_percent(ctx: Context<LockTokens>, bps: u16, unlock_timestamp: i64) -> Result<()> {
         require!(
-            ctx.accounts.vault.key() != ctx.accounts.owner_token_account.key(),
-            ErrorCode::DuplicateAccounts
+            bps > 0 && bps as u64 <= BPS_DENOMINATOR,
+            ErrorCode::InvalidBps
         );
 
-        require!(additional_amount > 0, ErrorCode::AmountZero);
+        let vault_bump = ctx.bumps.vault;
+        let balance = ctx.accounts.owner_token_account.amount;
+        let amount = (balance as u128 * bps as u128 / BPS_DENOMINATOR as u128) as u64;
+        ctx.accounts
+            .create_lock(vault_bump, amount, unlock_timestamp, LockOptions::default())?;
+        Ok(())
+    }
+
+    /// First half of a lock split across two transactions, to spread its cost: create the
+    /// lock account and its vault and reserve lock id `id`
+    /// - `id` must be the next id (`global_state.lock_counter`), so ids can't be squatted
+    /// - The prepared lock is inert (`is_unlocked` set, nothing locked) until `finalize_lock`;
+    ///   the owner may abandon it with `force_close` to get the rent back
+    /// - Counts against the epoch's rate limit, the fee is charged by `finalize_lock`
+    pub fn prepare_vault(ctx: Context<PrepareVault>, id: u64) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        require!(
+            global_state.authority != Pubkey::default(),
+            ErrorCode::NotInitialized
+        );
+        require!(!global_state.paused, ErrorCode::Paused);
+        global_state.require_enabled(DISABLE_LOCK)?;
+        require!(id == global_state.lock_counter, ErrorCode::LockIdMismatch);
+        require_not_denied(
+            global_state,
+            &ctx.accounts.denylist,
+            &ctx.accounts.owner.key(),
+        )?;
+        require_mint_not_flagged(&ctx.accounts.mint_status)?;
+        global_state.count_new_lock()?;
+        global_state.lock_counter = id.checked_add(1).ok_or(ErrorCode::AmountOverflow)?;
 
+        let owner = ctx.accounts.owner.key();
+        let token_program = ctx.accounts.token_program.key();
         let lock = &mut ctx.accounts.lock;
+        lock.id = id;
+        lock.owner = owner;
+        lock.mint = ctx.accounts.mint.key();
+        lock.amount = 0;
+        lock.unlock_timestamp = 0;
+        lock.created_at = 0;
+        lock.vault_bump = ctx.bumps.vault;
+        lock.is_unlocked = true;
+        lock.arbiter = Pubkey::default();
+        lock.counterparty = Pubkey::default();
+        lock.beneficiary = owner;
+        lock.pending_beneficiary = Pubkey::default();
+        lock.beneficiary_change_at = 0;
+        lock.reducible = false;
+        lock.token_program = token_program;
+        lock.is_token_2022 = token_program == anchor_spl::token_2022::ID;
+        lock.recovery_after = 0;
+        lock.allow_partial = false;
+        lock.unlocked_at = 0;
+        lock.secret_hash = [0; 32];
+        lock.vault_scheme = VAULT_SCHEME_PDA;
+        lock.vesting_curve = VESTING_NONE;
+        lock.vested_claimed = 0;
+        lock.cancellable = false;
+        lock.vault_salt = [0; 32];
+        lock.deposit_lamports = 0;
+        lock.claim_window = 0;
+        lock.bounty_bps = 0;
+        lock.burn_on_unlock = false;
+        lock.receipt_mint = Pubkey::default();
+        lock.reference_mint = Pubkey::default();
+        lock.unlock_slot = 0;
 
-        require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+        msg!("Prepared lock #{} for mint {}", id, lock.mint);
+        Ok(())
+    }
 
-        let decimals = ctx.accounts.mint.decimals;
+    /// Second half of the split lock: move `amount` tokens into the vault of a lock made by
+    /// `prepare_vault` and activate it until `unlock_timestamp`
+    /// - Owner only, and only on a prepared lock that was never finalized (`LockNotPrepared`),
+    ///   so it can't reopen a lock that was unlocked
+    /// - The vault must still be empty (`VaultBalanceMismatch`), so the lock holds exactly
+    ///   `amount`; tokens sent to it meanwhile are swept by abandoning it with `force_close`
+    /// - Same checks, fee, indexes, stats and default options as `lock`
+    pub fn finalize_lock(
+        ctx: Context<FinalizeLock>,
+        amount: u64,
+        unlock_timestamp: i64,
+    ) -> Result<()> {
+        let accounts = ctx.accounts;
+        let lock = &accounts.lock;
+        require!(
+            lock.is_unlocked && lock.created_at == 0 && lock.amount == 0,
+            ErrorCode::LockNotPrepared
+        );
+        require!(accounts.vault.amount == 0, ErrorCode::VaultBalanceMismatch);
+        require_keys_eq!(
+            accounts.token_program.key(),
+            lock.token_program,
+            ErrorCode::WrongTokenProgram
+        );
+
+        let global_state = &accounts.global_state;
+        require!(!global_state.paused, ErrorCode::Paused);
+        global_state.require_enabled(DISABLE_LOCK)?;
+        require_keys_eq!(
+            accounts.fee_recipient.key(),
+            global_state.fee_recipient,
+            ErrorCode::InvalidFeeRecipient
+        );
+        require_keys_neq!(
+            accounts.owner.key(),
+            accounts.fee_recipient.key(),
+            ErrorCode::OwnerIsFeeRecipient
+        );
+        require_keys_neq!(
+            accounts.owner_token_account.owner,
+            accounts.fee_recipient.key(),
+            ErrorCode::OwnerIsFeeRecipient
+        );
+        require!(amount > 0, ErrorCode::AmountZero);
+        global_state.require_within_max_amount(amount)?;
+        require!(
+            accounts.owner_token_account.amount >= amount,
+            ErrorCode::InsufficientTokenBalance
+        );
+
+        let current_ts = current_timestamp(global_state)?;
+        require!(unlock_timestamp > current_ts, ErrorCode::TimestampInPast);
+        require_aligned(global_state, unlock_timestamp)?;
+        require_not_denied(global_state, &accounts.denylist, &accounts.owner.key())?;
+        require_deposits_open(&accounts.mint_status, &accounts.mint_stats, amount)?;
+        require_mint_not_flagged(&accounts.mint_status)?;
+        let fee = lock_fee(global_state, &accounts.fee_override)?;
+
+        let lock_id = lock.id;
+        add_to_expiry_index(
+            &accounts.expiry_index,
+            &accounts.owner,
+            &accounts.system_program,
+            unlock_timestamp,
+            lock_id,
+        )?;
+        add_to_creation_index(
+            &accounts.creation_index,
+            &accounts.owner,
+            &accounts.system_program,
+            current_ts,
+            lock_id,
+        )?;
 
-        // Transfer additional tokens from owner to vault
         token_interface::transfer_checked(
             CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
+                accounts.token_program.to_account_info(),
                 TransferChecked {
-                    from: ctx.accounts.owner_token_account.to_account_info(),
-                    mint: ctx.accounts.mint.to_account_info(),
-                    to: ctx.accounts.vault.to_account_info(),
-                    authority: ctx.accounts.owner.to_account_info(),
+                    from: accounts.owner_token_account.to_account_info(),
+                    mint: accounts.mint.to_account_info(),
+                    to: accounts.vault.to_account_info(),
+                    authority: accounts.owner.to_account_info(),
                 },
             ),
-            additional_amount,
-            decimals,
+            amount,
+            accounts.mint.decimals,
+        )?;
+        pay_lock_fee(
+            &accounts.fee_recipient,
+            &accounts.owner,
+            &accounts.system_program,
+            fee,
         )?;
 
-        // Update lock amount
-        lock.amount = lock.amount.checked_add(additional_amount).unwrap();
+        let lock = &mut accounts.lock;
+        lock.amount = amount;
+        lock.unlock_timestamp = unlock_timestamp;
+        lock.created_at = current_ts;
+        lock.is_unlocked = false;
+        lock.allow_partial = LockOptions::default().allow_partial;
 
+        if accounts.owner_mint_stats.data_is_empty() {
+            create_owner_mint_stats(
+                &accounts.owner_mint_stats,
+                &accounts.owner,
+                &accounts.mint.key(),
+                &accounts.system_program,
+            )?;
+        }
+        record_deposit(&accounts.mint_stats, amount, true)?;
+        record_owner_deposit(&accounts.owner_mint_stats, amount)?;
+        record_lock_created(&accounts.program_stats, fee)?;
+
+        emit!(AmountEvent {
+            lock_id,
+            mint: accounts.lock.mint,
+            amount,
+        });
         msg!(
-            "Added {} tokens to lock #{} (new total: {})",
-            additional_amount,
-            lock.id,
-            lock.amount
+            "Locked {} tokens of mint {} until timestamp {} (lock #{}, finalized)",
+            amount,
+            accounts.lock.mint,
+            unlock_timestamp,
+            lock_id
         );
+        Ok(())
+    }
 
+    /// Lock tokens with optional per-lock settings (see `LockOptions`)
+    /// - Escrow: an arbiter may release the tokens to a counterparty before maturity
+    /// - Reducible: the owner may withdraw part of the tokens before maturity
+    pub fn lock_with_options(
+        ctx: Context<LockTokens>,
+        amount: u64,
+        unlock_timestamp: i64,
+        options: LockOptions,
+    ) -> Result<()> {
+        let vault_bump = ctx.bumps.vault;
+        ctx.accounts
+            .create_lock(vault_bump, amount, unlock_timestamp, options)?;
         Ok(())
     }
 
-    /// Extend the unlock timestamp of an existing lock
-    /// - Only the lock owner can extend
-    /// - Lock must not be unlocked
-    /// - New timestamp must be greater than current timestamp (can only extend, not shorten)
-    pub fn extend(ctx: Context<ExtendLock>, new_unlock_timestamp: i64) -> Result<()> {
-        let lock = &mut ctx.accounts.lock;
+    /// Lock tokens at most once per client-supplied key, so retries can't create duplicate locks
+    /// - Same as `lock_with_options`, plus an `IdempotencyRecord` PDA seeded by owner and key
+    /// - A retry of a confirmed call fails because the record already exists
+    pub fn lock_idempotent(
+        ctx: Context<LockIdempotent>,
+        amount: u64,
+        unlock_timestamp: i64,
+        options: LockOptions,
+        idempotency_key: [u8; 16],
+    ) -> Result<()> {
+        let vault_bump = ctx.bumps.lock_tokens.vault;
+        let lock_id =
+            ctx.accounts
+                .lock_tokens
+                .create_lock(vault_bump, amount, unlock_timestamp, options)?;
 
-        require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+        let record = &mut ctx.accounts.idempotency_record;
+        record.owner = ctx.accounts.lock_tokens.owner.key();
+        record.idempotency_key = idempotency_key;
+        record.lock_id = lock_id;
+        record.bump = ctx.bumps.idempotency_record;
+
+        Ok(())
+    }
+
+    /// Lock tokens with the settings of an authority-defined `LockTemplate`
+    /// - Unlocks `template.duration` seconds from now (rounded up to a whole minute
+    ///   when aligned timestamps are required)
+    /// - Fails with `TemplateNotFound` if no template has this id
+    pub fn lock_from_template(
+        ctx: Context<LockFromTemplate>,
+        template_id: u64,
+        amount: u64,
+    ) -> Result<()> {
+        let template = load_lock_template(&ctx.accounts.template)?;
+
+        let global_state = &ctx.accounts.lock_tokens.global_state;
+        let unlock_timestamp = current_timestamp(global_state)?
+            .checked_add(template.duration)
+            .ok_or(ErrorCode::InvalidTemplate)?;
+        let unlock_timestamp = round_up_to_alignment(global_state, unlock_timestamp);
+
+        let options = LockOptions {
+            reducible: template.reducible,
+            ..LockOptions::default()
+        };
+        let vault_bump = ctx.bumps.lock_tokens.vault;
+        let lock_id =
+            ctx.accounts
+                .lock_tokens
+                .create_lock(vault_bump, amount, unlock_timestamp, options)?;
+
+        msg!("Lock #{} created from template #{}", lock_id, template_id);
+        Ok(())
+    }
+
+    /// Lock tokens released in fixed tranches at specific dates
+    /// - Milestone amounts must sum to the locked amount
+    /// - Milestone timestamps must be strictly increasing and in the future
+    /// - The lock's unlock timestamp is the last milestone
+    pub fn lock_with_milestones(
+        ctx: Context<LockWithMilestones>,
+        amount: u64,
+        milestones: Vec<Milestone>,
+    ) -> Result<()> {
         require!(
-            new_unlock_timestamp > lock.unlock_timestamp,
-            ErrorCode::CannotShortenTimestamp
+            !milestones.is_empty() && milestones.len() <= MAX_MILESTONES,
+            ErrorCode::InvalidSchedule
+        );
+
+        let mut total: u64 = 0;
+        let mut previous_ts = current_timestamp(&ctx.accounts.lock_tokens.global_state)?;
+        for milestone in milestones.iter() {
+            require!(milestone.amount > 0, ErrorCode::InvalidSchedule);
+            require!(
+                milestone.timestamp > previous_ts,
+                ErrorCode::InvalidSchedule
+            );
+            previous_ts = milestone.timestamp;
+            total = total
+                .checked_add(milestone.amount)
+                .ok_or(ErrorCode::InvalidSchedule)?;
+        }
+        require!(total == amount, ErrorCode::InvalidSchedule);
+
+        let vault_bump = ctx.bumps.lock_tokens.vault;
+        let lock_id = ctx.accounts.lock_tokens.create_lock(
+            vault_bump,
+            amount,
+            previous_ts,
+            LockOptions::default(),
+        )?;
+
+        let schedule = &mut ctx.accounts.schedule;
+        schedule.lock_id = lock_id;
+        schedule.milestones = milestones;
+        schedule.claimed_count = 0;
+        schedule.bump = ctx.bumps.schedule;
+
+        msg!(
+            "Lock #{} released over {} milestones",
+            lock_id,
+            schedule.milestones.len()
+        );
+
+        Ok(())
+    }
+
+    /// Lock tokens with fallback claimants, for inheritance
+    /// - Up to `MAX_HEIRS` heirs with strictly increasing, positive grace offsets
+    /// - Each heir may unlock once `unlock_timestamp + grace_offset` has passed,
+    ///   if nobody claimed before (see `heir_unlock`)
+    pub fn lock_with_heirs(
+        ctx: Context<LockWithHeirs>,
+        amount: u64,
+        unlock_timestamp: i64,
+        heirs: Vec<Heir>,
+    ) -> Result<()> {
+        require!(
+            !heirs.is_empty() && heirs.len() <= MAX_HEIRS,
+            ErrorCode::InvalidHeirs
+        );
+        let mut previous_offset = 0;
+        for heir in heirs.iter() {
+            require!(heir.heir != Pubkey::default(), ErrorCode::InvalidHeirs);
+            require!(heir.grace_offset > previous_offset, ErrorCode::InvalidHeirs);
+            previous_offset = heir.grace_offset;
+        }
+
+        let vault_bump = ctx.bumps.lock_tokens.vault;
+        let lock_id = ctx.accounts.lock_tokens.create_lock(
+            vault_bump,
+            amount,
+            unlock_timestamp,
+            LockOptions::default(),
+        )?;
+
+        let lock_heirs = &mut ctx.accounts.heirs;
+        lock_heirs.lock_id = lock_id;
+        lock_heirs.heirs = heirs;
+        lock_heirs.bump = ctx.bumps.heirs;
+
+        msg!("Lock #{} has {} heirs", lock_id, lock_heirs.heirs.len());
+
+        Ok(())
+    }
+
+    /// Unlock a lock as one of its heirs, once the owner (and every earlier heir) left it unclaimed
+    /// - The heir must be listed and its grace offset past maturity must have elapsed
+    /// - Tokens go to the heir's token account
+    pub fn heir_unlock(ctx: Context<HeirUnlock>) -> Result<()> {
+        let heir = ctx
+            .accounts
+            .heirs
+            .heirs
+            .iter()
+            .find(|heir| heir.heir == ctx.accounts.heir.key())
+            .ok_or(ErrorCode::HeirNotEligible)?;
+
+        let now = current_timestamp(&ctx.accounts.global_state)?;
+        let eligible_at = ctx
+            .accounts
+            .lock
+            .unlock_timestamp
+            .saturating_add(heir.grace_offset);
+        require!(now >= eligible_at, ErrorCode::HeirNotEligible);
+        require_canonical_vault_bump(&ctx.accounts.lock, ctx.bumps.vault)?;
+
+        let amount = process_unlock(
+            &mut ctx.accounts.lock,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.heir_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            &ctx.accounts.global_state,
+            &ctx.accounts.denylist,
+            now,
+        )?;
+        record_withdrawal(&ctx.accounts.mint_stats, amount, true)?;
+        record_owner_withdrawal(&ctx.accounts.owner_mint_stats, amount)?;
+        record_lock_closed(&ctx.accounts.program_stats)?;
+
+        msg!(
+            "Lock #{} claimed by heir {}",
+            ctx.accounts.lock.id,
+            ctx.accounts.heir.key()
+        );
+
+        Ok(())
+    }
+
+    /// Claim a matured lock by revealing the preimage of its `secret_hash` (hash-locked claims)
+    /// - The secret is bound to the claimant: `secret_hash = sha256(preimage || claimant)`, so a
+    ///   preimage seen in a pending transaction can't be replayed by another signer
+    /// - Fails with `WrongSecret` otherwise, so locks created without a secret can't be claimed
+    ///   this way
+    /// - The owner can still `unlock` a matured lock that was never claimed
+    pub fn unlock_with_secret(ctx: Context<UnlockWithSecret>, preimage: Vec<u8>) -> Result<()> {
+        require!(
+            ctx.accounts
+                .lock
+                .is_secret_claimant(&preimage, &ctx.accounts.claimant.key()),
+            ErrorCode::WrongSecret
+        );
+        require_canonical_vault_bump(&ctx.accounts.lock, ctx.bumps.vault)?;
+
+        let now = current_timestamp(&ctx.accounts.global_state)?;
+        let amount = process_unlock(
+            &mut ctx.accounts.lock,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.claimant_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            &ctx.accounts.global_state,
+            &ctx.accounts.denylist,
+            now,
+        )?;
+        record_withdrawal(&ctx.accounts.mint_stats, amount, true)?;
+        record_owner_withdrawal(&ctx.accounts.owner_mint_stats, amount)?;
+        record_lock_closed(&ctx.accounts.program_stats)?;
+
+        msg!(
+            "Lock #{} claimed with its secret by {}",
+            ctx.accounts.lock.id,
+            ctx.accounts.claimant.key()
+        );
+
+        Ok(())
+    }
+
+    /// Unlock tokens after the timestamp has passed
+    /// - Only the original owner can unlock
+    /// - Transfers tokens from vault to the lock's beneficiary
+    ///   (or to `recipient_token_account` while the owner is the beneficiary)
+    /// - Burn-on-unlock locks (`LockOptions.burn_on_unlock`) burn the tokens instead, emitting
+    ///   `Burned`; no other instruction can release them
+    /// - An optional memo (up to `MAX_MEMO_LEN` bytes) is attached through the SPL Memo program
+    pub fn unlock(ctx: Context<UnlockTokens>, memo: Option<String>) -> Result<()> {
+        // Prevent duplicate mutable accounts attack
+        require!(
+            ctx.accounts.vault.key() != ctx.accounts.owner_token_account.key(),
+            ErrorCode::DuplicateAccounts
+        );
+        require_canonical_vault_bump(&ctx.accounts.lock, ctx.bumps.vault)?;
+
+        // Memo right before the transfer, which is also where Token-2022 required-memo accounts look
+        if let Some(memo) = memo {
+            require!(memo.len() <= MAX_MEMO_LEN, ErrorCode::MemoTooLong);
+            let memo_program = ctx
+                .accounts
+                .memo_program
+                .as_ref()
+                .ok_or(ErrorCode::MemoProgramMissing)?;
+            memo::build_memo(
+                CpiContext::new(memo_program.to_account_info(), BuildMemo {}),
+                memo.as_bytes(),
+            )?;
+        }
+
+        let now = current_timestamp(&ctx.accounts.global_state)?;
+        if !ctx.accounts.lock.is_unlocked
+            && ctx
+                .accounts
+                .global_state
+                .is_lock_matured(&ctx.accounts.lock, now)?
+        {
+            ctx.accounts.settle_deposit(now)?;
+        }
+        let amount = ctx.accounts.unlock_whole(now)?;
+        record_withdrawal(&ctx.accounts.mint_stats, amount, true)?;
+        record_owner_withdrawal(&ctx.accounts.owner_mint_stats, amount)?;
+        record_lock_closed(&ctx.accounts.program_stats)?;
+
+        Ok(())
+    }
+
+    /// Same as `unlock` without a memo, but idempotent for bots that may resubmit it
+    /// - Succeeds without doing anything on a lock that is already unlocked or not matured yet,
+    ///   returning whether the tokens were released
+    /// - Other failures (denied owner, disabled unlocks, wrong accounts) still error
+    pub fn try_unlock(ctx: Context<UnlockTokens>) -> Result<bool> {
+        require!(
+            ctx.accounts.vault.key() != ctx.accounts.owner_token_account.key(),
+            ErrorCode::DuplicateAccounts
+        );
+        require_canonical_vault_bump(&ctx.accounts.lock, ctx.bumps.vault)?;
+
+        let now = current_timestamp(&ctx.accounts.global_state)?;
+        let lock = &ctx.accounts.lock;
+        if lock.is_unlocked {
+            msg!("Lock #{} is already unlocked", lock.id);
+            return Ok(false);
+        }
+        if !ctx.accounts.global_state.is_lock_matured(lock, now)? {
+            msg!("Lock #{} is not matured yet", lock.id);
+            return Ok(false);
+        }
+
+        ctx.accounts.settle_deposit(now)?;
+        let amount = ctx.accounts.unlock_whole(now)?;
+        record_withdrawal(&ctx.accounts.mint_stats, amount, true)?;
+        record_owner_withdrawal(&ctx.accounts.owner_mint_stats, amount)?;
+        record_lock_closed(&ctx.accounts.program_stats)?;
+
+        Ok(true)
+    }
+
+    /// Unlock a matured lock straight into the configured staking program, the tokens never
+    /// pass through the beneficiary's wallet
+    /// - Same checks as `unlock`, for locks held in their PDA vault (`VAULT_SCHEME_PDA`)
+    /// - Invokes `stake(amount: u64)` (Anchor discriminator of `global:stake`) on
+    ///   `GlobalState.staking_program`, with the accounts `[vault (writable), vault authority
+    ///   (signer, the vault PDA itself), staker (the lock's beneficiary), mint, token_program]`
+    ///   followed by this instruction's remaining accounts, passed through as is
+    /// - The vault PDA signs, and the staking program must take exactly the lock's amount
+    ///   from the vault (`StakeMismatch` otherwise)
+    pub fn unlock_and_stake<'info>(
+        ctx: Context<'_, '_, 'info, 'info, UnlockAndStake<'info>>,
+    ) -> Result<()> {
+        let unlock = &mut ctx.accounts.unlock;
+        require!(
+            unlock.lock.vault_scheme == VAULT_SCHEME_PDA,
+            ErrorCode::WrongVaultScheme
+        );
+        require_canonical_vault_bump(&unlock.lock, ctx.bumps.unlock.vault)?;
+        require_keys_eq!(
+            unlock.token_program.key(),
+            unlock.lock.token_program,
+            ErrorCode::WrongTokenProgram
+        );
+        require_keys_eq!(unlock.vault.mint, unlock.lock.mint, ErrorCode::InvalidMint);
+
+        let now = current_timestamp(&unlock.global_state)?;
+        if !unlock.lock.is_unlocked && unlock.global_state.is_lock_matured(&unlock.lock, now)? {
+            unlock.settle_deposit(now)?;
+        }
+        let amount = begin_unlock(
+            &mut unlock.lock,
+            &unlock.global_state,
+            &unlock.denylist,
+            now,
+            false,
+        )?;
+        let vault_before = unlock.vault.amount;
+
+        let mut data = hash(b"global:stake").to_bytes()[..8].to_vec();
+        data.extend_from_slice(&amount.to_le_bytes());
+        let mut accounts = vec![
+            AccountMeta::new(unlock.vault.key(), false),
+            // The vault is its own authority, listed again as the signer
+            AccountMeta::new_readonly(unlock.vault.key(), true),
+            AccountMeta::new_readonly(ctx.accounts.staker.key(), false),
+            AccountMeta::new_readonly(unlock.mint.key(), false),
+            AccountMeta::new_readonly(unlock.token_program.key(), false),
+        ];
+        let mut account_infos = vec![
+            unlock.vault.to_account_info(),
+            unlock.vault.to_account_info(),
+            ctx.accounts.staker.to_account_info(),
+            unlock.mint.to_account_info(),
+            unlock.token_program.to_account_info(),
+        ];
+        for account in ctx.remaining_accounts {
+            accounts.push(AccountMeta {
+                pubkey: account.key(),
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            });
+            account_infos.push(account.clone());
+        }
+        account_infos.push(ctx.accounts.staking_program.to_account_info());
+
+        let lock_id_bytes = unlock.lock.id.to_le_bytes();
+        let vault_seeds = &[
+            VAULT_SEED,
+            lock_id_bytes.as_ref(),
+            &[unlock.lock.vault_bump],
+        ];
+        anchor_lang::solana_program::program::invoke_signed(
+            &anchor_lang::solana_program::instruction::Instruction {
+                program_id: ctx.accounts.staking_program.key(),
+                accounts,
+                data,
+            },
+            &account_infos,
+            &[&vault_seeds[..]],
+        )?;
+
+        unlock.vault.reload()?;
+        require!(
+            vault_before.checked_sub(unlock.vault.amount) == Some(amount),
+            ErrorCode::StakeMismatch
+        );
+        record_withdrawal(&unlock.mint_stats, amount, true)?;
+        record_owner_withdrawal(&unlock.owner_mint_stats, amount)?;
+        record_lock_closed(&unlock.program_stats)?;
+
+        msg!(
+            "Unlocked {} tokens from lock #{} into staking program {}",
+            amount,
+            unlock.lock.id,
+            ctx.accounts.staking_program.key()
+        );
+        Ok(())
+    }
+
+    /// Same as `unlock`, into the beneficiary's associated token account for the mint
+    /// - Creates the associated token account if it was closed or never existed, the owner pays its rent
+    pub fn unlock_to_ata(ctx: Context<UnlockToAta>) -> Result<()> {
+        require_canonical_vault_bump(&ctx.accounts.lock, ctx.bumps.vault)?;
+
+        let now = current_timestamp(&ctx.accounts.global_state)?;
+        let amount = process_unlock(
+            &mut ctx.accounts.lock,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.beneficiary_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            &ctx.accounts.global_state,
+            &ctx.accounts.denylist,
+            now,
+        )?;
+        record_withdrawal(&ctx.accounts.mint_stats, amount, true)?;
+        record_owner_withdrawal(&ctx.accounts.owner_mint_stats, amount)?;
+        record_lock_closed(&ctx.accounts.program_stats)?;
+
+        Ok(())
+    }
+
+    /// Unlock several matured locks of the signer in one instruction
+    /// - Remaining accounts, per lock:
+    ///   `[lock, vault, mint, destination token account, mint stats, owner mint stats]`
+    /// - At most `global_state.max_batch_size` locks (`BatchTooLarge`)
+    /// - Same rules as `unlock` for every lock, the destination must belong to its beneficiary;
+    ///   deposit locks fail with `DepositNotSettled`, as only `unlock` settles their deposit
+    /// - The clock is read once for the whole batch; any failing lock reverts the batch
+    pub fn batch_unlock<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BatchUnlock<'info>>,
+    ) -> Result<()> {
+        let remaining_accounts = ctx.remaining_accounts;
+        require!(
+            !remaining_accounts.is_empty()
+                && remaining_accounts
+                    .chunks_exact(BATCH_UNLOCK_ACCOUNTS)
+                    .remainder()
+                    .is_empty(),
+            ErrorCode::InvalidBatch
+        );
+        require!(
+            remaining_accounts.len() / BATCH_UNLOCK_ACCOUNTS
+                <= ctx.accounts.global_state.max_batch_size as usize,
+            ErrorCode::BatchTooLarge
+        );
+
+        let now = current_timestamp(&ctx.accounts.global_state)?;
+
+        for accounts in remaining_accounts.chunks(BATCH_UNLOCK_ACCOUNTS) {
+            let mut lock = Account::<Lock>::try_from(&accounts[0])?;
+            require_keys_eq!(
+                lock.owner,
+                ctx.accounts.owner.key(),
+                ErrorCode::Unauthorized
+            );
+
+            let (expected_vault, vault_bump) =
+                Pubkey::find_program_address(&[VAULT_SEED, &lock.id.to_le_bytes()], &crate::ID);
+            require_keys_eq!(accounts[1].key(), expected_vault, ErrorCode::InvalidBatch);
+            require_canonical_vault_bump(&lock, vault_bump)?;
+            let vault = InterfaceAccount::<TokenAccount>::try_from(&accounts[1])?;
+
+            let mint = InterfaceAccount::<Mint>::try_from(&accounts[2])?;
+            require_keys_eq!(mint.key(), lock.mint, ErrorCode::InvalidMint);
+
+            let destination = InterfaceAccount::<TokenAccount>::try_from(&accounts[3])?;
+            require_keys_eq!(destination.mint, lock.mint, ErrorCode::InvalidMint);
+            require_keys_eq!(
+                destination.owner,
+                lock.beneficiary,
+                ErrorCode::InvalidDestination
+            );
+            // Prevent duplicate mutable accounts attack
+            require!(
+                vault.key() != destination.key(),
+                ErrorCode::DuplicateAccounts
+            );
+
+            let (expected_mint_stats, _) =
+                Pubkey::find_program_address(&[MINT_STATS_SEED, lock.mint.as_ref()], &crate::ID);
+            require_keys_eq!(
+                accounts[4].key(),
+                expected_mint_stats,
+                ErrorCode::InvalidBatch
+            );
+            let (expected_owner_mint_stats, _) = Pubkey::find_program_address(
+                &[
+                    OWNER_MINT_STATS_SEED,
+                    lock.owner.as_ref(),
+                    lock.mint.as_ref(),
+                ],
+                &crate::ID,
+            );
+            require_keys_eq!(
+                accounts[5].key(),
+                expected_owner_mint_stats,
+                ErrorCode::InvalidBatch
+            );
+
+            let amount = process_unlock(
+                &mut lock,
+                &vault,
+                &mint,
+                accounts[3].clone(),
+                &ctx.accounts.token_program,
+                &ctx.accounts.global_state,
+                &ctx.accounts.denylist,
+                now,
+            )?;
+            record_withdrawal(&accounts[4], amount, true)?;
+            record_owner_withdrawal(&accounts[5], amount)?;
+            record_lock_closed(&ctx.accounts.program_stats)?;
+
+            // Remaining accounts aren't persisted by Anchor
+            lock.exit(&crate::ID)?;
+        }
+
+        Ok(())
+    }
+
+    /// Withdraw part of a matured lock, leaving the rest locked
+    /// - Only the original owner can unlock
+    /// - The lock is marked unlocked once nothing is left
+    pub fn partial_unlock(ctx: Context<UnlockTokens>, amount: u64) -> Result<()> {
+        require_canonical_vault_bump(&ctx.accounts.lock, ctx.bumps.vault)?;
+        ctx.accounts.release_partial(amount)
+    }
+
+    /// Same as `partial_unlock`, with the amount given as a fraction of the lock in basis points
+    /// - `bps` must be between 1 and 10000 (100%)
+    pub fn partial_unlock_bps(ctx: Context<UnlockTokens>, bps: u16) -> Result<()> {
+        require!(
+            bps > 0 && bps as u64 <= BPS_DENOMINATOR,
+            ErrorCode::InvalidBps
+        );
+        require_canonical_vault_bump(&ctx.accounts.lock, ctx.bumps.vault)?;
+
+        let amount =
+            (ctx.accounts.lock.amount as u128 * bps as u128 / BPS_DENOMINATOR as u128) as u64;
+        ctx.accounts.release_partial(amount)
+    }
+
+    /// Withdraw the tokens vested so far on a lock created with `options.vesting_curve`
+    /// - Only the original owner can claim, to the same destination as `partial_unlock`
+    /// - Vests the lock's total (claimed plus remaining, so top-ups vest on the same schedule)
+    ///   along the curve from creation to the unlock timestamp, minus what was already claimed
+    pub fn claim_vested(ctx: Context<UnlockTokens>) -> Result<()> {
+        require!(
+            ctx.accounts.lock.vesting_curve != VESTING_NONE,
+            ErrorCode::NotVesting
+        );
+        require_canonical_vault_bump(&ctx.accounts.lock, ctx.bumps.vault)?;
+
+        let now = current_timestamp(&ctx.accounts.global_state)?;
+        let lock = &mut ctx.accounts.lock;
+        let total = lock
+            .amount
+            .checked_add(lock.vested_claimed)
+            .ok_or(ErrorCode::AmountOverflow)?;
+        let vested = vested_amount(
+            total,
+            lock.created_at,
+            lock.unlock_timestamp,
+            now,
+            lock.vesting_curve,
+        );
+        let claimable = vested.saturating_sub(lock.vested_claimed);
+        require!(claimable > 0, ErrorCode::TooEarly);
+
+        // Record the claim before moving tokens (checks-effects-interactions)
+        lock.vested_claimed = vested;
+        ctx.accounts.release(claimable)
+    }
+
+    /// Withdraw part of a reducible lock before maturity
+    /// - Only the original owner can reduce, only locks created with `options.reducible`
+    /// - Tokens go to the lock's beneficiary, like an unlock
+    pub fn reduce(ctx: Context<UnlockTokens>, amount: u64) -> Result<()> {
+        require!(ctx.accounts.lock.reducible, ErrorCode::NotReducible);
+        require_canonical_vault_bump(&ctx.accounts.lock, ctx.bumps.vault)?;
+        ctx.accounts.release(amount)
+    }
+
+    /// Cancel a cancellable lock at any time, before or after maturity
+    /// - Only the original owner can cancel, only locks created with `options.cancellable`
+    /// - The lock's beneficiary gets everything minus `GlobalState.cancel_fee_bps` of it, which
+    ///   goes to a token account of the fee recipient; the vault is left empty
+    pub fn cancel(ctx: Context<CancelLock>) -> Result<()> {
+        require!(
+            ctx.accounts.unlock.lock.cancellable,
+            ErrorCode::NotCancellable
+        );
+        require_canonical_vault_bump(&ctx.accounts.unlock.lock, ctx.bumps.unlock.vault)?;
+        require!(
+            !ctx.accounts.unlock.lock.is_unlocked,
+            ErrorCode::AlreadyUnlocked
+        );
+
+        let amount = ctx.accounts.unlock.lock.amount;
+        let fee = (amount as u128 * ctx.accounts.unlock.global_state.cancel_fee_bps as u128
+            / BPS_DENOMINATOR as u128) as u64;
+        let refund = amount - fee;
+        ctx.accounts.unlock.release(refund)?;
+
+        if fee > 0 {
+            let now = current_timestamp(&ctx.accounts.unlock.global_state)?;
+            let unlock = &mut ctx.accounts.unlock;
+            unlock.lock.amount = 0;
+            unlock.lock.is_unlocked = true;
+            unlock.lock.unlocked_at = now;
+
+            transfer_from_vault(
+                &unlock.token_program,
+                &unlock.vault,
+                &unlock.mint,
+                ctx.accounts.fee_recipient_token_account.to_account_info(),
+                &unlock.lock,
+                fee,
+            )?;
+            record_withdrawal(&unlock.mint_stats, fee, true)?;
+            record_owner_withdrawal(&unlock.owner_mint_stats, fee)?;
+            record_lock_closed(&unlock.program_stats)?;
+            if unlock.receipt_token_account.is_some() {
+                unlock.burn_receipt()?;
+            }
+        }
+
+        emit!(LockCancelled {
+            lock_id: ctx.accounts.unlock.lock.id,
+            owner: ctx.accounts.unlock.lock.owner,
+            refund,
+            fee,
+        });
+
+        Ok(())
+    }
+
+    /// Add more tokens to an existing lock
+    /// - Only the lock owner can add tokens
+    /// - Lock must not be unlocked, nor drained to a zero amount (`EmptyLock`)
+    /// - Mint must match the existing lock
+    pub fn top_up(ctx: Context<TopUpLock>, additional_amount: u64) -> Result<()> {
+        // Prevent duplicate mutable accounts attack
+        require!(
+            ctx.accounts.vault.key() != ctx.accounts.owner_token_account.key(),
+            ErrorCode::DuplicateAccounts
+        );
+
+        require!(additional_amount > 0, ErrorCode::AmountZero);
+        ctx.accounts
+            .global_state
+            .require_within_max_amount(additional_amount)?;
+        require!(!ctx.accounts.global_state.paused, ErrorCode::Paused);
+        ctx.accounts.global_state.require_enabled(DISABLE_TOP_UP)?;
+        require_deposits_open(
+            &ctx.accounts.mint_status,
+            &ctx.accounts.mint_stats,
+            additional_amount,
+        )?;
+
+        let lock = &mut ctx.accounts.lock;
+
+        require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+        require!(lock.amount > 0, ErrorCode::EmptyLock);
+        require_keys_eq!(
+            ctx.accounts.token_program.key(),
+            lock.token_program,
+            ErrorCode::WrongTokenProgram
+        );
+
+        let decimals = ctx.accounts.mint.decimals;
+
+        // Transfer additional tokens from owner to vault
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.owner_token_account.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            additional_amount,
+            decimals,
+        )?;
+
+        // Update lock amount
+        lock.amount = lock
+            .amount
+            .checked_add(additional_amount)
+            .ok_or(ErrorCode::AmountOverflow)?;
+        record_deposit(&ctx.accounts.mint_stats, additional_amount, false)?;
+        record_owner_deposit(&ctx.accounts.owner_mint_stats, additional_amount)?;
+
+        emit!(ToppedUp {
+            lock_id: lock.id,
+            amount: additional_amount,
+            new_amount: lock.amount,
+        });
+        msg!(
+            "Added {} tokens to lock #{} (new total: {})",
+            additional_amount,
+            lock.id,
+            lock.amount
+        );
+
+        Ok(())
+    }
+
+    /// Extend the unlock timestamp of an existing lock
+    /// - Only the lock owner can extend
+    /// - Lock must not be unlocked, nor drained to a zero amount (`EmptyLock`)
+    /// - New timestamp must be greater than current timestamp (can only extend, not shorten):
+    ///   `TimestampUnchanged` when equal, `CannotShortenTimestamp` when earlier
+    pub fn extend(ctx: Context<ExtendLock>, new_unlock_timestamp: i64) -> Result<()> {
+        require!(
+            ctx.accounts.global_state.allow_extend,
+            ErrorCode::ExtendDisabled
+        );
+        ctx.accounts.global_state.require_enabled(DISABLE_EXTEND)?;
+
+        require_aligned(&ctx.accounts.global_state, new_unlock_timestamp)?;
+
+        let lock = &mut ctx.accounts.lock;
+
+        require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+        require!(lock.amount > 0, ErrorCode::EmptyLock);
+        require!(lock.unlock_slot == 0, ErrorCode::AmbiguousUnlockCondition);
+        require!(
+            new_unlock_timestamp != lock.unlock_timestamp,
+            ErrorCode::TimestampUnchanged
+        );
+        require!(
+            new_unlock_timestamp > lock.unlock_timestamp,
+            ErrorCode::CannotShortenTimestamp
         );
 
         let old_timestamp = lock.unlock_timestamp;
         lock.unlock_timestamp = new_unlock_timestamp;
 
+        // Move the lock to the expiry bucket of its new maturity day
+        if day_bucket(old_timestamp) != day_bucket(new_unlock_timestamp) {
+            remove_from_expiry_index(&ctx.accounts.old_expiry_index, old_timestamp, lock.id)?;
+            add_to_expiry_index(
+                &ctx.accounts.new_expiry_index,
+                &ctx.accounts.owner,
+                &ctx.accounts.system_program,
+                new_unlock_timestamp,
+                lock.id,
+            )?;
+        }
+
+        emit!(Extended {
+            lock_id: lock.id,
+            old_unlock_timestamp: old_timestamp,
+            new_unlock_timestamp,
+        });
         msg!(
             "Extended lock #{} unlock timestamp from {} to {}",
             lock.id,
@@ -218,71 +1619,6198 @@ pub mod lockfun {
 
         Ok(())
     }
-}
 
-// ============================================================================
-// Accounts
-// ============================================================================
+    /// Extend several locks of the signer to the same unlock timestamp in one instruction
+    /// - Remaining accounts, per lock: `[lock, expiry index bucket of its current unlock timestamp]`
+    /// - Same rules as `extend` for every lock, except that locks already unlocking at or after
+    ///   `new_unlock_timestamp` are skipped instead of failing the batch
+    /// - Returns the number of locks extended
+    pub fn batch_extend<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BatchExtend<'info>>,
+        new_unlock_timestamp: i64,
+    ) -> Result<u32> {
+        require!(
+            ctx.accounts.global_state.allow_extend,
+            ErrorCode::ExtendDisabled
+        );
+        ctx.accounts.global_state.require_enabled(DISABLE_EXTEND)?;
+        require_aligned(&ctx.accounts.global_state, new_unlock_timestamp)?;
 
-#[account]
-#[derive(InitSpace)]
-pub struct GlobalState {
-    /// Authority (admin)
-    pub authority: Pubkey,
-    /// Counter for unique lock IDs
-    /// This represents the total number of locks created.
-    /// When a new lock is created, this counter is incremented and
-    /// the new lock's ID is set to the current counter value.
-    /// To fetch the latest locks, query locks with IDs from (lock_counter - N) to (lock_counter - 1).
-    pub lock_counter: u64,
-}
+        let remaining_accounts = ctx.remaining_accounts;
+        require!(
+            !remaining_accounts.is_empty()
+                && remaining_accounts
+                    .chunks_exact(BATCH_EXTEND_ACCOUNTS)
+                    .remainder()
+                    .is_empty(),
+            ErrorCode::InvalidBatch
+        );
 
-#[account]
-#[derive(InitSpace)]
-pub struct Lock {
-    /// Unique lock ID (for PDA derivation)
-    /// This is a sequential number representing which lock this is (1st, 2nd, 3rd, etc.)
-    /// The ID is assigned from GlobalState.lock_counter when the lock is created.
-    /// Offset: 8 (discriminator)
-    pub id: u64,
-    /// Owner who locked the tokens
-    /// Offset: 8 + 8 = 16
-    pub owner: Pubkey,
-    /// Token mint address
-    /// Offset: 8 + 8 + 32 = 48
-    pub mint: Pubkey,
-    /// Amount of tokens locked
-    /// Offset: 8 + 8 + 32 + 32 = 80
-    pub amount: u64,
-    /// Unix timestamp when tokens can be unlocked
-    /// Offset: 8 + 8 + 32 + 32 + 8 = 88
-    pub unlock_timestamp: i64,
-    /// Unix timestamp when lock was created (for sorting/pagination)
-    /// Offset: 8 + 8 + 32 + 32 + 8 + 8 = 96
-    pub created_at: i64,
-    /// Bump seed for the vault PDA
-    /// Offset: 8 + 8 + 32 + 32 + 8 + 8 + 8 = 104
-    pub vault_bump: u8,
-    /// Whether tokens have been unlocked
-    /// Offset: 8 + 8 + 32 + 32 + 8 + 8 + 8 + 1 = 105
-    pub is_unlocked: bool,
-}
+        let mut extended: u32 = 0;
+        for accounts in remaining_accounts.chunks(BATCH_EXTEND_ACCOUNTS) {
+            let mut lock = Account::<Lock>::try_from(&accounts[0])?;
+            require_keys_eq!(
+                lock.owner,
+                ctx.accounts.owner.key(),
+                ErrorCode::Unauthorized
+            );
+            require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+            require!(lock.amount > 0, ErrorCode::EmptyLock);
+            require!(lock.unlock_slot == 0, ErrorCode::AmbiguousUnlockCondition);
+
+            if lock.unlock_timestamp >= new_unlock_timestamp {
+                msg!(
+                    "Lock #{} already unlocks at {}, skipped",
+                    lock.id,
+                    lock.unlock_timestamp
+                );
+                continue;
+            }
+
+            let old_timestamp = lock.unlock_timestamp;
+            lock.unlock_timestamp = new_unlock_timestamp;
+
+            // Move the lock to the expiry bucket of its new maturity day
+            if day_bucket(old_timestamp) != day_bucket(new_unlock_timestamp) {
+                remove_from_expiry_index(&accounts[1], old_timestamp, lock.id)?;
+                add_to_expiry_index(
+                    &ctx.accounts.new_expiry_index,
+                    &ctx.accounts.owner,
+                    &ctx.accounts.system_program,
+                    new_unlock_timestamp,
+                    lock.id,
+                )?;
+            }
+
+            // Remaining accounts aren't persisted by Anchor
+            lock.exit(&crate::ID)?;
+            emit!(Extended {
+                lock_id: lock.id,
+                old_unlock_timestamp: old_timestamp,
+                new_unlock_timestamp,
+            });
+            extended += 1;
+        }
+
+        msg!(
+            "Extended {} locks to unlock at {}",
+            extended,
+            new_unlock_timestamp
+        );
+
+        Ok(extended)
+    }
+
+    /// Idempotent variant of `extend`, safe to retry
+    /// - Succeeds without changing anything if the lock already unlocks at `unlock_timestamp`
+    /// - Otherwise same rules as `extend`
+    pub fn set_unlock_timestamp(ctx: Context<ExtendLock>, unlock_timestamp: i64) -> Result<()> {
+        if ctx.accounts.lock.unlock_timestamp == unlock_timestamp {
+            msg!(
+                "Lock #{} already unlocks at {}, nothing to do",
+                ctx.accounts.lock.id,
+                unlock_timestamp
+            );
+            return Ok(());
+        }
+
+        extend(ctx, unlock_timestamp)
+    }
+
+    /// Push the unlock timestamp back by `additional_seconds`, computed on-chain from the stored
+    /// timestamp so clients don't depend on their own clock
+    /// - `additional_seconds` must be positive, otherwise same rules as `extend`
+    /// - Pass the expiry index bucket of `unlock_timestamp + additional_seconds`
+    pub fn extend_by(ctx: Context<ExtendLock>, additional_seconds: i64) -> Result<()> {
+        require!(additional_seconds > 0, ErrorCode::InvalidExtension);
+        let new_unlock_timestamp = ctx
+            .accounts
+            .lock
+            .unlock_timestamp
+            .checked_add(additional_seconds)
+            .ok_or(ErrorCode::AmountOverflow)?;
+
+        extend(ctx, new_unlock_timestamp)
+    }
+
+    /// Add tokens and extend the unlock timestamp in one atomic step
+    /// - Same rules as `top_up` and `extend`; if either fails, nothing changes
+    /// - Always emits `ToppedUp` then `Extended`, in that order, so indexers can rely on it
+    pub fn extend_with_top_up(
+        ctx: Context<ExtendWithTopUp>,
+        new_unlock_timestamp: i64,
+        additional_amount: u64,
+    ) -> Result<()> {
+        // Prevent duplicate mutable accounts attack
+        require!(
+            ctx.accounts.vault.key() != ctx.accounts.owner_token_account.key(),
+            ErrorCode::DuplicateAccounts
+        );
+
+        require!(additional_amount > 0, ErrorCode::AmountZero);
+        ctx.accounts
+            .global_state
+            .require_within_max_amount(additional_amount)?;
+        require!(!ctx.accounts.global_state.paused, ErrorCode::Paused);
+        ctx.accounts
+            .global_state
+            .require_enabled(DISABLE_TOP_UP | DISABLE_EXTEND)?;
+        require_deposits_open(
+            &ctx.accounts.mint_status,
+            &ctx.accounts.mint_stats,
+            additional_amount,
+        )?;
+        require!(
+            ctx.accounts.global_state.allow_extend,
+            ErrorCode::ExtendDisabled
+        );
+        require_aligned(&ctx.accounts.global_state, new_unlock_timestamp)?;
+
+        let lock = &mut ctx.accounts.lock;
+
+        require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+        require!(lock.amount > 0, ErrorCode::EmptyLock);
+        require!(lock.unlock_slot == 0, ErrorCode::AmbiguousUnlockCondition);
+        require!(
+            new_unlock_timestamp != lock.unlock_timestamp,
+            ErrorCode::TimestampUnchanged
+        );
+        require!(
+            new_unlock_timestamp > lock.unlock_timestamp,
+            ErrorCode::CannotShortenTimestamp
+        );
+        require_keys_eq!(
+            ctx.accounts.token_program.key(),
+            lock.token_program,
+            ErrorCode::WrongTokenProgram
+        );
+
+        // Transfer additional tokens from owner to vault
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.owner_token_account.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            additional_amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        lock.amount = lock
+            .amount
+            .checked_add(additional_amount)
+            .ok_or(ErrorCode::AmountOverflow)?;
+        record_deposit(&ctx.accounts.mint_stats, additional_amount, false)?;
+        record_owner_deposit(&ctx.accounts.owner_mint_stats, additional_amount)?;
+        emit!(ToppedUp {
+            lock_id: lock.id,
+            amount: additional_amount,
+            new_amount: lock.amount,
+        });
+
+        let old_timestamp = lock.unlock_timestamp;
+        lock.unlock_timestamp = new_unlock_timestamp;
+
+        // Move the lock to the expiry bucket of its new maturity day
+        if day_bucket(old_timestamp) != day_bucket(new_unlock_timestamp) {
+            remove_from_expiry_index(&ctx.accounts.old_expiry_index, old_timestamp, lock.id)?;
+            add_to_expiry_index(
+                &ctx.accounts.new_expiry_index,
+                &ctx.accounts.owner,
+                &ctx.accounts.system_program,
+                new_unlock_timestamp,
+                lock.id,
+            )?;
+        }
+
+        emit!(Extended {
+            lock_id: lock.id,
+            old_unlock_timestamp: old_timestamp,
+            new_unlock_timestamp,
+        });
+        msg!(
+            "Added {} tokens to lock #{} (new total: {}) and extended it from {} to {}",
+            additional_amount,
+            lock.id,
+            lock.amount,
+            old_timestamp,
+            new_unlock_timestamp
+        );
+
+        Ok(())
+    }
+
+    /// Release every milestone whose timestamp has passed and that hasn't been claimed yet
+    /// - Only the lock owner can claim
+    /// - The lock is marked unlocked once the last milestone is claimed
+    pub fn claim_milestone(ctx: Context<ClaimMilestone>) -> Result<()> {
+        // Prevent duplicate mutable accounts attack
+        require!(
+            ctx.accounts.vault.key() != ctx.accounts.owner_token_account.key(),
+            ErrorCode::DuplicateAccounts
+        );
+
+        let lock = &ctx.accounts.lock;
+        require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+        ctx.accounts.global_state.require_enabled(DISABLE_UNLOCK)?;
+        require_not_denied(
+            &ctx.accounts.global_state,
+            &ctx.accounts.denylist,
+            &lock.owner,
+        )?;
+
+        let current_ts = current_timestamp(&ctx.accounts.global_state)?;
+        let schedule = &mut ctx.accounts.schedule;
+
+        // Milestones are sorted, so the claimable ones are a contiguous run after the claimed prefix
+        let mut release: u64 = 0;
+        let mut claimed_count = schedule.claimed_count as usize;
+        while claimed_count < schedule.milestones.len()
+            && schedule.milestones[claimed_count].timestamp <= current_ts
+        {
+            release = release
+                .checked_add(schedule.milestones[claimed_count].amount)
+                .ok_or(ErrorCode::AmountOverflow)?;
+            claimed_count += 1;
+        }
+        require!(release > 0, ErrorCode::TooEarly);
+
+        // Top-ups may have grown the lock, but milestones never release more than it holds
+        let release = release.min(lock.amount);
+
+        // Record the claim before moving tokens (checks-effects-interactions)
+        schedule.claimed_count = claimed_count as u8;
+
+        let lock = &mut ctx.accounts.lock;
+        lock.amount = lock
+            .amount
+            .checked_sub(release)
+            .ok_or(ErrorCode::AmountOverflow)?;
+        if claimed_count == schedule.milestones.len() && lock.amount == 0 {
+            lock.is_unlocked = true;
+            lock.unlocked_at = current_ts;
+        }
+        record_withdrawal(&ctx.accounts.mint_stats, release, lock.is_unlocked)?;
+        record_owner_withdrawal(&ctx.accounts.owner_mint_stats, release)?;
+        if lock.is_unlocked {
+            record_lock_closed(&ctx.accounts.program_stats)?;
+        }
+
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.owner_token_account.to_account_info(),
+            lock,
+            release,
+        )?;
+
+        msg!(
+            "Released {} tokens from lock #{} ({}/{} milestones claimed)",
+            release,
+            lock.id,
+            claimed_count,
+            schedule.milestones.len()
+        );
+
+        Ok(())
+    }
+
+    /// Lower a lock's recorded amount to what its vault actually holds
+    /// - Authority only, repair tool for locks whose vault received less than recorded
+    ///   (e.g. Token-2022 transfer fees taken on the way in), which makes unlock fail
+    /// - Only ever lowers the amount
+    pub fn reconcile_amount(ctx: Context<ReconcileAmount>) -> Result<()> {
+        let lock = &mut ctx.accounts.lock;
+        require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+
+        let vault_amount = ctx.accounts.vault.amount;
+        require!(vault_amount < lock.amount, ErrorCode::NothingToReconcile);
+
+        let old_amount = lock.amount;
+        lock.amount = vault_amount;
+        record_withdrawal(&ctx.accounts.mint_stats, old_amount - vault_amount, false)?;
+        record_owner_withdrawal(&ctx.accounts.owner_mint_stats, old_amount - vault_amount)?;
+
+        emit!(Reconciled {
+            lock_id: lock.id,
+            old_amount,
+            new_amount: vault_amount,
+        });
+
+        msg!(
+            "Reconciled lock #{} amount from {} to {}",
+            lock.id,
+            old_amount,
+            vault_amount
+        );
+
+        Ok(())
+    }
+
+    /// Raise a lock's recorded amount to what its vault actually holds, so tokens sent straight
+    /// to the vault (e.g. compounded rewards) unlock with the principal
+    /// - Anyone may call: it only ever raises the amount (`NothingToRecognize` otherwise)
+    /// - The tokens are already in the vault, so mint caps and frozen deposits don't apply
+    pub fn recognize_deposit(ctx: Context<RecognizeDeposit>) -> Result<()> {
+        let lock = &mut ctx.accounts.lock;
+        require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+
+        let vault_amount = ctx.accounts.vault.amount;
+        require!(vault_amount > lock.amount, ErrorCode::NothingToRecognize);
+
+        let old_amount = lock.amount;
+        lock.amount = vault_amount;
+        record_deposit(&ctx.accounts.mint_stats, vault_amount - old_amount, false)?;
+        record_owner_deposit(&ctx.accounts.owner_mint_stats, vault_amount - old_amount)?;
+
+        emit!(DepositRecognized {
+            lock_id: lock.id,
+            old_amount,
+            new_amount: vault_amount,
+        });
+
+        msg!(
+            "Recognized lock #{} amount from {} to {}",
+            lock.id,
+            old_amount,
+            vault_amount
+        );
+
+        Ok(())
+    }
+
+    /// Rent in lamports a new plain lock of `mint` costs on top of the fee
+    /// - Read-only, meant to be simulated (`.view()`) so UIs can show the all-in cost
+    /// - Always the Lock account and its vault
+    /// - Plus the rent of the accounts `lock` creates on first use, for those passed that don't
+    ///   exist yet: the expiry index bucket of the unlock day, the creation index bucket of
+    ///   today and the owner's `OwnerMintStats`. Without them the estimate is a lower bound.
+    pub fn estimate_rent(ctx: Context<EstimateRent>) -> Result<u64> {
+        let rent = Rent::get()?;
+        let accounts = &ctx.accounts;
+        let lock_rent = rent.minimum_balance(8 + Lock::INIT_SPACE);
+        let vault_rent = rent.minimum_balance(vault_space(&accounts.mint.to_account_info())?);
+        let first_use_rent = [
+            (&accounts.expiry_index, 8 + ExpiryIndex::INIT_SPACE),
+            (&accounts.creation_index, 8 + CreationIndex::INIT_SPACE),
+            (&accounts.owner_mint_stats, 8 + OwnerMintStats::INIT_SPACE),
+        ]
+        .into_iter()
+        .filter_map(|(account, space)| {
+            let account = account.as_ref()?;
+            account.data_is_empty().then(|| {
+                rent.minimum_balance(space)
+                    .saturating_sub(account.lamports())
+            })
+        })
+        .try_fold(0u64, |total, rent| total.checked_add(rent))
+        .ok_or(ErrorCode::AmountOverflow)?;
+
+        Ok(lock_rent
+            .checked_add(vault_rent)
+            .and_then(|total| total.checked_add(first_use_rent))
+            .ok_or(ErrorCode::AmountOverflow)?)
+    }
+
+    /// Current program configuration, so clients read the fee and recipient instead of hardcoding them
+    /// - Read-only, meant to be simulated (`.view()`)
+    /// - The fee is the default one: a mint may have its own `MintFeeOverride`
+    pub fn get_config(ctx: Context<GetConfig>) -> Result<ConfigView> {
+        let global_state = &ctx.accounts.global_state;
+        Ok(ConfigView {
+            fee_amount: global_state.fee_amount,
+            fee_recipient: global_state.fee_recipient,
+            authority: global_state.authority,
+            paused: global_state.paused,
+            schema_version: SCHEMA_VERSION,
+        })
+    }
+
+    /// Full state of a lock, including the `reference_mint` dashboards group and value it by
+    /// - Read-only, meant to be simulated (`.view()`)
+    pub fn get_lock(ctx: Context<GetLock>) -> Result<Lock> {
+        Ok(ctx.accounts.lock.clone().into_inner())
+    }
+
+    /// One page of active locks, for clients paging through the program without fetching and
+    /// filtering every lock account themselves
+    /// - Read-only, meant to be simulated (`.view()`)
+    /// - Remaining accounts: the lock PDAs of ids `start_id..start_id + limit`, in order, one
+    ///   per id (`InvalidBatch` otherwise); `limit` is between 1 and `MAX_LIST_LIMIT`
+    /// - Returns a `LockSummary` of each of them that is still locked; closed or never created
+    ///   ids and unlocked locks are left out. The next page starts at `start_id + limit`.
+    pub fn list_active_locks<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ListActiveLocks>,
+        start_id: u64,
+        limit: u8,
+    ) -> Result<Vec<LockSummary>> {
+        require!(
+            limit > 0 && limit <= MAX_LIST_LIMIT,
+            ErrorCode::InvalidListLimit
+        );
+        require!(
+            ctx.remaining_accounts.len() == limit as usize,
+            ErrorCode::InvalidBatch
+        );
+
+        let mut summaries = Vec::with_capacity(limit as usize);
+        for (lock_id, account) in (start_id..).zip(ctx.remaining_accounts.iter()) {
+            let (expected_lock, _) =
+                Pubkey::find_program_address(&[LOCK_SEED, &lock_id.to_le_bytes()], &ID);
+            require_keys_eq!(account.key(), expected_lock, ErrorCode::InvalidBatch);
+            if account.data_is_empty() {
+                continue;
+            }
+
+            let lock = Account::<Lock>::try_from(account)?;
+            if !lock.is_unlocked {
+                summaries.push(LockSummary {
+                    id: lock.id,
+                    owner: lock.owner,
+                    mint: lock.mint,
+                    amount: lock.amount,
+                    unlock_timestamp: lock.unlock_timestamp,
+                });
+            }
+        }
+
+        Ok(summaries)
+    }
+
+    /// Remaining amount of the lock in whole tokens, as a decimal string (e.g. `"1.5"`)
+    /// - Read-only, meant to be simulated (`.view()`)
+    /// - Formatted from the mint's decimals without floating point; trailing zeros are trimmed
+    pub fn ui_amount(ctx: Context<UiAmount>) -> Result<String> {
+        Ok(format_ui_amount(
+            ctx.accounts.lock.amount,
+            ctx.accounts.mint.decimals,
+        ))
+    }
+
+    /// Whether `candidate` may sign an instruction releasing the lock, timing rules aside
+    /// - Read-only, meant to be simulated (`.view()`) by integrators before building a CPI
+    /// - See `Lock::is_authorized_unlocker` for the signers of each path; pass the claim's
+    ///   `preimage` to check `unlock_with_secret`
+    /// - A beneficiary receives the tokens but can't unlock
+    /// - False while the owner is on an enabled denylist, which blocks every path
+    pub fn is_authorized_unlocker(
+        ctx: Context<IsAuthorizedUnlocker>,
+        candidate: Pubkey,
+        preimage: Option<Vec<u8>>,
+    ) -> Result<bool> {
+        let heirs = if ctx.accounts.heirs.data_is_empty() {
+            None
+        } else {
+            let data = ctx.accounts.heirs.try_borrow_data()?;
+            Some(Heirs::try_deserialize(&mut &data[..])?)
+        };
+        if !ctx.accounts.lock.is_authorized_unlocker(
+            &candidate,
+            &ctx.accounts.global_state.authority,
+            heirs.as_ref(),
+            preimage.as_deref(),
+        ) {
+            return Ok(false);
+        }
+        Ok(!is_denied(
+            &ctx.accounts.global_state,
+            &ctx.accounts.denylist,
+            &ctx.accounts.lock.owner,
+        )?)
+    }
+
+    /// Health check of a lock for monitoring, failing with the first violated invariant
+    /// - Read-only, meant to be simulated (`.view()`) across the fleet
+    /// - The lock sits at its PDA `[LOCK_SEED, lock.id]` (`LockPdaMismatch`)
+    /// - The vault is the lock's vault for its scheme (`VaultMismatch`), and its authority is
+    ///   the vault PDA `[VAULT_SEED, lock.id]` (`VaultAuthorityMismatch`)
+    /// - While the lock is active, the vault holds exactly `lock.amount` (`VaultBalanceMismatch`);
+    ///   skipped for transfer-fee mints, whose vaults legitimately hold less
+    pub fn check_invariants(ctx: Context<CheckInvariants>) -> Result<()> {
+        let lock = &ctx.accounts.lock;
+        let vault = &ctx.accounts.vault;
+        let lock_id_bytes = lock.id.to_le_bytes();
+
+        let (expected_lock, _) = Pubkey::find_program_address(&[LOCK_SEED, &lock_id_bytes], &ID);
+        require_keys_eq!(lock.key(), expected_lock, ErrorCode::LockPdaMismatch);
+        require_keys_eq!(ctx.accounts.mint.key(), lock.mint, ErrorCode::InvalidMint);
+
+        let (expected_vault, vault_authority) = lock.vault_address();
+        require_keys_eq!(vault.key(), expected_vault, ErrorCode::VaultMismatch);
+        require_keys_eq!(
+            vault.owner,
+            vault_authority,
+            ErrorCode::VaultAuthorityMismatch
+        );
+
+        if !lock.is_unlocked && !has_transfer_fee(&ctx.accounts.mint.to_account_info())? {
+            require!(vault.amount == lock.amount, ErrorCode::VaultBalanceMismatch);
+        }
+
+        Ok(())
+    }
+
+    /// Status code of the lock, so clients don't re-derive it from raw fields
+    /// - Read-only, meant to be simulated (`.view()`)
+    /// - `LOCK_STATUS_UNLOCKED` once fully released, else `LOCK_STATUS_FROZEN` while the owner
+    ///   is on an enabled denylist, else `LOCK_STATUS_MATURED` from `unlock_timestamp` on,
+    ///   else `LOCK_STATUS_ACTIVE`
+    pub fn lock_status(ctx: Context<LockStatus>) -> Result<u8> {
+        let lock = &ctx.accounts.lock;
+        if lock.is_unlocked {
+            return Ok(LOCK_STATUS_UNLOCKED);
+        }
+        if is_denied(
+            &ctx.accounts.global_state,
+            &ctx.accounts.denylist,
+            &lock.owner,
+        )? {
+            return Ok(LOCK_STATUS_FROZEN);
+        }
+        let global_state = &ctx.accounts.global_state;
+        if global_state.is_lock_matured(lock, current_timestamp(global_state)?)? {
+            return Ok(LOCK_STATUS_MATURED);
+        }
+        Ok(LOCK_STATUS_ACTIVE)
+    }
+
+    /// Seconds since the lock was created, by chain time, for "locked for X days" displays
+    /// - Read-only, meant to be simulated (`.view()`)
+    /// - Never negative, even if the clock reads earlier than `created_at`
+    pub fn elapsed_since_creation(ctx: Context<ElapsedSinceCreation>) -> Result<i64> {
+        let now = current_timestamp(&ctx.accounts.global_state)?;
+        Ok(now.saturating_sub(ctx.accounts.lock.created_at).max(0))
+    }
+
+    /// Tokens of a mint an owner currently has locked, across all their locks
+    /// - Read-only, meant to be simulated (`.view()`)
+    /// - 0 when the owner never locked the mint; locks created before their stats existed
+    ///   aren't counted
+    pub fn owner_mint_total(ctx: Context<OwnerMintTotal>) -> Result<u64> {
+        let stats = &ctx.accounts.owner_mint_stats;
+        if stats.data_is_empty() {
+            return Ok(0);
+        }
+
+        let data = stats.try_borrow_data()?;
+        Ok(OwnerMintStats::try_deserialize(&mut &data[..])?.total_locked)
+    }
+
+    /// Locks of a mint still to be withdrawn, for liquidity planning
+    /// - Read-only, meant to be simulated (`.view()`)
+    /// - Reads `MintStats.active_locks`, kept by every lock creation and full withdrawal: it
+    ///   counts locks that aren't unlocked rather than not matured, since maturity isn't an
+    ///   on-chain event, so a matured lock counts until it's unlocked
+    /// - `MintStatsNotFound` until the mint's stats are backfilled (`backfill_mint_stats`)
+    pub fn future_maturities(ctx: Context<FutureMaturities>) -> Result<u64> {
+        let stats = &ctx.accounts.mint_stats;
+        require!(!stats.data_is_empty(), ErrorCode::MintStatsNotFound);
+
+        let data = stats.try_borrow_data()?;
+        Ok(MintStats::try_deserialize(&mut &data[..])?.active_locks)
+    }
+
+    /// Lock fee in lamports that `lock` would charge `owner` for `amount` tokens of `mint`, so
+    /// clients don't recompute the fee rules themselves
+    /// - Read-only, meant to be simulated (`.view()`)
+    /// - The mint's `MintFeeOverride` if any, else `GlobalState.fee_amount`; the fee doesn't
+    ///   depend on `amount` today, which is still checked like `lock` does (`AmountZero`,
+    ///   `AmountTooLarge`)
+    /// - With a `fee_voucher`, checked exactly like `lock` does (the simulated transaction
+    ///   carries its Ed25519 instruction, `owner` and the instructions sysvar): 0 when valid,
+    ///   the voucher's error otherwise
+    pub fn quote_fee(
+        ctx: Context<QuoteFee>,
+        amount: u64,
+        fee_voucher: Option<FeeVoucher>,
+    ) -> Result<u64> {
+        let global_state = &ctx.accounts.global_state;
+        require!(amount > 0, ErrorCode::AmountZero);
+        global_state.require_within_max_amount(amount)?;
+
+        effective_lock_fee(
+            global_state,
+            &ctx.accounts.fee_override,
+            ctx.accounts.owner.as_ref().map(|owner| owner.key()),
+            fee_voucher,
+            ctx.accounts
+                .instructions_sysvar
+                .as_ref()
+                .map(|sysvar| sysvar.as_ref()),
+            current_timestamp(global_state)?,
+        )
+    }
+
+    /// Earliest time at which more of the lock can be withdrawn, for reminder scheduling
+    /// - Read-only, meant to be simulated (`.view()`)
+    /// - `unlock_timestamp` for plain locks; for milestone locks, the first unclaimed milestone;
+    ///   for vesting locks, now if something is claimable, else the first second at which
+    ///   `claim_vested` releases another token
+    /// - `NO_NEXT_UNLOCK` once fully released, and for slot locks, which have no unlock time
+    ///   (see `Lock.unlock_slot`)
+    pub fn next_unlock_time(ctx: Context<NextUnlockTime>) -> Result<i64> {
+        let lock = &ctx.accounts.lock;
+        if lock.is_unlocked {
+            return Ok(NO_NEXT_UNLOCK);
+        }
+        if !ctx.accounts.schedule.data_is_empty() {
+            let data = ctx.accounts.schedule.try_borrow_data()?;
+            let schedule = MilestoneSchedule::try_deserialize(&mut &data[..])?;
+            if let Some(milestone) = schedule.milestones.get(schedule.claimed_count as usize) {
+                return Ok(milestone.timestamp);
+            }
+        }
+        if lock.vesting_curve == VESTING_NONE {
+            return Ok(lock.unlock_timestamp);
+        }
+
+        let now = current_timestamp(&ctx.accounts.global_state)?;
+        let total = lock
+            .amount
+            .checked_add(lock.vested_claimed)
+            .ok_or(ErrorCode::AmountOverflow)?;
+        let vested_at = |ts: i64| {
+            vested_amount(
+                total,
+                lock.created_at,
+                lock.unlock_timestamp,
+                ts,
+                lock.vesting_curve,
+            )
+        };
+        if now >= lock.unlock_timestamp {
+            return Ok(lock.unlock_timestamp);
+        }
+        if vested_at(now) > lock.vested_claimed {
+            return Ok(now);
+        }
+
+        // Vested amounts never decrease over time: binary search the first second releasing more
+        let (mut low, mut high) = (now, lock.unlock_timestamp);
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            if vested_at(mid) > lock.vested_claimed {
+                high = mid;
+            } else {
+                low = mid;
+            }
+        }
+        Ok(high)
+    }
+
+    /// Start changing the account that receives the lock's tokens
+    /// - Only the lock owner can request
+    /// - Takes effect on `confirm_beneficiary_change`, no earlier than the global delay from now,
+    ///   so a compromised owner key can't redirect funds before the owner notices
+    /// - Replaces any pending request
+    pub fn request_beneficiary_change(
+        ctx: Context<ChangeBeneficiary>,
+        new_beneficiary: Pubkey,
+    ) -> Result<()> {
+        require!(
+            new_beneficiary != Pubkey::default(),
+            ErrorCode::InvalidBeneficiary
+        );
+
+        let current_ts = current_timestamp(&ctx.accounts.global_state)?;
+        let available_at = current_ts
+            .checked_add(ctx.accounts.global_state.beneficiary_change_delay)
+            .ok_or(ErrorCode::AmountOverflow)?;
+
+        let lock = &mut ctx.accounts.lock;
+        require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+        lock.pending_beneficiary = new_beneficiary;
+        lock.beneficiary_change_at = available_at;
+
+        emit!(BeneficiaryChangeRequested {
+            lock_id: lock.id,
+            new_beneficiary,
+            available_at,
+        });
+
+        msg!(
+            "Lock #{} beneficiary change to {} requested, confirmable from {}",
+            lock.id,
+            new_beneficiary,
+            available_at
+        );
+
+        Ok(())
+    }
+
+    /// Apply a pending beneficiary change once its delay has passed
+    /// - Only the lock owner can confirm
+    pub fn confirm_beneficiary_change(ctx: Context<ChangeBeneficiary>) -> Result<()> {
+        let current_ts = current_timestamp(&ctx.accounts.global_state)?;
+
+        let lock = &mut ctx.accounts.lock;
+        require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+        require!(
+            lock.pending_beneficiary != Pubkey::default(),
+            ErrorCode::NoPendingBeneficiaryChange
+        );
+        require!(
+            current_ts >= lock.beneficiary_change_at,
+            ErrorCode::BeneficiaryChangeTimelocked
+        );
+
+        let old_beneficiary = lock.beneficiary;
+        lock.beneficiary = lock.pending_beneficiary;
+        lock.pending_beneficiary = Pubkey::default();
+        lock.beneficiary_change_at = 0;
+
+        emit!(BeneficiaryChanged {
+            lock_id: lock.id,
+            old_beneficiary,
+            new_beneficiary: lock.beneficiary,
+        });
+
+        msg!(
+            "Lock #{} beneficiary changed from {} to {}",
+            lock.id,
+            old_beneficiary,
+            lock.beneficiary
+        );
+
+        Ok(())
+    }
+
+    /// Drop a pending beneficiary change
+    /// - Only the lock owner can cancel
+    pub fn cancel_beneficiary_change(ctx: Context<ChangeBeneficiary>) -> Result<()> {
+        let lock = &mut ctx.accounts.lock;
+        require!(
+            lock.pending_beneficiary != Pubkey::default(),
+            ErrorCode::NoPendingBeneficiaryChange
+        );
+
+        lock.pending_beneficiary = Pubkey::default();
+        lock.beneficiary_change_at = 0;
+
+        emit!(BeneficiaryChangeCancelled { lock_id: lock.id });
+
+        msg!("Lock #{} beneficiary change cancelled", lock.id);
+
+        Ok(())
+    }
+
+    /// Set the lock fee charged for mints without an override
+    /// - Authority only, emits `ConfigChanged`
+    pub fn set_fee(ctx: Context<UpdateGlobalState>, fee_amount: u64) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed("fee_amount", global_state.fee_amount, fee_amount);
+        global_state.fee_amount = fee_amount;
+        Ok(())
+    }
+
+    /// Set the account receiving lock fees
+    /// - Authority only, emits `ConfigChanged`
+    pub fn set_fee_recipient(ctx: Context<UpdateGlobalState>, fee_recipient: Pubkey) -> Result<()> {
+        require!(
+            fee_recipient != Pubkey::default(),
+            ErrorCode::InvalidFeeRecipient
+        );
+
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed("fee_recipient", global_state.fee_recipient, fee_recipient);
+        global_state.fee_recipient = fee_recipient;
+        Ok(())
+    }
+
+    /// Hand the program authority over to `new_authority`
+    /// - Authority only, emits `ConfigChanged`
+    /// - Takes effect immediately, the current authority loses every admin right
+    pub fn transfer_authority(
+        ctx: Context<UpdateGlobalState>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        require!(
+            new_authority != Pubkey::default(),
+            ErrorCode::InvalidAuthority
+        );
+
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed("authority", global_state.authority, new_authority);
+        global_state.authority = new_authority;
+        Ok(())
+    }
+
+    /// Turn individual instruction families off (or back on), finer grained than `pause`
+    /// - Authority only, emits `ConfigChanged`
+    /// - `disabled_instructions` replaces the current flags and only takes `DISABLE_*` bits
+    /// - `DISABLE_UNLOCK` is independent from the other bits: clearing it alone always restores
+    ///   withdrawals, so funds are never trapped
+    pub fn set_disabled_instructions(
+        ctx: Context<UpdateGlobalState>,
+        disabled_instructions: u8,
+    ) -> Result<()> {
+        require!(
+            disabled_instructions & !DISABLE_ALL == 0,
+            ErrorCode::InvalidInstructionFlags
+        );
+
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed(
+            "disabled_instructions",
+            global_state.disabled_instructions,
+            disabled_instructions,
+        );
+        global_state.disabled_instructions = disabled_instructions;
+        Ok(())
+    }
+
+    /// Set the staking program `unlock_and_stake` restakes into, `Pubkey::default()` to turn it off
+    /// - Authority only, emits `ConfigChanged`
+    /// - The program must implement the `stake` instruction described on `unlock_and_stake`
+    pub fn set_staking_program(
+        ctx: Context<UpdateGlobalState>,
+        staking_program: Pubkey,
+    ) -> Result<()> {
+        require_keys_neq!(staking_program, crate::ID, ErrorCode::InvalidStakingProgram);
+
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed(
+            "staking_program",
+            global_state.staking_program,
+            staking_program,
+        );
+        global_state.staking_program = staking_program;
+        Ok(())
+    }
+
+    /// Stop new deposits program-wide: lock creation, `top_up` and `extend_with_top_up`
+    /// - Authority only, emits `ConfigChanged`
+    /// - Unlocks and claims keep working, so funds are never trapped
+    pub fn pause(ctx: Context<UpdateGlobalState>) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed("paused", global_state.paused, true);
+        global_state.paused = true;
+        Ok(())
+    }
+
+    /// Resume deposits after `pause`
+    /// - Authority only, emits `ConfigChanged`
+    pub fn unpause(ctx: Context<UpdateGlobalState>) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed("paused", global_state.paused, false);
+        global_state.paused = false;
+        Ok(())
+    }
+
+    /// Allow or forbid extending locks, for products where the stated duration must be final
+    /// - Authority only, emits `ConfigChanged`
+    pub fn set_allow_extend(ctx: Context<UpdateGlobalState>, allow_extend: bool) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed("allow_extend", global_state.allow_extend, allow_extend);
+        global_state.allow_extend = allow_extend;
+        Ok(())
+    }
+
+    /// Require unlock timestamps to be whole minutes, making maturity boundaries predictable
+    /// - Authority only, emits `ConfigChanged`
+    /// - Applies to new locks and extensions, existing locks are unaffected
+    pub fn set_require_aligned_timestamps(
+        ctx: Context<UpdateGlobalState>,
+        require_aligned_timestamps: bool,
+    ) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed(
+            "require_aligned_timestamps",
+            global_state.require_aligned_timestamps,
+            require_aligned_timestamps,
+        );
+        global_state.require_aligned_timestamps = require_aligned_timestamps;
+        Ok(())
+    }
+
+    /// Set the share of a lock withheld when it is cancelled, in basis points of its amount
+    /// - Authority only, emits `ConfigChanged`
+    /// - Must be below 10000, so a cancellation always refunds something
+    pub fn set_cancel_fee_bps(ctx: Context<UpdateGlobalState>, cancel_fee_bps: u16) -> Result<()> {
+        require!(
+            (cancel_fee_bps as u64) < BPS_DENOMINATOR,
+            ErrorCode::InvalidBps
+        );
+
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed(
+            "cancel_fee_bps",
+            global_state.cancel_fee_bps,
+            cancel_fee_bps,
+        );
+        global_state.cancel_fee_bps = cancel_fee_bps;
+        Ok(())
+    }
+
+    /// Choose whether locks mature after their unlock timestamp second (strict) or at it (default)
+    /// - Authority only, emits `ConfigChanged`
+    /// - Strict suits products promising "locked until T inclusive"; it costs holders one second
+    ///   and applies to existing locks too, so it should be chosen before launch
+    pub fn set_strict_boundary(
+        ctx: Context<UpdateGlobalState>,
+        strict_boundary: bool,
+    ) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed(
+            "strict_boundary",
+            global_state.strict_boundary,
+            strict_boundary,
+        );
+        global_state.strict_boundary = strict_boundary;
+        Ok(())
+    }
+
+    /// Let locks mature `clock_tolerance_seconds` before their unlock timestamp, so validator clock
+    /// drift doesn't cause spurious `TooEarly` errors right at the boundary
+    /// - Authority only, emits `ConfigChanged`; at most `MAX_CLOCK_TOLERANCE`
+    /// - Applies to every lock: tokens can leave up to that many seconds early, so keep it to
+    ///   the few seconds of drift actually observed (default 0)
+    pub fn set_clock_tolerance(
+        ctx: Context<UpdateGlobalState>,
+        clock_tolerance_seconds: u32,
+    ) -> Result<()> {
+        require!(
+            clock_tolerance_seconds <= MAX_CLOCK_TOLERANCE,
+            ErrorCode::InvalidClockTolerance
+        );
+
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed(
+            "clock_tolerance_seconds",
+            global_state.clock_tolerance_seconds,
+            clock_tolerance_seconds,
+        );
+        global_state.clock_tolerance_seconds = clock_tolerance_seconds;
+        Ok(())
+    }
+
+    /// Cap the number of locks a single `batch_unlock` may unlock, bounding its compute and events
+    /// - Authority only, emits `ConfigChanged`; must be at least 1 (`InvalidBatchSize`)
+    pub fn set_max_batch_size(ctx: Context<UpdateGlobalState>, max_batch_size: u8) -> Result<()> {
+        require!(max_batch_size > 0, ErrorCode::InvalidBatchSize);
+
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed(
+            "max_batch_size",
+            global_state.max_batch_size,
+            max_batch_size,
+        );
+        global_state.max_batch_size = max_batch_size;
+        Ok(())
+    }
+
+    /// Cap the tokens a single instruction may lock or top up, limiting the damage of e.g. a
+    /// compromised client
+    /// - Authority only, emits `ConfigChanged`; 0 (the default) disables the cap
+    /// - Enforced by every lock instruction, `finalize_lock`, `top_up` and `extend_with_top_up`
+    ///   (`AmountTooLarge`); a lock can still grow past it over several top-ups
+    pub fn set_max_amount_per_lock(
+        ctx: Context<UpdateGlobalState>,
+        max_amount_per_lock: u64,
+    ) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed(
+            "max_amount_per_lock",
+            global_state.max_amount_per_lock,
+            max_amount_per_lock,
+        );
+        global_state.max_amount_per_lock = max_amount_per_lock;
+        Ok(())
+    }
+
+    /// Set the Ed25519 key whose signed `FeeVoucher`s waive the lock fee of `lock`
+    /// - Authority only, emits `ConfigChanged`; `Pubkey::default()` (the default) disables vouchers
+    /// - Rotating the key invalidates every outstanding voucher
+    pub fn set_voucher_signer(
+        ctx: Context<UpdateGlobalState>,
+        voucher_signer: Pubkey,
+    ) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed(
+            "voucher_signer",
+            global_state.voucher_signer,
+            voucher_signer,
+        );
+        global_state.voucher_signer = voucher_signer;
+        Ok(())
+    }
+
+    /// Cap the number of locks created per epoch, protecting validators from vault spam
+    /// - Authority only, emits `ConfigChanged`
+    /// - 0 disables the cap
+    pub fn set_max_locks_per_epoch(
+        ctx: Context<UpdateGlobalState>,
+        max_locks_per_epoch: u64,
+    ) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed(
+            "max_locks_per_epoch",
+            global_state.max_locks_per_epoch,
+            max_locks_per_epoch,
+        );
+        global_state.max_locks_per_epoch = max_locks_per_epoch;
+        Ok(())
+    }
+
+    /// Set the delay between requesting and confirming a beneficiary change
+    /// - Authority only, emits `ConfigChanged`
+    /// - Applies to requests made after the change
+    pub fn set_beneficiary_change_delay(ctx: Context<UpdateGlobalState>, delay: i64) -> Result<()> {
+        require!(delay >= 0, ErrorCode::InvalidDelay);
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed(
+            "beneficiary_change_delay",
+            global_state.beneficiary_change_delay,
+            delay,
+        );
+        global_state.beneficiary_change_delay = delay;
+        Ok(())
+    }
+
+    /// Release an escrow lock to its counterparty before maturity
+    /// - Only the lock's arbiter can release
+    /// - After maturity only the owner's normal unlock path remains
+    pub fn arbiter_release(ctx: Context<ArbiterRelease>) -> Result<()> {
+        // Prevent duplicate mutable accounts attack
+        require!(
+            ctx.accounts.vault.key() != ctx.accounts.counterparty_token_account.key(),
+            ErrorCode::DuplicateAccounts
+        );
+
+        let lock = &ctx.accounts.lock;
+
+        require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+
+        let current_ts = current_timestamp(&ctx.accounts.global_state)?;
+        require!(
+            !ctx.accounts
+                .global_state
+                .is_lock_matured(lock, current_ts)?,
+            ErrorCode::LockMatured
+        );
+
+        let amount = lock.amount;
+
+        // Mark as unlocked before moving tokens (checks-effects-interactions)
+        let lock = &mut ctx.accounts.lock;
+        lock.is_unlocked = true;
+        lock.unlocked_at = current_ts;
+
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.counterparty_token_account.to_account_info(),
+            lock,
+            amount,
+        )?;
+        record_withdrawal(&ctx.accounts.mint_stats, amount, true)?;
+        record_owner_withdrawal(&ctx.accounts.owner_mint_stats, amount)?;
+        record_lock_closed(&ctx.accounts.program_stats)?;
+
+        msg!(
+            "Arbiter released {} tokens from lock #{} to counterparty {}",
+            amount,
+            lock.id,
+            lock.counterparty
+        );
+
+        Ok(())
+    }
+
+    /// Sweep an abandoned lock into the mint's recovery vault, to prevent permanent loss
+    /// - Authority only, only locks created with `options.recovery_after`
+    /// - Only once `recovery_after` has passed, and at least `MIN_RECOVERY_DELAY` after maturity
+    pub fn recover(ctx: Context<Recover>) -> Result<()> {
+        let lock = &ctx.accounts.lock;
+
+        require!(lock.recovery_after != 0, ErrorCode::RecoveryDisabled);
+        require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+
+        let current_ts = current_timestamp(&ctx.accounts.global_state)?;
+        require!(
+            current_ts >= lock.recovery_after
+                && current_ts >= lock.unlock_timestamp.saturating_add(MIN_RECOVERY_DELAY),
+            ErrorCode::RecoveryTooEarly
+        );
+
+        let amount = lock.amount;
+
+        // Mark as unlocked before moving tokens (checks-effects-interactions)
+        let lock = &mut ctx.accounts.lock;
+        lock.is_unlocked = true;
+        lock.unlocked_at = current_ts;
+
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.recovery_vault.to_account_info(),
+            lock,
+            amount,
+        )?;
+        record_withdrawal(&ctx.accounts.mint_stats, amount, true)?;
+        record_owner_withdrawal(&ctx.accounts.owner_mint_stats, amount)?;
+        record_lock_closed(&ctx.accounts.program_stats)?;
+
+        emit!(Recovered {
+            lock_id: lock.id,
+            owner: lock.owner,
+            mint: lock.mint,
+            amount,
+        });
+        msg!(
+            "Recovered {} tokens from abandoned lock #{}",
+            amount,
+            lock.id
+        );
+
+        Ok(())
+    }
+
+    /// Unlock a matured lock on the user's behalf, for support cases
+    /// - Authority only, same maturity and denylist rules as `unlock`; deposit locks fail with
+    ///   `DepositNotSettled`, as only `unlock` settles their deposit
+    /// - Tokens only go to a token account of the lock's beneficiary (the owner unless they
+    ///   changed it), so the authority can trigger the unlock but never redirect it
+    /// - Emits `AssistedUnlock`
+    pub fn assisted_unlock(ctx: Context<AssistUnlock>) -> Result<()> {
+        require_canonical_vault_bump(&ctx.accounts.lock, ctx.bumps.vault)?;
+
+        let now = current_timestamp(&ctx.accounts.global_state)?;
+        let amount = process_unlock(
+            &mut ctx.accounts.lock,
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            ctx.accounts.owner_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            &ctx.accounts.global_state,
+            &ctx.accounts.denylist,
+            now,
+        )?;
+        record_withdrawal(&ctx.accounts.mint_stats, amount, true)?;
+        record_owner_withdrawal(&ctx.accounts.owner_mint_stats, amount)?;
+        record_lock_closed(&ctx.accounts.program_stats)?;
+
+        emit!(AssistedUnlock {
+            lock_id: ctx.accounts.lock.id,
+            owner: ctx.accounts.lock.owner,
+            authority: ctx.accounts.authority.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Unlock a matured lock carrying a keeper bounty (`LockOptions.bounty_bps`)
+    /// - Anyone may sign: `bounty_bps` of the amount goes to the keeper's token account and
+    ///   the rest to a token account of the beneficiary
+    /// - Same maturity and denylist rules as `unlock`; `NoBounty` on locks without a bounty
+    /// - Emits `BountyPaid`
+    pub fn keeper_unlock(ctx: Context<KeeperUnlock>) -> Result<()> {
+        require_canonical_vault_bump(&ctx.accounts.lock, ctx.bumps.vault)?;
+        require!(ctx.accounts.lock.bounty_bps > 0, ErrorCode::NoBounty);
+        require_keys_eq!(
+            ctx.accounts.vault.mint,
+            ctx.accounts.lock.mint,
+            ErrorCode::InvalidMint
+        );
+
+        let now = current_timestamp(&ctx.accounts.global_state)?;
+        let amount = begin_unlock(
+            &mut ctx.accounts.lock,
+            &ctx.accounts.global_state,
+            &ctx.accounts.denylist,
+            now,
+            false,
+        )?;
+        let bounty = (amount as u128 * ctx.accounts.lock.bounty_bps as u128
+            / BPS_DENOMINATOR as u128) as u64;
+
+        if bounty > 0 {
+            transfer_from_vault(
+                &ctx.accounts.token_program,
+                &ctx.accounts.vault,
+                &ctx.accounts.mint,
+                ctx.accounts.keeper_token_account.to_account_info(),
+                &ctx.accounts.lock,
+                bounty,
+            )?;
+        }
+        if amount > bounty {
+            transfer_from_vault(
+                &ctx.accounts.token_program,
+                &ctx.accounts.vault,
+                &ctx.accounts.mint,
+                ctx.accounts.owner_token_account.to_account_info(),
+                &ctx.accounts.lock,
+                amount - bounty,
+            )?;
+        }
+        record_withdrawal(&ctx.accounts.mint_stats, amount, true)?;
+        record_owner_withdrawal(&ctx.accounts.owner_mint_stats, amount)?;
+        record_lock_closed(&ctx.accounts.program_stats)?;
+
+        emit!(BountyPaid {
+            lock_id: ctx.accounts.lock.id,
+            keeper: ctx.accounts.keeper.key(),
+            bounty,
+            amount,
+        });
+        msg!(
+            "Unlocked {} tokens from lock #{}, {} paid to keeper {}",
+            amount,
+            ctx.accounts.lock.id,
+            bounty,
+            ctx.accounts.keeper.key()
+        );
+        Ok(())
+    }
+
+    /// Add an owner to the denylist, creating it on first use
+    /// - Authority only
+    /// - Only enforced while `denylist_enabled` is set
+    pub fn add_to_denylist(ctx: Context<ManageDenylist>, owner: Pubkey) -> Result<()> {
+        let denylist = &mut ctx.accounts.denylist;
+        denylist.bump = ctx.bumps.denylist;
+
+        if denylist.owners.contains(&owner) {
+            msg!("{} is already denied", owner);
+            return Ok(());
+        }
+        require!(
+            denylist.owners.len() < MAX_DENYLIST_ENTRIES,
+            ErrorCode::DenylistFull
+        );
+        denylist.owners.push(owner);
+
+        msg!("Denied {}", owner);
+        Ok(())
+    }
+
+    /// Remove an owner from the denylist
+    /// - Authority only
+    /// - Releases matured funds the owner couldn't unlock while denied
+    pub fn remove_from_denylist(ctx: Context<ManageDenylist>, owner: Pubkey) -> Result<()> {
+        let denylist = &mut ctx.accounts.denylist;
+        denylist.bump = ctx.bumps.denylist;
+        denylist.owners.retain(|denied| *denied != owner);
+
+        msg!("Removed {} from the denylist", owner);
+        Ok(())
+    }
+
+    /// Set the lock fee charged for a mint instead of `GlobalState.fee_amount`
+    /// - Authority only
+    /// - 0 makes locks of the mint fee-free
+    pub fn set_mint_fee_override(ctx: Context<SetMintFeeOverride>, fee_amount: u64) -> Result<()> {
+        let fee_override = &mut ctx.accounts.fee_override;
+        fee_override.mint = ctx.accounts.mint.key();
+        fee_override.fee_amount = fee_amount;
+        fee_override.bump = ctx.bumps.fee_override;
+
+        msg!(
+            "Fee for mint {} set to {} lamports",
+            fee_override.mint,
+            fee_amount
+        );
+        Ok(())
+    }
+
+    /// Define or update the lock template `template_id`, used by `lock_from_template`
+    /// - Authority only, pays for the template on first use
+    /// - Changes only apply to locks created afterwards
+    pub fn set_lock_template(
+        ctx: Context<SetLockTemplate>,
+        template_id: u64,
+        duration: i64,
+        reducible: bool,
+    ) -> Result<()> {
+        require!(duration > 0, ErrorCode::InvalidTemplate);
+
+        let template = &mut ctx.accounts.template;
+        template.template_id = template_id;
+        template.duration = duration;
+        template.reducible = reducible;
+        template.bump = ctx.bumps.template;
+
+        msg!(
+            "Lock template #{} set to {} seconds (reducible: {})",
+            template_id,
+            duration,
+            reducible
+        );
+        Ok(())
+    }
+
+    /// Stop or resume deposits of a mint, e.g. to offboard a token
+    /// - Authority only, pays for the mint status on first use
+    /// - While frozen, `lock` and `top_up` of the mint fail with `DepositsFrozen`;
+    ///   existing locks can still be unlocked
+    pub fn set_deposits_frozen(ctx: Context<SetMintStatus>, deposits_frozen: bool) -> Result<()> {
+        let mint_status = &mut ctx.accounts.mint_status;
+        mint_status.mint = ctx.accounts.mint.key();
+        mint_status.deposits_frozen = deposits_frozen;
+        mint_status.bump = ctx.bumps.mint_status;
+
+        msg!(
+            "Deposits of mint {} frozen: {}",
+            mint_status.mint,
+            deposits_frozen
+        );
+        Ok(())
+    }
+
+    /// Flag or clear a mint as suspicious, e.g. after its price collapsed
+    /// - Authority only, pays for the mint status on first use
+    /// - While flagged, new locks of the mint fail with `MintFlagged`; top-ups and unlocks of
+    ///   existing locks keep working
+    pub fn flag_mint_suspicious(ctx: Context<SetMintStatus>, flagged: bool) -> Result<()> {
+        let mint_status = &mut ctx.accounts.mint_status;
+        mint_status.mint = ctx.accounts.mint.key();
+        mint_status.flagged = flagged;
+        mint_status.bump = ctx.bumps.mint_status;
+
+        msg!(
+            "Mint {} flagged as suspicious: {}",
+            mint_status.mint,
+            flagged
+        );
+        Ok(())
+    }
+
+    /// Cap how much of a mint all locks may hold together
+    /// - Authority only, pays for the mint status on first use
+    /// - Lock creations and top-ups that would take `MintStats.total_locked` over
+    ///   `max_total_locked` fail with `MintCapExceeded`; 0 removes the cap
+    /// - Requires the mint's stats (see `backfill_mint_stats`), which track the locked total
+    pub fn set_mint_cap(ctx: Context<SetMintCap>, max_total_locked: u64) -> Result<()> {
+        let mint_status = &mut ctx.accounts.mint_status;
+        mint_status.mint = ctx.accounts.mint.key();
+        mint_status.max_total_locked = max_total_locked;
+        mint_status.bump = ctx.bumps.mint_status;
+
+        msg!(
+            "Locks of mint {} capped at {} tokens ({} locked)",
+            mint_status.mint,
+            max_total_locked,
+            ctx.accounts.mint_stats.total_locked
+        );
+        Ok(())
+    }
+
+    /// Register the 1:1 swap of a mint to its replacement, used by `migrate_mint`
+    /// - Authority only, pays for the migration PDA; a mint can only be registered once
+    /// - Both mints must have the same decimals
+    /// - The project funds the swap by sending `new_mint` tokens to a token account owned by
+    ///   the migration PDA (the reserve), typically its associated token account
+    pub fn register_mint_migration(ctx: Context<RegisterMintMigration>) -> Result<()> {
+        let old_mint = &ctx.accounts.old_mint;
+        let new_mint = &ctx.accounts.new_mint;
+        require_keys_neq!(old_mint.key(), new_mint.key(), ErrorCode::InvalidMigration);
+        require!(
+            old_mint.decimals == new_mint.decimals,
+            ErrorCode::MigrationDecimalsMismatch
+        );
+
+        let migration = &mut ctx.accounts.migration;
+        migration.old_mint = old_mint.key();
+        migration.new_mint = new_mint.key();
+        migration.bump = ctx.bumps.migration;
+
+        msg!(
+            "Locks of mint {} can migrate to mint {}",
+            migration.old_mint,
+            migration.new_mint
+        );
+        Ok(())
+    }
+
+    /// Swap the tokens of a lock to the replacement of its mint, 1:1
+    /// - Authority only, for a registered migration and a lock holding the old mint in its
+    ///   PDA vault (`VAULT_SCHEME_PDA`)
+    /// - Burns the locked old tokens, then moves as many new tokens from the migration reserve
+    ///   into the salted vault `[VAULT_SEED, lock_id, new_mint]`, which the authority pays for;
+    ///   the emptied old vault is closed, refunding its rent to the authority
+    /// - The lock keeps its owner, beneficiary and timing, and is unlocked with
+    ///   `unlock_salted_vault` from then on
+    /// - `VaultBoundLock` for locks released by another path than `unlock` (escrow, heirs,
+    ///   milestones, vesting, ...), which all read the PDA vault; `DepositNotSettled` for locks
+    ///   holding a deposit and `BurnOnUnlock` for burn-on-unlock locks, which only `unlock`
+    ///   settles and burns
+    pub fn migrate_mint(ctx: Context<MigrateMint>) -> Result<()> {
+        let lock = &ctx.accounts.lock;
+        require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+        require!(
+            lock.vault_scheme == VAULT_SCHEME_PDA,
+            ErrorCode::WrongVaultScheme
+        );
+        require_vault_movable(
+            lock,
+            &ctx.accounts.heirs.to_account_info(),
+            &ctx.accounts.schedule.to_account_info(),
+        )?;
+        require_canonical_vault_bump(lock, ctx.bumps.vault)?;
+        require_keys_eq!(
+            ctx.accounts.token_program.key(),
+            lock.token_program,
+            ErrorCode::WrongTokenProgram
+        );
+
+        let amount = lock.amount;
+        let lock_id_bytes = lock.id.to_le_bytes();
+        let vault_seeds = &[VAULT_SEED, lock_id_bytes.as_ref(), &[lock.vault_bump]];
+
+        // Retire the old tokens for good rather than leaving them claimable anywhere
+        token_interface::burn(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.old_mint.to_account_info(),
+                    from: ctx.accounts.vault.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                &[&vault_seeds[..]],
+            ),
+            amount,
+        )?;
+        ctx.accounts.vault.reload()?;
+        if ctx.accounts.vault.amount == 0 {
+            close_vault(
+                &ctx.accounts.token_program,
+                &ctx.accounts.vault,
+                ctx.accounts.authority.to_account_info(),
+                lock,
+            )?;
+        }
+
+        let old_mint_key = ctx.accounts.old_mint.key();
+        let migration_seeds = &[
+            MIGRATION_SEED,
+            old_mint_key.as_ref(),
+            &[ctx.accounts.migration.bump],
+        ];
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.reserve.to_account_info(),
+                    mint: ctx.accounts.new_mint.to_account_info(),
+                    to: ctx.accounts.new_vault.to_account_info(),
+                    authority: ctx.accounts.migration.to_account_info(),
+                },
+                &[&migration_seeds[..]],
+            ),
+            amount,
+            ctx.accounts.new_mint.decimals,
+        )?;
+
+        let new_mint_key = ctx.accounts.new_mint.key();
+        let lock = &mut ctx.accounts.lock;
+        lock.mint = new_mint_key;
+        lock.vault_scheme = VAULT_SCHEME_SALTED;
+        lock.vault_salt = new_mint_key.to_bytes();
+        record_withdrawal(&ctx.accounts.old_mint_stats, amount, true)?;
+        record_owner_withdrawal(&ctx.accounts.old_owner_mint_stats, amount)?;
+        record_deposit(&ctx.accounts.new_mint_stats, amount, true)?;
+        record_owner_deposit(&ctx.accounts.new_owner_mint_stats, amount)?;
+
+        emit!(MintMigrated {
+            lock_id: lock.id,
+            old_mint: old_mint_key,
+            new_mint: new_mint_key,
+            amount,
+        });
+        Ok(())
+    }
+
+    /// Move a lock's tokens to a freshly created vault, e.g. when a delegate was set on the old one
+    /// - Signed by both the authority and the lock owner; the lock must not be unlocked
+    /// - Creates the salted vault `[VAULT_SEED, lock_id, old_vault]` owned by the same vault PDA,
+    ///   paid by the owner; moves the whole old vault balance into it and closes the old vault,
+    ///   refunding its rent to the owner
+    /// - Works from any vault scheme, and can be repeated: the lock is a `VAULT_SCHEME_SALTED`
+    ///   lock from then on, unlocked with `unlock_salted_vault` (`lock.vault_bump`, the vault
+    ///   PDA's bump, doesn't change)
+    /// - `VaultBalanceMismatch` if the new vault ends up holding less than the lock (a transfer
+    ///   fee taken on the move)
+    /// - Only for locks released by `unlock`, like `migrate_mint`: `VaultBoundLock`,
+    ///   `DepositNotSettled` or `BurnOnUnlock` otherwise
+    pub fn rotate_vault(ctx: Context<RotateVault>) -> Result<()> {
+        let lock = &ctx.accounts.lock;
+        require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+        require_vault_movable(
+            lock,
+            &ctx.accounts.heirs.to_account_info(),
+            &ctx.accounts.schedule.to_account_info(),
+        )?;
+        require_canonical_vault_bump(lock, ctx.bumps.vault_authority)?;
+        let (expected_vault, _) = lock.vault_address();
+        require_keys_eq!(
+            ctx.accounts.vault.key(),
+            expected_vault,
+            ErrorCode::VaultMismatch
+        );
+
+        let amount = ctx.accounts.vault.amount;
+        if amount > 0 {
+            transfer_signed_by_vault_pda(
+                &ctx.accounts.token_program,
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.vault_authority.to_account_info(),
+                &ctx.accounts.mint,
+                ctx.accounts.new_vault.to_account_info(),
+                lock,
+                amount,
+            )?;
+        }
+        close_vault_signed_by_vault_pda(
+            &ctx.accounts.token_program,
+            ctx.accounts.vault.to_account_info(),
+            ctx.accounts.vault_authority.to_account_info(),
+            ctx.accounts.owner.to_account_info(),
+            lock,
+        )?;
+
+        ctx.accounts.new_vault.reload()?;
+        require!(
+            ctx.accounts.new_vault.amount >= lock.amount,
+            ErrorCode::VaultBalanceMismatch
+        );
+
+        let old_vault = ctx.accounts.vault.key();
+        let new_vault = ctx.accounts.new_vault.key();
+        let lock = &mut ctx.accounts.lock;
+        lock.vault_scheme = VAULT_SCHEME_SALTED;
+        lock.vault_salt = old_vault.to_bytes();
+
+        emit!(VaultRotated {
+            lock_id: lock.id,
+            old_vault,
+            new_vault,
+            amount,
+        });
+        Ok(())
+    }
+
+    /// Create a mint's stats from its existing locks, for mints locked before stats existed
+    /// - Authority only; pass every lock of the mint as remaining accounts, by increasing id
+    /// - Sums the amount of the locks that aren't unlocked yet
+    /// - The stats account must not exist yet, so locks can't be counted twice
+    pub fn backfill_mint_stats<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BackfillMintStats<'info>>,
+    ) -> Result<()> {
+        let mint = ctx.accounts.mint.key();
+        let mut total_locked: u64 = 0;
+        let mut active_locks: u64 = 0;
+        let mut next_id: u64 = 0;
+
+        for info in ctx.remaining_accounts.iter() {
+            let lock = Account::<Lock>::try_from(info)?;
+            require_keys_eq!(lock.mint, mint, ErrorCode::InvalidMint);
+            // Strictly increasing ids rule out passing a lock twice
+            require!(lock.id >= next_id, ErrorCode::InvalidBatch);
+            next_id = lock.id + 1;
+
+            if !lock.is_unlocked {
+                total_locked = total_locked
+                    .checked_add(lock.amount)
+                    .ok_or(ErrorCode::AmountOverflow)?;
+                active_locks += 1;
+            }
+        }
+
+        let mint_stats = &mut ctx.accounts.mint_stats;
+        mint_stats.mint = mint;
+        mint_stats.total_locked = total_locked;
+        mint_stats.active_locks = active_locks;
+        mint_stats.bump = ctx.bumps.mint_stats;
+
+        msg!(
+            "Mint {} stats backfilled: {} tokens in {} active locks",
+            mint,
+            total_locked,
+            active_locks
+        );
+        Ok(())
+    }
+
+    /// Remove a mint's fee override so its locks pay `GlobalState.fee_amount` again
+    /// - Authority only, rent goes back to the authority
+    pub fn remove_mint_fee_override(ctx: Context<RemoveMintFeeOverride>) -> Result<()> {
+        msg!(
+            "Fee override removed for mint {}",
+            ctx.accounts.fee_override.mint
+        );
+        Ok(())
+    }
+
+    /// Turn denylist enforcement on `lock`, `unlock` and `claim_milestone` on or off
+    /// - Authority only, emits `ConfigChanged`
+    pub fn set_denylist_enabled(ctx: Context<UpdateGlobalState>, enabled: bool) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        emit_config_changed("denylist_enabled", global_state.denylist_enabled, enabled);
+        global_state.denylist_enabled = enabled;
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Current unix timestamp used for every time-based rule.
+/// Builds with the `test-clock` feature return the authority-set mock instead, when non-zero.
+#[cfg_attr(not(feature = "test-clock"), allow(unused_variables))]
+fn current_timestamp(global_state: &GlobalState) -> Result<i64> {
+    #[cfg(feature = "test-clock")]
+    if global_state.mock_timestamp != 0 {
+        return Ok(global_state.mock_timestamp);
+    }
+    Ok(Clock::get()?.unix_timestamp)
+}
+
+/// Whether the denylist is enabled and lists `owner`.
+/// A denylist that was never created lists nobody.
+fn is_denied(global_state: &GlobalState, denylist: &AccountInfo, owner: &Pubkey) -> Result<bool> {
+    if !global_state.denylist_enabled || denylist.data_is_empty() {
+        return Ok(false);
+    }
+
+    let data = denylist.try_borrow_data()?;
+    let denylist = Denylist::try_deserialize(&mut &data[..])?;
+    Ok(denylist.owners.contains(owner))
+}
+
+/// Reject `owner` when the denylist is enabled and lists them
+fn require_not_denied(
+    global_state: &GlobalState,
+    denylist: &AccountInfo,
+    owner: &Pubkey,
+) -> Result<()> {
+    require!(
+        !is_denied(global_state, denylist, owner)?,
+        ErrorCode::OwnerDenied
+    );
+    Ok(())
+}
+
+/// Lock fee in lamports for the mint whose override PDA is `fee_override`.
+/// Mints without an override pay `GlobalState.fee_amount`.
+fn lock_fee(global_state: &GlobalState, fee_override: &AccountInfo) -> Result<u64> {
+    if fee_override.data_is_empty() {
+        return Ok(global_state.fee_amount);
+    }
+
+    let data = fee_override.try_borrow_data()?;
+    Ok(MintFeeOverride::try_deserialize(&mut &data[..])?.fee_amount)
+}
+
+/// Lock fee `owner` pays at `now`: 0 with a valid `fee_voucher`, else `lock_fee`.
+/// Shared by `lock` and `quote_fee`, so a quote is always what gets charged.
+fn effective_lock_fee(
+    global_state: &GlobalState,
+    fee_override: &AccountInfo,
+    owner: Option<Pubkey>,
+    fee_voucher: Option<FeeVoucher>,
+    instructions_sysvar: Option<&AccountInfo>,
+    now: i64,
+) -> Result<u64> {
+    let Some(voucher) = fee_voucher else {
+        return lock_fee(global_state, fee_override);
+    };
+    let (Some(owner), Some(instructions_sysvar)) = (owner, instructions_sysvar) else {
+        return err!(ErrorCode::FeeVoucherAccountsMissing);
+    };
+    verify_fee_voucher(
+        &voucher,
+        global_state.voucher_signer,
+        owner,
+        now,
+        instructions_sysvar,
+    )?;
+    Ok(0)
+}
+
+/// Fail unless `voucher` is unexpired and signed by `voucher_signer` for `owner`.
+/// The signature itself is verified by the Ed25519 program instruction right before the
+/// current one, which is read back through the instructions sysvar.
+fn verify_fee_voucher(
+    voucher: &FeeVoucher,
+    voucher_signer: Pubkey,
+    owner: Pubkey,
+    now: i64,
+    instructions_sysvar: &AccountInfo,
+) -> Result<()> {
+    require_keys_neq!(
+        voucher_signer,
+        Pubkey::default(),
+        ErrorCode::FeeVouchersDisabled
+    );
+    require!(now < voucher.expiry, ErrorCode::FeeVoucherExpired);
+
+    let current_index = load_current_index_checked(instructions_sysvar)?;
+    require!(current_index > 0, ErrorCode::InvalidFeeVoucher);
+    let ed25519_ix = load_instruction_at_checked(current_index as usize - 1, instructions_sysvar)?;
+    require_keys_eq!(
+        ed25519_ix.program_id,
+        ed25519_program::ID,
+        ErrorCode::InvalidFeeVoucher
+    );
+
+    require!(
+        ed25519_verifies(
+            &ed25519_ix.data,
+            voucher_signer.as_ref(),
+            &voucher.signature,
+            &voucher.message(owner),
+        ),
+        ErrorCode::InvalidFeeVoucher
+    );
+    Ok(())
+}
+
+/// Whether `data`, an Ed25519 program instruction, verifies exactly one `signature` of `message`
+/// by `public_key`, all three stored in the instruction itself
+fn ed25519_verifies(data: &[u8], public_key: &[u8], signature: &[u8], message: &[u8]) -> bool {
+    // Signature count and padding, then one offsets entry of seven u16 fields
+    if data.len() < 2 + 14 || data[0] != 1 {
+        return false;
+    }
+    let field = |i: usize| u16::from_le_bytes([data[2 + 2 * i], data[3 + 2 * i]]) as usize;
+    let (signature_offset, signature_ix) = (field(0), field(1));
+    let (public_key_offset, public_key_ix) = (field(2), field(3));
+    let (message_offset, message_size, message_ix) = (field(4), field(5), field(6));
+    // u16::MAX points at the Ed25519 instruction itself rather than another instruction
+    let inline = u16::MAX as usize;
+    if signature_ix != inline || public_key_ix != inline || message_ix != inline {
+        return false;
+    }
+
+    let slice = |offset: usize, len: usize| data.get(offset..offset + len);
+    slice(signature_offset, signature.len()) == Some(signature)
+        && slice(public_key_offset, public_key.len()) == Some(public_key)
+        && message_size == message.len()
+        && slice(message_offset, message.len()) == Some(message)
+}
+
+/// Transfer the lock fee from `owner` to `fee_recipient`, a no-op for a zero fee
+fn pay_lock_fee<'info>(
+    fee_recipient: &AccountInfo<'info>,
+    owner: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    fee: u64,
+) -> Result<()> {
+    if fee == 0 {
+        return Ok(());
+    }
+
+    // The fee recipient is assumed to be a plain wallet: program-owned accounts (PDAs)
+    // and programs can't be relied on to hold lamports sent by a system transfer
+    require_keys_eq!(
+        *fee_recipient.owner,
+        anchor_lang::system_program::ID,
+        ErrorCode::FeeRecipientNotWallet
+    );
+    require!(
+        !fee_recipient.executable && fee_recipient.data_is_empty(),
+        ErrorCode::FeeRecipientNotWallet
+    );
+
+    let recipient_before = fee_recipient.lamports();
+    anchor_lang::system_program::transfer(
+        CpiContext::new(
+            system_program.clone(),
+            anchor_lang::system_program::Transfer {
+                from: owner.clone(),
+                to: fee_recipient.clone(),
+            },
+        ),
+        fee,
+    )?;
+
+    // Never collect anything but the computed fee: a mismatch (e.g. rounding in a future
+    // fee formula) fails the whole instruction, so the owner is charged nothing at all
+    require!(
+        fee_recipient.lamports().checked_sub(recipient_before) == Some(fee),
+        ErrorCode::FeeMismatch
+    );
+    require!(
+        Rent::get()?.is_exempt(fee_recipient.lamports(), 0),
+        ErrorCode::FeeRecipientNotRentExempt
+    );
+    Ok(())
+}
+
+/// Log an admin change of a `GlobalState` field as a `ConfigChanged` event
+fn emit_config_changed(field: &str, old_value: impl ToString, new_value: impl ToString) {
+    let event = ConfigChanged {
+        field: field.to_string(),
+        old_value: old_value.to_string(),
+        new_value: new_value.to_string(),
+    };
+    msg!(
+        "Config {} changed from {} to {}",
+        event.field,
+        event.old_value,
+        event.new_value
+    );
+    emit!(event);
+}
+
+/// Apply `update` to the mint stats stored at `mint_stats`.
+/// Mints without stats (see `backfill_mint_stats`) aren't tracked.
+fn update_mint_stats(mint_stats: &AccountInfo, update: impl FnOnce(&mut MintStats)) -> Result<()> {
+    if mint_stats.data_is_empty() {
+        return Ok(());
+    }
+
+    let mut data = mint_stats.try_borrow_mut_data()?;
+    let mut stats = MintStats::try_deserialize(&mut &data[..])?;
+    update(&mut stats);
+    stats.try_serialize(&mut &mut data[..])
+}
+
+/// Count `amount` tokens entering the mint's locks, as a new lock or a top-up
+fn record_deposit(mint_stats: &AccountInfo, amount: u64, new_lock: bool) -> Result<()> {
+    update_mint_stats(mint_stats, |stats| {
+        stats.total_locked = stats.total_locked.saturating_add(amount);
+        if new_lock {
+            stats.active_locks = stats.active_locks.saturating_add(1);
+        }
+    })
+}
+
+/// Count `amount` tokens leaving the mint's locks, `lock_closed` when the lock is now unlocked
+fn record_withdrawal(mint_stats: &AccountInfo, amount: u64, lock_closed: bool) -> Result<()> {
+    update_mint_stats(mint_stats, |stats| {
+        stats.total_locked = stats.total_locked.saturating_sub(amount);
+        if lock_closed {
+            stats.active_locks = stats.active_locks.saturating_sub(1);
+        }
+    })
+}
+
+/// Apply `update` to an owner's stats for a mint if they exist
+fn update_owner_mint_stats(
+    owner_mint_stats: &AccountInfo,
+    update: impl FnOnce(&mut OwnerMintStats) -> Option<()>,
+) -> Result<()> {
+    if owner_mint_stats.data_is_empty() {
+        return Ok(());
+    }
+
+    let mut data = owner_mint_stats.try_borrow_mut_data()?;
+    let mut stats = OwnerMintStats::try_deserialize(&mut &data[..])?;
+    update(&mut stats).ok_or(ErrorCode::AmountOverflow)?;
+    stats.try_serialize(&mut &mut data[..])
+}
+
+/// Create `owner`'s stats for `mint` at `owner_mint_stats`, paid by `owner`
+fn create_owner_mint_stats<'info>(
+    owner_mint_stats: &AccountInfo<'info>,
+    owner: &AccountInfo<'info>,
+    mint: &Pubkey,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    let owner_key = owner.key();
+    let (_, bump) = Pubkey::find_program_address(
+        &[OWNER_MINT_STATS_SEED, owner_key.as_ref(), mint.as_ref()],
+        &crate::ID,
+    );
+    create_pda_account(
+        owner_mint_stats,
+        owner,
+        system_program,
+        &[
+            OWNER_MINT_STATS_SEED,
+            owner_key.as_ref(),
+            mint.as_ref(),
+            &[bump],
+        ],
+        8 + OwnerMintStats::INIT_SPACE,
+    )?;
+
+    let stats = OwnerMintStats {
+        owner: owner_key,
+        mint: *mint,
+        total_locked: 0,
+        bump,
+    };
+    let mut data = owner_mint_stats.try_borrow_mut_data()?;
+    stats.try_serialize(&mut &mut data[..])
+}
+
+/// Count `amount` tokens entering an owner's locks of a mint
+fn record_owner_deposit(owner_mint_stats: &AccountInfo, amount: u64) -> Result<()> {
+    update_owner_mint_stats(owner_mint_stats, |stats| {
+        stats.total_locked = stats.total_locked.checked_add(amount)?;
+        Some(())
+    })
+}
+
+/// Count `amount` tokens leaving an owner's locks of a mint. Saturates rather than failing:
+/// locks created before the stats existed were never counted, and must still unlock.
+fn record_owner_withdrawal(owner_mint_stats: &AccountInfo, amount: u64) -> Result<()> {
+    update_owner_mint_stats(owner_mint_stats, |stats| {
+        stats.total_locked = stats.total_locked.saturating_sub(amount);
+        Some(())
+    })
+}
+
+/// Apply `update` to the program stats PDA if it exists, failing on overflow
+fn update_program_stats(
+    program_stats: &AccountInfo,
+    update: impl FnOnce(&mut ProgramStats) -> Option<()>,
+) -> Result<()> {
+    if program_stats.data_is_empty() {
+        return Ok(());
+    }
+
+    let mut data = program_stats.try_borrow_mut_data()?;
+    let mut stats = ProgramStats::try_deserialize(&mut &data[..])?;
+    update(&mut stats).ok_or(ErrorCode::AmountOverflow)?;
+    stats.try_serialize(&mut &mut data[..])
+}
+
+/// Count a new lock and the `fee` lamports paid for it
+fn record_lock_created(program_stats: &AccountInfo, fee: u64) -> Result<()> {
+    update_program_stats(program_stats, |stats| {
+        stats.total_locks_ever = stats.total_locks_ever.checked_add(1)?;
+        stats.active_locks = stats.active_locks.checked_add(1)?;
+        stats.total_fees = stats.total_fees.checked_add(fee)?;
+        Some(())
+    })
+}
+
+/// Create the program stats at `program_stats`, paid by `payer`
+fn create_program_stats<'info>(
+    program_stats: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    bump: u8,
+) -> Result<()> {
+    create_pda_account(
+        program_stats,
+        payer,
+        system_program,
+        &[PROGRAM_STATS_SEED, &[bump]],
+        8 + ProgramStats::INIT_SPACE,
+    )?;
+
+    let stats = ProgramStats {
+        total_locks_ever: 0,
+        active_locks: 0,
+        total_unlocks: 0,
+        total_fees: 0,
+        bump,
+    };
+    let mut data = program_stats.try_borrow_mut_data()?;
+    stats.try_serialize(&mut &mut data[..])
+}
+
+/// Count a lock that is now fully withdrawn
+fn record_lock_closed(program_stats: &AccountInfo) -> Result<()> {
+    update_program_stats(program_stats, |stats| {
+        stats.active_locks = stats.active_locks.checked_sub(1)?;
+        stats.total_unlocks = stats.total_unlocks.checked_add(1)?;
+        Some(())
+    })
+}
+
+/// Count `amount` lamports collected by the fee recipient outside of lock fees
+fn record_fee(program_stats: &AccountInfo, amount: u64) -> Result<()> {
+    update_program_stats(program_stats, |stats| {
+        stats.total_fees = stats.total_fees.checked_add(amount)?;
+        Some(())
+    })
+}
+
+/// Reject a deposit of `amount` tokens of a mint whose status PDA `mint_status` has deposits
+/// frozen, or whose cap the mint's locked total (from `mint_stats`) would exceed.
+/// Mints without a status accept deposits.
+fn require_deposits_open(
+    mint_status: &AccountInfo,
+    mint_stats: &AccountInfo,
+    amount: u64,
+) -> Result<()> {
+    if mint_status.data_is_empty() {
+        return Ok(());
+    }
+
+    let data = mint_status.try_borrow_data()?;
+    let mint_status = MintStatus::try_deserialize(&mut &data[..])?;
+    require!(!mint_status.deposits_frozen, ErrorCode::DepositsFrozen);
+
+    if mint_status.max_total_locked > 0 {
+        // `set_mint_cap` requires the mint's stats, so a capped mint always has them
+        let total_locked = if mint_stats.data_is_empty() {
+            0
+        } else {
+            let data = mint_stats.try_borrow_data()?;
+            MintStats::try_deserialize(&mut &data[..])?.total_locked
+        };
+        require!(
+            total_locked
+                .checked_add(amount)
+                .is_some_and(|total| total <= mint_status.max_total_locked),
+            ErrorCode::MintCapExceeded
+        );
+    }
+    Ok(())
+}
+
+/// Reject new locks of a mint whose status PDA `mint_status` flags it as suspicious
+fn require_mint_not_flagged(mint_status: &AccountInfo) -> Result<()> {
+    if mint_status.data_is_empty() {
+        return Ok(());
+    }
+
+    let data = mint_status.try_borrow_data()?;
+    let mint_status = MintStatus::try_deserialize(&mut &data[..])?;
+    require!(!mint_status.flagged, ErrorCode::MintFlagged);
+    Ok(())
+}
+
+/// Lock template stored at `template`, or `TemplateNotFound` if it was never defined
+fn load_lock_template(template: &AccountInfo) -> Result<LockTemplate> {
+    require!(!template.data_is_empty(), ErrorCode::TemplateNotFound);
+
+    let data = template.try_borrow_data()?;
+    LockTemplate::try_deserialize(&mut &data[..])
+}
+
+/// Size of a vault token account for `mint`, including the extensions Token-2022 requires.
+/// Mirrors the sizing Anchor's `init` uses when creating the vault.
+fn vault_space(mint: &AccountInfo) -> Result<usize> {
+    use anchor_spl::token_2022::spl_token_2022::extension::{
+        BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+    };
+    use anchor_spl::token_2022::spl_token_2022::state::{Account, Mint as MintState};
+
+    if *mint.owner != anchor_spl::token_2022::ID {
+        return Ok(anchor_spl::token::TokenAccount::LEN);
+    }
+
+    let mint_data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<MintState>::unpack(&mint_data)?;
+    let mint_extensions = mint_state.get_extension_types()?;
+    let required_extensions = ExtensionType::get_required_init_account_extensions(&mint_extensions);
+    Ok(ExtensionType::try_calculate_account_len::<Account>(
+        &required_extensions,
+    )?)
+}
+
+/// Whether `mint` is a Token-2022 mint with the transfer fee extension
+fn has_transfer_fee(mint: &AccountInfo) -> Result<bool> {
+    use anchor_spl::token_2022::spl_token_2022::extension::{
+        BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+    };
+    use anchor_spl::token_2022::spl_token_2022::state::Mint as MintState;
+
+    if *mint.owner != anchor_spl::token_2022::ID {
+        return Ok(false);
+    }
+
+    let mint_data = mint.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<MintState>::unpack(&mint_data)?;
+    Ok(mint_state
+        .get_extension_types()?
+        .contains(&ExtensionType::TransferFeeConfig))
+}
+
+/// Reject a lock whose stored vault bump isn't the canonical one, so a corrupted bump
+/// can't make a non-canonical vault address acceptable
+fn require_canonical_vault_bump(lock: &Lock, canonical_bump: u8) -> Result<()> {
+    require!(lock.vault_bump == canonical_bump, ErrorCode::InvalidBump);
+    Ok(())
+}
+
+/// Reject a lock whose tokens can't leave its PDA vault: a salted vault is only ever released
+/// by `unlock_salted_vault`, so escrow, heirs, milestone, vesting, cancel, reduce, recovery,
+/// secret, bounty and receipt locks would be left without their own release path, a
+/// deposit could never be settled and burn-on-unlock tokens never burned (only `unlock` does).
+/// `heirs` and `schedule` are the lock's heirs and milestone PDAs, which may not exist.
+fn require_vault_movable(lock: &Lock, heirs: &AccountInfo, schedule: &AccountInfo) -> Result<()> {
+    require!(lock.deposit_lamports == 0, ErrorCode::DepositNotSettled);
+    require!(!lock.burn_on_unlock, ErrorCode::BurnOnUnlock);
+    require!(
+        lock.arbiter == Pubkey::default()
+            && lock.recovery_after == 0
+            && lock.secret_hash == [0; 32]
+            && lock.vesting_curve == VESTING_NONE
+            && !lock.cancellable
+            && !lock.reducible
+            && lock.bounty_bps == 0
+            && lock.receipt_mint == Pubkey::default()
+            && heirs.data_is_empty()
+            && schedule.data_is_empty(),
+        ErrorCode::VaultBoundLock
+    );
+    Ok(())
+}
+
+/// Reject unlock timestamps that aren't a multiple of `TIMESTAMP_ALIGNMENT`, when required
+fn require_aligned(global_state: &GlobalState, unlock_timestamp: i64) -> Result<()> {
+    require!(
+        !global_state.require_aligned_timestamps
+            || unlock_timestamp.rem_euclid(TIMESTAMP_ALIGNMENT) == 0,
+        ErrorCode::TimestampNotAligned
+    );
+    Ok(())
+}
+
+/// Round `timestamp` up to the next multiple of `TIMESTAMP_ALIGNMENT`, when aligned timestamps
+/// are required
+fn round_up_to_alignment(global_state: &GlobalState, timestamp: i64) -> i64 {
+    let remainder = timestamp.rem_euclid(TIMESTAMP_ALIGNMENT);
+    if !global_state.require_aligned_timestamps || remainder == 0 {
+        return timestamp;
+    }
+    timestamp + TIMESTAMP_ALIGNMENT - remainder
+}
+
+/// Day bucket of a timestamp, used to seed expiry index PDAs
+pub fn day_bucket(timestamp: i64) -> i64 {
+    timestamp.div_euclid(SECONDS_PER_DAY)
+}
+
+/// Days since the Unix epoch of the Gregorian date `year-month-day` (month in 1..=12)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Gregorian `(year, month, day)` of a number of days since the Unix epoch
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// Number of days in `month` (1..=12) of `year`
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// `timestamp` moved forward by `months` calendar months, then `days` days, keeping the time
+/// of day (UTC). A day past the end of the target month is clamped to its last day.
+/// `None` on overflow.
+fn add_calendar_duration(timestamp: i64, months: u8, days: u16) -> Option<i64> {
+    let (year, month, day) = civil_from_days(timestamp.div_euclid(SECONDS_PER_DAY));
+    let time_of_day = timestamp.rem_euclid(SECONDS_PER_DAY);
+
+    let month_index = year.checked_mul(12)? + (month - 1) + months as i64;
+    let target_year = month_index.div_euclid(12);
+    let target_month = month_index.rem_euclid(12) + 1;
+    let target_day = day.min(days_in_month(target_year, target_month));
+
+    (days_from_civil(target_year, target_month, target_day) + days as i64)
+        .checked_mul(SECONDS_PER_DAY)?
+        .checked_add(time_of_day)
+}
+
+/// Create a program-owned PDA of `space` bytes, funded by `payer`.
+/// Like Anchor's `init`, this also handles a PDA that was pre-funded by someone else.
+fn create_pda_account<'info>(
+    info: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    signer_seeds: &[&[u8]],
+    space: usize,
+) -> Result<()> {
+    create_pda_account_owned_by(info, payer, system_program, signer_seeds, space, &crate::ID)
+}
+
+/// `create_pda_account`, assigning the new account to `owner` instead of this program.
+fn create_pda_account_owned_by<'info>(
+    info: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    signer_seeds: &[&[u8]],
+    space: usize,
+    owner: &Pubkey,
+) -> Result<()> {
+    let rent = Rent::get()?.minimum_balance(space);
+    let current_lamports = info.lamports();
+
+    if current_lamports == 0 {
+        return anchor_lang::system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                anchor_lang::system_program::CreateAccount {
+                    from: payer.clone(),
+                    to: info.clone(),
+                },
+                &[signer_seeds],
+            ),
+            rent,
+            space as u64,
+            owner,
+        );
+    }
+
+    let top_up = rent.saturating_sub(current_lamports);
+    if top_up > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                anchor_lang::system_program::Transfer {
+                    from: payer.clone(),
+                    to: info.clone(),
+                },
+            ),
+            top_up,
+        )?;
+    }
+    anchor_lang::system_program::allocate(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            anchor_lang::system_program::Allocate {
+                account_to_allocate: info.clone(),
+            },
+            &[signer_seeds],
+        ),
+        space as u64,
+    )?;
+    anchor_lang::system_program::assign(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            anchor_lang::system_program::Assign {
+                account_to_assign: info.clone(),
+            },
+            &[signer_seeds],
+        ),
+        owner,
+    )
+}
+
+/// Read a program account still in its legacy layout of `legacy_len` bytes (discriminator
+/// included), failing with `NotLegacyAccount` for any other owner, size or discriminator
+fn read_legacy_account<T: AnchorDeserialize>(
+    info: &AccountInfo,
+    legacy_len: usize,
+    discriminator: &[u8],
+) -> Result<T> {
+    require_keys_eq!(*info.owner, ID, ErrorCode::NotLegacyAccount);
+    let data = info.try_borrow_data()?;
+    require!(
+        data.len() == legacy_len && data.starts_with(discriminator),
+        ErrorCode::NotLegacyAccount
+    );
+    Ok(T::deserialize(&mut &data[discriminator.len()..])?)
+}
+
+/// Grow a program account to `space` bytes, `payer` topping up its rent
+fn grow_account<'info>(
+    info: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    space: usize,
+) -> Result<()> {
+    let top_up = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(info.lamports());
+    if top_up > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                anchor_lang::system_program::Transfer {
+                    from: payer.clone(),
+                    to: info.clone(),
+                },
+            ),
+            top_up,
+        )?;
+    }
+    info.resize(space)?;
+    Ok(())
+}
+
+/// Record `lock_id` in the expiry index bucket of `unlock_timestamp`, creating the bucket on first use.
+/// A full bucket only counts the lock in `overflow_count`, so indexing never blocks a lock.
+fn add_to_expiry_index<'info>(
+    index: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    unlock_timestamp: i64,
+    lock_id: u64,
+) -> Result<()> {
+    let bucket = day_bucket(unlock_timestamp);
+    let bucket_bytes = bucket.to_le_bytes();
+    let (expected_index, bump) =
+        Pubkey::find_program_address(&[EXPIRY_SEED, &bucket_bytes], &crate::ID);
+    require_keys_eq!(index.key(), expected_index, ErrorCode::InvalidExpiryIndex);
+
+    let mut expiry_index = if index.data_is_empty() {
+        create_pda_account(
+            index,
+            payer,
+            system_program,
+            &[EXPIRY_SEED, &bucket_bytes, &[bump]],
+            8 + ExpiryIndex::INIT_SPACE,
+        )?;
+        ExpiryIndex {
+            bucket,
+            lock_ids: Vec::new(),
+            overflow_count: 0,
+            bump,
+        }
+    } else {
+        let data = index.try_borrow_data()?;
+        ExpiryIndex::try_deserialize(&mut &data[..])?
+    };
+
+    if expiry_index.lock_ids.len() < MAX_EXPIRY_INDEX_ENTRIES {
+        expiry_index.lock_ids.push(lock_id);
+    } else {
+        expiry_index.overflow_count = expiry_index
+            .overflow_count
+            .checked_add(1)
+            .ok_or(ErrorCode::AmountOverflow)?;
+        msg!(
+            "Expiry bucket {} is full, lock #{} not indexed",
+            bucket,
+            lock_id
+        );
+    }
+
+    let mut data = index.try_borrow_mut_data()?;
+    expiry_index.try_serialize(&mut &mut data[..])
+}
+
+/// Record `lock_id` in the creation index bucket of `created_at`, creating the bucket on first use.
+/// Locks beyond `MAX_CREATION_INDEX_ENTRIES` are only counted in `overflow_count`.
+fn add_to_creation_index<'info>(
+    index: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    created_at: i64,
+    lock_id: u64,
+) -> Result<()> {
+    let bucket = day_bucket(created_at);
+    let bucket_bytes = bucket.to_le_bytes();
+    let (expected_index, bump) =
+        Pubkey::find_program_address(&[CREATION_SEED, &bucket_bytes], &crate::ID);
+    require_keys_eq!(index.key(), expected_index, ErrorCode::InvalidCreationIndex);
+
+    let mut creation_index = if index.data_is_empty() {
+        create_pda_account(
+            index,
+            payer,
+            system_program,
+            &[CREATION_SEED, &bucket_bytes, &[bump]],
+            8 + CreationIndex::INIT_SPACE,
+        )?;
+        CreationIndex {
+            bucket,
+            lock_ids: Vec::new(),
+            overflow_count: 0,
+            bump,
+        }
+    } else {
+        let data = index.try_borrow_data()?;
+        CreationIndex::try_deserialize(&mut &data[..])?
+    };
+
+    if creation_index.lock_ids.len() < MAX_CREATION_INDEX_ENTRIES {
+        creation_index.lock_ids.push(lock_id);
+    } else {
+        creation_index.overflow_count = creation_index
+            .overflow_count
+            .checked_add(1)
+            .ok_or(ErrorCode::AmountOverflow)?;
+        msg!(
+            "Creation bucket {} is full, lock #{} not indexed",
+            bucket,
+            lock_id
+        );
+    }
+
+    let mut data = index.try_borrow_mut_data()?;
+    creation_index.try_serialize(&mut &mut data[..])
+}
+
+/// Remove `lock_id` from the expiry index bucket of `unlock_timestamp`.
+/// Buckets that were never created (locks older than the index) are left alone.
+fn remove_from_expiry_index(
+    index: &AccountInfo,
+    unlock_timestamp: i64,
+    lock_id: u64,
+) -> Result<()> {
+    let bucket_bytes = day_bucket(unlock_timestamp).to_le_bytes();
+    let (expected_index, _) =
+        Pubkey::find_program_address(&[EXPIRY_SEED, &bucket_bytes], &crate::ID);
+    require_keys_eq!(index.key(), expected_index, ErrorCode::InvalidExpiryIndex);
+
+    if index.data_is_empty() {
+        return Ok(());
+    }
+
+    let mut expiry_index = {
+        let data = index.try_borrow_data()?;
+        ExpiryIndex::try_deserialize(&mut &data[..])?
+    };
+
+    if let Some(position) = expiry_index.lock_ids.iter().position(|id| *id == lock_id) {
+        expiry_index.lock_ids.remove(position);
+    } else {
+        // The lock didn't fit when it was added, so it was only counted
+        expiry_index.overflow_count = expiry_index.overflow_count.saturating_sub(1);
+    }
+
+    let mut data = index.try_borrow_mut_data()?;
+    expiry_index.try_serialize(&mut &mut data[..])
+}
+
+/// Unlock a whole matured lock: send its tokens to `destination` and mark it unlocked.
+/// Shared by `unlock` and `batch_unlock`, which read `now` once for the whole batch.
+#[allow(clippy::too_many_arguments)]
+fn process_unlock<'info>(
+    lock: &mut Account<'info, Lock>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    destination: AccountInfo<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+    global_state: &GlobalState,
+    denylist: &AccountInfo<'info>,
+    now: i64,
+) -> Result<u64> {
+    // The vault seeds already tie it to the lock, check the mint explicitly before moving tokens
+    require_keys_eq!(vault.mint, lock.mint, ErrorCode::InvalidMint);
+
+    let amount = begin_unlock(lock, global_state, denylist, now, false)?;
+
+    // Transfer tokens from vault to the beneficiary using PDA signer
+    transfer_from_vault(token_program, vault, mint, destination, lock, amount)?;
+
+    msg!("Unlocked {} tokens from lock #{}", amount, lock.id);
+
+    Ok(amount)
+}
+
+/// Check that a whole lock can be unlocked at `now` and mark it unlocked, before any transfer.
+/// `burn` tells whether the caller burns the tokens: burn-on-unlock locks can't be released
+/// any other way, and only they are burnt. Returns the amount to release from its vault.
+fn begin_unlock(
+    lock: &mut Lock,
+    global_state: &GlobalState,
+    denylist: &AccountInfo,
+    now: i64,
+    burn: bool,
+) -> Result<u64> {
+    require!(!lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+    require!(lock.burn_on_unlock == burn, ErrorCode::BurnOnUnlock);
+    global_state.require_enabled(DISABLE_UNLOCK)?;
+    require_not_denied(global_state, denylist, &lock.owner)?;
+    require!(
+        global_state.is_lock_matured(lock, now)?,
+        ErrorCode::TooEarly
+    );
+    require!(lock.deposit_lamports == 0, ErrorCode::DepositNotSettled);
+
+    // Mark as unlocked before the transfer or any later CPI (checks-effects-interactions),
+    // so nothing invoked along the way can see the lock as still claimable
+    lock.is_unlocked = true;
+    lock.unlocked_at = now;
+
+    Ok(lock.amount)
+}
+
+/// Transfer tokens out of a lock's vault, signing with the vault PDA.
+/// Only through the token program the lock was created with.
+fn transfer_from_vault<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: AccountInfo<'info>,
+    lock: &Lock,
+    amount: u64,
+) -> Result<()> {
+    transfer_signed_by_vault_pda(
+        token_program,
+        vault.to_account_info(),
+        vault.to_account_info(),
+        mint,
+        to,
+        lock,
+        amount,
+    )
+}
+
+/// Amount of `total` vested at `now` along `curve`, from `start` (nothing) to `end` (everything).
+/// Integer-only: every intermediate fits in a `u128` and never exceeds `total`.
+fn vested_amount(total: u64, start: i64, end: i64, now: i64, curve: u8) -> u64 {
+    if now >= end {
+        return total;
+    }
+    if now <= start {
+        return 0;
+    }
+
+    let total = total as u128;
+    let duration = (end - start) as u128;
+    let elapsed = (now - start) as u128;
+    let remaining = duration - elapsed;
+    let vested = match curve {
+        VESTING_LINEAR => total * elapsed / duration,
+        VESTING_FRONT_LOADED => total - total * remaining / duration * remaining / duration,
+        VESTING_BACK_LOADED => total * elapsed / duration * elapsed / duration,
+        _ => 0,
+    };
+    vested as u64
+}
+
+/// `amount` base units of a mint with `decimals` as a decimal string of whole tokens, with
+/// trailing fractional zeros trimmed: 1500000 with 6 decimals is `"1.5"`, 5 with 0 is `"5"`
+fn format_ui_amount(amount: u64, decimals: u8) -> String {
+    let decimals = decimals as usize;
+    let digits = format!("{:0>width$}", amount, width = decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+/// Burn a lock's receipt NFT from `receipt_token_account`, held by the signing `owner`.
+/// Fails with `ReceiptAccountsMissing` unless both receipt accounts are given.
+fn burn_receipt<'info>(
+    lock: &Lock,
+    receipt_mint: Option<&InterfaceAccount<'info, Mint>>,
+    receipt_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    owner: &Signer<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<()> {
+    let (Some(receipt_mint), Some(receipt_token_account)) = (receipt_mint, receipt_token_account)
+    else {
+        return err!(ErrorCode::ReceiptAccountsMissing);
+    };
+    require!(
+        lock.receipt_mint != Pubkey::default(),
+        ErrorCode::InvalidReceiptMint
+    );
+    require_keys_eq!(
+        receipt_mint.key(),
+        lock.receipt_mint,
+        ErrorCode::InvalidReceiptMint
+    );
+
+    token_interface::burn(
+        CpiContext::new(
+            token_program.to_account_info(),
+            Burn {
+                mint: receipt_mint.to_account_info(),
+                from: receipt_token_account.to_account_info(),
+                authority: owner.to_account_info(),
+            },
+        ),
+        receipt_token_account.amount,
+    )?;
+    msg!("Burned the receipt of lock #{}", lock.id);
+    Ok(())
+}
+
+/// Close an empty PDA vault, sending its rent to `destination`
+fn close_vault<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    destination: AccountInfo<'info>,
+    lock: &Lock,
+) -> Result<()> {
+    close_vault_signed_by_vault_pda(
+        token_program,
+        vault.to_account_info(),
+        vault.to_account_info(),
+        destination,
+        lock,
+    )
+}
+
+/// `close_vault` for a vault whose authority is the lock's vault PDA `vault_authority`, of
+/// any vault scheme (see `transfer_signed_by_vault_pda`)
+fn close_vault_signed_by_vault_pda<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    vault: AccountInfo<'info>,
+    vault_authority: AccountInfo<'info>,
+    destination: AccountInfo<'info>,
+    lock: &Lock,
+) -> Result<()> {
+    require_keys_eq!(
+        token_program.key(),
+        lock.token_program,
+        ErrorCode::WrongTokenProgram
+    );
+
+    let lock_id_bytes = lock.id.to_le_bytes();
+    let seeds = &[VAULT_SEED, lock_id_bytes.as_ref(), &[lock.vault_bump]];
+    token_interface::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: vault,
+            destination,
+            authority: vault_authority,
+        },
+        &[&seeds[..]],
+    ))
+}
+
+/// Transfer tokens out of `vault`, whose authority is the lock's vault PDA `vault_authority`:
+/// the vault itself (`VAULT_SCHEME_PDA`) or the owner of the vault ATA (`VAULT_SCHEME_ATA`)
+/// or salted vault (`VAULT_SCHEME_SALTED`)
+fn transfer_signed_by_vault_pda<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    vault: AccountInfo<'info>,
+    vault_authority: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: AccountInfo<'info>,
+    lock: &Lock,
+    amount: u64,
+) -> Result<()> {
+    require_keys_eq!(
+        token_program.key(),
+        lock.token_program,
+        ErrorCode::WrongTokenProgram
+    );
+
+    let lock_id_bytes = lock.id.to_le_bytes();
+    let seeds = &[VAULT_SEED, lock_id_bytes.as_ref(), &[lock.vault_bump]];
+    let signer_seeds = &[&seeds[..]];
+
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: vault,
+                mint: mint.to_account_info(),
+                to,
+                authority: vault_authority,
+            },
+            signer_seeds,
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+// ============================================================================
+// Lock creation
+// ============================================================================
+
+impl<'info> LockTokens<'info> {
+    /// Populate the new lock, move the tokens into its vault and collect the fee.
+    /// Shared by every instruction that creates a lock. Returns the new lock id.
+    fn create_lock(
+        &mut self,
+        vault_bump: u8,
+        amount: u64,
+        unlock_timestamp: i64,
+        options: LockOptions,
+    ) -> Result<u64> {
+        let vault = self.vault.to_account_info();
+        self.create_lock_into(vault, vault_bump, amount, unlock_timestamp, options, None)
+    }
+
+    /// `create_lock`, depositing the tokens into `vault` instead of the PDA vault
+    /// (the vault ATA of `lock_with_ata_vault`), with the fee waived by a valid `fee_voucher`
+    fn create_lock_into(
+        &mut self,
+        vault: AccountInfo<'info>,
+        vault_bump: u8,
+        amount: u64,
+        unlock_timestamp: i64,
+        options: LockOptions,
+        fee_voucher: Option<FeeVoucher>,
+    ) -> Result<u64> {
+        // First lock of a deployment that was never initialized
+        if self.global_state.authority == Pubkey::default() {
+            self.bootstrap_global_state()?;
+        }
+
+        require!(!self.global_state.paused, ErrorCode::Paused);
+        self.global_state.require_enabled(DISABLE_LOCK)?;
+        require_keys_eq!(
+            self.fee_recipient.key(),
+            self.global_state.fee_recipient,
+            ErrorCode::InvalidFeeRecipient
+        );
+        // The fee recipient can't lock (or be the source of a lock) and pay the fee to itself
+        require_keys_neq!(
+            self.owner.key(),
+            self.fee_recipient.key(),
+            ErrorCode::OwnerIsFeeRecipient
+        );
+        require_keys_neq!(
+            self.owner_token_account.owner,
+            self.fee_recipient.key(),
+            ErrorCode::OwnerIsFeeRecipient
+        );
+
+        require!(amount > 0, ErrorCode::AmountZero);
+        self.global_state.require_within_max_amount(amount)?;
+        // An escrow needs both parties, a plain lock neither
+        require!(
+            (options.arbiter == Pubkey::default()) == (options.counterparty == Pubkey::default()),
+            ErrorCode::InvalidEscrow
+        );
+        require!(
+            options.recovery_after == 0
+                || options.recovery_after >= unlock_timestamp.saturating_add(MIN_RECOVERY_DELAY),
+            ErrorCode::InvalidRecoveryAfter
+        );
+        require!(
+            options.vesting_curve <= VESTING_BACK_LOADED,
+            ErrorCode::InvalidVestingCurve
+        );
+        require!(
+            options.bounty_bps as u64 <= BPS_DENOMINATOR,
+            ErrorCode::InvalidBountyBps
+        );
+        // Only `unlock` burns, so no other flow may take the tokens out
+        require!(
+            !options.burn_on_unlock
+                || (options.arbiter == Pubkey::default()
+                    && options.recovery_after == 0
+                    && options.secret_hash == [0; 32]
+                    && options.vesting_curve == VESTING_NONE
+                    && !options.cancellable
+                    && !options.reducible
+                    && !options.allow_partial
+                    && options.bounty_bps == 0),
+            ErrorCode::BurnRequiresPlainLock
+        );
+        if options.deposit_lamports > 0 {
+            require!(options.claim_window > 0, ErrorCode::InvalidClaimWindow);
+            // Only `unlock` settles the deposit, so no other flow may end the lock
+            require!(
+                options.arbiter == Pubkey::default()
+                    && options.recovery_after == 0
+                    && options.secret_hash == [0; 32]
+                    && options.vesting_curve == VESTING_NONE
+                    && !options.cancellable
+                    && !options.reducible
+                    && !options.allow_partial
+                    && options.bounty_bps == 0,
+                ErrorCode::DepositRequiresPlainLock
+            );
+        }
+
+        let current_ts = current_timestamp(&self.global_state)?;
+        if options.unlock_slot == 0 {
+            require!(unlock_timestamp > current_ts, ErrorCode::TimestampInPast);
+            require_aligned(&self.global_state, unlock_timestamp)?;
+        } else {
+            // Exactly one unlock condition, and no option measured from the missing timestamp
+            require!(unlock_timestamp == 0, ErrorCode::AmbiguousUnlockCondition);
+            require!(
+                options.recovery_after == 0
+                    && options.vesting_curve == VESTING_NONE
+                    && options.deposit_lamports == 0,
+                ErrorCode::SlotLockRequiresPlainLock
+            );
+            require!(
+                options.unlock_slot > Clock::get()?.slot,
+                ErrorCode::UnlockSlotInPast
+            );
+        }
+        require_not_denied(&self.global_state, &self.denylist, &self.owner.key())?;
+        require_deposits_open(&self.mint_status, &self.mint_stats, amount)?;
+        require_mint_not_flagged(&self.mint_status)?;
+
+        // Fail with clear errors up front rather than deep inside the token and system CPIs.
+        // The Lock and vault rent was already paid when Anchor created them.
+        require!(
+            self.owner_token_account.amount >= amount,
+            ErrorCode::InsufficientTokenBalance
+        );
+        let fee = effective_lock_fee(
+            &self.global_state,
+            &self.fee_override,
+            Some(self.owner.key()),
+            fee_voucher,
+            self.instructions_sysvar
+                .as_ref()
+                .map(|sysvar| sysvar.as_ref()),
+            current_ts,
+        )?;
+        // Slot locks have no expiry bucket
+        let expiry_index_rent = if options.unlock_slot == 0 && self.expiry_index.data_is_empty() {
+            Rent::get()?
+                .minimum_balance(8 + ExpiryIndex::INIT_SPACE)
+                .saturating_sub(self.expiry_index.lamports())
+        } else {
+            0
+        };
+        let creation_index_rent = if self.creation_index.data_is_empty() {
+            Rent::get()?
+                .minimum_balance(8 + CreationIndex::INIT_SPACE)
+                .saturating_sub(self.creation_index.lamports())
+        } else {
+            0
+        };
+        require!(
+            self.owner.lamports()
+                >= fee
+                    .saturating_add(options.deposit_lamports)
+                    .saturating_add(expiry_index_rent)
+                    .saturating_add(creation_index_rent),
+            ErrorCode::InsufficientLamports
+        );
+
+        self.global_state.count_new_lock()?;
+
+        // Assign sequential ID to this lock (represents which lock this is: 1st, 2nd, 3rd, etc.)
+        let lock_id = self.global_state.lock_counter;
+
+        // Populate lock account
+        let lock = &mut self.lock;
+        lock.id = lock_id; // Store the sequential number in the lock account
+        lock.owner = self.owner.key();
+        lock.mint = self.mint.key();
+        lock.amount = amount;
+        lock.unlock_timestamp = unlock_timestamp;
+        lock.created_at = current_ts;
+        lock.vault_bump = vault_bump;
+        lock.is_unlocked = false;
+        lock.arbiter = options.arbiter;
+        lock.counterparty = options.counterparty;
+        lock.beneficiary = self.owner.key();
+        lock.pending_beneficiary = Pubkey::default();
+        lock.beneficiary_change_at = 0;
+        lock.reducible = options.reducible;
+        lock.token_program = self.token_program.key();
+        lock.is_token_2022 = self.token_program.key() == anchor_spl::token_2022::ID;
+        lock.recovery_after = options.recovery_after;
+        lock.allow_partial = options.allow_partial;
+        lock.unlocked_at = 0;
+        lock.secret_hash = options.secret_hash;
+        lock.vault_scheme = VAULT_SCHEME_PDA;
+        lock.vesting_curve = options.vesting_curve;
+        lock.vested_claimed = 0;
+        lock.cancellable = options.cancellable;
+        lock.vault_salt = [0; 32];
+        lock.deposit_lamports = options.deposit_lamports;
+        lock.claim_window = options.claim_window;
+        lock.bounty_bps = options.bounty_bps;
+        lock.burn_on_unlock = options.burn_on_unlock;
+        lock.unlock_slot = options.unlock_slot;
+        lock.receipt_mint = Pubkey::default();
+        lock.reference_mint = match &self.reference_mint {
+            Some(reference_mint) => {
+                msg!(
+                    "Lock #{} valued against mint {} ({} decimals)",
+                    lock_id,
+                    reference_mint.key(),
+                    reference_mint.decimals
+                );
+                reference_mint.key()
+            }
+            None => Pubkey::default(),
+        };
+
+        if options.unlock_slot == 0 {
+            add_to_expiry_index(
+                &self.expiry_index,
+                &self.owner,
+                &self.system_program,
+                unlock_timestamp,
+                lock_id,
+            )?;
+        }
+        add_to_creation_index(
+            &self.creation_index,
+            &self.owner,
+            &self.system_program,
+            current_ts,
+            lock_id,
+        )?;
+
+        // Get decimals for transfer
+        let decimals = self.mint.decimals;
+
+        // Transfer tokens from owner to vault
+        token_interface::transfer_checked(
+            CpiContext::new(
+                self.token_program.to_account_info(),
+                TransferChecked {
+                    from: self.owner_token_account.to_account_info(),
+                    mint: self.mint.to_account_info(),
+                    to: vault,
+                    authority: self.owner.to_account_info(),
+                },
+            ),
+            amount,
+            decimals,
+        )?;
+
+        // Transfer fee (0.03 SOL by default, or the mint's override) to fee recipient
+        pay_lock_fee(&self.fee_recipient, &self.owner, &self.system_program, fee)?;
+
+        // The deposit sits in the lock account on top of its rent until `unlock` settles it
+        if options.deposit_lamports > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    self.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: self.owner.to_account_info(),
+                        to: self.lock.to_account_info(),
+                    },
+                ),
+                options.deposit_lamports,
+            )?;
+        }
+
+        if options.mint_receipt {
+            self.lock.receipt_mint = self.mint_receipt(lock_id)?;
+        }
+
+        // Increment the global counter for the next lock
+        // This allows easy fetching of total lock count and recent locks
+        self.global_state.lock_counter = self
+            .global_state
+            .lock_counter
+            .checked_add(1)
+            .ok_or(ErrorCode::AmountOverflow)?;
+        if self.owner_mint_stats.data_is_empty() {
+            create_owner_mint_stats(
+                &self.owner_mint_stats,
+                &self.owner,
+                &self.mint.key(),
+                &self.system_program,
+            )?;
+        }
+        record_deposit(&self.mint_stats, amount, true)?;
+        record_owner_deposit(&self.owner_mint_stats, amount)?;
+        record_lock_created(&self.program_stats, fee)?;
+
+        emit!(AmountEvent {
+            lock_id,
+            mint: self.lock.mint,
+            amount,
+        });
+        msg!(
+            "Locked {} tokens of mint {} until timestamp {} (lock #{})",
+            amount,
+            self.lock.mint,
+            unlock_timestamp,
+            lock_id
+        );
+
+        Ok(lock_id)
+    }
+
+    /// Create the receipt NFT of lock `lock_id` and mint it to the owner's associated token
+    /// account. The mint is the `[RECEIPT_SEED, lock_id]` PDA with 0 decimals; its mint
+    /// authority is dropped right after minting, so exactly one receipt ever exists.
+    fn mint_receipt(&self, lock_id: u64) -> Result<Pubkey> {
+        let (Some(receipt_mint), Some(receipt_token_account), Some(associated_token_program)) = (
+            &self.receipt_mint,
+            &self.receipt_token_account,
+            &self.associated_token_program,
+        ) else {
+            return err!(ErrorCode::ReceiptAccountsMissing);
+        };
+
+        let lock_id_bytes = lock_id.to_le_bytes();
+        let (expected_mint, bump) =
+            Pubkey::find_program_address(&[RECEIPT_SEED, &lock_id_bytes], &crate::ID);
+        require_keys_eq!(
+            receipt_mint.key(),
+            expected_mint,
+            ErrorCode::InvalidReceiptMint
+        );
+        let receipt_seeds = &[RECEIPT_SEED, lock_id_bytes.as_ref(), &[bump]];
+
+        create_pda_account_owned_by(
+            &receipt_mint.to_account_info(),
+            &self.owner.to_account_info(),
+            &self.system_program.to_account_info(),
+            receipt_seeds,
+            anchor_spl::token::Mint::LEN,
+            &self.token_program.key(),
+        )?;
+        token_interface::initialize_mint2(
+            CpiContext::new(
+                self.token_program.to_account_info(),
+                token_interface::InitializeMint2 {
+                    mint: receipt_mint.to_account_info(),
+                },
+            ),
+            0,
+            &expected_mint,
+            None,
+        )?;
+        anchor_spl::associated_token::create(CpiContext::new(
+            associated_token_program.to_account_info(),
+            anchor_spl::associated_token::Create {
+                payer: self.owner.to_account_info(),
+                associated_token: receipt_token_account.to_account_info(),
+                authority: self.owner.to_account_info(),
+                mint: receipt_mint.to_account_info(),
+                system_program: self.system_program.to_account_info(),
+                token_program: self.token_program.to_account_info(),
+            },
+        ))?;
+        token_interface::mint_to(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                token_interface::MintTo {
+                    mint: receipt_mint.to_account_info(),
+                    to: receipt_token_account.to_account_info(),
+                    authority: receipt_mint.to_account_info(),
+                },
+                &[&receipt_seeds[..]],
+            ),
+            1,
+        )?;
+        token_interface::set_authority(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                token_interface::SetAuthority {
+                    current_authority: receipt_mint.to_account_info(),
+                    account_or_mint: receipt_mint.to_account_info(),
+                },
+                &[&receipt_seeds[..]],
+            ),
+            token_interface::spl_token_2022::instruction::AuthorityType::MintTokens,
+            None,
+        )?;
+
+        msg!("Minted receipt {} of lock #{}", expected_mint, lock_id);
+        Ok(expected_mint)
+    }
+
+    /// Initialize the global state created by this lock, with the owner as authority.
+    /// Only the program's upgrade authority (the deployer) may do this: anyone else could
+    /// front-run the deployer's first transaction and claim the authority. Other callers get
+    /// `NotInitialized` and the global state creation is rolled back.
+    fn bootstrap_global_state(&mut self) -> Result<()> {
+        let program_data = self
+            .program_data
+            .as_ref()
+            .ok_or(ErrorCode::NotInitialized)?;
+        let (expected_program_data, _) = Pubkey::find_program_address(
+            &[crate::ID.as_ref()],
+            &anchor_lang::solana_program::bpf_loader_upgradeable::ID,
+        );
+        require_keys_eq!(
+            program_data.key(),
+            expected_program_data,
+            ErrorCode::NotInitialized
+        );
+        require!(
+            program_data.upgrade_authority_address == Some(self.owner.key()),
+            ErrorCode::NotInitialized
+        );
+
+        let (_, bump) = Pubkey::find_program_address(&[GLOBAL_STATE_SEED], &crate::ID);
+        self.global_state.init(self.owner.key(), bump);
+        msg!("Lockfun initialized by the first lock");
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Partial unlock
+// ============================================================================
+
+impl<'info> UnlockTokens<'info> {
+    /// Send `amount` of a matured lock to its beneficiary and deduct it from the lock.
+    /// Shared by the partial unlock instructions.
+    fn release_partial(&mut self, amount: u64) -> Result<()> {
+        require!(self.lock.allow_partial, ErrorCode::PartialNotAllowed);
+
+        let current_ts = current_timestamp(&self.global_state)?;
+        require!(
+            self.global_state.is_lock_matured(&self.lock, current_ts)?,
+            ErrorCode::TooEarly
+        );
+
+        self.release(amount)
+    }
+
+    /// Token account receiving a withdrawal: `recipient_token_account` when given,
+    /// `owner_token_account` otherwise. Overrides are only accepted while the owner is the
+    /// beneficiary, so they can't bypass the beneficiary change timelock.
+    fn destination(&self) -> Result<AccountInfo<'info>> {
+        let destination = match &self.recipient_token_account {
+            Some(recipient) => {
+                require_keys_eq!(
+                    self.lock.beneficiary,
+                    self.lock.owner,
+                    ErrorCode::RecipientOverrideNotAllowed
+                );
+                recipient
+            }
+            None => &self.owner_token_account,
+        };
+
+        // Prevent duplicate mutable accounts attack
+        require!(
+            self.vault.key() != destination.key(),
+            ErrorCode::DuplicateAccounts
+        );
+
+        Ok(destination.to_account_info())
+    }
+
+    /// Unlock the whole lock at `now`: release it to the destination, or burn it for
+    /// burn-on-unlock locks. Shared by `unlock` and `try_unlock`.
+    fn unlock_whole(&mut self, now: i64) -> Result<u64> {
+        if self.receipt_token_account.is_some() {
+            self.burn_receipt()?;
+        }
+        if !self.lock.burn_on_unlock {
+            let destination = self.destination()?;
+            return process_unlock(
+                &mut self.lock,
+                &self.vault,
+                &self.mint,
+                destination,
+                &self.token_program,
+                &self.global_state,
+                &self.denylist,
+                now,
+            );
+        }
+
+        require_keys_eq!(self.vault.mint, self.lock.mint, ErrorCode::InvalidMint);
+        let amount = begin_unlock(
+            &mut self.lock,
+            &self.global_state,
+            &self.denylist,
+            now,
+            true,
+        )?;
+
+        // The vault PDA owns the vault token account, which gives it burn rights under both
+        // SPL Token and Token-2022
+        require_keys_eq!(
+            self.token_program.key(),
+            self.lock.token_program,
+            ErrorCode::WrongTokenProgram
+        );
+        let lock_id_bytes = self.lock.id.to_le_bytes();
+        let vault_seeds = &[VAULT_SEED, lock_id_bytes.as_ref(), &[self.lock.vault_bump]];
+        token_interface::burn(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                Burn {
+                    mint: self.mint.to_account_info(),
+                    from: self.vault.to_account_info(),
+                    authority: self.vault.to_account_info(),
+                },
+                &[&vault_seeds[..]],
+            ),
+            amount,
+        )?;
+
+        emit!(Burned {
+            lock_id: self.lock.id,
+            mint: self.lock.mint,
+            amount,
+        });
+        msg!("Burned {} tokens of lock #{}", amount, self.lock.id);
+
+        Ok(amount)
+    }
+
+    /// Burn the lock's receipt NFT from `receipt_token_account`, held by the owner
+    fn burn_receipt(&self) -> Result<()> {
+        burn_receipt(
+            &self.lock,
+            self.receipt_mint.as_ref(),
+            self.receipt_token_account.as_ref(),
+            &self.owner,
+            &self.token_program,
+        )
+    }
+
+    /// Pay out the lock's deposit on a whole unlock at `now`: back to the owner within the claim
+    /// window after maturity, to the fee recipient (which must then be passed) past it
+    fn settle_deposit(&mut self, now: i64) -> Result<()> {
+        let deposit = self.lock.deposit_lamports;
+        if deposit == 0 {
+            return Ok(());
+        }
+
+        let refunded = now
+            <= self
+                .lock
+                .unlock_timestamp
+                .saturating_add(self.lock.claim_window);
+        let destination = if refunded {
+            self.owner.to_account_info()
+        } else {
+            self.fee_recipient
+                .as_ref()
+                .ok_or(ErrorCode::FeeRecipientMissing)?
+                .to_account_info()
+        };
+
+        self.lock.deposit_lamports = 0;
+        let lock_info = self.lock.to_account_info();
+        **lock_info.try_borrow_mut_lamports()? = lock_info
+            .lamports()
+            .checked_sub(deposit)
+            .ok_or(ErrorCode::AmountOverflow)?;
+        **destination.try_borrow_mut_lamports()? = destination
+            .lamports()
+            .checked_add(deposit)
+            .ok_or(ErrorCode::AmountOverflow)?;
+
+        if refunded {
+            msg!(
+                "Refunded the {} lamport deposit of lock #{}",
+                deposit,
+                self.lock.id
+            );
+        } else {
+            record_fee(&self.program_stats, deposit)?;
+            msg!(
+                "Forfeited the {} lamport deposit of lock #{}",
+                deposit,
+                self.lock.id
+            );
+        }
+        Ok(())
+    }
+
+    /// Send `amount` of the lock to its beneficiary and deduct it from the lock, matured or not.
+    /// Callers enforce when a withdrawal is allowed.
+    fn release(&mut self, amount: u64) -> Result<()> {
+        // Prevent duplicate mutable accounts attack
+        require!(
+            self.vault.key() != self.owner_token_account.key(),
+            ErrorCode::DuplicateAccounts
+        );
+
+        require_keys_eq!(self.vault.mint, self.lock.mint, ErrorCode::InvalidMint);
+
+        require!(amount > 0, ErrorCode::AmountZero);
+        require!(!self.lock.is_unlocked, ErrorCode::AlreadyUnlocked);
+        require!(
+            amount <= self.lock.amount,
+            ErrorCode::InsufficientLockedAmount
+        );
+        self.global_state.require_enabled(DISABLE_UNLOCK)?;
+        require_not_denied(&self.global_state, &self.denylist, &self.lock.owner)?;
+        let destination = self.destination()?;
+        let current_ts = current_timestamp(&self.global_state)?;
+
+        // Deduct before moving tokens (checks-effects-interactions)
+        let lock = &mut self.lock;
+        lock.amount = lock
+            .amount
+            .checked_sub(amount)
+            .ok_or(ErrorCode::AmountOverflow)?;
+        if lock.amount == 0 {
+            require!(lock.deposit_lamports == 0, ErrorCode::DepositNotSettled);
+            lock.is_unlocked = true;
+            lock.unlocked_at = current_ts;
+        }
+
+        transfer_from_vault(
+            &self.token_program,
+            &self.vault,
+            &self.mint,
+            destination,
+            &self.lock,
+            amount,
+        )?;
+        record_withdrawal(&self.mint_stats, amount, self.lock.is_unlocked)?;
+        record_owner_withdrawal(&self.owner_mint_stats, amount)?;
+        if self.lock.is_unlocked {
+            record_lock_closed(&self.program_stats)?;
+            if self.receipt_token_account.is_some() {
+                self.burn_receipt()?;
+            }
+        }
+
+        msg!(
+            "Unlocked {} tokens from lock #{} ({} remaining)",
+            amount,
+            self.lock.id,
+            self.lock.amount
+        );
+
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Accounts
+// ============================================================================
+
+#[account]
+#[derive(InitSpace)]
+pub struct GlobalState {
+    /// Authority (admin)
+    pub authority: Pubkey,
+    /// Counter for unique lock IDs
+    /// This represents the total number of locks created.
+    /// When a new lock is created, this counter is incremented and
+    /// the new lock's ID is set to the current counter value.
+    /// To fetch the latest locks, query locks with IDs from (lock_counter - N) to (lock_counter - 1).
+    pub lock_counter: u64,
+    /// Timestamp overriding the on-chain clock when non-zero.
+    /// Only read by builds with the `test-clock` feature; always 0 on deployed programs.
+    pub mock_timestamp: i64,
+    /// Whether `lock`, `unlock` and `claim_milestone` reject owners on the denylist
+    pub denylist_enabled: bool,
+    /// Seconds between requesting and confirming a beneficiary change
+    pub beneficiary_change_delay: i64,
+    /// Maximum number of locks created per epoch (0: unlimited)
+    pub max_locks_per_epoch: u64,
+    /// Epoch `locks_this_epoch` counts for
+    pub rate_limit_epoch: u64,
+    /// Locks created during `rate_limit_epoch`
+    pub locks_this_epoch: u64,
+    /// Whether `extend` and `extend_with_top_up` are allowed
+    pub allow_extend: bool,
+    /// Whether unlock timestamps must be a multiple of `TIMESTAMP_ALIGNMENT`
+    pub require_aligned_timestamps: bool,
+    /// Lock fee in lamports for mints without an override
+    pub fee_amount: u64,
+    /// Account receiving lock fees
+    pub fee_recipient: Pubkey,
+    /// Whether deposits (lock creation and top-ups) are stopped
+    pub paused: bool,
+    /// Whether a lock matures only after its unlock timestamp (`now > unlock_timestamp`)
+    /// rather than at it (`now >= unlock_timestamp`)
+    pub strict_boundary: bool,
+    /// Share of a lock sent to the fee recipient when it is cancelled, in basis points
+    pub cancel_fee_bps: u16,
+    /// Instruction families turned off by the authority (`DISABLE_*` bits)
+    pub disabled_instructions: u8,
+    /// Program `unlock_and_stake` restakes matured tokens into (`Pubkey::default()`: none)
+    pub staking_program: Pubkey,
+    /// Seconds before their unlock timestamp at which locks already count as matured,
+    /// absorbing validator clock drift (at most `MAX_CLOCK_TOLERANCE`)
+    pub clock_tolerance_seconds: u32,
+    /// Most locks a single `batch_unlock` may unlock (default `DEFAULT_MAX_BATCH_SIZE`)
+    pub max_batch_size: u8,
+    /// Most tokens a single instruction may lock or top up, 0 for no cap
+    pub max_amount_per_lock: u64,
+    /// Bump seed of the GlobalState PDA, so contexts skip the bump search
+    pub bump: u8,
+    /// Ed25519 key signing the `FeeVoucher`s that waive the lock fee, default for none
+    pub voucher_signer: Pubkey,
+}
+
+impl GlobalState {
+    /// Default settings of a new deployment, administered by `authority`
+    fn init(&mut self, authority: Pubkey, bump: u8) {
+        self.authority = authority;
+        self.lock_counter = 0;
+        self.mock_timestamp = 0;
+        self.denylist_enabled = false;
+        self.beneficiary_change_delay = DEFAULT_BENEFICIARY_CHANGE_DELAY;
+        self.max_locks_per_epoch = 0;
+        self.rate_limit_epoch = 0;
+        self.locks_this_epoch = 0;
+        self.allow_extend = true;
+        self.require_aligned_timestamps = false;
+        self.fee_amount = FEE_AMOUNT;
+        self.fee_recipient = FEE_RECIPIENT;
+        self.paused = false;
+        self.strict_boundary = false;
+        self.cancel_fee_bps = 0;
+        self.disabled_instructions = 0;
+        self.staking_program = Pubkey::default();
+        self.clock_tolerance_seconds = 0;
+        self.max_batch_size = DEFAULT_MAX_BATCH_SIZE;
+        self.max_amount_per_lock = 0;
+        self.bump = bump;
+        self.voucher_signer = Pubkey::default();
+    }
+
+    /// Fail with `AmountTooLarge` if `amount` is above `max_amount_per_lock`, when capped
+    fn require_within_max_amount(&self, amount: u64) -> Result<()> {
+        require!(
+            self.max_amount_per_lock == 0 || amount <= self.max_amount_per_lock,
+            ErrorCode::AmountTooLarge
+        );
+        Ok(())
+    }
+
+    /// Fail with `InstructionDisabled` if the authority turned off `instruction` (a `DISABLE_*` bit)
+    fn require_enabled(&self, instruction: u8) -> Result<()> {
+        require!(
+            self.disabled_instructions & instruction == 0,
+            ErrorCode::InstructionDisabled
+        );
+        Ok(())
+    }
+
+    /// Count a new lock against the current epoch's rate limit, failing with `RateLimited` once
+    /// `max_locks_per_epoch` is reached
+    fn count_new_lock(&mut self) -> Result<()> {
+        let epoch = Clock::get()?.epoch;
+        if self.rate_limit_epoch != epoch {
+            self.rate_limit_epoch = epoch;
+            self.locks_this_epoch = 0;
+        }
+        require!(
+            self.max_locks_per_epoch == 0 || self.locks_this_epoch < self.max_locks_per_epoch,
+            ErrorCode::RateLimited
+        );
+        self.locks_this_epoch += 1;
+        Ok(())
+    }
+
+    /// Whether a lock maturing at `unlock_timestamp` can be unlocked at `now`
+    fn is_matured(&self, unlock_timestamp: i64, now: i64) -> bool {
+        let now = now.saturating_add(self.clock_tolerance_seconds as i64);
+        if self.strict_boundary {
+            now > unlock_timestamp
+        } else {
+            now >= unlock_timestamp
+        }
+    }
+
+    /// Whether `lock` can be unlocked at `now`: from its unlock slot for a slot lock, otherwise
+    /// per `is_matured`. The clock tolerance and strict boundary only apply to timestamps.
+    fn is_lock_matured(&self, lock: &Lock, now: i64) -> Result<bool> {
+        if lock.unlock_slot != 0 {
+            return Ok(Clock::get()?.slot >= lock.unlock_slot);
+        }
+        Ok(self.is_matured(lock.unlock_timestamp, now))
+    }
+}
+
+/// A token lock. Seeds: `[LOCK_SEED, id]`.
+/// The layout up to `is_unlocked` is a committed ABI for memcmp filters (see `LOCK_OWNER_OFFSET`,
+/// `LOCK_MINT_OFFSET` and `LOCK_IS_UNLOCKED_OFFSET`): new fields are appended at the end only.
+#[account]
+#[derive(InitSpace)]
+pub struct Lock {
+    /// Unique lock ID (for PDA derivation)
+    /// This is a sequential number representing which lock this is (1st, 2nd, 3rd, etc.)
+    /// The ID is assigned from GlobalState.lock_counter when the lock is created.
+    /// Offset: 8 (discriminator)
+    pub id: u64,
+    /// Owner who locked the tokens
+    /// Offset: 8 + 8 = 16
+    pub owner: Pubkey,
+    /// Token mint address
+    /// Offset: 8 + 8 + 32 = 48
+    pub mint: Pubkey,
+    /// Amount of tokens locked
+    /// Offset: 8 + 8 + 32 + 32 = 80
+    pub amount: u64,
+    /// Unix timestamp when tokens can be unlocked
+    /// Offset: 8 + 8 + 32 + 32 + 8 = 88
+    pub unlock_timestamp: i64,
+    /// Unix timestamp when lock was created (for sorting/pagination)
+    /// Offset: 8 + 8 + 32 + 32 + 8 + 8 = 96
+    pub created_at: i64,
+    /// Bump seed for the vault PDA
+    /// Offset: 8 + 8 + 32 + 32 + 8 + 8 + 8 = 104
+    pub vault_bump: u8,
+    /// Whether tokens have been unlocked
+    /// Offset: 8 + 8 + 32 + 32 + 8 + 8 + 8 + 1 = 105
+    pub is_unlocked: bool,
+    /// Escrow arbiter allowed to release to `counterparty` before maturity (default: none)
+    /// Offset: 105 + 1 = 106
+    pub arbiter: Pubkey,
+    /// Recipient of an arbiter release (default: none)
+    /// Offset: 106 + 32 = 138
+    pub counterparty: Pubkey,
+    /// Account whose token accounts receive unlocked tokens (the owner unless changed)
+    /// Offset: 138 + 32 = 170
+    pub beneficiary: Pubkey,
+    /// Beneficiary requested by `request_beneficiary_change` (default: none pending)
+    /// Offset: 170 + 32 = 202
+    pub pending_beneficiary: Pubkey,
+    /// Unix timestamp from which the pending beneficiary can be confirmed
+    /// Offset: 202 + 32 = 234
+    pub beneficiary_change_at: i64,
+    /// Whether the owner may `reduce` the lock before maturity
+    /// Offset: 234 + 8 = 242
+    pub reducible: bool,
+    /// Token program (SPL Token or Token-2022) the lock was created with
+    /// Offset: 242 + 1 = 243
+    pub token_program: Pubkey,
+    /// Unix timestamp from which the authority may `recover` the lock if still unclaimed (0: never)
+    /// Offset: 243 + 32 = 275
+    pub recovery_after: i64,
+    /// Whether `partial_unlock` may withdraw part of the lock; otherwise only a full unlock
+    /// Offset: 275 + 8 = 283
+    pub allow_partial: bool,
+    /// Unix timestamp when the lock became fully unlocked (0 while still locked)
+    /// Offset: 283 + 1 = 284
+    pub unlocked_at: i64,
+    /// `sha256(preimage || claimant)` accepted by `unlock_with_secret` (all zeroes: none)
+    /// Offset: 284 + 8 = 292
+    pub secret_hash: [u8; 32],
+    /// How the vault is derived: `VAULT_SCHEME_PDA`, `VAULT_SCHEME_ATA` or `VAULT_SCHEME_SALTED`
+    /// Offset: 292 + 32 = 324
+    pub vault_scheme: u8,
+    /// Curve along which `claim_vested` releases the lock (`VESTING_NONE`: no vesting)
+    /// Offset: 324 + 1 = 325
+    pub vesting_curve: u8,
+    /// Amount already released by `claim_vested`
+    /// Offset: 325 + 1 = 326
+    pub vested_claimed: u64,
+    /// Whether the owner can `cancel` the lock at any time, for a fee
+    /// Offset: 326 + 8 = 334
+    pub cancellable: bool,
+    /// Mint clients group and value the lock by, `Pubkey::default()` for none.
+    /// Metadata only: nothing is converted on-chain.
+    /// Offset: 334 + 1 = 335
+    pub reference_mint: Pubkey,
+    /// Owner-chosen salt of a `VAULT_SCHEME_SALTED` vault, zeroes for other schemes
+    /// Offset: 335 + 32 = 367
+    pub vault_salt: [u8; 32],
+    /// Whether `token_program` is Token-2022 rather than SPL Token, for clients picking the
+    /// token program of an unlock
+    /// Offset: 367 + 32 = 399
+    pub is_token_2022: bool,
+    /// Lamports held by the lock account on top of its rent, refunded to the owner by a timely
+    /// `unlock` and forfeited to the fee recipient after `claim_window`, 0 once settled
+    /// Offset: 399 + 1 = 400
+    pub deposit_lamports: u64,
+    /// Seconds after `unlock_timestamp` during which `unlock` refunds the deposit
+    /// Offset: 400 + 8 = 408
+    pub claim_window: i64,
+    /// Share of the lock, in basis points, paid to whoever runs `keeper_unlock` (0: no bounty)
+    /// Offset: 408 + 8 = 416
+    pub bounty_bps: u16,
+    /// Whether `unlock` burns the tokens instead of releasing them
+    /// Offset: 416 + 2 = 418
+    pub burn_on_unlock: bool,
+    /// Mint of the receipt NFT given to the owner at creation, `Pubkey::default()` for none.
+    /// The receipt isn't authoritative: `is_unlocked` is, and a receipt may outlive its lock.
+    /// Offset: 418 + 1 = 419
+    pub receipt_mint: Pubkey,
+    /// Slot from which a slot lock can be unlocked, 0 for a timestamp lock.
+    /// A slot lock has no `unlock_timestamp` (0).
+    /// Offset: 419 + 32 = 451
+    pub unlock_slot: u64,
+}
+
+/// GlobalState as first deployed, read by `migrate_global_state`
+#[derive(AnchorDeserialize)]
+struct LegacyGlobalState {
+    authority: Pubkey,
+    lock_counter: u64,
+}
+
+/// Lock as first deployed, read by `migrate_lock`
+#[derive(AnchorDeserialize)]
+struct LegacyLock {
+    id: u64,
+    owner: Pubkey,
+    mint: Pubkey,
+    amount: u64,
+    unlock_timestamp: i64,
+    created_at: i64,
+    vault_bump: u8,
+    is_unlocked: bool,
+}
+
+impl Lock {
+    /// Whether `candidate` may sign an instruction releasing this lock, timing rules aside:
+    /// - the owner (`unlock`, `partial_unlock`, `claim_milestone`, ...)
+    /// - `authority` (`assisted_unlock`) and the listed `heirs` (`heir_unlock`), except on
+    ///   deposit and burn-on-unlock locks, which only `unlock` releases
+    /// - the arbiter of an escrow (`arbiter_release`)
+    /// - anyone on a bounty lock (`keeper_unlock`)
+    /// - the claimant `secret_hash` is bound to, given the `preimage` (`unlock_with_secret`)
+    pub fn is_authorized_unlocker(
+        &self,
+        candidate: &Pubkey,
+        authority: &Pubkey,
+        heirs: Option<&Heirs>,
+        preimage: Option<&[u8]>,
+    ) -> bool {
+        let released_by_others = self.deposit_lamports == 0 && !self.burn_on_unlock;
+        *candidate == self.owner
+            || (released_by_others && candidate == authority)
+            || (released_by_others
+                && heirs
+                    .is_some_and(|heirs| heirs.heirs.iter().any(|heir| heir.heir == *candidate)))
+            || (self.arbiter != Pubkey::default() && *candidate == self.arbiter)
+            || self.bounty_bps > 0
+            || preimage.is_some_and(|preimage| self.is_secret_claimant(preimage, candidate))
+    }
+
+    /// Whether `preimage` releases this lock to `claimant` with `unlock_with_secret`:
+    /// `sha256(preimage || claimant) == secret_hash`, never for locks without a secret
+    pub fn is_secret_claimant(&self, preimage: &[u8], claimant: &Pubkey) -> bool {
+        self.secret_hash != [0; 32]
+            && hashv(&[preimage, claimant.as_ref()]).to_bytes() == self.secret_hash
+    }
+
+    /// Address of the vault holding this lock's tokens for its `vault_scheme`, and of the
+    /// vault PDA `[VAULT_SEED, id]` that is its authority
+    pub fn vault_address(&self) -> (Pubkey, Pubkey) {
+        let lock_id_bytes = self.id.to_le_bytes();
+        let (vault_authority, _) = Pubkey::find_program_address(&[VAULT_SEED, &lock_id_bytes], &ID);
+        let vault = match self.vault_scheme {
+            VAULT_SCHEME_ATA => {
+                anchor_spl::associated_token::get_associated_token_address_with_program_id(
+                    &vault_authority,
+                    &self.mint,
+                    &self.token_program,
+                )
+            }
+            VAULT_SCHEME_SALTED => {
+                Pubkey::find_program_address(&[VAULT_SEED, &lock_id_bytes, &self.vault_salt], &ID).0
+            }
+            _ => vault_authority,
+        };
+        (vault, vault_authority)
+    }
+}
+
+/// Lock ids maturing during one day, so clients can read a single account per day
+/// instead of scanning every lock. Seeds: `[EXPIRY_SEED, day_bucket(unlock_timestamp)]`.
+///
+/// Ids are appended by `lock` and moved by `extend`; unlocked locks are not removed.
+/// Once a bucket holds `MAX_EXPIRY_INDEX_ENTRIES` ids, further locks maturing that day are
+/// only counted in `overflow_count`: when it is non-zero, clients must fall back to a
+/// `getProgramAccounts` scan filtered on `unlock_timestamp` to see the full day.
+#[account]
+#[derive(InitSpace)]
+pub struct ExpiryIndex {
+    /// Day bucket (`unlock_timestamp / 86400`)
+    pub bucket: i64,
+    /// Ids of locks maturing during this day, in insertion order
+    #[max_len(MAX_EXPIRY_INDEX_ENTRIES)]
+    pub lock_ids: Vec<u64>,
+    /// Locks maturing during this day that didn't fit in `lock_ids`
+    pub overflow_count: u32,
+    /// Bump seed for the index PDA
+    pub bump: u8,
+}
+
+/// Lock ids created during one day, for "new locks today" queries without scanning every lock.
+/// Seeds: `[CREATION_SEED, day_bucket(created_at)]`.
+///
+/// Ids are appended by `lock` and never move: `extend` only affects the expiry index.
+/// Once a bucket holds `MAX_CREATION_INDEX_ENTRIES` ids, further locks created that day are
+/// only counted in `overflow_count`: when it is non-zero, clients must fall back to a
+/// `getProgramAccounts` scan to see the full day.
+#[account]
+#[derive(InitSpace)]
+pub struct CreationIndex {
+    /// Day bucket (`created_at / 86400`)
+    pub bucket: i64,
+    /// Ids of locks created during this day, in creation order
+    #[max_len(MAX_CREATION_INDEX_ENTRIES)]
+    pub lock_ids: Vec<u64>,
+    /// Locks created during this day that didn't fit in `lock_ids`
+    pub overflow_count: u32,
+    /// Bump seed for the index PDA
+    pub bump: u8,
+}
+
+/// Owners blocked from creating or withdrawing locks while `GlobalState.denylist_enabled` is set.
+/// Seeds: `[DENYLIST_SEED]`. Bounded to `MAX_DENYLIST_ENTRIES` so the check stays cheap.
+///
+/// Matured funds of a denied owner stay in their vault until the owner is removed from the list.
+#[account]
+#[derive(InitSpace)]
+pub struct Denylist {
+    /// Denied owners, in insertion order
+    #[max_len(MAX_DENYLIST_ENTRIES)]
+    pub owners: Vec<Pubkey>,
+    /// Bump seed for the denylist PDA
+    pub bump: u8,
+}
+
+/// Lock fee charged for one mint instead of `GlobalState.fee_amount`, managed by the authority.
+/// Seeds: `[FEE_OVERRIDE_SEED, mint]`.
+#[account]
+#[derive(InitSpace)]
+pub struct MintFeeOverride {
+    /// Mint this fee applies to
+    pub mint: Pubkey,
+    /// Fee in lamports charged per lock of this mint (0: fee-free)
+    pub fee_amount: u64,
+    /// Bump seed for the override PDA
+    pub bump: u8,
+}
+
+/// Deposit controls of one mint, managed by the authority. Seeds: `[MINT_STATUS_SEED, mint]`.
+/// Frozen or capped mints reject new locks and top-ups, flagged mints new locks; withdrawals
+/// from existing locks stay open.
+/// Mints without a status accept deposits.
+#[account]
+#[derive(InitSpace)]
+pub struct MintStatus {
+    /// Mint this status applies to
+    pub mint: Pubkey,
+    /// Whether new locks and top-ups of this mint are rejected
+    pub deposits_frozen: bool,
+    /// Bump seed for the status PDA
+    pub bump: u8,
+    /// Most tokens of this mint all locks may hold together, 0 for no cap
+    pub max_total_locked: u64,
+    /// Whether the mint is flagged as suspicious (e.g. rugged), rejecting new locks of it
+    pub flagged: bool,
+}
+
+/// Tokens of one mint an owner currently has locked. Seeds: `[OWNER_MINT_STATS_SEED, owner, mint]`.
+/// Created by the owner's first lock of the mint; earlier locks aren't counted.
+#[account]
+#[derive(InitSpace)]
+pub struct OwnerMintStats {
+    /// Lock owner
+    pub owner: Pubkey,
+    /// Token mint
+    pub mint: Pubkey,
+    /// Tokens currently held by the owner's locks of the mint
+    pub total_locked: u64,
+    /// Bump seed for the stats PDA
+    pub bump: u8,
+}
+
+/// Protocol-wide totals, the single account to read for a dashboard. Seeds: `[PROGRAM_STATS_SEED]`.
+/// Created by `initialize` or `initialize_and_lock`; deployments bootstrapped by a plain lock
+/// have none.
+#[account]
+#[derive(InitSpace)]
+pub struct ProgramStats {
+    /// Locks ever created
+    pub total_locks_ever: u64,
+    /// Locks not fully withdrawn yet
+    pub active_locks: u64,
+    /// Locks fully withdrawn, whatever the flow (unlock, cancel, recovery...)
+    pub total_unlocks: u64,
+    /// Lamports collected by the fee recipient: lock fees and forfeited deposits
+    pub total_fees: u64,
+    /// Bump seed for the stats PDA
+    pub bump: u8,
+}
+
+/// Registered 1:1 replacement of a mint, for `migrate_mint`. Seeds: `[MIGRATION_SEED, old_mint]`.
+/// Also the authority of the reserve token accounts holding the replacement tokens.
+#[account]
+#[derive(InitSpace)]
+pub struct MintMigration {
+    /// Mint being replaced
+    pub old_mint: Pubkey,
+    /// Replacement mint, with the same decimals
+    pub new_mint: Pubkey,
+    /// Bump seed for the migration PDA
+    pub bump: u8,
+}
+
+/// Total value locked of one mint. Seeds: `[MINT_STATS_SEED, mint]`.
+/// Created by `backfill_mint_stats`, then kept up to date by every deposit and withdrawal.
+#[account]
+#[derive(InitSpace)]
+pub struct MintStats {
+    /// Mint these stats are for
+    pub mint: Pubkey,
+    /// Tokens currently held by the mint's locks
+    pub total_locked: u64,
+    /// Locks of the mint that aren't unlocked yet
+    pub active_locks: u64,
+    /// Bump seed for the stats PDA
+    pub bump: u8,
+}
+
+/// Lock settings defined by the authority, used by `lock_from_template`.
+/// Seeds: `[TEMPLATE_SEED, template_id]`.
+#[account]
+#[derive(InitSpace)]
+pub struct LockTemplate {
+    /// Template id chosen by the authority
+    pub template_id: u64,
+    /// Seconds between lock creation and unlock
+    pub duration: i64,
+    /// Whether locks created from this template are reducible
+    pub reducible: bool,
+    /// Bump seed for the template PDA
+    pub bump: u8,
+}
+
+/// Marks a client idempotency key as used by `lock_idempotent`.
+/// Seeds: `[IDEMPOTENCY_SEED, owner, idempotency_key]`.
+///
+/// Clients generate a random key per intended lock and reuse it on every retry of that lock.
+/// A retry after the first call landed fails (stale lock id: seeds mismatch; refreshed lock id:
+/// record already in use) instead of creating a second lock. When unsure whether a call landed,
+/// fetch this record: if it exists, `lock_id` is the lock it created.
+#[account]
+#[derive(InitSpace)]
+pub struct IdempotencyRecord {
+    /// Owner of the lock
+    pub owner: Pubkey,
+    /// Client-supplied key
+    pub idempotency_key: [u8; 16],
+    /// Lock created with this key
+    pub lock_id: u64,
+    /// Bump seed for the record PDA
+    pub bump: u8,
+}
+
+/// Off-chain promotion voucher waiving the lock fee of `owner`'s `lock`s until `expiry`.
+/// `signature` is the voucher signer's Ed25519 signature of `FeeVoucher::message`, which the
+/// transaction must also verify with an Ed25519 program instruction right before `lock`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct FeeVoucher {
+    /// Timestamp from which the voucher is rejected
+    pub expiry: i64,
+    pub signature: [u8; 64],
+}
+
+impl FeeVoucher {
+    /// Signed message: the owner's key followed by the little-endian expiry
+    fn message(&self, owner: Pubkey) -> [u8; 40] {
+        let mut message = [0u8; 40];
+        message[..32].copy_from_slice(owner.as_ref());
+        message[32..].copy_from_slice(&self.expiry.to_le_bytes());
+        message
+    }
+}
+
+/// Optional per-lock settings chosen at creation. `Default` is a plain lock.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LockOptions {
+    /// Escrow arbiter, `Pubkey::default()` for none
+    pub arbiter: Pubkey,
+    /// Escrow counterparty, must be set together with `arbiter`
+    pub counterparty: Pubkey,
+    /// Let the owner withdraw part of the lock before maturity with `reduce`
+    pub reducible: bool,
+    /// Let the authority `recover` the lock from this timestamp if it's still unclaimed (0: never).
+    /// Must be at least `MIN_RECOVERY_DELAY` after the unlock timestamp.
+    pub recovery_after: i64,
+    /// Let the owner withdraw part of the matured lock with `partial_unlock` (default: true)
+    pub allow_partial: bool,
+    /// `sha256(preimage || claimant)` letting that claimant `unlock_with_secret` (zeroes: none)
+    pub secret_hash: [u8; 32],
+    /// Let the owner `claim_vested` along this curve before maturity (`VESTING_NONE`: no vesting)
+    pub vesting_curve: u8,
+    /// Let the owner `cancel` the lock at any time, for a fee
+    pub cancellable: bool,
+    /// Lamports posted with the lock, refunded if it is unlocked within `claim_window` seconds
+    /// of maturity and forfeited to the fee recipient otherwise (0: no deposit)
+    pub deposit_lamports: u64,
+    /// Seconds after maturity during which `unlock` refunds the deposit
+    pub claim_window: i64,
+    /// Share of the lock, in basis points, paid to the keeper who runs `keeper_unlock`
+    /// (0: no bounty, only the owner can unlock)
+    pub bounty_bps: u16,
+    /// Burn the tokens at maturity instead of returning them: `unlock` burns the vault balance,
+    /// reducing the mint's supply. Only for plain locks without partial withdrawals.
+    pub burn_on_unlock: bool,
+    /// Mint a receipt NFT of the lock to the owner, so the position shows up in wallets
+    /// (needs the receipt accounts of `LockTokens`)
+    pub mint_receipt: bool,
+    /// Unlock from this slot instead of a timestamp, which can drift (0: timestamp lock).
+    /// The lock's `unlock_timestamp` must then be 0.
+    pub unlock_slot: u64,
+}
+
+impl Default for LockOptions {
+    fn default() -> Self {
+        Self {
+            arbiter: Pubkey::default(),
+            counterparty: Pubkey::default(),
+            reducible: false,
+            recovery_after: 0,
+            allow_partial: true,
+            secret_hash: [0; 32],
+            vesting_curve: VESTING_NONE,
+            cancellable: false,
+            deposit_lamports: 0,
+            claim_window: 0,
+            bounty_bps: 0,
+            burn_on_unlock: false,
+            mint_receipt: false,
+            unlock_slot: 0,
+        }
+    }
+}
+
+/// Program configuration returned by `get_config`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ConfigView {
+    /// Default lock fee in lamports
+    pub fee_amount: u64,
+    /// Account receiving lock fees
+    pub fee_recipient: Pubkey,
+    /// Program authority (admin)
+    pub authority: Pubkey,
+    /// Whether deposits are stopped
+    pub paused: bool,
+    /// `SCHEMA_VERSION` of the deployed program
+    pub schema_version: u8,
+}
+
+/// Active lock returned by `list_active_locks`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LockSummary {
+    /// Lock id
+    pub id: u64,
+    /// Account that locked the tokens
+    pub owner: Pubkey,
+    /// Locked token mint
+    pub mint: Pubkey,
+    /// Amount still locked
+    pub amount: u64,
+    /// Unix timestamp from which the lock can be unlocked
+    pub unlock_timestamp: i64,
+}
+
+/// A single tranche of a milestone schedule
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct Milestone {
+    /// Unix timestamp from which this tranche can be claimed
+    pub timestamp: i64,
+    /// Amount of tokens released by this tranche
+    pub amount: u64,
+}
+
+/// A fallback claimant of a lock
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct Heir {
+    /// Wallet allowed to claim
+    pub heir: Pubkey,
+    /// Seconds after the lock's unlock timestamp from which this heir may claim
+    pub grace_offset: i64,
+}
+
+/// Heirs of a lock, tried in order. Seeds: `[HEIRS_SEED, lock_id]`.
+#[account]
+#[derive(InitSpace)]
+pub struct Heirs {
+    /// Lock these heirs may claim
+    pub lock_id: u64,
+    /// Heirs, sorted by strictly increasing grace offset
+    #[max_len(MAX_HEIRS)]
+    pub heirs: Vec<Heir>,
+    /// Bump seed for the heirs PDA
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct MilestoneSchedule {
+    /// Lock this schedule releases
+    pub lock_id: u64,
+    /// Tranches, sorted by strictly increasing timestamp
+    #[max_len(MAX_MILESTONES)]
+    pub milestones: Vec<Milestone>,
+    /// Number of tranches already claimed (always a prefix of `milestones`)
+    pub claimed_count: u8,
+    /// Bump seed for the schedule PDA
+    pub bump: u8,
+}
+
+// ============================================================================
+// Instruction Contexts
+// ============================================================================
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    /// Created here, or by the first lock (see `bootstrap_global_state`): a populated state is
+    /// rejected in the handler with a clear error rather than Anchor's "already in use"
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + GlobalState::INIT_SPACE,
+        seeds = [GLOBAL_STATE_SEED],
+        bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Program-wide stats, only kept by deployments initialized with `initialize`
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + ProgramStats::INIT_SPACE,
+        seeds = [PROGRAM_STATS_SEED],
+        bump
+    )]
+    pub program_stats: Account<'info, ProgramStats>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateGlobalState<'info> {
+    /// GlobalState in the legacy layout, which `Account` can't deserialize
+    /// CHECK: Address is validated by seeds, layout and authority in the handler
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump
+    )]
+    pub global_state: UncheckedAccount<'info>,
+
+    /// Authority stored in the legacy GlobalState, pays the extra rent
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateLock<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Lock in the legacy layout, which `Account` can't deserialize
+    /// CHECK: Owner, layout and address (derived from the stored id) are validated in the handler
+    #[account(mut)]
+    pub lock: UncheckedAccount<'info>,
+
+    /// Mint of the lock, whose owner is the lock's token program
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Lock owner or program authority, pays the extra rent
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct LockTokens<'info> {
+    /// Created by the first lock when `initialize` hasn't run (see `bootstrap_global_state`)
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + GlobalState::INIT_SPACE,
+        seeds = [GLOBAL_STATE_SEED],
+        bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Owner denylist, may not exist yet
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Lock::INIT_SPACE,
+        seeds = [LOCK_SEED, &global_state.lock_counter.to_le_bytes()],
+        bump
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault to hold the locked tokens (PDA-owned token account)
+    #[account(
+        init,
+        payer = owner,
+        token::mint = mint,
+        token::authority = vault,
+        seeds = [VAULT_SEED, &global_state.lock_counter.to_le_bytes()],
+        bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the owner for the mint, created by the owner's first lock of the mint
+    /// CHECK: Address is validated by seeds, created or updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, owner.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Program-wide stats, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [PROGRAM_STATS_SEED],
+        bump
+    )]
+    pub program_stats: UncheckedAccount<'info>,
+
+    /// Owner's token account (source of tokens)
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// Fee recipient account (receives the lock fee)
+    /// CHECK: Address is validated against `global_state.fee_recipient` in the handler,
+    /// once a bootstrapped global state is populated
+    #[account(mut)]
+    pub fee_recipient: AccountInfo<'info>,
+
+    /// Fee override of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [FEE_OVERRIDE_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub fee_override: UncheckedAccount<'info>,
+
+    /// Deposit status of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [MINT_STATUS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_status: UncheckedAccount<'info>,
+
+    /// Expiry index bucket of the unlock timestamp, created on first use
+    /// CHECK: Address is validated against the unlock timestamp's bucket in the handler
+    #[account(mut)]
+    pub expiry_index: UncheckedAccount<'info>,
+
+    /// Creation index bucket of the current day, created on first use
+    /// CHECK: Address is validated against the current day's bucket in the handler
+    #[account(mut)]
+    pub creation_index: UncheckedAccount<'info>,
+
+    /// Program data of this program, only needed for the first lock of a deployment
+    pub program_data: Option<Account<'info, ProgramData>>,
+
+    /// Mint the lock is valued against by clients (e.g. USDC), stored as `lock.reference_mint`
+    pub reference_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    /// Receipt NFT mint, only with `LockOptions.mint_receipt`
+    /// CHECK: Address is validated against the lock id in the handler, which creates it
+    #[account(mut)]
+    pub receipt_mint: Option<UncheckedAccount<'info>>,
+
+    /// Owner's associated token account of the receipt mint, only with `LockOptions.mint_receipt`
+    /// CHECK: Created (and its address validated) by the associated token program
+    #[account(mut)]
+    pub receipt_token_account: Option<UncheckedAccount<'info>>,
+
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+
+    /// Instructions sysvar, only needed to check a `FeeVoucher`
+    /// CHECK: Address is the instructions sysvar
+    #[account(address = solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct PrepareVault<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Owner denylist, may not exist yet
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: UncheckedAccount<'info>,
+
+    /// Lock reserving `id`, populated by `finalize_lock`
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Lock::INIT_SPACE,
+        seeds = [LOCK_SEED, &id.to_le_bytes()],
+        bump
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault of the lock, empty until `finalize_lock`
+    #[account(
+        init,
+        payer = owner,
+        token::mint = mint,
+        token::authority = vault,
+        seeds = [VAULT_SEED, &id.to_le_bytes()],
+        bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Deposit status of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [MINT_STATUS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_status: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeLock<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Owner denylist, may not exist yet
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: UncheckedAccount<'info>,
+
+    /// Lock made by `prepare_vault`
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = owner @ ErrorCode::Unauthorized,
+        has_one = mint @ ErrorCode::InvalidMint
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault of the lock, receives the tokens
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump = lock.vault_bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint (must match lock.mint)
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the owner for the mint, created by the owner's first lock of the mint
+    /// CHECK: Address is validated by seeds, created or updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, owner.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Program-wide stats, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [PROGRAM_STATS_SEED],
+        bump
+    )]
+    pub program_stats: UncheckedAccount<'info>,
+
+    /// Owner's token account (source of tokens)
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// Fee recipient account (receives the lock fee)
+    /// CHECK: Address is validated against `global_state.fee_recipient` in the handler
+    #[account(mut)]
+    pub fee_recipient: AccountInfo<'info>,
+
+    /// Fee override of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [FEE_OVERRIDE_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub fee_override: UncheckedAccount<'info>,
+
+    /// Deposit status of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [MINT_STATUS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_status: UncheckedAccount<'info>,
+
+    /// Expiry index bucket of the unlock timestamp, created on first use
+    /// CHECK: Address is validated against the unlock timestamp's bucket in the handler
+    #[account(mut)]
+    pub expiry_index: UncheckedAccount<'info>,
+
+    /// Creation index bucket of the current day, created on first use
+    /// CHECK: Address is validated against the current day's bucket in the handler
+    #[account(mut)]
+    pub creation_index: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct LockWithAtaVault<'info> {
+    pub lock_tokens: LockTokens<'info>,
+
+    /// Associated token account of the vault PDA, holding the locked tokens
+    #[account(
+        init,
+        payer = lock_tokens.owner,
+        associated_token::mint = lock_tokens.mint,
+        associated_token::authority = lock_tokens.vault,
+        associated_token::token_program = token_program
+    )]
+    pub ata_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Same token program as `lock_tokens.token_program`
+    #[account(address = lock_tokens.token_program.key())]
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnlockAtaVault<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Owner denylist, may not exist yet
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = owner @ ErrorCode::Unauthorized,
+        has_one = mint @ ErrorCode::InvalidMint
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault PDA owning the vault ATA, derived with the canonical bump
+    /// (checked against `lock.vault_bump` in the handler)
+    /// CHECK: Address is validated by seeds, only signs the transfer
+    #[account(
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+
+    /// Vault ATA holding the locked tokens
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = vault_authority,
+        associated_token::token_program = token_program
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), lock.mint.as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Program-wide stats, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [PROGRAM_STATS_SEED],
+        bump
+    )]
+    pub program_stats: UncheckedAccount<'info>,
+
+    /// Beneficiary's token account (destination for tokens)
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = lock.beneficiary
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Original owner who locked the tokens
+    pub owner: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, unlock_timestamp: i64, salt: [u8; 32])]
+pub struct LockWithSaltedVault<'info> {
+    pub lock_tokens: LockTokens<'info>,
+
+    /// Token account `[VAULT_SEED, lock_id, salt]` owned by the vault PDA, holding the locked tokens
+    #[account(
+        init,
+        payer = lock_tokens.owner,
+        token::mint = lock_tokens.mint,
+        token::authority = lock_tokens.vault,
+        token::token_program = token_program,
+        seeds = [VAULT_SEED, &lock_tokens.global_state.lock_counter.to_le_bytes(), &salt],
+        bump
+    )]
+    pub salted_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Same token program as `lock_tokens.token_program`
+    #[account(address = lock_tokens.token_program.key())]
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnlockSaltedVault<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Owner denylist, may not exist yet
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = owner @ ErrorCode::Unauthorized,
+        has_one = mint @ ErrorCode::InvalidMint
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault PDA owning the salted vault, derived with the canonical bump
+    /// (checked against `lock.vault_bump` in the handler)
+    /// CHECK: Address is validated by seeds, only signs the transfer
+    #[account(
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+
+    /// Salted vault holding the locked tokens, re-derived from the stored salt
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes(), &lock.vault_salt],
+        bump,
+        token::mint = mint,
+        token::authority = vault_authority
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), lock.mint.as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Program-wide stats, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [PROGRAM_STATS_SEED],
+        bump
+    )]
+    pub program_stats: UncheckedAccount<'info>,
+
+    /// Beneficiary's token account (destination for tokens)
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = lock.beneficiary
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Original owner who locked the tokens
+    pub owner: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ForceClose<'info> {
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = owner @ ErrorCode::Unauthorized,
+        has_one = mint @ ErrorCode::InvalidMint,
+        close = owner
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault of the lock, derived with the canonical bump
+    /// (checked against `lock.vault_bump` in the handler)
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Owner's token account (destination for the dust)
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Lock owner, receives the rent
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// Receipt NFT mint of the lock, to burn a receipt left by the release
+    #[account(mut)]
+    pub receipt_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    /// Owner's token account holding the receipt NFT, burnt by `force_close` when given
+    #[account(
+        mut,
+        token::authority = owner
+    )]
+    pub receipt_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct LockWithHeirs<'info> {
+    pub lock_tokens: LockTokens<'info>,
+
+    /// Heirs of the new lock
+    #[account(
+        init,
+        payer = lock_tokens.owner,
+        space = 8 + Heirs::INIT_SPACE,
+        seeds = [HEIRS_SEED, &lock_tokens.global_state.lock_counter.to_le_bytes()],
+        bump
+    )]
+    pub heirs: Account<'info, Heirs>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(template_id: u64)]
+pub struct LockFromTemplate<'info> {
+    pub lock_tokens: LockTokens<'info>,
+
+    /// Template to apply, may not exist
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [TEMPLATE_SEED, &template_id.to_le_bytes()],
+        bump
+    )]
+    pub template: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct LockWithMilestones<'info> {
+    pub lock_tokens: LockTokens<'info>,
+
+    /// Milestone schedule for the new lock
+    #[account(
+        init,
+        payer = lock_tokens.owner,
+        space = 8 + MilestoneSchedule::INIT_SPACE,
+        seeds = [MILESTONE_SEED, &lock_tokens.global_state.lock_counter.to_le_bytes()],
+        bump
+    )]
+    pub schedule: Account<'info, MilestoneSchedule>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, unlock_timestamp: i64, options: LockOptions, idempotency_key: [u8; 16])]
+pub struct LockIdempotent<'info> {
+    pub lock_tokens: LockTokens<'info>,
+
+    /// Record of the idempotency key, creation fails if the key was already used
+    #[account(
+        init,
+        payer = lock_tokens.owner,
+        space = 8 + IdempotencyRecord::INIT_SPACE,
+        seeds = [IDEMPOTENCY_SEED, lock_tokens.owner.key().as_ref(), idempotency_key.as_ref()],
+        bump
+    )]
+    pub idempotency_record: Account<'info, IdempotencyRecord>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnlockTokens<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Owner denylist, may not exist yet
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: UncheckedAccount<'info>,
+
+    /// The seeds are re-derived from the stored `id`, so an account whose `id` doesn't match
+    /// its own address (`find_program_address([LOCK_SEED, id])`) is rejected with `ConstraintSeeds`
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = owner @ ErrorCode::Unauthorized,
+        has_one = mint @ ErrorCode::InvalidMint
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault holding the locked tokens, derived with the canonical bump
+    /// (checked against `lock.vault_bump` in the handler)
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint, writable for the supply change of burn-on-unlock locks
+    #[account(mut)]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), lock.mint.as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Program-wide stats, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [PROGRAM_STATS_SEED],
+        bump
+    )]
+    pub program_stats: UncheckedAccount<'info>,
+
+    /// Beneficiary's token account (destination for tokens)
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = lock.beneficiary
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Any token account of the mint to send the tokens to instead of `owner_token_account`
+    /// (only while the owner is the beneficiary)
+    #[account(
+        mut,
+        token::mint = mint
+    )]
+    pub recipient_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Original owner who locked the tokens, receives the refunded deposit
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// Receipt NFT mint of the lock, to burn the receipt when the withdrawal closes the lock
+    #[account(mut)]
+    pub receipt_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    /// Owner's token account holding the receipt NFT, burnt when given and the lock closes
+    #[account(
+        mut,
+        token::authority = owner
+    )]
+    pub receipt_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// SPL Memo program, only needed when unlocking with a memo
+    pub memo_program: Option<Program<'info, Memo>>,
+
+    /// Fee recipient, only needed to forfeit the deposit of a lock unlocked past its claim window
+    /// CHECK: Only receives lamports, address is checked against the global state
+    #[account(
+        mut,
+        address = global_state.fee_recipient @ ErrorCode::InvalidFeeRecipient
+    )]
+    pub fee_recipient: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct CancelLock<'info> {
+    pub unlock: UnlockTokens<'info>,
+
+    /// Fee recipient's token account, receiving the cancellation fee
+    #[account(
+        mut,
+        token::mint = unlock.mint,
+        token::authority = unlock.global_state.fee_recipient
+    )]
+    pub fee_recipient_token_account: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct UnlockAndStake<'info> {
+    pub unlock: UnlockTokens<'info>,
+
+    /// Staking program configured by the authority
+    /// CHECK: Address is checked against the global state, only invoked
+    #[account(
+        executable,
+        address = unlock.global_state.staking_program @ ErrorCode::InvalidStakingProgram
+    )]
+    pub staking_program: UncheckedAccount<'info>,
+
+    /// Account credited with the stake: the lock's beneficiary
+    /// CHECK: Address is checked against the lock, only passed to the staking program
+    #[account(address = unlock.lock.beneficiary @ ErrorCode::Unauthorized)]
+    pub staker: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UnlockToAta<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Owner denylist, may not exist yet
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = owner @ ErrorCode::Unauthorized,
+        has_one = mint @ ErrorCode::InvalidMint
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault holding the locked tokens, derived with the canonical bump
+    /// (checked against `lock.vault_bump` in the handler)
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), lock.mint.as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Program-wide stats, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [PROGRAM_STATS_SEED],
+        bump
+    )]
+    pub program_stats: UncheckedAccount<'info>,
+
+    /// Lock beneficiary (the owner by default)
+    /// CHECK: Address is validated against the lock's beneficiary
+    #[account(address = lock.beneficiary @ ErrorCode::InvalidDestination)]
+    pub beneficiary: UncheckedAccount<'info>,
+
+    /// Beneficiary's associated token account for the mint, created if missing
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint,
+        associated_token::authority = beneficiary,
+        associated_token::token_program = token_program
+    )]
+    pub beneficiary_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Lock owner (pays for the associated token account if it has to be created)
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct HeirUnlock<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Owner denylist, may not exist yet
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint
+    )]
+    pub lock: Account<'info, Lock>,
+
+    #[account(
+        seeds = [HEIRS_SEED, &lock.id.to_le_bytes()],
+        bump = heirs.bump
+    )]
+    pub heirs: Account<'info, Heirs>,
+
+    /// Vault holding the locked tokens, derived with the canonical bump
+    /// (checked against `lock.vault_bump` in the handler)
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), lock.mint.as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Program-wide stats, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [PROGRAM_STATS_SEED],
+        bump
+    )]
+    pub program_stats: UncheckedAccount<'info>,
+
+    /// Heir's token account (destination for tokens)
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = heir
+    )]
+    pub heir_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub heir: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct UnlockWithSecret<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Owner denylist, may not exist yet
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault holding the locked tokens, derived with the canonical bump
+    /// (checked against `lock.vault_bump` in the handler)
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), lock.mint.as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Program-wide stats, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [PROGRAM_STATS_SEED],
+        bump
+    )]
+    pub program_stats: UncheckedAccount<'info>,
+
+    /// Claimant's token account (destination for tokens)
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = claimant
+    )]
+    pub claimant_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub claimant: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct BatchUnlock<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Owner denylist, may not exist yet
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: UncheckedAccount<'info>,
+
+    /// Program-wide stats, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [PROGRAM_STATS_SEED],
+        bump
+    )]
+    pub program_stats: UncheckedAccount<'info>,
+
+    /// Owner of every lock in the batch
+    pub owner: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct TopUpLock<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = owner @ ErrorCode::Unauthorized,
+        has_one = mint @ ErrorCode::InvalidMint
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault holding the locked tokens
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump = lock.vault_bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint (must match lock.mint)
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), lock.mint.as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Owner's token account (source of additional tokens)
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Deposit status of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [MINT_STATUS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_status: UncheckedAccount<'info>,
+
+    /// Lock owner who wants to add tokens
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ExtendLock<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = owner @ ErrorCode::Unauthorized
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Expiry index bucket of the current unlock timestamp
+    /// CHECK: Address is validated against the current unlock timestamp's bucket in the handler
+    #[account(mut)]
+    pub old_expiry_index: UncheckedAccount<'info>,
+
+    /// Expiry index bucket of the new unlock timestamp, created on first use
+    /// CHECK: Address is validated against the new unlock timestamp's bucket in the handler
+    #[account(mut)]
+    pub new_expiry_index: UncheckedAccount<'info>,
+
+    /// Lock owner who wants to extend the duration (pays for a new expiry bucket)
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BatchExtend<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Expiry index bucket of the new unlock timestamp, created on first use
+    /// CHECK: Address is validated against the new unlock timestamp's bucket in the handler
+    #[account(mut)]
+    pub new_expiry_index: UncheckedAccount<'info>,
+
+    /// Owner of every lock in the batch (pays for a new expiry bucket)
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExtendWithTopUp<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = owner @ ErrorCode::Unauthorized,
+        has_one = mint @ ErrorCode::InvalidMint
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault holding the locked tokens
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump = lock.vault_bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint (must match lock.mint)
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), lock.mint.as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Owner's token account (source of additional tokens)
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Deposit status of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [MINT_STATUS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_status: UncheckedAccount<'info>,
+
+    /// Expiry index bucket of the current unlock timestamp
+    /// CHECK: Address is validated against the current unlock timestamp's bucket in the handler
+    #[account(mut)]
+    pub old_expiry_index: UncheckedAccount<'info>,
+
+    /// Expiry index bucket of the new unlock timestamp, created on first use
+    /// CHECK: Address is validated against the new unlock timestamp's bucket in the handler
+    #[account(mut)]
+    pub new_expiry_index: UncheckedAccount<'info>,
+
+    /// Lock owner (pays for a new expiry bucket)
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimMilestone<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Owner denylist, may not exist yet
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = owner @ ErrorCode::Unauthorized,
+        has_one = mint @ ErrorCode::InvalidMint
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Milestone schedule of the lock
+    #[account(
+        mut,
+        seeds = [MILESTONE_SEED, &lock.id.to_le_bytes()],
+        bump = schedule.bump
+    )]
+    pub schedule: Account<'info, MilestoneSchedule>,
+
+    /// Vault holding the locked tokens
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump = lock.vault_bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), lock.mint.as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Program-wide stats, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [PROGRAM_STATS_SEED],
+        bump
+    )]
+    pub program_stats: UncheckedAccount<'info>,
+
+    /// Beneficiary's token account (destination for released tokens)
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = lock.beneficiary
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Original owner who locked the tokens
+    pub owner: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ArbiterRelease<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = arbiter @ ErrorCode::NotArbiter,
+        has_one = mint @ ErrorCode::InvalidMint
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault holding the locked tokens
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump = lock.vault_bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), lock.mint.as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Program-wide stats, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [PROGRAM_STATS_SEED],
+        bump
+    )]
+    pub program_stats: UncheckedAccount<'info>,
+
+    /// Counterparty's token account (destination for tokens)
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = lock.counterparty
+    )]
+    pub counterparty_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Escrow arbiter
+    pub arbiter: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ReconcileAmount<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault holding the locked tokens
+    #[account(
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump = lock.vault_bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, lock.mint.as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), lock.mint.as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Program authority (admin)
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RecognizeDeposit<'info> {
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault holding the locked tokens
+    #[account(
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump = lock.vault_bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, lock.mint.as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), lock.mint.as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct IsAuthorizedUnlocker<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Owner denylist, may not exist yet
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Heirs of the lock, may not exist
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [HEIRS_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub heirs: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ListActiveLocks {}
+
+#[derive(Accounts)]
+pub struct CheckInvariants<'info> {
+    /// Lock to check, its address is verified in the handler
+    pub lock: Account<'info, Lock>,
+
+    /// Vault of the lock, its address and authority are verified in the handler
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The lock's mint
+    pub mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct ElapsedSinceCreation<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub lock: Account<'info, Lock>,
+}
+
+#[derive(Accounts)]
+pub struct OwnerMintTotal<'info> {
+    /// CHECK: Only used to derive the stats address
+    pub owner: UncheckedAccount<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Locked total of the owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [OWNER_MINT_STATS_SEED, owner.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FutureMaturities<'info> {
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct QuoteFee<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Fee override of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [FEE_OVERRIDE_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub fee_override: UncheckedAccount<'info>,
+
+    /// Owner the quote is for, only needed to check a `FeeVoucher`
+    /// CHECK: Only its address is read
+    pub owner: Option<UncheckedAccount<'info>>,
+
+    /// Instructions sysvar, only needed to check a `FeeVoucher`
+    /// CHECK: Address is the instructions sysvar
+    #[account(address = solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct NextUnlockTime<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Milestone schedule of the lock, only exists for `lock_with_milestones` locks
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [MILESTONE_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub schedule: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct LockStatus<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Owner denylist, may not exist yet
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub lock: Account<'info, Lock>,
+}
+
+#[derive(Accounts)]
+pub struct GetLock<'info> {
+    #[account(
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub lock: Account<'info, Lock>,
+}
+
+#[derive(Accounts)]
+pub struct UiAmount<'info> {
+    #[account(
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// The lock's token mint, for its decimals
+    pub mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct GetConfig<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+}
+
+#[derive(Accounts)]
+pub struct EstimateRent<'info> {
+    /// The token mint to lock
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Expiry index bucket of the intended unlock day, counted if it doesn't exist yet
+    /// CHECK: Only its data length and lamports are read
+    pub expiry_index: Option<UncheckedAccount<'info>>,
+
+    /// Creation index bucket of today, counted if it doesn't exist yet
+    /// CHECK: Only its data length and lamports are read
+    pub creation_index: Option<UncheckedAccount<'info>>,
+
+    /// The owner's stats for `mint`, counted if they don't exist yet
+    /// CHECK: Only its data length and lamports are read
+    pub owner_mint_stats: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct ChangeBeneficiary<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = owner @ ErrorCode::Unauthorized
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Lock owner
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateGlobalState<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Program authority (admin)
+    pub authority: Signer<'info>,
+}
+
+#[cfg(feature = "test-clock")]
+#[derive(Accounts)]
+pub struct SetMockVaultBump<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Program authority (admin)
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AssistUnlock<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Owner denylist, may not exist yet
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault holding the locked tokens, derived with the canonical bump
+    /// (checked against `lock.vault_bump` in the handler)
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), lock.mint.as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Program-wide stats, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [PROGRAM_STATS_SEED],
+        bump
+    )]
+    pub program_stats: UncheckedAccount<'info>,
+
+    /// Beneficiary's token account, the only possible destination
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = lock.beneficiary
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Program authority (admin)
+    pub authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct KeeperUnlock<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    /// Owner denylist, may not exist yet
+    /// CHECK: Address is validated by seeds, contents are read in the handler
+    #[account(
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault holding the locked tokens, derived with the canonical bump
+    /// (checked against `lock.vault_bump` in the handler)
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), lock.mint.as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Program-wide stats, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [PROGRAM_STATS_SEED],
+        bump
+    )]
+    pub program_stats: UncheckedAccount<'info>,
+
+    /// Beneficiary's token account, receives everything but the bounty
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = lock.beneficiary
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Keeper's token account, receives the bounty
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = keeper
+    )]
+    pub keeper_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Anyone running the unlock
+    pub keeper: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct Recover<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint
+    )]
+    pub lock: Account<'info, Lock>,
+
+    /// Vault holding the locked tokens
+    #[account(
+        mut,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
+        bump = lock.vault_bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Stats of the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), lock.mint.as_ref()],
+        bump
+    )]
+    pub owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Program-wide stats, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [PROGRAM_STATS_SEED],
+        bump
+    )]
+    pub program_stats: UncheckedAccount<'info>,
+
+    /// Program-owned vault collecting recovered tokens of the mint, created on first use
+    #[account(
+        init_if_needed,
+        payer = authority,
+        token::mint = mint,
+        token::authority = recovery_vault,
+        token::token_program = token_program,
+        seeds = [RECOVERY_VAULT_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub recovery_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Program authority (admin), pays for the recovery vault on first use
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ManageDenylist<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + Denylist::INIT_SPACE,
+        seeds = [DENYLIST_SEED],
+        bump
+    )]
+    pub denylist: Account<'info, Denylist>,
+
+    /// Program authority (admin), pays for the denylist on first use
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetMintFeeOverride<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + MintFeeOverride::INIT_SPACE,
+        seeds = [FEE_OVERRIDE_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub fee_override: Account<'info, MintFeeOverride>,
+
+    /// The token mint the fee applies to
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Program authority (admin), pays for the override on first use
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(template_id: u64)]
+pub struct SetLockTemplate<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub global_state: Account<'info, GlobalState>,
 
-// ============================================================================
-// Instruction Contexts
-// ============================================================================
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + LockTemplate::INIT_SPACE,
+        seeds = [TEMPLATE_SEED, &template_id.to_le_bytes()],
+        bump
+    )]
+    pub template: Account<'info, LockTemplate>,
+
+    /// Program authority (admin), pays for the template on first use
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
 
 #[derive(Accounts)]
-pub struct Initialize<'info> {
+pub struct SetMintStatus<'info> {
     #[account(
-        init,
-        payer = authority,
-        space = 8 + GlobalState::INIT_SPACE,
         seeds = [GLOBAL_STATE_SEED],
-        bump
+        bump = global_state.bump,
+        has_one = authority @ ErrorCode::NotAuthority
     )]
     pub global_state: Account<'info, GlobalState>,
 
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + MintStatus::INIT_SPACE,
+        seeds = [MINT_STATUS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_status: Account<'info, MintStatus>,
+
+    /// The token mint the status applies to
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Program authority (admin), pays for the status on first use
     #[account(mut)]
     pub authority: Signer<'info>,
 
@@ -290,98 +7818,204 @@ pub struct Initialize<'info> {
 }
 
 #[derive(Accounts)]
-pub struct LockTokens<'info> {
+pub struct SetMintCap<'info> {
     #[account(
-        mut,
         seeds = [GLOBAL_STATE_SEED],
-        bump
+        bump = global_state.bump,
+        has_one = authority @ ErrorCode::NotAuthority
     )]
     pub global_state: Account<'info, GlobalState>,
 
     #[account(
-        init,
-        payer = owner,
-        space = 8 + Lock::INIT_SPACE,
-        seeds = [LOCK_SEED, &global_state.lock_counter.to_le_bytes()],
+        init_if_needed,
+        payer = authority,
+        space = 8 + MintStatus::INIT_SPACE,
+        seeds = [MINT_STATUS_SEED, mint.key().as_ref()],
         bump
     )]
-    pub lock: Account<'info, Lock>,
+    pub mint_status: Account<'info, MintStatus>,
 
-    /// Vault to hold the locked tokens (PDA-owned token account)
+    /// Stats of the mint, must exist so the cap can be enforced
     #[account(
-        init,
-        payer = owner,
-        token::mint = mint,
-        token::authority = vault,
-        seeds = [VAULT_SEED, &global_state.lock_counter.to_le_bytes()],
-        bump
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump = mint_stats.bump
     )]
-    pub vault: InterfaceAccount<'info, TokenAccount>,
+    pub mint_stats: Account<'info, MintStats>,
 
-    /// The token mint
+    /// The token mint to cap
     pub mint: InterfaceAccount<'info, Mint>,
 
-    /// Owner's token account (source of tokens)
+    /// Program authority (admin), pays for the status on first use
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterMintMigration<'info> {
     #[account(
-        mut,
-        token::mint = mint,
-        token::authority = owner
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        has_one = authority @ ErrorCode::NotAuthority
     )]
-    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
-
-    #[account(mut)]
-    pub owner: Signer<'info>,
+    pub global_state: Account<'info, GlobalState>,
 
-    /// Fee recipient account (receives 0.03 SOL per lock creation)
-    /// CHECK: Address is validated to match the hardcoded fee recipient
     #[account(
-        mut,
-        address = FEE_RECIPIENT @ ErrorCode::InvalidFeeRecipient
+        init,
+        payer = authority,
+        space = 8 + MintMigration::INIT_SPACE,
+        seeds = [MIGRATION_SEED, old_mint.key().as_ref()],
+        bump
     )]
-    pub fee_recipient: AccountInfo<'info>,
+    pub migration: Account<'info, MintMigration>,
+
+    /// Mint being replaced
+    pub old_mint: InterfaceAccount<'info, Mint>,
+
+    /// Replacement mint
+    pub new_mint: InterfaceAccount<'info, Mint>,
+
+    /// Program authority (admin), pays for the migration
+    #[account(mut)]
+    pub authority: Signer<'info>,
 
-    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct UnlockTokens<'info> {
+pub struct MigrateMint<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    #[account(
+        seeds = [MIGRATION_SEED, old_mint.key().as_ref()],
+        bump = migration.bump,
+        has_one = old_mint @ ErrorCode::InvalidMigration,
+        has_one = new_mint @ ErrorCode::InvalidMigration
+    )]
+    pub migration: Account<'info, MintMigration>,
+
     #[account(
         mut,
         seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
         bump,
-        has_one = owner @ ErrorCode::Unauthorized,
-        has_one = mint @ ErrorCode::InvalidMint
+        constraint = lock.mint == old_mint.key() @ ErrorCode::InvalidMint
     )]
     pub lock: Account<'info, Lock>,
 
-    /// Vault holding the locked tokens
+    /// Heirs of the lock, may not exist (heir locks can't migrate)
+    /// CHECK: Address is validated by seeds, only checked to be empty in the handler
+    #[account(
+        seeds = [HEIRS_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub heirs: UncheckedAccount<'info>,
+
+    /// Milestone schedule of the lock, may not exist (milestone locks can't migrate)
+    /// CHECK: Address is validated by seeds, only checked to be empty in the handler
+    #[account(
+        seeds = [MILESTONE_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub schedule: UncheckedAccount<'info>,
+
+    /// PDA vault holding the old tokens, derived with the canonical bump
+    /// (checked against `lock.vault_bump` in the handler)
     #[account(
         mut,
         seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
-        bump = lock.vault_bump
+        bump
     )]
     pub vault: InterfaceAccount<'info, TokenAccount>,
 
-    /// The token mint
-    pub mint: InterfaceAccount<'info, Mint>,
+    /// Mint being replaced, its supply shrinks by the burned amount
+    #[account(mut)]
+    pub old_mint: InterfaceAccount<'info, Mint>,
 
-    /// Owner's token account (destination for tokens)
+    /// Replacement mint
+    pub new_mint: InterfaceAccount<'info, Mint>,
+
+    /// Replacement tokens funded by the project
     #[account(
         mut,
-        token::mint = mint,
-        token::authority = owner
+        token::mint = new_mint,
+        token::authority = migration
     )]
-    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub reserve: InterfaceAccount<'info, TokenAccount>,
 
-    /// Original owner who locked the tokens
-    pub owner: Signer<'info>,
+    /// Salted vault `[VAULT_SEED, lock_id, new_mint]` receiving the replacement tokens, owned
+    /// by the same vault PDA as the old vault
+    #[account(
+        init,
+        payer = authority,
+        token::mint = new_mint,
+        token::authority = vault,
+        token::token_program = token_program,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes(), new_mint.key().as_ref()],
+        bump
+    )]
+    pub new_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// Stats of the old mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, old_mint.key().as_ref()],
+        bump
+    )]
+    pub old_mint_stats: UncheckedAccount<'info>,
+
+    /// Stats of the new mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [MINT_STATS_SEED, new_mint.key().as_ref()],
+        bump
+    )]
+    pub new_mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the old mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), old_mint.key().as_ref()],
+        bump
+    )]
+    pub old_owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Locked total of the lock owner for the new mint, may not exist
+    /// CHECK: Address is validated by seeds, contents are updated in the handler
+    #[account(
+        mut,
+        seeds = [OWNER_MINT_STATS_SEED, lock.owner.as_ref(), new_mint.key().as_ref()],
+        bump
+    )]
+    pub new_owner_mint_stats: UncheckedAccount<'info>,
+
+    /// Program authority (admin), pays for the new vault
+    #[account(mut)]
+    pub authority: Signer<'info>,
 
     pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct TopUpLock<'info> {
+pub struct RotateVault<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
+    pub authority: Signer<'info>,
+
     #[account(
         mut,
         seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
@@ -391,54 +8025,265 @@ pub struct TopUpLock<'info> {
     )]
     pub lock: Account<'info, Lock>,
 
-    /// Vault holding the locked tokens
+    /// Heirs of the lock, may not exist (heir locks can't rotate)
+    /// CHECK: Address is validated by seeds, only checked to be empty in the handler
+    #[account(
+        seeds = [HEIRS_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub heirs: UncheckedAccount<'info>,
+
+    /// Milestone schedule of the lock, may not exist (milestone locks can't rotate)
+    /// CHECK: Address is validated by seeds, only checked to be empty in the handler
+    #[account(
+        seeds = [MILESTONE_SEED, &lock.id.to_le_bytes()],
+        bump
+    )]
+    pub schedule: UncheckedAccount<'info>,
+
+    /// Lock owner, pays for the new vault and gets the old vault's rent back
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// Vault PDA owning the vault of every scheme, derived with the canonical bump
+    /// (checked against `lock.vault_bump` in the handler)
+    /// CHECK: Address is validated by seeds, only signs the transfer and the close
     #[account(
-        mut,
         seeds = [VAULT_SEED, &lock.id.to_le_bytes()],
-        bump = lock.vault_bump
+        bump
     )]
+    pub vault_authority: UncheckedAccount<'info>,
+
+    /// Current vault of the lock, its address is checked against the lock's scheme in the handler
+    #[account(mut)]
     pub vault: InterfaceAccount<'info, TokenAccount>,
 
-    /// The token mint (must match lock.mint)
+    /// Fresh salted vault `[VAULT_SEED, lock_id, old_vault]`
+    #[account(
+        init,
+        payer = owner,
+        token::mint = mint,
+        token::authority = vault_authority,
+        token::token_program = token_program,
+        seeds = [VAULT_SEED, &lock.id.to_le_bytes(), vault.key().as_ref()],
+        bump
+    )]
+    pub new_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The token mint
     pub mint: InterfaceAccount<'info, Mint>,
 
-    /// Owner's token account (source of additional tokens)
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BackfillMintStats<'info> {
     #[account(
-        mut,
-        token::mint = mint,
-        token::authority = owner
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        has_one = authority @ ErrorCode::NotAuthority
     )]
-    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub global_state: Account<'info, GlobalState>,
 
-    /// Lock owner who wants to add tokens
+    /// Stats to create, fails if the mint already has stats
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + MintStats::INIT_SPACE,
+        seeds = [MINT_STATS_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub mint_stats: Account<'info, MintStats>,
+
+    /// The token mint the stats are for
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// Program authority (admin), pays for the stats
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub authority: Signer<'info>,
 
-    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ExtendLock<'info> {
+pub struct RemoveMintFeeOverride<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump = global_state.bump,
+        has_one = authority @ ErrorCode::NotAuthority
+    )]
+    pub global_state: Account<'info, GlobalState>,
+
     #[account(
         mut,
-        seeds = [LOCK_SEED, &lock.id.to_le_bytes()],
-        bump,
-        has_one = owner @ ErrorCode::Unauthorized
+        close = authority,
+        seeds = [FEE_OVERRIDE_SEED, fee_override.mint.as_ref()],
+        bump = fee_override.bump
     )]
-    pub lock: Account<'info, Lock>,
+    pub fee_override: Account<'info, MintFeeOverride>,
 
-    /// Lock owner who wants to extend the duration
-    pub owner: Signer<'info>,
+    /// Program authority (admin)
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+// ============================================================================
+// Events
+// ============================================================================
+
+/// A lock's recorded amount was lowered to its actual vault balance
+#[event]
+pub struct Reconciled {
+    pub lock_id: u64,
+    pub old_amount: u64,
+    pub new_amount: u64,
+}
+
+/// A burn-on-unlock lock matured and its tokens were burnt
+#[event]
+pub struct Burned {
+    pub lock_id: u64,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+/// A lock's recorded amount was raised to its actual vault balance
+#[event]
+pub struct DepositRecognized {
+    pub lock_id: u64,
+    pub old_amount: u64,
+    pub new_amount: u64,
+}
+
+/// A lock's tokens were moved to a fresh vault by `rotate_vault`
+#[event]
+pub struct VaultRotated {
+    pub lock_id: u64,
+    pub old_vault: Pubkey,
+    pub new_vault: Pubkey,
+    pub amount: u64,
+}
+
+/// Tokens were added to an existing lock, `new_amount` is its total after the top-up
+#[event]
+pub struct ToppedUp {
+    pub lock_id: u64,
+    pub amount: u64,
+    pub new_amount: u64,
+}
+
+/// A lock's unlock timestamp was pushed back
+#[event]
+pub struct Extended {
+    pub lock_id: u64,
+    pub old_unlock_timestamp: i64,
+    pub new_unlock_timestamp: i64,
+}
+
+/// A lock owner asked to redirect unlocked tokens to a new beneficiary
+#[event]
+pub struct BeneficiaryChangeRequested {
+    pub lock_id: u64,
+    pub new_beneficiary: Pubkey,
+    pub available_at: i64,
+}
+
+/// A pending beneficiary change was confirmed
+#[event]
+pub struct BeneficiaryChanged {
+    pub lock_id: u64,
+    pub old_beneficiary: Pubkey,
+    pub new_beneficiary: Pubkey,
+}
+
+/// A pending beneficiary change was dropped
+#[event]
+pub struct BeneficiaryChangeCancelled {
+    pub lock_id: u64,
+}
+
+/// The authority swept an abandoned lock into the mint's recovery vault
+#[event]
+pub struct Recovered {
+    pub lock_id: u64,
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+/// The authority unlocked a matured lock on the user's behalf, to the beneficiary
+#[event]
+pub struct AssistedUnlock {
+    pub lock_id: u64,
+    pub owner: Pubkey,
+    pub authority: Pubkey,
+    pub amount: u64,
+}
+
+/// A keeper unlocked a matured lock and collected its bounty, the rest went to the beneficiary
+#[event]
+pub struct BountyPaid {
+    pub lock_id: u64,
+    pub keeper: Pubkey,
+    pub bounty: u64,
+    pub amount: u64,
+}
+
+/// The authority swapped a lock's tokens to the replacement of its mint
+#[event]
+pub struct MintMigrated {
+    pub lock_id: u64,
+    pub old_mint: Pubkey,
+    pub new_mint: Pubkey,
+    pub amount: u64,
+}
+
+/// The owner cancelled a lock: `refund` went to the beneficiary, `fee` to the fee recipient
+#[event]
+pub struct LockCancelled {
+    pub lock_id: u64,
+    pub owner: Pubkey,
+    pub refund: u64,
+    pub fee: u64,
+}
+
+/// Tokens were locked. Amounts are full `u64`s, never narrowed, so any token supply round-trips.
+#[event]
+pub struct AmountEvent {
+    pub lock_id: u64,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+/// The authority changed a `GlobalState` setting; values are rendered as strings
+#[event]
+pub struct ConfigChanged {
+    pub field: String,
+    pub old_value: String,
+    pub new_value: String,
 }
 
 // ============================================================================
 // Errors
 // ============================================================================
 
+/// First lockfun error code. Error codes are `LOCKFUN_ERROR_CODE_BASE` plus the variant's
+/// position in `ErrorCode`, a committed ABI: variants are only ever appended. The range sits
+/// far above Anchor's default `6000` start, so callers bubbling lockfun errors up through a
+/// CPI can't mistake them for their own.
+pub const LOCKFUN_ERROR_CODE_BASE: u32 = 100_000;
+
+// Anchor adds `ERROR_CODE_OFFSET` to the discriminant, in the program and in the IDL alike
+const _: () = assert!(
+    ErrorCode::Unauthorized as u32 + anchor_lang::error::ERROR_CODE_OFFSET
+        == LOCKFUN_ERROR_CODE_BASE
+);
+
 #[error_code]
 pub enum ErrorCode {
     #[msg("Unauthorized - only the lock owner can unlock")]
-    Unauthorized,
+    Unauthorized = 94_000,
     #[msg("Amount must be greater than zero")]
     AmountZero,
     #[msg("Unlock timestamp must be in the future")]
@@ -455,4 +8300,202 @@ pub enum ErrorCode {
     DuplicateAccounts,
     #[msg("Invalid fee recipient address")]
     InvalidFeeRecipient,
+    #[msg("Invalid milestone schedule - amounts must sum to the locked amount with increasing timestamps")]
+    InvalidSchedule,
+    #[msg("Unauthorized - only the program authority can perform this action")]
+    NotAuthority,
+    #[msg("Unauthorized - only the lock's arbiter can release it")]
+    NotArbiter,
+    #[msg("Escrow locks need both an arbiter and a counterparty")]
+    InvalidEscrow,
+    #[msg("Lock has already matured")]
+    LockMatured,
+    #[msg("Expiry index account doesn't match the unlock timestamp's day bucket")]
+    InvalidExpiryIndex,
+    #[msg("Vault already holds at least the recorded amount")]
+    NothingToReconcile,
+    #[msg("Owner is on the denylist")]
+    OwnerDenied,
+    #[msg("Denylist is full")]
+    DenylistFull,
+    #[msg("Amount exceeds the tokens left in the lock")]
+    InsufficientLockedAmount,
+    #[msg("Basis points must be between 1 and 10000")]
+    InvalidBps,
+    #[msg("Beneficiary must be a valid account")]
+    InvalidBeneficiary,
+    #[msg("No beneficiary change is pending")]
+    NoPendingBeneficiaryChange,
+    #[msg("Beneficiary change delay has not passed yet")]
+    BeneficiaryChangeTimelocked,
+    #[msg("Delay must not be negative")]
+    InvalidDelay,
+    #[msg("Too many locks created this epoch, try again next epoch")]
+    RateLimited,
+    #[msg("Extending locks is disabled")]
+    ExtendDisabled,
+    #[msg("Lock is not reducible")]
+    NotReducible,
+    #[msg("Memo is too long")]
+    MemoTooLong,
+    #[msg("A memo requires the memo program account")]
+    MemoProgramMissing,
+    #[msg("Recipient override not allowed once the beneficiary was changed")]
+    RecipientOverrideNotAllowed,
+    #[msg("Token program doesn't match the one the lock was created with")]
+    WrongTokenProgram,
+    #[msg("Program not initialized - run initialize first (or make the first lock from the upgrade authority)")]
+    NotInitialized,
+    #[msg("Unlock timestamp must be a whole minute")]
+    TimestampNotAligned,
+    #[msg("Invalid batch accounts (wrong grouping, order or derivation)")]
+    InvalidBatch,
+    #[msg("Destination token account must belong to the lock's beneficiary")]
+    InvalidDestination,
+    #[msg("Owner token account holds less than the amount to lock")]
+    InsufficientTokenBalance,
+    #[msg("Owner doesn't have enough SOL for the lock fee and rent")]
+    InsufficientLamports,
+    #[msg("No lock template with this id")]
+    TemplateNotFound,
+    #[msg("Lock template duration must be positive")]
+    InvalidTemplate,
+    #[msg("Lock vault bump isn't the canonical bump")]
+    InvalidBump,
+    #[msg("Deposits of this mint are frozen")]
+    DepositsFrozen,
+    #[msg("Authority can't be the default address")]
+    InvalidAuthority,
+    #[msg("Deposits are paused")]
+    Paused,
+    #[msg("Heirs must be 1 to 4 wallets with strictly increasing, positive grace offsets")]
+    InvalidHeirs,
+    #[msg("Signer isn't an heir of this lock or its grace period hasn't elapsed")]
+    HeirNotEligible,
+    #[msg("Recovery must be at least a year after the unlock timestamp")]
+    InvalidRecoveryAfter,
+    #[msg("Lock wasn't created with recovery")]
+    RecoveryDisabled,
+    #[msg("Lock can't be recovered yet")]
+    RecoveryTooEarly,
+    #[msg("Lock only allows a full unlock")]
+    PartialNotAllowed,
+    #[msg("Preimage doesn't match the lock's secret hash")]
+    WrongSecret,
+    #[msg("Extension must be a positive number of seconds")]
+    InvalidExtension,
+    #[msg("New unlock timestamp equals the current one")]
+    TimestampUnchanged,
+    #[msg("Collected fee differs from the computed fee")]
+    FeeMismatch,
+    #[msg("Invalid lock controller")]
+    InvalidController,
+    #[msg("Arithmetic overflow")]
+    AmountOverflow,
+    #[msg("Instruction doesn't apply to this lock's vault scheme")]
+    WrongVaultScheme,
+    #[msg("Lock is not fully unlocked yet")]
+    LockStillActive,
+    #[msg("Creation index account doesn't match the current day bucket")]
+    InvalidCreationIndex,
+    #[msg("Global state is already initialized")]
+    AlreadyInitialized,
+    #[msg("Lock has no vesting schedule")]
+    NotVesting,
+    #[msg("Unknown vesting curve")]
+    InvalidVestingCurve,
+    #[msg("Lock is not cancellable")]
+    NotCancellable,
+    #[msg("Fee recipient must be a system-owned wallet, not a program or program account")]
+    FeeRecipientNotWallet,
+    #[msg("Fee recipient would not be rent-exempt after receiving the fee")]
+    FeeRecipientNotRentExempt,
+    #[msg("Duplicate accounts detected - the owner and owner token account must not belong to the fee recipient")]
+    OwnerIsFeeRecipient,
+    #[msg("Unlock timestamp looks like seconds, expected milliseconds")]
+    TimestampNotMilliseconds,
+    #[msg("This instruction is currently disabled")]
+    InstructionDisabled,
+    #[msg("Unknown instruction flags")]
+    InvalidInstructionFlags,
+    #[msg("Deposit would take the mint's locked total over its cap")]
+    MintCapExceeded,
+    #[msg("Invalid mint migration")]
+    InvalidMigration,
+    #[msg("Migrated mints must have the same decimals")]
+    MigrationDecimalsMismatch,
+    #[msg("A deposit needs a positive claim window")]
+    InvalidClaimWindow,
+    #[msg("Deposits are only supported on plain locks")]
+    DepositRequiresPlainLock,
+    #[msg("Unlock the whole lock with unlock to settle its deposit")]
+    DepositNotSettled,
+    #[msg("The fee recipient account is required to forfeit the deposit")]
+    FeeRecipientMissing,
+    #[msg("Invalid or unset staking program")]
+    InvalidStakingProgram,
+    #[msg("The staking program didn't take exactly the locked amount")]
+    StakeMismatch,
+    #[msg("This mint is flagged as suspicious, new locks are rejected")]
+    MintFlagged,
+    #[msg("Clock tolerance exceeds the maximum")]
+    InvalidClockTolerance,
+    #[msg("Lock id is not the next id to be assigned")]
+    LockIdMismatch,
+    #[msg("Lock is not a prepared, empty lock")]
+    LockNotPrepared,
+    #[msg("Bounty must be at most 10000 basis points")]
+    InvalidBountyBps,
+    #[msg("Lock has no keeper bounty")]
+    NoBounty,
+    #[msg("Batch exceeds the maximum batch size")]
+    BatchTooLarge,
+    #[msg("Maximum batch size must be at least 1")]
+    InvalidBatchSize,
+    #[msg("Vault holds no more than the recorded amount")]
+    NothingToRecognize,
+    #[msg("Burn on unlock is only supported on plain locks without partial withdrawals")]
+    BurnRequiresPlainLock,
+    #[msg("Burn-on-unlock locks can only be unlocked, and burnt, with unlock")]
+    BurnOnUnlock,
+    #[msg("The receipt mint, receipt token account and associated token program are required")]
+    ReceiptAccountsMissing,
+    #[msg("Receipt mint doesn't belong to this lock")]
+    InvalidReceiptMint,
+    #[msg("Lock holds no tokens")]
+    EmptyLock,
+    #[msg("Lock account isn't at the PDA of its id")]
+    LockPdaMismatch,
+    #[msg("Vault isn't the lock's vault")]
+    VaultMismatch,
+    #[msg("Vault authority isn't the lock's vault PDA")]
+    VaultAuthorityMismatch,
+    #[msg("Vault balance doesn't match the lock amount")]
+    VaultBalanceMismatch,
+    #[msg("Calendar duration must be at least one month or one day")]
+    InvalidCalendarDuration,
+    #[msg("List limit must be between 1 and MAX_LIST_LIMIT")]
+    InvalidListLimit,
+    #[msg("Amount is above the maximum per lock")]
+    AmountTooLarge,
+    #[msg("Mint has no stats yet, see backfill_mint_stats")]
+    MintStatsNotFound,
+    #[msg("Fee vouchers are disabled")]
+    FeeVouchersDisabled,
+    #[msg("Fee voucher has expired")]
+    FeeVoucherExpired,
+    #[msg("Fee voucher is not signed by the voucher signer")]
+    InvalidFeeVoucher,
+    #[msg("A fee voucher requires the owner and instructions sysvar accounts")]
+    FeeVoucherAccountsMissing,
+    #[msg("A lock unlocks either at a timestamp or at a slot, not both")]
+    AmbiguousUnlockCondition,
+    #[msg("Unlock slot must be in the future")]
+    UnlockSlotInPast,
+    #[msg("Slot locks can't use recovery, vesting or deposits, which count from a timestamp")]
+    SlotLockRequiresPlainLock,
+    #[msg("Account is not in the legacy layout")]
+    NotLegacyAccount,
+    #[msg("Lock is released through its PDA vault and can't move to another vault")]
+    VaultBoundLock,
 }
@@ -0,0 +1,53 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+
+declare_id!("3Q2NyaAvrRK2bPekNirvML8boN4vJFUaohgYKHzCFQSz");
+
+/// Test double of a staking program, implementing the interface `lockfun::unlock_and_stake`
+/// invokes: it moves the staked tokens into a pool token account and logs the staker.
+#[program]
+pub mod mock_stake {
+    use super::*;
+
+    /// Move `amount` tokens from `source` into `pool`, on behalf of `staker`
+    pub fn stake(ctx: Context<Stake>, amount: u64) -> Result<()> {
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.source.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.pool.to_account_info(),
+                    authority: ctx.accounts.source_authority.to_account_info(),
+                },
+            ),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        msg!("Staked {} tokens for {}", amount, ctx.accounts.staker.key());
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct Stake<'info> {
+    /// Token account the staked tokens come from
+    #[account(mut, token::mint = mint)]
+    pub source: InterfaceAccount<'info, TokenAccount>,
+
+    /// Authority of `source`
+    pub source_authority: Signer<'info>,
+
+    /// Account credited with the stake
+    /// CHECK: Only logged
+    pub staker: UncheckedAccount<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// Token account holding all stakes
+    #[account(mut, token::mint = mint)]
+    pub pool: InterfaceAccount<'info, TokenAccount>,
+}
@@ -2,17 +2,35 @@ import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { Lockfun } from "../target/types/lockfun";
 import { expect } from "chai";
+import { createHash } from "crypto";
+import { readFileSync } from "fs";
 import {
   PublicKey,
   Keypair,
   LAMPORTS_PER_SOL,
+  SystemProgram,
+  Ed25519Program,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+  Transaction,
+  sendAndConfirmTransaction,
 } from "@solana/web3.js";
 import {
   createMint,
+  createAccount,
   createAssociatedTokenAccount,
   mintTo,
+  transfer,
   getAccount,
+  getMint,
+  getAssociatedTokenAddressSync,
+  closeAccount,
+  getMintLen,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  ExtensionType,
   TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
 } from "@solana/spl-token";
 
 // =============================================================================
@@ -29,6 +47,29 @@ interface LockAccount {
     createdAt: anchor.BN;
     vaultBump: number;
     isUnlocked: boolean;
+    arbiter: PublicKey;
+    counterparty: PublicKey;
+    beneficiary: PublicKey;
+    pendingBeneficiary: PublicKey;
+    beneficiaryChangeAt: anchor.BN;
+    reducible: boolean;
+    tokenProgram: PublicKey;
+    recoveryAfter: anchor.BN;
+    allowPartial: boolean;
+    unlockedAt: anchor.BN;
+    secretHash: number[];
+    vaultScheme: number;
+    vestingCurve: number;
+    vestedClaimed: anchor.BN;
+    cancellable: boolean;
+    referenceMint: PublicKey;
+    vaultSalt: number[];
+    isToken2022: boolean;
+    depositLamports: anchor.BN;
+    claimWindow: anchor.BN;
+    bountyBps: number;
+    burnOnUnlock: boolean;
+    receiptMint: PublicKey;
   };
 }
 
@@ -49,6 +90,29 @@ class LockFetcher {
     CREATED_AT: 96,      // 8 + 8 + 32 + 32 + 8 + 8
     VAULT_BUMP: 104,     // 8 + 8 + 32 + 32 + 8 + 8 + 8
     IS_UNLOCKED: 105,    // 8 + 8 + 32 + 32 + 8 + 8 + 8 + 1
+    ARBITER: 106,        // 105 + 1
+    COUNTERPARTY: 138,   // 106 + 32
+    BENEFICIARY: 170,    // 138 + 32
+    PENDING_BENEFICIARY: 202, // 170 + 32
+    BENEFICIARY_CHANGE_AT: 234, // 202 + 32
+    REDUCIBLE: 242,      // 234 + 8
+    TOKEN_PROGRAM: 243,  // 242 + 1
+    RECOVERY_AFTER: 275, // 243 + 32
+    ALLOW_PARTIAL: 283,  // 275 + 8
+    UNLOCKED_AT: 284,    // 283 + 1
+    SECRET_HASH: 292,    // 284 + 8
+    VAULT_SCHEME: 324,   // 292 + 32
+    VESTING_CURVE: 325,  // 324 + 1
+    VESTED_CLAIMED: 326, // 325 + 1
+    CANCELLABLE: 334,    // 326 + 8
+    REFERENCE_MINT: 335, // 334 + 1
+    VAULT_SALT: 367,     // 335 + 32
+    IS_TOKEN_2022: 399,  // 367 + 32
+    DEPOSIT_LAMPORTS: 400, // 399 + 1
+    CLAIM_WINDOW: 408,   // 400 + 8
+    BOUNTY_BPS: 416,     // 408 + 8
+    BURN_ON_UNLOCK: 418, // 416 + 2
+    RECEIPT_MINT: 419,   // 418 + 1
   };
 
   constructor(program: Program<Lockfun>) {
@@ -352,6 +416,161 @@ describe("lockfun", () => {
     return pda;
   };
 
+  // Helper to derive the expiry index bucket PDA for an unlock timestamp
+  // Helper to derive the creation index bucket of today, where new locks are recorded
+  const getCreationIndexPda = (): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("created"), new anchor.BN(Math.floor(Date.now() / 1000 / 86400)).toArrayLike(Buffer, "le", 8)],
+      program.programId
+    )[0];
+
+  const getExpiryIndexPda = (unlockTimestamp: number | anchor.BN): PublicKey => {
+    const ts = typeof unlockTimestamp === "number" ? unlockTimestamp : unlockTimestamp.toNumber();
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("expiry"), new anchor.BN(Math.floor(ts / 86400)).toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    return pda;
+  };
+
+  // Helper to derive the expiry index bucket PDA of a lock's current unlock timestamp
+  const getLockExpiryIndexPda = async (lockPda: PublicKey): Promise<PublicKey> => {
+    const lock = await program.account.lock.fetch(lockPda);
+    return getExpiryIndexPda(lock.unlockTimestamp);
+  };
+
+  // Helper to derive milestone schedule PDA
+  const getMilestoneSchedulePda = (lockId: number | anchor.BN): PublicKey => {
+    const id = typeof lockId === "number" ? new anchor.BN(lockId) : lockId;
+    const [pda] = PublicKey.findProgramAddressSync(
+      [Buffer.from("milestones"), id.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    return pda;
+  };
+
+  // Helper to derive a mint's stats PDA
+  const getMintStatsPda = (mint: PublicKey): PublicKey =>
+    PublicKey.findProgramAddressSync([Buffer.from("mint_stats"), mint.toBuffer()], program.programId)[0];
+
+  // Helper to derive an owner's stats PDA for a mint
+  const getOwnerMintStatsPda = (owner: PublicKey, mint: PublicKey): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("owner_mint_stats"), owner.toBuffer(), mint.toBuffer()],
+      program.programId
+    )[0];
+
+  // Default per-lock options (a plain lock); spread and override fields in tests
+  const defaultLockOptions = () => ({
+    arbiter: PublicKey.default,
+    counterparty: PublicKey.default,
+    reducible: false,
+    recoveryAfter: new anchor.BN(0),
+    allowPartial: true,
+    secretHash: Array(32).fill(0),
+    vestingCurve: 0,
+    cancellable: false,
+    depositLamports: new anchor.BN(0),
+    claimWindow: new anchor.BN(0),
+    bountyBps: 0,
+    burnOnUnlock: false,
+    mintReceipt: false,
+    unlockSlot: new anchor.BN(0),
+  });
+
+  // Helper to create a lock with per-lock options
+  const createLockWithOptions = async (
+    user: Keypair,
+    userTokenAccount: PublicKey,
+    mint: PublicKey,
+    amount: anchor.BN,
+    unlockTimestamp: anchor.BN,
+    options: ReturnType<typeof defaultLockOptions>
+  ): Promise<number> => {
+    const globalState = await program.account.globalState.fetch(globalStatePda);
+    const lockId = globalState.lockCounter.toNumber();
+
+    await program.methods
+      .lockWithOptions(amount, unlockTimestamp, options)
+      .accounts({
+        globalState: globalStatePda,
+        lock: getLockPda(lockId),
+        vault: getVaultPda(lockId),
+        expiryIndex: getExpiryIndexPda(unlockTimestamp),
+        creationIndex: getCreationIndexPda(),
+        mint: mint,
+        ownerTokenAccount: userTokenAccount,
+        owner: user.publicKey,
+        feeRecipient: FEE_RECIPIENT,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    return lockId;
+  };
+
+  // Helper to create a Token-2022 mint charging `feeBasisPoints` on every transfer
+  const createTransferFeeMint = async (decimals: number, feeBasisPoints: number): Promise<PublicKey> => {
+    const mintKeypair = Keypair.generate();
+    const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+    const lamports = await provider.connection.getMinimumBalanceForRentExemption(mintLen);
+
+    const tx = new Transaction().add(
+      SystemProgram.createAccount({
+        fromPubkey: mintAuthority.publicKey,
+        newAccountPubkey: mintKeypair.publicKey,
+        space: mintLen,
+        lamports,
+        programId: TOKEN_2022_PROGRAM_ID,
+      }),
+      createInitializeTransferFeeConfigInstruction(
+        mintKeypair.publicKey,
+        mintAuthority.publicKey,
+        mintAuthority.publicKey,
+        feeBasisPoints,
+        BigInt("18446744073709551615"), // no maximum fee
+        TOKEN_2022_PROGRAM_ID
+      ),
+      createInitializeMintInstruction(
+        mintKeypair.publicKey,
+        decimals,
+        mintAuthority.publicKey,
+        null,
+        TOKEN_2022_PROGRAM_ID
+      )
+    );
+    await sendAndConfirmTransaction(provider.connection, tx, [mintAuthority, mintKeypair]);
+
+    return mintKeypair.publicKey;
+  };
+
+  // Helper to decode the Anchor events emitted by a confirmed transaction
+  const getEvents = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return [...parser.parseLogs(tx?.meta?.logMessages ?? [])];
+  };
+
+  // Whether the deployed build exposes an instruction (feature-gated instructions are absent otherwise)
+  const hasInstruction = (name: string): boolean =>
+    program.idl.instructions.some((ix) => ix.name === name);
+
+  // Helper to set the mock clock (test-clock builds only), 0 restores the real clock
+  const setMockTimestamp = async (timestamp: number) => {
+    await program.methods
+      .setMockTimestamp(new anchor.BN(timestamp))
+      .accounts({
+        globalState: globalStatePda,
+        authority: authority.publicKey,
+      })
+      .rpc();
+  };
+
   // Helper to create a lock with specific parameters
   const createLock = async (
     user: Keypair,
@@ -366,11 +585,13 @@ describe("lockfun", () => {
     const vaultPda = getVaultPda(lockId);
 
     await program.methods
-      .lock(amount, unlockTimestamp)
+      .lock(amount, unlockTimestamp, null)
       .accounts({
         globalState: globalStatePda,
         lock: lockPda,
         vault: vaultPda,
+        expiryIndex: getExpiryIndexPda(unlockTimestamp),
+        creationIndex: getCreationIndexPda(),
         mint: mint,
         ownerTokenAccount: userTokenAccount,
         owner: user.publicKey,
@@ -531,6 +752,66 @@ describe("lockfun", () => {
   // ===========================================================================
   // INITIALIZATION
   // ===========================================================================
+  // Simulated, so the cold start doesn't persist and `initialize` below still finds a fresh
+  // deployment
+  describe("initialize_and_lock on a fresh deployment", () => {
+    const BPF_LOADER_UPGRADEABLE_ID = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+    const [programData] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      BPF_LOADER_UPGRADEABLE_ID
+    );
+
+    const simulateInitializeAndLock = async (owner: Keypair, ownerTokenAccount: PublicKey) => {
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+      const tx = await program.methods
+        .initializeAndLock(new anchor.BN(1_000_000), unlockTimestamp)
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(0),
+          vault: getVaultPda(0),
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
+          mint: mint1,
+          ownerTokenAccount,
+          owner: owner.publicKey,
+          feeRecipient: FEE_RECIPIENT,
+          programData,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .transaction();
+      const { value } = await provider.connection.simulateTransaction(tx, [owner]);
+      return value;
+    };
+
+    before(async function () {
+      if (await provider.connection.getAccountInfo(globalStatePda)) {
+        this.skip();
+      }
+    });
+
+    it("initializes and creates the first lock for the upgrade authority", async () => {
+      const deployer = (provider.wallet as anchor.Wallet).payer;
+      const deployerTokenAccount = await createAssociatedTokenAccount(
+        provider.connection,
+        mintAuthority,
+        mint1,
+        deployer.publicKey
+      );
+      await mintTo(provider.connection, mintAuthority, mint1, deployerTokenAccount, mintAuthority, 1_000_000);
+
+      const result = await simulateInitializeAndLock(deployer, deployerTokenAccount);
+      expect(result.err).to.equal(null);
+      expect(result.logs!.join("\n")).to.include("Lockfun initialized by the first lock");
+    });
+
+    it("rejects anyone else, who could front-run the deployer", async () => {
+      const result = await simulateInitializeAndLock(user1, user1TokenAccount1);
+      expect(result.err).to.not.equal(null);
+      expect(result.logs!.join("\n")).to.include("NotInitialized");
+    });
+  });
+
   describe("initialize", () => {
     it("initializes global state", async () => {
       await program.methods
@@ -545,6 +826,11 @@ describe("lockfun", () => {
       const globalState = await program.account.globalState.fetch(globalStatePda);
       expect(globalState.authority.toString()).to.equal(authority.publicKey.toString());
       expect(globalState.lockCounter.toNumber()).to.equal(0);
+
+      const [programStatsPda] = PublicKey.findProgramAddressSync([Buffer.from("program_stats")], program.programId);
+      const stats = await program.account.programStats.fetch(programStatsPda);
+      expect(stats.totalLocksEver.toNumber()).to.equal(0);
+      expect(stats.activeLocks.toNumber()).to.equal(0);
     });
 
     it("cannot initialize twice", async () => {
@@ -559,7 +845,7 @@ describe("lockfun", () => {
           .rpc();
         expect.fail("Should have thrown error");
       } catch (err: any) {
-        expect(err.message).to.include("already in use");
+        expect(err.error?.errorCode?.code).to.equal("AlreadyInitialized");
       }
     });
   });
@@ -579,11 +865,13 @@ describe("lockfun", () => {
       const beforeTimestamp = Math.floor(Date.now() / 1000);
 
       await program.methods
-        .lock(amount, unlockTimestamp)
+        .lock(amount, unlockTimestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda,
           vault: vaultPda,
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -617,11 +905,13 @@ describe("lockfun", () => {
 
       try {
         await program.methods
-          .lock(amount, unlockTimestamp)
+          .lock(amount, unlockTimestamp, null)
           .accounts({
             globalState: globalStatePda,
             lock: lockPda,
             vault: vaultPda,
+            expiryIndex: getExpiryIndexPda(unlockTimestamp),
+            creationIndex: getCreationIndexPda(),
             mint: mint1,
             ownerTokenAccount: user1TokenAccount1,
             owner: user1.publicKey,
@@ -648,11 +938,13 @@ describe("lockfun", () => {
 
       try {
         await program.methods
-          .lock(amount, unlockTimestamp)
+          .lock(amount, unlockTimestamp, null)
           .accounts({
             globalState: globalStatePda,
             lock: lockPda,
             vault: vaultPda,
+            expiryIndex: getExpiryIndexPda(unlockTimestamp),
+            creationIndex: getCreationIndexPda(),
             mint: mint1,
             ownerTokenAccount: user1TokenAccount1,
             owner: user1.publicKey,
@@ -682,11 +974,13 @@ describe("lockfun", () => {
       const ownerBalanceBefore = await provider.connection.getBalance(user1.publicKey);
 
       await program.methods
-        .lock(amount, unlockTimestamp)
+        .lock(amount, unlockTimestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda,
           vault: vaultPda,
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -740,11 +1034,13 @@ describe("lockfun", () => {
         const vaultPda = getVaultPda(lockId);
 
         await program.methods
-          .lock(amounts[i], timestamps[i])
+          .lock(amounts[i], timestamps[i], null)
           .accounts({
             globalState: globalStatePda,
             lock: lockPda,
             vault: vaultPda,
+            expiryIndex: getExpiryIndexPda(timestamps[i]),
+            creationIndex: getCreationIndexPda(),
             mint: mint1,
             ownerTokenAccount: user1TokenAccount1,
             owner: user1.publicKey,
@@ -792,11 +1088,13 @@ describe("lockfun", () => {
       const vaultPda1 = getVaultPda(lockId1);
 
       await program.methods
-        .lock(amount, timestamp)
+        .lock(amount, timestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda1,
           vault: vaultPda1,
+          expiryIndex: getExpiryIndexPda(timestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -814,11 +1112,13 @@ describe("lockfun", () => {
       const vaultPda2 = getVaultPda(lockId2);
 
       await program.methods
-        .lock(amount, timestamp)
+        .lock(amount, timestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda2,
           vault: vaultPda2,
+          expiryIndex: getExpiryIndexPda(timestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -860,11 +1160,13 @@ describe("lockfun", () => {
       const vaultPda1 = getVaultPda(lockId1);
 
       await program.methods
-        .lock(lock1Amount, lock1Timestamp)
+        .lock(lock1Amount, lock1Timestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda1,
           vault: vaultPda1,
+          expiryIndex: getExpiryIndexPda(lock1Timestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -882,11 +1184,13 @@ describe("lockfun", () => {
       const vaultPda2 = getVaultPda(lockId2);
 
       await program.methods
-        .lock(lock2Amount, lock2Timestamp)
+        .lock(lock2Amount, lock2Timestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda2,
           vault: vaultPda2,
+          expiryIndex: getExpiryIndexPda(lock2Timestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -924,11 +1228,13 @@ describe("lockfun", () => {
       const vaultPda1 = getVaultPda(lockId1);
 
       await program.methods
-        .lock(initialAmount, timestamp)
+        .lock(initialAmount, timestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda1,
           vault: vaultPda1,
+          expiryIndex: getExpiryIndexPda(timestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -945,11 +1251,13 @@ describe("lockfun", () => {
       const vaultPda2 = getVaultPda(lockId2);
 
       await program.methods
-        .lock(initialAmount, timestamp)
+        .lock(initialAmount, timestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda2,
           vault: vaultPda2,
+          expiryIndex: getExpiryIndexPda(timestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -997,11 +1305,13 @@ describe("lockfun", () => {
       // First lock unlocks soon
       const unlockTimestamp1 = new anchor.BN(now + 2);
       await program.methods
-        .lock(amount, unlockTimestamp1)
+        .lock(amount, unlockTimestamp1, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda1,
           vault: vaultPda1,
+          expiryIndex: getExpiryIndexPda(unlockTimestamp1),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -1020,11 +1330,13 @@ describe("lockfun", () => {
       // Second lock unlocks later
       const unlockTimestamp2 = new anchor.BN(now + 3600);
       await program.methods
-        .lock(amount, unlockTimestamp2)
+        .lock(amount, unlockTimestamp2, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda2,
           vault: vaultPda2,
+          expiryIndex: getExpiryIndexPda(unlockTimestamp2),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -1040,7 +1352,7 @@ describe("lockfun", () => {
 
       // Unlock only the first lock
       await program.methods
-        .unlock()
+        .unlock(null)
         .accounts({
           lock: lockPda1,
           vault: vaultPda1,
@@ -1081,11 +1393,13 @@ describe("lockfun", () => {
         const vaultPda = getVaultPda(lockId);
 
         await program.methods
-          .lock(amounts[i], timestamp)
+          .lock(amounts[i], timestamp, null)
           .accounts({
             globalState: globalStatePda,
             lock: lockPda,
             vault: vaultPda,
+            expiryIndex: getExpiryIndexPda(timestamp),
+            creationIndex: getCreationIndexPda(),
             mint: mint1,
             ownerTokenAccount: user1TokenAccount1,
             owner: user1.publicKey,
@@ -1134,11 +1448,13 @@ describe("lockfun", () => {
         const vaultPda = getVaultPda(lockId);
 
         await program.methods
-          .lock(amount, timestamp)
+          .lock(amount, timestamp, null)
           .accounts({
             globalState: globalStatePda,
             lock: lockPda,
             vault: vaultPda,
+            expiryIndex: getExpiryIndexPda(timestamp),
+            creationIndex: getCreationIndexPda(),
             mint: mint1,
             ownerTokenAccount: user1TokenAccount1,
             owner: user1.publicKey,
@@ -1190,11 +1506,13 @@ describe("lockfun", () => {
       const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 2);
 
       await program.methods
-        .lock(unlockAmount, unlockTimestamp)
+        .lock(unlockAmount, unlockTimestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: unlockableLockPda,
           vault: unlockableVaultPda,
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -1210,7 +1528,7 @@ describe("lockfun", () => {
 
     it("unlocks tokens after timestamp", async () => {
       await program.methods
-        .unlock()
+        .unlock(null)
         .accounts({
           lock: unlockableLockPda,
           vault: unlockableVaultPda,
@@ -1238,11 +1556,13 @@ describe("lockfun", () => {
 
       // Create lock (this will send fees)
       await program.methods
-        .lock(testAmount, unlockTimestamp)
+        .lock(testAmount, unlockTimestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: testLockPda,
           vault: testVaultPda,
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -1260,7 +1580,7 @@ describe("lockfun", () => {
 
       // Unlock tokens
       await program.methods
-        .unlock()
+        .unlock(null)
         .accounts({
           lock: testLockPda,
           vault: testVaultPda,
@@ -1282,7 +1602,7 @@ describe("lockfun", () => {
     it("cannot unlock twice", async () => {
       try {
         await program.methods
-          .unlock()
+          .unlock(null)
           .accounts({
             lock: unlockableLockPda,
             vault: unlockableVaultPda,
@@ -1305,7 +1625,7 @@ describe("lockfun", () => {
 
       try {
         await program.methods
-          .unlock()
+          .unlock(null)
           .accounts({
             lock: lockPda,
             vault: vaultPda,
@@ -1328,7 +1648,7 @@ describe("lockfun", () => {
 
       try {
         await program.methods
-          .unlock()
+          .unlock(null)
           .accounts({
             lock: lockPda,
             vault: vaultPda,
@@ -1365,11 +1685,13 @@ describe("lockfun", () => {
       const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
 
       await program.methods
-        .lock(initialAmount, unlockTimestamp)
+        .lock(initialAmount, unlockTimestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: topUpLockPda,
           vault: topUpVaultPda,
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -1462,11 +1784,13 @@ describe("lockfun", () => {
       const testAmount = new anchor.BN(10_000_000_000);
 
       await program.methods
-        .lock(testAmount, unlockTimestamp)
+        .lock(testAmount, unlockTimestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: testLockPda,
           vault: testVaultPda,
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -1480,7 +1804,7 @@ describe("lockfun", () => {
       await new Promise((resolve) => setTimeout(resolve, 3000));
 
       await program.methods
-        .unlock()
+        .unlock(null)
         .accounts({
           lock: testLockPda,
           vault: testVaultPda,
@@ -1543,11 +1867,13 @@ describe("lockfun", () => {
       const testAmount = new anchor.BN(100_000_000); // 6 decimals for mint2
 
       await program.methods
-        .lock(testAmount, unlockTimestamp)
+        .lock(testAmount, unlockTimestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: testLockPda,
           vault: testVaultPda,
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint2,
           ownerTokenAccount: user1TokenAccount2,
           owner: user1.publicKey,
@@ -1637,11 +1963,13 @@ describe("lockfun", () => {
       initialTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600); // 1 hour from now
 
       await program.methods
-        .lock(initialAmount, initialTimestamp)
+        .lock(initialAmount, initialTimestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: extendLockPda,
           vault: extendVaultPda,
+          expiryIndex: getExpiryIndexPda(initialTimestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -1663,6 +1991,8 @@ describe("lockfun", () => {
         .extend(newTimestamp)
         .accounts({
           lock: extendLockPda,
+          oldExpiryIndex: await getLockExpiryIndexPda(extendLockPda),
+          newExpiryIndex: getExpiryIndexPda(newTimestamp),
           owner: user1.publicKey,
         })
         .signers([user1])
@@ -1688,6 +2018,8 @@ describe("lockfun", () => {
         .extend(newTimestamp)
         .accounts({
           lock: extendLockPda,
+          oldExpiryIndex: await getLockExpiryIndexPda(extendLockPda),
+          newExpiryIndex: getExpiryIndexPda(newTimestamp),
           owner: user1.publicKey,
         })
         .signers([user1])
@@ -1710,6 +2042,8 @@ describe("lockfun", () => {
           .extend(shorterTimestamp)
           .accounts({
             lock: extendLockPda,
+            oldExpiryIndex: await getLockExpiryIndexPda(extendLockPda),
+            newExpiryIndex: getExpiryIndexPda(shorterTimestamp),
             owner: user1.publicKey,
           })
           .signers([user1])
@@ -1729,13 +2063,15 @@ describe("lockfun", () => {
           .extend(new anchor.BN(currentTimestamp))
           .accounts({
             lock: extendLockPda,
+            oldExpiryIndex: await getLockExpiryIndexPda(extendLockPda),
+            newExpiryIndex: getExpiryIndexPda(new anchor.BN(currentTimestamp)),
             owner: user1.publicKey,
           })
           .signers([user1])
           .rpc();
         expect.fail("Should have thrown error");
       } catch (err: any) {
-        expect(err.error?.errorCode?.code).to.equal("CannotShortenTimestamp");
+        expect(err.error?.errorCode?.code).to.equal("TimestampUnchanged");
       }
     });
 
@@ -1749,6 +2085,8 @@ describe("lockfun", () => {
           .extend(oneSecondEarlier)
           .accounts({
             lock: extendLockPda,
+            oldExpiryIndex: await getLockExpiryIndexPda(extendLockPda),
+            newExpiryIndex: getExpiryIndexPda(oneSecondEarlier),
             owner: user1.publicKey,
           })
           .signers([user1])
@@ -1769,6 +2107,8 @@ describe("lockfun", () => {
           .extend(manyHoursEarlier)
           .accounts({
             lock: extendLockPda,
+            oldExpiryIndex: await getLockExpiryIndexPda(extendLockPda),
+            newExpiryIndex: getExpiryIndexPda(manyHoursEarlier),
             owner: user1.publicKey,
           })
           .signers([user1])
@@ -1789,6 +2129,8 @@ describe("lockfun", () => {
         .extend(extendedTimestamp)
         .accounts({
           lock: extendLockPda,
+          oldExpiryIndex: await getLockExpiryIndexPda(extendLockPda),
+          newExpiryIndex: getExpiryIndexPda(extendedTimestamp),
           owner: user1.publicKey,
         })
         .signers([user1])
@@ -1804,6 +2146,8 @@ describe("lockfun", () => {
           .extend(new anchor.BN(timestampBefore))
           .accounts({
             lock: extendLockPda,
+            oldExpiryIndex: await getLockExpiryIndexPda(extendLockPda),
+            newExpiryIndex: getExpiryIndexPda(new anchor.BN(timestampBefore)),
             owner: user1.publicKey,
           })
           .signers([user1])
@@ -1835,6 +2179,8 @@ describe("lockfun", () => {
             .extend(pastTimestamp)
             .accounts({
               lock: extendLockPda,
+              oldExpiryIndex: await getLockExpiryIndexPda(extendLockPda),
+              newExpiryIndex: getExpiryIndexPda(pastTimestamp),
               owner: user1.publicKey,
             })
             .signers([user1])
@@ -1859,11 +2205,13 @@ describe("lockfun", () => {
       const testAmount = new anchor.BN(10_000_000_000);
 
       await program.methods
-        .lock(testAmount, unlockTimestamp)
+        .lock(testAmount, unlockTimestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: testLockPda,
           vault: testVaultPda,
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -1877,7 +2225,7 @@ describe("lockfun", () => {
       await new Promise((resolve) => setTimeout(resolve, 3000));
 
       await program.methods
-        .unlock()
+        .unlock(null)
         .accounts({
           lock: testLockPda,
           vault: testVaultPda,
@@ -1896,6 +2244,8 @@ describe("lockfun", () => {
           .extend(newTimestamp)
           .accounts({
             lock: testLockPda,
+            oldExpiryIndex: await getLockExpiryIndexPda(testLockPda),
+            newExpiryIndex: getExpiryIndexPda(newTimestamp),
             owner: user1.publicKey,
           })
           .signers([user1])
@@ -1916,6 +2266,8 @@ describe("lockfun", () => {
           .extend(newTimestamp)
           .accounts({
             lock: extendLockPda,
+            oldExpiryIndex: await getLockExpiryIndexPda(extendLockPda),
+            newExpiryIndex: getExpiryIndexPda(newTimestamp),
             owner: user2.publicKey,
           })
           .signers([user2])
@@ -1936,6 +2288,8 @@ describe("lockfun", () => {
         .extend(firstExtension)
         .accounts({
           lock: extendLockPda,
+          oldExpiryIndex: await getLockExpiryIndexPda(extendLockPda),
+          newExpiryIndex: getExpiryIndexPda(firstExtension),
           owner: user1.publicKey,
         })
         .signers([user1])
@@ -1947,6 +2301,8 @@ describe("lockfun", () => {
         .extend(secondExtension)
         .accounts({
           lock: extendLockPda,
+          oldExpiryIndex: await getLockExpiryIndexPda(extendLockPda),
+          newExpiryIndex: getExpiryIndexPda(secondExtension),
           owner: user1.publicKey,
         })
         .signers([user1])
@@ -1973,11 +2329,13 @@ describe("lockfun", () => {
 
       // Create lock
       await program.methods
-        .lock(initialAmount, initialTimestamp)
+        .lock(initialAmount, initialTimestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda,
           vault: vaultPda,
+          expiryIndex: getExpiryIndexPda(initialTimestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -2009,6 +2367,8 @@ describe("lockfun", () => {
         .extend(newTimestamp)
         .accounts({
           lock: lockPda,
+          oldExpiryIndex: await getLockExpiryIndexPda(lockPda),
+          newExpiryIndex: getExpiryIndexPda(newTimestamp),
           owner: user1.publicKey,
         })
         .signers([user1])
@@ -2032,11 +2392,13 @@ describe("lockfun", () => {
 
       // Create lock
       await program.methods
-        .lock(initialAmount, initialTimestamp)
+        .lock(initialAmount, initialTimestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda,
           vault: vaultPda,
+          expiryIndex: getExpiryIndexPda(initialTimestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -2053,6 +2415,8 @@ describe("lockfun", () => {
         .extend(newTimestamp)
         .accounts({
           lock: lockPda,
+          oldExpiryIndex: await getLockExpiryIndexPda(lockPda),
+          newExpiryIndex: getExpiryIndexPda(newTimestamp),
           owner: user1.publicKey,
         })
         .signers([user1])
@@ -2091,11 +2455,13 @@ describe("lockfun", () => {
 
       // 1. Create lock
       await program.methods
-        .lock(initialAmount, initialTimestamp)
+        .lock(initialAmount, initialTimestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda,
           vault: vaultPda,
+          expiryIndex: getExpiryIndexPda(initialTimestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -2134,6 +2500,8 @@ describe("lockfun", () => {
         .extend(extendedTimestamp)
         .accounts({
           lock: lockPda,
+          oldExpiryIndex: await getLockExpiryIndexPda(lockPda),
+          newExpiryIndex: getExpiryIndexPda(extendedTimestamp),
           owner: user1.publicKey,
         })
         .signers([user1])
@@ -2146,7 +2514,7 @@ describe("lockfun", () => {
       await new Promise((resolve) => setTimeout(resolve, 6000)); // Wait 6 seconds to be sure
 
       await program.methods
-        .unlock()
+        .unlock(null)
         .accounts({
           lock: lockPda,
           vault: vaultPda,
@@ -2181,11 +2549,13 @@ describe("lockfun", () => {
       const vaultPda1 = getVaultPda(lockId1);
 
       await program.methods
-        .lock(amount1, timestamp)
+        .lock(amount1, timestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda1,
           vault: vaultPda1,
+          expiryIndex: getExpiryIndexPda(timestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -2203,11 +2573,13 @@ describe("lockfun", () => {
       const vaultPda2 = getVaultPda(lockId2);
 
       await program.methods
-        .lock(amount2, timestamp)
+        .lock(amount2, timestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda2,
           vault: vaultPda2,
+          expiryIndex: getExpiryIndexPda(timestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user2TokenAccount1,
           owner: user2.publicKey,
@@ -2250,11 +2622,13 @@ describe("lockfun", () => {
         const vaultPda = getVaultPda(lockId);
 
         await program.methods
-          .lock(amount, timestamp)
+          .lock(amount, timestamp, null)
           .accounts({
             globalState: globalStatePda,
             lock: lockPda,
             vault: vaultPda,
+            expiryIndex: getExpiryIndexPda(timestamp),
+            creationIndex: getCreationIndexPda(),
             mint: mint1,
             ownerTokenAccount: account,
             owner: user.publicKey,
@@ -2308,11 +2682,13 @@ describe("lockfun", () => {
       const availableAmount = new anchor.BN(1_000_000_000_000); // 1000 tokens
 
       await program.methods
-        .lock(availableAmount, timestamp)
+        .lock(availableAmount, timestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda,
           vault: vaultPda,
+          expiryIndex: getExpiryIndexPda(timestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -2339,11 +2715,13 @@ describe("lockfun", () => {
       const vaultPda = getVaultPda(lockId);
 
       await program.methods
-        .lock(amount, farFutureTimestamp)
+        .lock(amount, farFutureTimestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda,
           vault: vaultPda,
+          expiryIndex: getExpiryIndexPda(farFutureTimestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -2370,11 +2748,13 @@ describe("lockfun", () => {
 
       // Create lock
       await program.methods
-        .lock(initialAmount, initialTimestamp)
+        .lock(initialAmount, initialTimestamp, null)
         .accounts({
           globalState: globalStatePda,
           lock: lockPda,
           vault: vaultPda,
+          expiryIndex: getExpiryIndexPda(initialTimestamp),
+          creationIndex: getCreationIndexPda(),
           mint: mint1,
           ownerTokenAccount: user1TokenAccount1,
           owner: user1.publicKey,
@@ -2411,6 +2791,8 @@ describe("lockfun", () => {
         .extend(new anchor.BN(now + 7200))
         .accounts({
           lock: lockPda,
+          oldExpiryIndex: await getLockExpiryIndexPda(lockPda),
+          newExpiryIndex: getExpiryIndexPda(new anchor.BN(now + 7200)),
           owner: user1.publicKey,
         })
         .signers([user1])
@@ -2460,11 +2842,13 @@ describe("lockfun", () => {
         const vaultPda = getVaultPda(lockId);
 
         await program.methods
-          .lock(amount, timestamp)
+          .lock(amount, timestamp, null)
           .accounts({
             globalState: globalStatePda,
             lock: lockPda,
             vault: vaultPda,
+            expiryIndex: getExpiryIndexPda(timestamp),
+            creationIndex: getCreationIndexPda(),
             mint: mint1,
             ownerTokenAccount: user1TokenAccount1,
             owner: user1.publicKey,
@@ -3467,4 +3851,8076 @@ describe("lockfun", () => {
       expect(latest100.length).to.be.greaterThan(0);
     });
   });
+
+  // ===========================================================================
+  // MILESTONE SCHEDULES
+  // ===========================================================================
+  describe("lock_with_milestones", () => {
+    let milestoneLockId: number;
+    const trancheAmount = new anchor.BN(1_000_000_000); // 1 token per tranche
+
+    const lockWithMilestones = async (
+      lockId: number,
+      amount: anchor.BN,
+      milestones: { timestamp: anchor.BN; amount: anchor.BN }[]
+    ) => {
+      await program.methods
+        .lockWithMilestones(amount, milestones)
+        .accounts({
+          lockTokens: {
+            globalState: globalStatePda,
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            expiryIndex: getExpiryIndexPda(milestones[milestones.length - 1].timestamp),
+            creationIndex: getCreationIndexPda(),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            feeRecipient: FEE_RECIPIENT,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          },
+          schedule: getMilestoneSchedulePda(lockId),
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+    };
+
+    const claimMilestone = async (lockId: number) => {
+      await program.methods
+        .claimMilestone()
+        .accounts({
+          lock: getLockPda(lockId),
+          schedule: getMilestoneSchedulePda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+    };
+
+    it("rejects a schedule that doesn't sum to the locked amount", async () => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+      const now = Math.floor(Date.now() / 1000);
+
+      try {
+        await lockWithMilestones(lockId, trancheAmount.muln(3), [
+          { timestamp: new anchor.BN(now + 3600), amount: trancheAmount },
+          { timestamp: new anchor.BN(now + 7200), amount: trancheAmount },
+        ]);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidSchedule");
+      }
+    });
+
+    it("rejects milestones that are not in increasing order", async () => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+      const now = Math.floor(Date.now() / 1000);
+
+      try {
+        await lockWithMilestones(lockId, trancheAmount.muln(2), [
+          { timestamp: new anchor.BN(now + 7200), amount: trancheAmount },
+          { timestamp: new anchor.BN(now + 3600), amount: trancheAmount },
+        ]);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidSchedule");
+      }
+    });
+
+    it("locks with a schedule ending at the last milestone", async () => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      milestoneLockId = globalState.lockCounter.toNumber();
+      const now = Math.floor(Date.now() / 1000);
+      const lastTimestamp = new anchor.BN(now + 3600);
+
+      await lockWithMilestones(milestoneLockId, trancheAmount.muln(4), [
+        { timestamp: new anchor.BN(now + 2), amount: trancheAmount },
+        { timestamp: new anchor.BN(now + 3), amount: trancheAmount },
+        { timestamp: new anchor.BN(now + 1800), amount: trancheAmount },
+        { timestamp: lastTimestamp, amount: trancheAmount },
+      ]);
+
+      const lock = await program.account.lock.fetch(getLockPda(milestoneLockId));
+      expect(lock.amount.toString()).to.equal(trancheAmount.muln(4).toString());
+      expect(lock.unlockTimestamp.toNumber()).to.equal(lastTimestamp.toNumber());
+
+      const schedule = await program.account.milestoneSchedule.fetch(
+        getMilestoneSchedulePda(milestoneLockId)
+      );
+      expect(schedule.lockId.toNumber()).to.equal(milestoneLockId);
+      expect(schedule.milestones.length).to.equal(4);
+      expect(schedule.claimedCount).to.equal(0);
+    });
+
+    it("releases every milestone that has passed in one claim", async () => {
+      await new Promise((resolve) => setTimeout(resolve, 5000));
+
+      const balanceBefore = (await getAccount(provider.connection, user1TokenAccount1)).amount;
+      await claimMilestone(milestoneLockId);
+      const balanceAfter = (await getAccount(provider.connection, user1TokenAccount1)).amount;
+
+      expect((balanceAfter - balanceBefore).toString()).to.equal(trancheAmount.muln(2).toString());
+
+      const lock = await program.account.lock.fetch(getLockPda(milestoneLockId));
+      expect(lock.amount.toString()).to.equal(trancheAmount.muln(2).toString());
+      expect(lock.isUnlocked).to.equal(false);
+
+      const schedule = await program.account.milestoneSchedule.fetch(
+        getMilestoneSchedulePda(milestoneLockId)
+      );
+      expect(schedule.claimedCount).to.equal(2);
+    });
+
+    it("cannot claim before the next milestone", async () => {
+      try {
+        await claimMilestone(milestoneLockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TooEarly");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // MOCK CLOCK (only runs against builds with the `test-clock` feature)
+  // ===========================================================================
+  describe("test clock", () => {
+    before(function () {
+      if (!hasInstruction("set_mock_timestamp")) {
+        this.skip();
+      }
+    });
+
+    after(async () => {
+      if (hasInstruction("set_mock_timestamp")) {
+        await setMockTimestamp(0);
+      }
+    });
+
+    it("only the authority can set the mock timestamp", async () => {
+      try {
+        await program.methods
+          .setMockTimestamp(new anchor.BN(1))
+          .accounts({
+            globalState: globalStatePda,
+            authority: user1.publicKey,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+
+    it("warping past the unlock timestamp makes the lock unlockable", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const unlockTimestamp = new anchor.BN(now + 30 * 86400); // 30 days
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000_000),
+        unlockTimestamp
+      );
+
+      const unlock = () =>
+        program.methods
+          .unlock(null)
+          .accounts({
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user1])
+          .rpc();
+
+      try {
+        await unlock();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TooEarly");
+      }
+
+      await setMockTimestamp(unlockTimestamp.toNumber());
+      await unlock();
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+    });
+
+    it("restoring the real clock re-enables maturity checks", async () => {
+      await setMockTimestamp(0);
+
+      const now = Math.floor(Date.now() / 1000);
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000_000),
+        new anchor.BN(now + 3600)
+      );
+
+      try {
+        await program.methods
+          .unlock(null)
+          .accounts({
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TooEarly");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // ESCROW LOCKS (ARBITER RELEASE)
+  // ===========================================================================
+  describe("arbiter_release", () => {
+    const arbiter = user3;
+    const counterparty = user2;
+    const counterpartyTokenAccount = () => user2TokenAccount1;
+    const escrowAmount = new anchor.BN(2_000_000_000);
+
+    const escrowOptions = () => ({
+      ...defaultLockOptions(),
+      arbiter: arbiter.publicKey,
+      counterparty: counterparty.publicKey,
+    });
+
+    const arbiterRelease = (lockId: number, signer: Keypair) =>
+      program.methods
+        .arbiterRelease()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          counterpartyTokenAccount: counterpartyTokenAccount(),
+          arbiter: signer.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([signer])
+        .rpc();
+
+    const ownerUnlock = (lockId: number) =>
+      program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    it("rejects an arbiter without a counterparty", async () => {
+      try {
+        await createLockWithOptions(
+          user1,
+          user1TokenAccount1,
+          mint1,
+          escrowAmount,
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          { ...defaultLockOptions(), arbiter: arbiter.publicKey }
+        );
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidEscrow");
+      }
+    });
+
+    it("stores the arbiter and counterparty on the lock", async () => {
+      const lockId = await createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        escrowAmount,
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+        escrowOptions()
+      );
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.arbiter.toString()).to.equal(arbiter.publicKey.toString());
+      expect(lock.counterparty.toString()).to.equal(counterparty.publicKey.toString());
+    });
+
+    it("only the arbiter can release", async () => {
+      const lockId = await createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        escrowAmount,
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+        escrowOptions()
+      );
+
+      try {
+        await arbiterRelease(lockId, counterparty);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotArbiter");
+      }
+    });
+
+    it("a plain lock has no arbiter", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        escrowAmount,
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+      try {
+        await arbiterRelease(lockId, arbiter);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotArbiter");
+      }
+    });
+
+    it("arbiter release before maturity pays the counterparty and blocks the owner", async () => {
+      const lockId = await createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        escrowAmount,
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+        escrowOptions()
+      );
+
+      const balanceBefore = (await getAccount(provider.connection, counterpartyTokenAccount())).amount;
+      await arbiterRelease(lockId, arbiter);
+      const balanceAfter = (await getAccount(provider.connection, counterpartyTokenAccount())).amount;
+
+      expect((balanceAfter - balanceBefore).toString()).to.equal(escrowAmount.toString());
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+
+      try {
+        await ownerUnlock(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AlreadyUnlocked");
+      }
+    });
+
+    it("owner unlock after maturity wins and the arbiter can no longer release", async () => {
+      const lockId = await createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        escrowAmount,
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2),
+        escrowOptions()
+      );
+
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      try {
+        await arbiterRelease(lockId, arbiter);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("LockMatured");
+      }
+
+      const balanceBefore = (await getAccount(provider.connection, user1TokenAccount1)).amount;
+      await ownerUnlock(lockId);
+      const balanceAfter = (await getAccount(provider.connection, user1TokenAccount1)).amount;
+
+      expect((balanceAfter - balanceBefore).toString()).to.equal(escrowAmount.toString());
+
+      try {
+        await arbiterRelease(lockId, arbiter);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AlreadyUnlocked");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // EXPIRY INDEX
+  // ===========================================================================
+  describe("expiry index", () => {
+    const amount = new anchor.BN(1_000_000_000);
+    // Far enough in the future that no other test uses these day buckets
+    const baseTimestamp = Math.floor(Date.now() / 1000) + 400 * 86400;
+
+    it("lock records its id in the bucket of its unlock day", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        amount,
+        new anchor.BN(baseTimestamp)
+      );
+
+      const index = await program.account.expiryIndex.fetch(getExpiryIndexPda(baseTimestamp));
+      expect(index.bucket.toNumber()).to.equal(Math.floor(baseTimestamp / 86400));
+      expect(index.lockIds.map((id) => id.toNumber())).to.include(lockId);
+      expect(index.overflowCount).to.equal(0);
+    });
+
+    it("extend moves the lock to the bucket of its new unlock day", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        amount,
+        new anchor.BN(baseTimestamp + 60)
+      );
+      const lockPda = getLockPda(lockId);
+      const newTimestamp = new anchor.BN(baseTimestamp + 3 * 86400);
+
+      await program.methods
+        .extend(newTimestamp)
+        .accounts({
+          lock: lockPda,
+          oldExpiryIndex: await getLockExpiryIndexPda(lockPda),
+          newExpiryIndex: getExpiryIndexPda(newTimestamp),
+          owner: user1.publicKey,
+        })
+        .signers([user1])
+        .rpc();
+
+      const oldIndex = await program.account.expiryIndex.fetch(getExpiryIndexPda(baseTimestamp + 60));
+      expect(oldIndex.lockIds.map((id) => id.toNumber())).to.not.include(lockId);
+
+      const newIndex = await program.account.expiryIndex.fetch(getExpiryIndexPda(newTimestamp));
+      expect(newIndex.lockIds.map((id) => id.toNumber())).to.include(lockId);
+    });
+
+    it("rejects an expiry index of another day", async () => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+      const unlockTimestamp = new anchor.BN(baseTimestamp);
+
+      try {
+        await program.methods
+          .lock(amount, unlockTimestamp, null)
+          .accounts({
+            globalState: globalStatePda,
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            expiryIndex: getExpiryIndexPda(baseTimestamp + 86400),
+            creationIndex: getCreationIndexPda(),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            feeRecipient: FEE_RECIPIENT,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidExpiryIndex");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // RECONCILE AMOUNT (TRANSFER-FEE SHORTFALL REPAIR)
+  // ===========================================================================
+  describe("reconcile_amount", () => {
+    let feeMint: PublicKey;
+    let user1FeeTokenAccount: PublicKey;
+    let shortLockId: number;
+    const lockedAmount = new anchor.BN(1_000_000_000); // 1000 tokens with 6 decimals
+
+    const reconcile = (lockId: number, signer?: Keypair) => {
+      const builder = program.methods.reconcileAmount().accounts({
+        globalState: globalStatePda,
+        lock: getLockPda(lockId),
+        vault: getVaultPda(lockId),
+        authority: signer ? signer.publicKey : authority.publicKey,
+      });
+      return signer ? builder.signers([signer]).rpc() : builder.rpc({ commitment: "confirmed" });
+    };
+
+    const unlockFeeLock = (lockId: number) =>
+      program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: feeMint,
+          ownerTokenAccount: user1FeeTokenAccount,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    before(async () => {
+      feeMint = await createTransferFeeMint(6, 100); // 1% transfer fee
+      user1FeeTokenAccount = await createAssociatedTokenAccount(
+        provider.connection,
+        user1,
+        feeMint,
+        user1.publicKey,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      await mintTo(
+        provider.connection,
+        mintAuthority,
+        feeMint,
+        user1FeeTokenAccount,
+        mintAuthority,
+        10_000_000_000,
+        [],
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      shortLockId = globalState.lockCounter.toNumber();
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 2);
+
+      await program.methods
+        .lock(lockedAmount, unlockTimestamp, null)
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(shortLockId),
+          vault: getVaultPda(shortLockId),
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
+          mint: feeMint,
+          ownerTokenAccount: user1FeeTokenAccount,
+          owner: user1.publicKey,
+          feeRecipient: FEE_RECIPIENT,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+    });
+
+    it("flags the lock as Token-2022 in get_lock", async () => {
+      const lock = await program.methods.getLock().accounts({ lock: getLockPda(shortLockId) }).view();
+      expect(lock.isToken2022).to.equal(true);
+      expect(lock.tokenProgram.toBase58()).to.equal(TOKEN_2022_PROGRAM_ID.toBase58());
+    });
+
+    it("a transfer-fee shortfall bricks unlock", async () => {
+      const vault = await getAccount(provider.connection, getVaultPda(shortLockId), undefined, TOKEN_2022_PROGRAM_ID);
+      expect(vault.amount < BigInt(lockedAmount.toString())).to.equal(true);
+
+      try {
+        await unlockFeeLock(shortLockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.message).to.not.include("Should have thrown error");
+      }
+    });
+
+    it("only the authority can reconcile", async () => {
+      try {
+        await reconcile(shortLockId, user1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+
+    it("sets the amount to the vault balance and emits Reconciled", async () => {
+      const vault = await getAccount(provider.connection, getVaultPda(shortLockId), undefined, TOKEN_2022_PROGRAM_ID);
+
+      const signature = await reconcile(shortLockId);
+
+      const lock = await program.account.lock.fetch(getLockPda(shortLockId));
+      expect(lock.amount.toString()).to.equal(vault.amount.toString());
+
+      const events = await getEvents(signature);
+      const reconciled = events.find((e) => e.name === "reconciled");
+      expect(reconciled).to.not.equal(undefined);
+      expect(reconciled!.data.lockId.toNumber()).to.equal(shortLockId);
+      expect(reconciled!.data.oldAmount.toString()).to.equal(lockedAmount.toString());
+      expect(reconciled!.data.newAmount.toString()).to.equal(vault.amount.toString());
+    });
+
+    it("unlock works after reconciling", async () => {
+      await unlockFeeLock(shortLockId);
+
+      const lock = await program.account.lock.fetch(getLockPda(shortLockId));
+      expect(lock.isUnlocked).to.equal(true);
+    });
+
+    it("rejects locks whose vault holds the recorded amount", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+      try {
+        await reconcile(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NothingToReconcile");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // DENYLIST
+  // ===========================================================================
+  describe("denylist", () => {
+    let deniedLockId: number;
+
+    const setDenylistEnabled = (enabled: boolean) =>
+      program.methods
+        .setDenylistEnabled(enabled)
+        .accounts({
+          globalState: globalStatePda,
+          authority: authority.publicKey,
+        })
+        .rpc();
+
+    const addToDenylist = (owner: PublicKey) =>
+      program.methods
+        .addToDenylist(owner)
+        .accounts({ authority: authority.publicKey })
+        .rpc();
+
+    const removeFromDenylist = (owner: PublicKey) =>
+      program.methods
+        .removeFromDenylist(owner)
+        .accounts({ authority: authority.publicKey })
+        .rpc();
+
+    const unlockUser3 = (lockId: number) =>
+      program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user3TokenAccount1,
+          owner: user3.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user3])
+        .rpc();
+
+    before(async () => {
+      // Matures before user3 gets denied
+      deniedLockId = await createLock(
+        user3,
+        user3TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+    });
+
+    after(async () => {
+      await setDenylistEnabled(false);
+    });
+
+    it("only the authority can manage the denylist", async () => {
+      try {
+        await program.methods
+          .addToDenylist(user3.publicKey)
+          .accounts({ authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+
+      try {
+        await program.methods
+          .setDenylistEnabled(true)
+          .accounts({ globalState: globalStatePda, authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+
+    it("listing an owner has no effect while the denylist is disabled", async () => {
+      await addToDenylist(user3.publicKey);
+
+      const [denylistPda] = PublicKey.findProgramAddressSync([Buffer.from("denylist")], program.programId);
+      const denylist = await program.account.denylist.fetch(denylistPda);
+      expect(denylist.owners.map((o: PublicKey) => o.toBase58())).to.include(user3.publicKey.toBase58());
+
+      const lockId = await createLock(
+        user3,
+        user3TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.owner.toBase58()).to.equal(user3.publicKey.toBase58());
+    });
+
+    it("rejects lock and unlock of a denied owner once enabled", async () => {
+      await setDenylistEnabled(true);
+
+      try {
+        await createLock(
+          user3,
+          user3TokenAccount1,
+          mint1,
+          new anchor.BN(1_000_000_000),
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+        );
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("OwnerDenied");
+      }
+
+      try {
+        await unlockUser3(deniedLockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("OwnerDenied");
+      }
+    });
+
+    it("other owners are unaffected", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.owner.toBase58()).to.equal(user1.publicKey.toBase58());
+    });
+
+    it("matured funds can be unlocked after removal from the denylist", async () => {
+      await removeFromDenylist(user3.publicKey);
+      await unlockUser3(deniedLockId);
+
+      const lock = await program.account.lock.fetch(getLockPda(deniedLockId));
+      expect(lock.isUnlocked).to.equal(true);
+    });
+  });
+
+  // ===========================================================================
+  // IDEMPOTENT LOCK CREATION
+  // ===========================================================================
+  describe("lock_idempotent", () => {
+    const getIdempotencyRecordPda = (owner: PublicKey, key: number[]): PublicKey => {
+      const [pda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("idempotency"), owner.toBuffer(), Buffer.from(key)],
+        program.programId
+      );
+      return pda;
+    };
+
+    // Builds the call against the current lock counter, like a client rebuilding a retried transaction
+    const lockIdempotent = async (key: number[], unlockTimestamp: anchor.BN): Promise<number> => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+
+      await program.methods
+        .lockIdempotent(new anchor.BN(1_000_000_000), unlockTimestamp, defaultLockOptions(), key)
+        .accounts({
+          lockTokens: {
+            globalState: globalStatePda,
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            expiryIndex: getExpiryIndexPda(unlockTimestamp),
+            creationIndex: getCreationIndexPda(),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            feeRecipient: FEE_RECIPIENT,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          },
+          idempotencyRecord: getIdempotencyRecordPda(user1.publicKey, key),
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+
+      return lockId;
+    };
+
+    const randomKey = (): number[] => Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+
+    it("creates a lock and records the key", async () => {
+      const key = randomKey();
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+      const lockId = await lockIdempotent(key, unlockTimestamp);
+
+      const record = await program.account.idempotencyRecord.fetch(getIdempotencyRecordPda(user1.publicKey, key));
+      expect(record.lockId.toNumber()).to.equal(lockId);
+      expect(record.owner.toBase58()).to.equal(user1.publicKey.toBase58());
+      expect(Array.from(record.idempotencyKey)).to.deep.equal(key);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.amount.toNumber()).to.equal(1_000_000_000);
+    });
+
+    it("a retry with the same key fails without creating a second lock", async () => {
+      const key = randomKey();
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+      await lockIdempotent(key, unlockTimestamp);
+      const counterBefore = (await program.account.globalState.fetch(globalStatePda)).lockCounter.toNumber();
+
+      try {
+        await lockIdempotent(key, unlockTimestamp);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect((err.logs ?? []).join("\n")).to.include("already in use");
+      }
+
+      const counterAfter = (await program.account.globalState.fetch(globalStatePda)).lockCounter.toNumber();
+      expect(counterAfter).to.equal(counterBefore);
+    });
+
+    it("different keys create different locks", async () => {
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+      const first = await lockIdempotent(randomKey(), unlockTimestamp);
+      const second = await lockIdempotent(randomKey(), unlockTimestamp);
+
+      expect(second).to.equal(first + 1);
+    });
+  });
+
+  // ===========================================================================
+  // PER-MINT FEE OVERRIDES
+  // ===========================================================================
+  describe("mint fee overrides", () => {
+    const DEFAULT_FEE = 30_000_000;
+
+    const getFeeOverridePda = (mint: PublicKey): PublicKey => {
+      const [pda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("fee_override"), mint.toBuffer()],
+        program.programId
+      );
+      return pda;
+    };
+
+    const setMintFeeOverride = (mint: PublicKey, feeAmount: number) =>
+      program.methods
+        .setMintFeeOverride(new anchor.BN(feeAmount))
+        .accounts({ mint, authority: authority.publicKey })
+        .rpc();
+
+    const removeMintFeeOverride = (mint: PublicKey) =>
+      program.methods
+        .removeMintFeeOverride()
+        .accounts({ feeOverride: getFeeOverridePda(mint), authority: authority.publicKey })
+        .rpc();
+
+    // Fee recipient balance change caused by one lock of `mint`
+    const feePaidForLock = async (user: Keypair, tokenAccount: PublicKey, mint: PublicKey): Promise<number> => {
+      const before = await provider.connection.getBalance(FEE_RECIPIENT);
+      await createLock(user, tokenAccount, mint, new anchor.BN(1_000_000), new anchor.BN(Math.floor(Date.now() / 1000) + 3600));
+      const after = await provider.connection.getBalance(FEE_RECIPIENT);
+      return after - before;
+    };
+
+    after(async () => {
+      for (const mint of [mint2, mint3]) {
+        if (await provider.connection.getAccountInfo(getFeeOverridePda(mint))) {
+          await removeMintFeeOverride(mint);
+        }
+      }
+    });
+
+    it("only the authority can set an override", async () => {
+      try {
+        await program.methods
+          .setMintFeeOverride(new anchor.BN(0))
+          .accounts({ mint: mint3, authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+
+    it("mints without an override pay the default fee", async () => {
+      expect(await feePaidForLock(user1, user1TokenAccount1, mint1)).to.equal(DEFAULT_FEE);
+    });
+
+    it("a zero override makes locks fee-free", async () => {
+      await setMintFeeOverride(mint3, 0);
+
+      const feeOverride = await program.account.mintFeeOverride.fetch(getFeeOverridePda(mint3));
+      expect(feeOverride.mint.toBase58()).to.equal(mint3.toBase58());
+      expect(feeOverride.feeAmount.toNumber()).to.equal(0);
+
+      expect(await feePaidForLock(user1, user1TokenAccount3, mint3)).to.equal(0);
+    });
+
+    it("a premium override charges more than the default", async () => {
+      await setMintFeeOverride(mint2, 2 * DEFAULT_FEE);
+      expect(await feePaidForLock(user1, user1TokenAccount2, mint2)).to.equal(2 * DEFAULT_FEE);
+
+      // Overrides can be updated in place
+      await setMintFeeOverride(mint2, DEFAULT_FEE / 2);
+      expect(await feePaidForLock(user1, user1TokenAccount2, mint2)).to.equal(DEFAULT_FEE / 2);
+    });
+
+    it("removing an override restores the default fee", async () => {
+      await removeMintFeeOverride(mint2);
+
+      expect(await provider.connection.getAccountInfo(getFeeOverridePda(mint2))).to.equal(null);
+      expect(await feePaidForLock(user1, user1TokenAccount2, mint2)).to.equal(DEFAULT_FEE);
+    });
+  });
+
+  // ===========================================================================
+  // PARTIAL UNLOCK
+  // ===========================================================================
+  describe("partial_unlock", () => {
+    const lockedAmount = 1_000_000_000;
+    let maturedLockId: number;
+
+    const unlockAccounts = (lockId: number) => ({
+      lock: getLockPda(lockId),
+      vault: getVaultPda(lockId),
+      mint: mint1,
+      ownerTokenAccount: user1TokenAccount1,
+      owner: user1.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    const partialUnlock = (lockId: number, amount: number) =>
+      program.methods
+        .partialUnlock(new anchor.BN(amount))
+        .accounts(unlockAccounts(lockId))
+        .signers([user1])
+        .rpc();
+
+    const partialUnlockBps = (lockId: number, bps: number) =>
+      program.methods
+        .partialUnlockBps(bps)
+        .accounts(unlockAccounts(lockId))
+        .signers([user1])
+        .rpc();
+
+    before(async () => {
+      maturedLockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(lockedAmount),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+    });
+
+    it("rejects partial unlocks before maturity", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(lockedAmount),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+      try {
+        await partialUnlock(lockId, 1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TooEarly");
+      }
+    });
+
+    it("rejects basis points outside 1..=10000", async () => {
+      for (const bps of [0, 10_001]) {
+        try {
+          await partialUnlockBps(maturedLockId, bps);
+          expect.fail("Should have thrown error");
+        } catch (err: any) {
+          expect(err.error?.errorCode?.code).to.equal("InvalidBps");
+        }
+      }
+    });
+
+    it("unlocks a fraction by basis points", async () => {
+      const before = await getAccount(provider.connection, user1TokenAccount1);
+
+      await partialUnlockBps(maturedLockId, 2_500);
+
+      const after = await getAccount(provider.connection, user1TokenAccount1);
+      expect(Number(after.amount - before.amount)).to.equal(lockedAmount / 4);
+
+      const lock = await program.account.lock.fetch(getLockPda(maturedLockId));
+      expect(lock.amount.toNumber()).to.equal((lockedAmount * 3) / 4);
+      expect(lock.isUnlocked).to.equal(false);
+    });
+
+    it("rejects amounts larger than what is left", async () => {
+      try {
+        await partialUnlock(maturedLockId, lockedAmount);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InsufficientLockedAmount");
+      }
+    });
+
+    it("marks the lock unlocked once emptied", async () => {
+      await partialUnlock(maturedLockId, lockedAmount / 4);
+      await partialUnlockBps(maturedLockId, 10_000);
+
+      const lock = await program.account.lock.fetch(getLockPda(maturedLockId));
+      expect(lock.amount.toNumber()).to.equal(0);
+      expect(lock.isUnlocked).to.equal(true);
+
+      const vault = await getAccount(provider.connection, getVaultPda(maturedLockId));
+      expect(Number(vault.amount)).to.equal(0);
+    });
+  });
+
+  // ===========================================================================
+  // UNLOCK VAULT / MINT CONSISTENCY
+  // ===========================================================================
+  describe("unlock vault mint check", () => {
+    it("rejects a vault of a different mint", async () => {
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 2);
+      const lockId = await createLock(user1, user1TokenAccount1, mint1, new anchor.BN(1_000_000_000), unlockTimestamp);
+      const otherLockId = await createLock(user1, user1TokenAccount2, mint2, new anchor.BN(1_000_000), unlockTimestamp);
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      const otherVaultBefore = await getAccount(provider.connection, getVaultPda(otherLockId));
+
+      try {
+        await program.methods
+          .unlock(null)
+          .accounts({
+            lock: getLockPda(lockId),
+            vault: getVaultPda(otherLockId),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(["ConstraintSeeds", "InvalidMint"]).to.include(err.error?.errorCode?.code);
+      }
+
+      const otherVaultAfter = await getAccount(provider.connection, getVaultPda(otherLockId));
+      expect(otherVaultAfter.amount).to.equal(otherVaultBefore.amount);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(false);
+    });
+  });
+
+  // ===========================================================================
+  // TIMELOCKED BENEFICIARY CHANGE
+  // ===========================================================================
+  describe("beneficiary change", () => {
+    const DEFAULT_DELAY = 2 * 86_400;
+    const TEST_DELAY = 3;
+    let lockId: number;
+
+    const setDelay = (delay: number) =>
+      program.methods
+        .setBeneficiaryChangeDelay(new anchor.BN(delay))
+        .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+        .rpc();
+
+    const requestChange = (newBeneficiary: PublicKey, signer: Keypair = user1) =>
+      program.methods
+        .requestBeneficiaryChange(newBeneficiary)
+        .accounts({ lock: getLockPda(lockId), owner: signer.publicKey })
+        .signers([signer])
+        .rpc({ commitment: "confirmed" });
+
+    const confirmChange = () =>
+      program.methods
+        .confirmBeneficiaryChange()
+        .accounts({ lock: getLockPda(lockId), owner: user1.publicKey })
+        .signers([user1])
+        .rpc();
+
+    const cancelChange = () =>
+      program.methods
+        .cancelBeneficiaryChange()
+        .accounts({ lock: getLockPda(lockId), owner: user1.publicKey })
+        .signers([user1])
+        .rpc();
+
+    const unlockTo = (destination: PublicKey) =>
+      program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: destination,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    before(async () => {
+      await setDelay(TEST_DELAY);
+      lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 8)
+      );
+    });
+
+    after(async () => {
+      await setDelay(DEFAULT_DELAY);
+    });
+
+    it("new locks pay out to their owner", async () => {
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.beneficiary.toBase58()).to.equal(user1.publicKey.toBase58());
+      expect(lock.pendingBeneficiary.toBase58()).to.equal(PublicKey.default.toBase58());
+    });
+
+    it("rejects a negative delay", async () => {
+      try {
+        await setDelay(-1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidDelay");
+      }
+    });
+
+    it("only the owner can request a change", async () => {
+      try {
+        await requestChange(user2.publicKey, user2);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("Unauthorized");
+      }
+    });
+
+    it("a cancelled request can't be confirmed", async () => {
+      await requestChange(user3.publicKey);
+      await cancelChange();
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.pendingBeneficiary.toBase58()).to.equal(PublicKey.default.toBase58());
+
+      try {
+        await confirmChange();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NoPendingBeneficiaryChange");
+      }
+    });
+
+    it("a request can only be confirmed after the delay", async () => {
+      const signature = await requestChange(user2.publicKey);
+
+      const events = await getEvents(signature);
+      const requested = events.find((e) => e.name === "beneficiaryChangeRequested");
+      expect(requested!.data.newBeneficiary.toBase58()).to.equal(user2.publicKey.toBase58());
+
+      try {
+        await confirmChange();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("BeneficiaryChangeTimelocked");
+      }
+
+      await new Promise((resolve) => setTimeout(resolve, (TEST_DELAY + 1) * 1000));
+      await confirmChange();
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.beneficiary.toBase58()).to.equal(user2.publicKey.toBase58());
+      expect(lock.pendingBeneficiary.toBase58()).to.equal(PublicKey.default.toBase58());
+    });
+
+    it("unlock pays the new beneficiary", async () => {
+      await new Promise((resolve) => setTimeout(resolve, 5000));
+
+      try {
+        await unlockTo(user1TokenAccount1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("ConstraintTokenOwner");
+      }
+
+      const before = await getAccount(provider.connection, user2TokenAccount1);
+      await unlockTo(user2TokenAccount1);
+      const after = await getAccount(provider.connection, user2TokenAccount1);
+
+      expect(Number(after.amount - before.amount)).to.equal(1_000_000_000);
+    });
+  });
+
+  // ===========================================================================
+  // PER-EPOCH RATE LIMIT
+  // ===========================================================================
+  describe("rate limit", () => {
+    const setMaxLocksPerEpoch = (max: number, signer?: Keypair) => {
+      const builder = program.methods
+        .setMaxLocksPerEpoch(new anchor.BN(max))
+        .accounts({
+          globalState: globalStatePda,
+          authority: signer ? signer.publicKey : authority.publicKey,
+        });
+      return signer ? builder.signers([signer]).rpc() : builder.rpc();
+    };
+
+    const lockOnce = () =>
+      createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+    after(async () => {
+      await setMaxLocksPerEpoch(0);
+    });
+
+    it("only the authority can set the cap", async () => {
+      try {
+        await setMaxLocksPerEpoch(1, user1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+
+    it("counts locks created during the current epoch", async () => {
+      await lockOnce();
+
+      const epoch = (await provider.connection.getEpochInfo()).epoch;
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      expect(globalState.rateLimitEpoch.toNumber()).to.be.at.most(epoch);
+      expect(globalState.locksThisEpoch.toNumber()).to.be.at.least(1);
+    });
+
+    it("rejects locks beyond the cap", async () => {
+      await setMaxLocksPerEpoch(1);
+
+      // The cap is reached by the first lock at the latest (earlier locks of this epoch count too)
+      try {
+        await lockOnce();
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("RateLimited");
+      }
+
+      const counterBefore = (await program.account.globalState.fetch(globalStatePda)).lockCounter.toNumber();
+      try {
+        await lockOnce();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("RateLimited");
+      }
+      const counterAfter = (await program.account.globalState.fetch(globalStatePda)).lockCounter.toNumber();
+      expect(counterAfter).to.equal(counterBefore);
+    });
+
+    it("a cap of 0 is unlimited", async () => {
+      await setMaxLocksPerEpoch(0);
+      await lockOnce();
+      await lockOnce();
+    });
+  });
+
+  // ===========================================================================
+  // RENT ESTIMATE
+  // ===========================================================================
+  describe("estimate_rent", () => {
+    it("matches the rent held by a new lock and its vault", async () => {
+      const estimate = await program.methods.estimateRent().accounts({ mint: mint1 }).view();
+
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+      const lockRent = await provider.connection.getBalance(getLockPda(lockId));
+      const vaultRent = await provider.connection.getBalance(getVaultPda(lockId));
+
+      expect(estimate.toNumber()).to.equal(lockRent + vaultRent);
+    });
+
+    it("accounts for Token-2022 vault extensions", async () => {
+      const feeMint = await createTransferFeeMint(6, 100);
+
+      const splEstimate = await program.methods.estimateRent().accounts({ mint: mint1 }).view();
+      const token2022Estimate = await program.methods.estimateRent().accounts({ mint: feeMint }).view();
+
+      expect(token2022Estimate.toNumber()).to.be.greaterThan(splEstimate.toNumber());
+    });
+
+    it("adds the index buckets and owner stats the lock creates on first use", async () => {
+      const mint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+      const tokenAccount = await createAssociatedTokenAccount(provider.connection, user2, mint, user2.publicKey);
+      await mintTo(provider.connection, mintAuthority, mint, tokenAccount, mintAuthority, 1_000_000);
+
+      // A day no other test locks until
+      const unlockTimestamp = new anchor.BN(
+        Math.floor(Date.now() / 1000) + 86400 * (2000 + Math.floor(Math.random() * 1000))
+      );
+      const expiryIndex = getExpiryIndexPda(unlockTimestamp);
+      const creationIndex = getCreationIndexPda();
+      const ownerMintStats = getOwnerMintStatsPda(user2.publicKey, mint);
+      const estimateRent = async (): Promise<number> =>
+        (
+          await program.methods.estimateRent().accounts({ mint, expiryIndex, creationIndex, ownerMintStats }).view()
+        ).toNumber();
+
+      const estimate = await estimateRent();
+      const creationIndexExisted = (await provider.connection.getAccountInfo(creationIndex)) !== null;
+      const lockId = await createLock(user2, tokenAccount, mint, new anchor.BN(1_000_000), unlockTimestamp);
+
+      const balance = (address: PublicKey) => provider.connection.getBalance(address);
+      const lockAndVault = (await balance(getLockPda(lockId))) + (await balance(getVaultPda(lockId)));
+      const created =
+        (await balance(expiryIndex)) +
+        (await balance(ownerMintStats)) +
+        (creationIndexExisted ? 0 : await balance(creationIndex));
+      expect(estimate).to.equal(lockAndVault + created);
+
+      // Once they exist, only the Lock account and its vault remain
+      expect(await estimateRent()).to.equal(lockAndVault);
+    });
+  });
+
+  // ===========================================================================
+  // EXTEND WITH TOP-UP
+  // ===========================================================================
+  describe("extend_with_top_up", () => {
+    const initialAmount = 1_000_000_000;
+    const initialTimestamp = Math.floor(Date.now() / 1000) + 3600;
+    let lockId: number;
+
+    const extendWithTopUp = async (newTimestamp: number, additionalAmount: number) => {
+      const lockPda = getLockPda(lockId);
+      return program.methods
+        .extendWithTopUp(new anchor.BN(newTimestamp), new anchor.BN(additionalAmount))
+        .accounts({
+          lock: lockPda,
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          oldExpiryIndex: await getLockExpiryIndexPda(lockPda),
+          newExpiryIndex: getExpiryIndexPda(newTimestamp),
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+    };
+
+    before(async () => {
+      lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(initialAmount),
+        new anchor.BN(initialTimestamp)
+      );
+    });
+
+    it("adds tokens and extends in one step", async () => {
+      const newTimestamp = initialTimestamp + 2 * 86_400;
+      await extendWithTopUp(newTimestamp, 500_000_000);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.amount.toNumber()).to.equal(initialAmount + 500_000_000);
+      expect(lock.unlockTimestamp.toNumber()).to.equal(newTimestamp);
+
+      const vault = await getAccount(provider.connection, getVaultPda(lockId));
+      expect(Number(vault.amount)).to.equal(initialAmount + 500_000_000);
+
+      const index = await program.account.expiryIndex.fetch(getExpiryIndexPda(newTimestamp));
+      expect(index.lockIds.map((id: anchor.BN) => id.toNumber())).to.include(lockId);
+    });
+
+    it("emits ToppedUp then Extended", async () => {
+      const before = await program.account.lock.fetch(getLockPda(lockId));
+      const newTimestamp = before.unlockTimestamp.toNumber() + 60;
+
+      const signature = await extendWithTopUp(newTimestamp, 1_000);
+
+      const events = await getEvents(signature);
+      expect(events.map((e) => e.name)).to.deep.equal(["toppedUp", "extended"]);
+      expect(events[0].data.amount.toNumber()).to.equal(1_000);
+      expect(events[0].data.newAmount.toNumber()).to.equal(before.amount.toNumber() + 1_000);
+      expect(events[1].data.oldUnlockTimestamp.toNumber()).to.equal(before.unlockTimestamp.toNumber());
+      expect(events[1].data.newUnlockTimestamp.toNumber()).to.equal(newTimestamp);
+    });
+
+    it("keeps the amount when the timestamp rule fails", async () => {
+      const before = await program.account.lock.fetch(getLockPda(lockId));
+
+      try {
+        await extendWithTopUp(before.unlockTimestamp.toNumber() - 1, 500_000_000);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("CannotShortenTimestamp");
+      }
+
+      const after = await program.account.lock.fetch(getLockPda(lockId));
+      expect(after.amount.toString()).to.equal(before.amount.toString());
+    });
+
+    it("keeps the timestamp when the amount rule fails", async () => {
+      const before = await program.account.lock.fetch(getLockPda(lockId));
+
+      try {
+        await extendWithTopUp(before.unlockTimestamp.toNumber() + 86_400, 0);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AmountZero");
+      }
+
+      const after = await program.account.lock.fetch(getLockPda(lockId));
+      expect(after.unlockTimestamp.toString()).to.equal(before.unlockTimestamp.toString());
+    });
+  });
+
+  // ===========================================================================
+  // EXTEND SWITCH
+  // ===========================================================================
+  describe("allow_extend", () => {
+    const setAllowExtend = (allowExtend: boolean) =>
+      program.methods
+        .setAllowExtend(allowExtend)
+        .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+        .rpc();
+
+    const extendLock = async (lockId: number, newTimestamp: number) => {
+      const lockPda = getLockPda(lockId);
+      await program.methods
+        .extend(new anchor.BN(newTimestamp))
+        .accounts({
+          lock: lockPda,
+          oldExpiryIndex: await getLockExpiryIndexPda(lockPda),
+          newExpiryIndex: getExpiryIndexPda(newTimestamp),
+          owner: user1.publicKey,
+        })
+        .signers([user1])
+        .rpc();
+    };
+
+    after(async () => {
+      await setAllowExtend(true);
+    });
+
+    it("extend is allowed by default", async () => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      expect(globalState.allowExtend).to.equal(true);
+    });
+
+    it("only the authority can toggle extend", async () => {
+      try {
+        await program.methods
+          .setAllowExtend(false)
+          .accounts({ globalState: globalStatePda, authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+
+    it("rejects extend while disabled and accepts it again once re-enabled", async () => {
+      const unlockTimestamp = Math.floor(Date.now() / 1000) + 3600;
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(unlockTimestamp)
+      );
+
+      await setAllowExtend(false);
+      try {
+        await extendLock(lockId, unlockTimestamp + 3600);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("ExtendDisabled");
+      }
+
+      await setAllowExtend(true);
+      await extendLock(lockId, unlockTimestamp + 3600);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.unlockTimestamp.toNumber()).to.equal(unlockTimestamp + 3600);
+    });
+  });
+
+  // ===========================================================================
+  // REDUCIBLE LOCKS
+  // ===========================================================================
+  describe("reduce", () => {
+    const lockedAmount = 1_000_000_000;
+
+    const reduce = (lockId: number, amount: number) =>
+      program.methods
+        .reduce(new anchor.BN(amount))
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    const createReducibleLock = (reducible: boolean) =>
+      createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(lockedAmount),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+        { ...defaultLockOptions(), reducible }
+      );
+
+    it("rejects locks not created as reducible", async () => {
+      const lockId = await createReducibleLock(false);
+
+      try {
+        await reduce(lockId, 1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotReducible");
+      }
+    });
+
+    it("withdraws part of a reducible lock before maturity", async () => {
+      const lockId = await createReducibleLock(true);
+      const before = await getAccount(provider.connection, user1TokenAccount1);
+
+      await reduce(lockId, lockedAmount / 4);
+
+      const after = await getAccount(provider.connection, user1TokenAccount1);
+      expect(Number(after.amount - before.amount)).to.equal(lockedAmount / 4);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.reducible).to.equal(true);
+      expect(lock.amount.toNumber()).to.equal((lockedAmount * 3) / 4);
+      expect(lock.isUnlocked).to.equal(false);
+    });
+
+    it("rejects reducing by more than what is left", async () => {
+      const lockId = await createReducibleLock(true);
+
+      try {
+        await reduce(lockId, lockedAmount + 1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InsufficientLockedAmount");
+      }
+    });
+
+    it("reducing to zero closes out the lock", async () => {
+      const lockId = await createReducibleLock(true);
+
+      await reduce(lockId, lockedAmount);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.amount.toNumber()).to.equal(0);
+      expect(lock.isUnlocked).to.equal(true);
+    });
+  });
+
+  // ===========================================================================
+  // UNLOCK MEMO
+  // ===========================================================================
+  describe("unlock memo", () => {
+    const MEMO_PROGRAM_ID = new PublicKey("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+
+    const unlockWithMemo = (lockId: number, memo: string) =>
+      program.methods
+        .unlock(memo)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          memoProgram: MEMO_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc({ commitment: "confirmed" });
+
+    let lockId: number;
+
+    before(async () => {
+      lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+    });
+
+    it("rejects memos longer than 128 bytes", async () => {
+      try {
+        await unlockWithMemo(lockId, "x".repeat(129));
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("MemoTooLong");
+      }
+    });
+
+    it("attaches the memo to the unlock transfer", async () => {
+      const memo = "deposit ref 42";
+      const signature = await unlockWithMemo(lockId, memo);
+
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const logs = (tx?.meta?.logMessages ?? []).join("\n");
+      expect(logs).to.include(MEMO_PROGRAM_ID.toBase58());
+      expect(logs).to.include(memo);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+    });
+  });
+
+  // ===========================================================================
+  // UNLOCK RECIPIENT OVERRIDE
+  // ===========================================================================
+  describe("unlock recipient override", () => {
+    const unlockToRecipient = (lockId: number, recipient: PublicKey) =>
+      program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          recipientTokenAccount: recipient,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    const createMaturingLock = () =>
+      createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+
+    const setDelay = (delay: number) =>
+      program.methods
+        .setBeneficiaryChangeDelay(new anchor.BN(delay))
+        .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+        .rpc();
+
+    it("sends unlocked tokens to a different recipient", async () => {
+      const lockId = await createMaturingLock();
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      const ownerBefore = await getAccount(provider.connection, user1TokenAccount1);
+      const recipientBefore = await getAccount(provider.connection, user2TokenAccount1);
+
+      await unlockToRecipient(lockId, user2TokenAccount1);
+
+      const ownerAfter = await getAccount(provider.connection, user1TokenAccount1);
+      const recipientAfter = await getAccount(provider.connection, user2TokenAccount1);
+      expect(ownerAfter.amount).to.equal(ownerBefore.amount);
+      expect(Number(recipientAfter.amount - recipientBefore.amount)).to.equal(1_000_000_000);
+    });
+
+    it("rejects the vault as recipient", async () => {
+      const lockId = await createMaturingLock();
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      try {
+        await unlockToRecipient(lockId, getVaultPda(lockId));
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("DuplicateAccounts");
+      }
+    });
+
+    it("rejects overrides once the beneficiary was changed", async () => {
+      const lockId = await createMaturingLock();
+
+      await setDelay(0);
+      try {
+        await program.methods
+          .requestBeneficiaryChange(user2.publicKey)
+          .accounts({ lock: getLockPda(lockId), owner: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        await program.methods
+          .confirmBeneficiaryChange()
+          .accounts({ lock: getLockPda(lockId), owner: user1.publicKey })
+          .signers([user1])
+          .rpc();
+      } finally {
+        await setDelay(2 * 86_400);
+      }
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      try {
+        await program.methods
+          .unlock(null)
+          .accounts({
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            mint: mint1,
+            ownerTokenAccount: user2TokenAccount1,
+            recipientTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("RecipientOverrideNotAllowed");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // TOKEN PROGRAM PINNING
+  // ===========================================================================
+  describe("lock token program", () => {
+    let lockId: number;
+
+    before(async () => {
+      lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+    });
+
+    it("stores the token program used at creation", async () => {
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.tokenProgram.toBase58()).to.equal(TOKEN_PROGRAM_ID.toBase58());
+      expect(lock.isToken2022).to.equal(false);
+    });
+
+    it("rejects top_up with the other token program", async () => {
+      try {
+        await program.methods
+          .topUp(new anchor.BN(1_000))
+          .accounts({
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            tokenProgram: TOKEN_2022_PROGRAM_ID,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("WrongTokenProgram");
+      }
+    });
+
+    it("rejects unlock with the other token program", async () => {
+      try {
+        await program.methods
+          .unlock(null)
+          .accounts({
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            tokenProgram: TOKEN_2022_PROGRAM_ID,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("WrongTokenProgram");
+      }
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(false);
+    });
+  });
+
+  // ===========================================================================
+  // GLOBAL STATE BOOTSTRAP
+  // ===========================================================================
+  // The bootstrap itself only runs on a fresh deployment, before `initialize` (see README);
+  // here the global state already exists, so locks must leave it untouched.
+  describe("global state bootstrap", () => {
+    const BPF_LOADER_UPGRADEABLE_ID = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+
+    it("a lock passing program data doesn't re-bootstrap an initialized program", async () => {
+      const [programData] = PublicKey.findProgramAddressSync(
+        [program.programId.toBuffer()],
+        BPF_LOADER_UPGRADEABLE_ID
+      );
+
+      const before = await program.account.globalState.fetch(globalStatePda);
+      const lockId = before.lockCounter.toNumber();
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+      await program.methods
+        .lock(new anchor.BN(1_000_000), unlockTimestamp, null)
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
+          mint: mint1,
+          ownerTokenAccount: user2TokenAccount1,
+          owner: user2.publicKey,
+          feeRecipient: FEE_RECIPIENT,
+          programData,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user2])
+        .rpc();
+
+      const after = await program.account.globalState.fetch(globalStatePda);
+      expect(after.authority.toBase58()).to.equal(before.authority.toBase58());
+      expect(after.lockCounter.toNumber()).to.equal(lockId + 1);
+      expect(after.allowExtend).to.equal(before.allowExtend);
+    });
+
+    it("initialize_and_lock only locks once the program is initialized", async () => {
+      const [programData] = PublicKey.findProgramAddressSync(
+        [program.programId.toBuffer()],
+        BPF_LOADER_UPGRADEABLE_ID
+      );
+
+      const before = await program.account.globalState.fetch(globalStatePda);
+      const lockId = before.lockCounter.toNumber();
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+      await program.methods
+        .initializeAndLock(new anchor.BN(1_000_000), unlockTimestamp)
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
+          mint: mint1,
+          ownerTokenAccount: user2TokenAccount1,
+          owner: user2.publicKey,
+          feeRecipient: FEE_RECIPIENT,
+          programData,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user2])
+        .rpc();
+
+      const after = await program.account.globalState.fetch(globalStatePda);
+      expect(after.authority.toBase58()).to.equal(before.authority.toBase58());
+      expect(after.lockCounter.toNumber()).to.equal(lockId + 1);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.owner.toBase58()).to.equal(user2.publicKey.toBase58());
+      expect(lock.amount.toNumber()).to.equal(1_000_000);
+    });
+  });
+
+  // ===========================================================================
+  // TIMESTAMP ALIGNMENT
+  // ===========================================================================
+  describe("aligned timestamps", () => {
+    const setRequireAligned = (required: boolean) =>
+      program.methods
+        .setRequireAlignedTimestamps(required)
+        .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+        .rpc();
+
+    // A whole minute at least an hour from now
+    const alignedTimestamp = () => (Math.floor(Date.now() / 1000 / 60) + 60) * 60;
+
+    before(async () => {
+      await setRequireAligned(true);
+    });
+
+    after(async () => {
+      await setRequireAligned(false);
+    });
+
+    it("rejects unaligned unlock timestamps", async () => {
+      try {
+        await createLock(user1, user1TokenAccount1, mint1, new anchor.BN(1_000_000), new anchor.BN(alignedTimestamp() + 1));
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TimestampNotAligned");
+      }
+    });
+
+    it("accepts whole minutes", async () => {
+      const unlockTimestamp = alignedTimestamp();
+      const lockId = await createLock(user1, user1TokenAccount1, mint1, new anchor.BN(1_000_000), new anchor.BN(unlockTimestamp));
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.unlockTimestamp.toNumber()).to.equal(unlockTimestamp);
+    });
+
+    it("applies to extensions too", async () => {
+      const unlockTimestamp = alignedTimestamp();
+      const lockId = await createLock(user1, user1TokenAccount1, mint1, new anchor.BN(1_000_000), new anchor.BN(unlockTimestamp));
+      const lockPda = getLockPda(lockId);
+
+      try {
+        await program.methods
+          .extend(new anchor.BN(unlockTimestamp + 90))
+          .accounts({
+            lock: lockPda,
+            oldExpiryIndex: await getLockExpiryIndexPda(lockPda),
+            newExpiryIndex: getExpiryIndexPda(unlockTimestamp + 90),
+            owner: user1.publicKey,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TimestampNotAligned");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // UNLOCK AUTHORIZATION VIEW
+  // ===========================================================================
+  describe("is_authorized_unlocker", () => {
+    let lockId: number;
+
+    const isAuthorizedUnlocker = (
+      candidate: PublicKey,
+      id: number = lockId,
+      preimage: Buffer | null = null
+    ): Promise<boolean> =>
+      program.methods.isAuthorizedUnlocker(candidate, preimage).accounts({ lock: getLockPda(id) }).view();
+
+    const lockWithOptions = (options: Partial<ReturnType<typeof defaultLockOptions>>) =>
+      createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+        { ...defaultLockOptions(), ...options }
+      );
+
+    before(async () => {
+      lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+    });
+
+    it("is true for the owner", async () => {
+      expect(await isAuthorizedUnlocker(user1.publicKey)).to.equal(true);
+    });
+
+    it("is false for anyone else", async () => {
+      expect(await isAuthorizedUnlocker(user2.publicKey)).to.equal(false);
+      expect(await isAuthorizedUnlocker(user3.publicKey)).to.equal(false);
+    });
+
+    it("is true for the authority, through assisted_unlock", async () => {
+      expect(await isAuthorizedUnlocker(authority.publicKey)).to.equal(true);
+
+      // Only unlock settles a deposit
+      const depositLockId = await lockWithOptions({
+        depositLamports: new anchor.BN(0.01 * LAMPORTS_PER_SOL),
+        claimWindow: new anchor.BN(600),
+        allowPartial: false,
+      });
+      expect(await isAuthorizedUnlocker(authority.publicKey, depositLockId)).to.equal(false);
+      expect(await isAuthorizedUnlocker(user1.publicKey, depositLockId)).to.equal(true);
+    });
+
+    it("is true for the arbiter of an escrow", async () => {
+      const escrowLockId = await lockWithOptions({ arbiter: user3.publicKey, counterparty: user2.publicKey });
+      expect(await isAuthorizedUnlocker(user3.publicKey, escrowLockId)).to.equal(true);
+      expect(await isAuthorizedUnlocker(user2.publicKey, escrowLockId)).to.equal(false);
+    });
+
+    it("is true for anyone on a bounty lock, through keeper_unlock", async () => {
+      const bountyLockId = await lockWithOptions({ bountyBps: 100 });
+      expect(await isAuthorizedUnlocker(user2.publicKey, bountyLockId)).to.equal(true);
+      expect(await isAuthorizedUnlocker(Keypair.generate().publicKey, bountyLockId)).to.equal(true);
+    });
+
+    it("is true for a listed heir", async () => {
+      const heirLockId = (await program.account.globalState.fetch(globalStatePda)).lockCounter.toNumber();
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+      await program.methods
+        .lockWithHeirs(new anchor.BN(1_000_000), unlockTimestamp, [
+          { heir: user2.publicKey, graceOffset: new anchor.BN(60) },
+        ])
+        .accounts({
+          lockTokens: {
+            globalState: globalStatePda,
+            lock: getLockPda(heirLockId),
+            vault: getVaultPda(heirLockId),
+            expiryIndex: getExpiryIndexPda(unlockTimestamp),
+            creationIndex: getCreationIndexPda(),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            feeRecipient: FEE_RECIPIENT,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          },
+          heirs: PublicKey.findProgramAddressSync(
+            [Buffer.from("heirs"), new anchor.BN(heirLockId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+          )[0],
+        })
+        .signers([user1])
+        .rpc();
+
+      expect(await isAuthorizedUnlocker(user2.publicKey, heirLockId)).to.equal(true);
+      expect(await isAuthorizedUnlocker(user3.publicKey, heirLockId)).to.equal(false);
+    });
+
+    it("is true for the claimant the secret is bound to, given the preimage", async () => {
+      const preimage = Buffer.from("view-code");
+      const secretHash = [
+        ...createHash("sha256")
+          .update(Buffer.concat([preimage, user2.publicKey.toBuffer()]))
+          .digest(),
+      ];
+      const secretLockId = await lockWithOptions({ secretHash });
+
+      expect(await isAuthorizedUnlocker(user2.publicKey, secretLockId, preimage)).to.equal(true);
+      expect(await isAuthorizedUnlocker(user2.publicKey, secretLockId)).to.equal(false);
+      expect(await isAuthorizedUnlocker(user3.publicKey, secretLockId, preimage)).to.equal(false);
+    });
+  });
+
+  // ===========================================================================
+  // LOCK ALL
+  // ===========================================================================
+  describe("lock_all", () => {
+    let mint: PublicKey;
+    let tokenAccount: PublicKey;
+    const balance = 5_000_000;
+
+    const lockAll = async (unlockTimestamp: anchor.BN): Promise<number> => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+
+      await program.methods
+        .lockAll(unlockTimestamp)
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
+          mint,
+          ownerTokenAccount: tokenAccount,
+          owner: user2.publicKey,
+          feeRecipient: FEE_RECIPIENT,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user2])
+        .rpc();
+
+      return lockId;
+    };
+
+    before(async () => {
+      mint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+      tokenAccount = await createAssociatedTokenAccount(provider.connection, user2, mint, user2.publicKey);
+      await mintTo(provider.connection, mintAuthority, mint, tokenAccount, mintAuthority, balance);
+    });
+
+    it("locks the whole balance", async () => {
+      const lockId = await lockAll(new anchor.BN(Math.floor(Date.now() / 1000) + 3600));
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.amount.toNumber()).to.equal(balance);
+
+      const vault = await getAccount(provider.connection, getVaultPda(lockId));
+      expect(Number(vault.amount)).to.equal(balance);
+
+      const account = await getAccount(provider.connection, tokenAccount);
+      expect(Number(account.amount)).to.equal(0);
+    });
+
+    it("rejects an empty balance", async () => {
+      try {
+        await lockAll(new anchor.BN(Math.floor(Date.now() / 1000) + 3600));
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AmountZero");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // BATCH UNLOCK
+  // ===========================================================================
+  describe("batch_unlock", () => {
+    const batchAccounts = (lockId: number, mint: PublicKey, destination: PublicKey) => [
+      { pubkey: getLockPda(lockId), isSigner: false, isWritable: true },
+      { pubkey: getVaultPda(lockId), isSigner: false, isWritable: true },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      { pubkey: destination, isSigner: false, isWritable: true },
+      { pubkey: getMintStatsPda(mint), isSigner: false, isWritable: true },
+      { pubkey: getOwnerMintStatsPda(user1.publicKey, mint), isSigner: false, isWritable: true },
+    ];
+
+    const batchUnlock = (remainingAccounts: ReturnType<typeof batchAccounts>) =>
+      program.methods
+        .batchUnlock()
+        .accounts({ owner: user1.publicKey, tokenProgram: TOKEN_PROGRAM_ID })
+        .remainingAccounts(remainingAccounts)
+        .signers([user1])
+        .rpc();
+
+    const createMaturingLock = (tokenAccount: PublicKey, mint: PublicKey, secondsFromNow = 2) =>
+      createLock(
+        user1,
+        tokenAccount,
+        mint,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + secondsFromNow)
+      );
+
+    it("unlocks several matured locks of different mints at once", async () => {
+      const first = await createMaturingLock(user1TokenAccount1, mint1);
+      const second = await createMaturingLock(user1TokenAccount1, mint1);
+      const third = await createMaturingLock(user1TokenAccount2, mint2);
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      const mint2Before = await getAccount(provider.connection, user1TokenAccount2);
+
+      await batchUnlock([
+        ...batchAccounts(first, mint1, user1TokenAccount1),
+        ...batchAccounts(second, mint1, user1TokenAccount1),
+        ...batchAccounts(third, mint2, user1TokenAccount2),
+      ]);
+
+      for (const lockId of [first, second, third]) {
+        const lock = await program.account.lock.fetch(getLockPda(lockId));
+        expect(lock.isUnlocked).to.equal(true);
+      }
+      const mint2After = await getAccount(provider.connection, user1TokenAccount2);
+      expect(Number(mint2After.amount - mint2Before.amount)).to.equal(1_000_000);
+    });
+
+    it("reverts the whole batch when one lock isn't matured", async () => {
+      const matured = await createMaturingLock(user1TokenAccount1, mint1);
+      const pending = await createMaturingLock(user1TokenAccount1, mint1, 3600);
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      try {
+        await batchUnlock([
+          ...batchAccounts(matured, mint1, user1TokenAccount1),
+          ...batchAccounts(pending, mint1, user1TokenAccount1),
+        ]);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TooEarly");
+      }
+
+      const lock = await program.account.lock.fetch(getLockPda(matured));
+      expect(lock.isUnlocked).to.equal(false);
+    });
+
+    it("rejects locks of another owner", async () => {
+      const lockId = await createLock(
+        user2,
+        user2TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      try {
+        await batchUnlock(batchAccounts(lockId, mint1, user1TokenAccount1));
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("Unauthorized");
+      }
+    });
+
+    it("rejects incomplete account groups", async () => {
+      const lockId = await createMaturingLock(user1TokenAccount1, mint1);
+
+      try {
+        await batchUnlock(batchAccounts(lockId, mint1, user1TokenAccount1).slice(0, 4));
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidBatch");
+      }
+    });
+
+    it("rejects batches over the authority's cap", async () => {
+      const setMaxBatchSize = (maxBatchSize: number) =>
+        program.methods
+          .setMaxBatchSize(maxBatchSize)
+          .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+          .rpc();
+
+      let globalState = await program.account.globalState.fetch(globalStatePda);
+      expect(globalState.maxBatchSize).to.equal(16);
+
+      const first = await createMaturingLock(user1TokenAccount1, mint1);
+      const second = await createMaturingLock(user1TokenAccount1, mint1);
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      await setMaxBatchSize(1);
+      try {
+        await batchUnlock([
+          ...batchAccounts(first, mint1, user1TokenAccount1),
+          ...batchAccounts(second, mint1, user1TokenAccount1),
+        ]);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("BatchTooLarge");
+      }
+      // A batch at the cap still goes through
+      await batchUnlock(batchAccounts(first, mint1, user1TokenAccount1));
+
+      try {
+        await setMaxBatchSize(0);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidBatchSize");
+      }
+      try {
+        await program.methods
+          .setMaxBatchSize(32)
+          .accounts({ globalState: globalStatePda, authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+
+      await setMaxBatchSize(16);
+      globalState = await program.account.globalState.fetch(globalStatePda);
+      expect(globalState.maxBatchSize).to.equal(16);
+    });
+  });
+
+  // ===========================================================================
+  // IDEMPOTENT UNLOCK TIMESTAMP
+  // ===========================================================================
+  describe("set_unlock_timestamp", () => {
+    const setUnlockTimestamp = async (lockId: number, unlockTimestamp: number) => {
+      const lockPda = getLockPda(lockId);
+      await program.methods
+        .setUnlockTimestamp(new anchor.BN(unlockTimestamp))
+        .accounts({
+          lock: lockPda,
+          oldExpiryIndex: await getLockExpiryIndexPda(lockPda),
+          newExpiryIndex: getExpiryIndexPda(unlockTimestamp),
+          owner: user1.publicKey,
+        })
+        .signers([user1])
+        .rpc();
+    };
+
+    it("succeeds without changing state when the timestamp is unchanged", async () => {
+      const unlockTimestamp = Math.floor(Date.now() / 1000) + 3600;
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(unlockTimestamp)
+      );
+      const before = await provider.connection.getAccountInfo(getLockPda(lockId));
+
+      await setUnlockTimestamp(lockId, unlockTimestamp);
+
+      const after = await provider.connection.getAccountInfo(getLockPda(lockId));
+      expect(after!.data.equals(before!.data)).to.equal(true);
+    });
+
+    it("extends like extend when the timestamp changes, and retries are no-ops", async () => {
+      const unlockTimestamp = Math.floor(Date.now() / 1000) + 3600;
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(unlockTimestamp)
+      );
+
+      await setUnlockTimestamp(lockId, unlockTimestamp + 3600);
+      await setUnlockTimestamp(lockId, unlockTimestamp + 3600);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.unlockTimestamp.toNumber()).to.equal(unlockTimestamp + 3600);
+    });
+
+    it("still refuses to shorten the lock", async () => {
+      const unlockTimestamp = Math.floor(Date.now() / 1000) + 3600;
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(unlockTimestamp)
+      );
+
+      try {
+        await setUnlockTimestamp(lockId, unlockTimestamp - 60);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("CannotShortenTimestamp");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // FUNDING PRE-CHECKS
+  // ===========================================================================
+  describe("lock funding checks", () => {
+    it("rejects a lock larger than the token balance with InsufficientTokenBalance", async () => {
+      const account = await getAccount(provider.connection, user3TokenAccount1);
+
+      try {
+        await createLock(
+          user3,
+          user3TokenAccount1,
+          mint1,
+          new anchor.BN(account.amount.toString()).addn(1),
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+        );
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InsufficientTokenBalance");
+      }
+    });
+
+    it("rejects an owner who can't pay the fee with InsufficientLamports", async () => {
+      // Enough for the Lock and vault rent, not for the 0.03 SOL fee
+      const owner = Keypair.generate();
+      const sig = await provider.connection.requestAirdrop(owner.publicKey, 0.02 * LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(sig);
+
+      const tokenAccount = await createAssociatedTokenAccount(
+        provider.connection,
+        mintAuthority,
+        mint1,
+        owner.publicKey
+      );
+      await mintTo(provider.connection, mintAuthority, mint1, tokenAccount, mintAuthority, 1_000_000);
+
+      try {
+        await createLock(
+          owner,
+          tokenAccount,
+          mint1,
+          new anchor.BN(1_000_000),
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+        );
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InsufficientLamports");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // LOCK TEMPLATES
+  // ===========================================================================
+  describe("lock templates", () => {
+    const templateId = new anchor.BN(1);
+    const duration = 3600;
+
+    const getTemplatePda = (id: anchor.BN): PublicKey =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("template"), id.toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+
+    const setLockTemplate = (id: anchor.BN, templateDuration: number, reducible: boolean) =>
+      program.methods
+        .setLockTemplate(id, new anchor.BN(templateDuration), reducible)
+        .accounts({ authority: authority.publicKey })
+        .rpc();
+
+    const lockFromTemplate = async (id: anchor.BN): Promise<number> => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+
+      await program.methods
+        .lockFromTemplate(id, new anchor.BN(1_000_000))
+        .accounts({
+          lockTokens: {
+            globalState: globalStatePda,
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            expiryIndex: getExpiryIndexPda(Math.floor(Date.now() / 1000) + duration),
+            creationIndex: getCreationIndexPda(),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            feeRecipient: FEE_RECIPIENT,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          },
+          template: getTemplatePda(id),
+        })
+        .signers([user1])
+        .rpc();
+
+      return lockId;
+    };
+
+    it("only the authority can define templates", async () => {
+      try {
+        await program.methods
+          .setLockTemplate(templateId, new anchor.BN(duration), true)
+          .accounts({ authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+
+    it("creates locks with the template's settings", async () => {
+      await setLockTemplate(templateId, duration, true);
+
+      const before = Math.floor(Date.now() / 1000);
+      const lockId = await lockFromTemplate(templateId);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.reducible).to.equal(true);
+      expect(lock.unlockTimestamp.toNumber() - lock.createdAt.toNumber()).to.equal(duration);
+      expect(lock.unlockTimestamp.toNumber()).to.be.closeTo(before + duration, 30);
+    });
+
+    it("applies template changes to later locks only", async () => {
+      const firstLockId = await lockFromTemplate(templateId);
+      await setLockTemplate(templateId, duration, false);
+      const secondLockId = await lockFromTemplate(templateId);
+
+      const first = await program.account.lock.fetch(getLockPda(firstLockId));
+      const second = await program.account.lock.fetch(getLockPda(secondLockId));
+      expect(first.reducible).to.equal(true);
+      expect(second.reducible).to.equal(false);
+    });
+
+    it("rejects a non-positive duration", async () => {
+      try {
+        await setLockTemplate(templateId, 0, false);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidTemplate");
+      }
+    });
+
+    it("rejects an unknown template with TemplateNotFound", async () => {
+      try {
+        await lockFromTemplate(new anchor.BN(999_999));
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TemplateNotFound");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // CANONICAL VAULT BUMP (only runs against builds with the `test-clock` feature)
+  // ===========================================================================
+  describe("canonical vault bump", () => {
+    before(function () {
+      if (!hasInstruction("set_mock_vault_bump")) {
+        this.skip();
+      }
+    });
+
+    const setVaultBump = (lockId: number, vaultBump: number) =>
+      program.methods
+        .setMockVaultBump(vaultBump)
+        .accounts({ lock: getLockPda(lockId), authority: authority.publicKey })
+        .rpc();
+
+    const unlock = (lockId: number) =>
+      program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    it("rejects unlock of a lock whose stored vault bump was tampered with", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      const { vaultBump } = await program.account.lock.fetch(getLockPda(lockId));
+      await setVaultBump(lockId, vaultBump - 1);
+
+      try {
+        await unlock(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidBump");
+      }
+
+      await setVaultBump(lockId, vaultBump);
+      await unlock(lockId);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+    });
+  });
+
+  // ===========================================================================
+  // FROZEN DEPOSITS
+  // ===========================================================================
+  describe("deposits frozen", () => {
+    let mint: PublicKey;
+    let tokenAccount: PublicKey;
+
+    const setDepositsFrozen = (depositsFrozen: boolean) =>
+      program.methods
+        .setDepositsFrozen(depositsFrozen)
+        .accounts({ mint, authority: authority.publicKey })
+        .rpc();
+
+    const lock = (secondsFromNow: number) =>
+      createLock(
+        user2,
+        tokenAccount,
+        mint,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + secondsFromNow)
+      );
+
+    before(async () => {
+      mint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+      tokenAccount = await createAssociatedTokenAccount(provider.connection, user2, mint, user2.publicKey);
+      await mintTo(provider.connection, mintAuthority, mint, tokenAccount, mintAuthority, 10_000_000);
+    });
+
+    it("only the authority can freeze deposits", async () => {
+      try {
+        await program.methods
+          .setDepositsFrozen(true)
+          .accounts({ mint, authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+
+    it("rejects new locks and top-ups of a frozen mint but still allows unlock", async () => {
+      const lockId = await lock(2);
+      await setDepositsFrozen(true);
+
+      try {
+        await lock(3600);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("DepositsFrozen");
+      }
+
+      try {
+        await program.methods
+          .topUp(new anchor.BN(1_000))
+          .accounts({
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            mint,
+            ownerTokenAccount: tokenAccount,
+            owner: user2.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user2])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("DepositsFrozen");
+      }
+
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      await program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint,
+          ownerTokenAccount: tokenAccount,
+          owner: user2.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user2])
+        .rpc();
+
+      const unlocked = await program.account.lock.fetch(getLockPda(lockId));
+      expect(unlocked.isUnlocked).to.equal(true);
+    });
+
+    it("accepts deposits again once unfrozen", async () => {
+      await setDepositsFrozen(false);
+      const lockId = await lock(3600);
+
+      const created = await program.account.lock.fetch(getLockPda(lockId));
+      expect(created.amount.toNumber()).to.equal(1_000_000);
+    });
+  });
+
+  // ===========================================================================
+  // ADMIN CONFIG AND AUDIT EVENTS
+  // ===========================================================================
+  describe("config changes", () => {
+    const confirmed = { commitment: "confirmed" as const };
+
+    const configChanged = async (signature: string) => {
+      const events = await getEvents(signature);
+      return events.find((e) => e.name === "configChanged")!.data;
+    };
+
+    const lock = () =>
+      createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+    after(async () => {
+      await program.methods.setFee(new anchor.BN(30_000_000)).accounts({ authority: authority.publicKey }).rpc();
+      await program.methods.setFeeRecipient(FEE_RECIPIENT).accounts({ authority: authority.publicKey }).rpc();
+      await program.methods.unpause().accounts({ authority: authority.publicKey }).rpc();
+    });
+
+    it("only the authority can change the config", async () => {
+      try {
+        await program.methods
+          .setFee(new anchor.BN(0))
+          .accounts({ authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+
+    it("set_fee changes the fee and emits ConfigChanged", async () => {
+      const signature = await program.methods
+        .setFee(new anchor.BN(1_000_000))
+        .accounts({ authority: authority.publicKey })
+        .rpc(confirmed);
+
+      const event = await configChanged(signature);
+      expect(event.field).to.equal("fee_amount");
+      expect(event.oldValue).to.equal("30000000");
+      expect(event.newValue).to.equal("1000000");
+
+      const before = await provider.connection.getBalance(FEE_RECIPIENT);
+      await lock();
+      const after = await provider.connection.getBalance(FEE_RECIPIENT);
+      expect(after - before).to.equal(1_000_000);
+    });
+
+    it("the older switches emit ConfigChanged too", async () => {
+      const before = await program.account.globalState.fetch(globalStatePda);
+      const toggles = [
+        {
+          field: "denylist_enabled",
+          old: before.denylistEnabled,
+          set: (value: boolean) => program.methods.setDenylistEnabled(value),
+        },
+        {
+          field: "allow_extend",
+          old: before.allowExtend,
+          set: (value: boolean) => program.methods.setAllowExtend(value),
+        },
+        {
+          field: "require_aligned_timestamps",
+          old: before.requireAlignedTimestamps,
+          set: (value: boolean) => program.methods.setRequireAlignedTimestamps(value),
+        },
+      ];
+
+      for (const { field, old, set } of toggles) {
+        const signature = await set(!old)
+          .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+          .rpc(confirmed);
+        const event = await configChanged(signature);
+        expect(event.field).to.equal(field);
+        expect(event.oldValue).to.equal(String(old));
+        expect(event.newValue).to.equal(String(!old));
+
+        await set(old).accounts({ globalState: globalStatePda, authority: authority.publicKey }).rpc();
+      }
+
+      const signature = await program.methods
+        .setMaxLocksPerEpoch(before.maxLocksPerEpoch)
+        .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+        .rpc(confirmed);
+      const event = await configChanged(signature);
+      expect(event.field).to.equal("max_locks_per_epoch");
+      expect(event.newValue).to.equal(before.maxLocksPerEpoch.toString());
+
+      const delaySignature = await program.methods
+        .setBeneficiaryChangeDelay(before.beneficiaryChangeDelay)
+        .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+        .rpc(confirmed);
+      const delayEvent = await configChanged(delaySignature);
+      expect(delayEvent.field).to.equal("beneficiary_change_delay");
+      expect(delayEvent.oldValue).to.equal(before.beneficiaryChangeDelay.toString());
+    });
+
+    it("set_fee_recipient redirects fees and emits ConfigChanged", async () => {
+      const newRecipient = Keypair.generate().publicKey;
+      const signature = await program.methods
+        .setFeeRecipient(newRecipient)
+        .accounts({ authority: authority.publicKey })
+        .rpc(confirmed);
+
+      const event = await configChanged(signature);
+      expect(event.field).to.equal("fee_recipient");
+      expect(event.oldValue).to.equal(FEE_RECIPIENT.toBase58());
+      expect(event.newValue).to.equal(newRecipient.toBase58());
+
+      try {
+        await lock();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidFeeRecipient");
+      }
+
+      await program.methods.setFeeRecipient(FEE_RECIPIENT).accounts({ authority: authority.publicKey }).rpc();
+    });
+
+    it("pause stops new locks until unpause, each emitting ConfigChanged", async () => {
+      const pauseSignature = await program.methods
+        .pause()
+        .accounts({ authority: authority.publicKey })
+        .rpc(confirmed);
+      const paused = await configChanged(pauseSignature);
+      expect(paused.field).to.equal("paused");
+      expect(paused.oldValue).to.equal("false");
+      expect(paused.newValue).to.equal("true");
+
+      try {
+        await lock();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("Paused");
+      }
+
+      const unpauseSignature = await program.methods
+        .unpause()
+        .accounts({ authority: authority.publicKey })
+        .rpc(confirmed);
+      const unpaused = await configChanged(unpauseSignature);
+      expect(unpaused.newValue).to.equal("false");
+
+      await lock();
+    });
+
+    it("transfer_authority hands over admin rights and emits ConfigChanged", async () => {
+      const signature = await program.methods
+        .transferAuthority(user3.publicKey)
+        .accounts({ authority: authority.publicKey })
+        .rpc(confirmed);
+
+      const event = await configChanged(signature);
+      expect(event.field).to.equal("authority");
+      expect(event.oldValue).to.equal(authority.publicKey.toBase58());
+      expect(event.newValue).to.equal(user3.publicKey.toBase58());
+
+      try {
+        await program.methods.pause().accounts({ authority: authority.publicKey }).rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+
+      await program.methods
+        .transferAuthority(authority.publicKey)
+        .accounts({ authority: user3.publicKey })
+        .signers([user3])
+        .rpc();
+
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      expect(globalState.authority.toBase58()).to.equal(authority.publicKey.toBase58());
+    });
+  });
+
+  // ===========================================================================
+  // UNLOCK TO ASSOCIATED TOKEN ACCOUNT
+  // ===========================================================================
+  describe("unlock_to_ata", () => {
+    it("recreates a closed associated token account and unlocks into it", async () => {
+      const mint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+      const ata = await createAssociatedTokenAccount(provider.connection, user2, mint, user2.publicKey);
+      await mintTo(provider.connection, mintAuthority, mint, ata, mintAuthority, 1_000_000);
+
+      const lockId = await createLock(
+        user2,
+        ata,
+        mint,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      await closeAccount(provider.connection, user2, ata, user2.publicKey, user2);
+      expect(await provider.connection.getAccountInfo(ata)).to.equal(null);
+
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      await program.methods
+        .unlockToAta()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint,
+          beneficiary: user2.publicKey,
+          owner: user2.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user2])
+        .rpc();
+
+      expect(getAssociatedTokenAddressSync(mint, user2.publicKey).toBase58()).to.equal(ata.toBase58());
+      const account = await getAccount(provider.connection, ata);
+      expect(Number(account.amount)).to.equal(1_000_000);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+    });
+
+    it("unlocks into an existing associated token account", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      const before = await getAccount(provider.connection, user1TokenAccount1);
+      await program.methods
+        .unlockToAta()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          beneficiary: user1.publicKey,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+      const after = await getAccount(provider.connection, user1TokenAccount1);
+      expect(Number(after.amount - before.amount)).to.equal(1_000_000);
+    });
+  });
+
+  // ===========================================================================
+  // HEIRS
+  // ===========================================================================
+  describe("heirs", () => {
+    const getHeirsPda = (lockId: number): PublicKey =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("heirs"), new anchor.BN(lockId).toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+
+    const lockWithHeirs = async (
+      unlockTimestamp: number,
+      heirs: { heir: PublicKey; graceOffset: anchor.BN }[]
+    ): Promise<number> => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+
+      await program.methods
+        .lockWithHeirs(new anchor.BN(1_000_000), new anchor.BN(unlockTimestamp), heirs)
+        .accounts({
+          lockTokens: {
+            globalState: globalStatePda,
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            expiryIndex: getExpiryIndexPda(unlockTimestamp),
+            creationIndex: getCreationIndexPda(),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            feeRecipient: FEE_RECIPIENT,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          },
+          heirs: getHeirsPda(lockId),
+        })
+        .signers([user1])
+        .rpc();
+
+      return lockId;
+    };
+
+    const heirUnlock = (lockId: number, heir: Keypair, heirTokenAccount: PublicKey) =>
+      program.methods
+        .heirUnlock()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          heirTokenAccount,
+          heir: heir.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([heir])
+        .rpc();
+
+    // Heir 1 may claim 2s after maturity, heir 2 after 4s
+    const twoHeirs = () => [
+      { heir: user2.publicKey, graceOffset: new anchor.BN(2) },
+      { heir: user3.publicKey, graceOffset: new anchor.BN(4) },
+    ];
+
+    const sleep = (seconds: number) => new Promise((resolve) => setTimeout(resolve, seconds * 1000));
+
+    it("rejects heirs whose grace offsets aren't strictly increasing", async () => {
+      try {
+        await lockWithHeirs(Math.floor(Date.now() / 1000) + 3600, [
+          { heir: user2.publicKey, graceOffset: new anchor.BN(10) },
+          { heir: user3.publicKey, graceOffset: new anchor.BN(10) },
+        ]);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidHeirs");
+      }
+    });
+
+    it("the first heir can claim after its grace period, before the second", async () => {
+      const lockId = await lockWithHeirs(Math.floor(Date.now() / 1000) + 2, twoHeirs());
+      await sleep(5);
+
+      try {
+        await heirUnlock(lockId, user3, user3TokenAccount1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("HeirNotEligible");
+      }
+
+      const before = await getAccount(provider.connection, user2TokenAccount1);
+      await heirUnlock(lockId, user2, user2TokenAccount1);
+      const after = await getAccount(provider.connection, user2TokenAccount1);
+      expect(Number(after.amount - before.amount)).to.equal(1_000_000);
+
+      // Later heirs can't claim once an earlier party did
+      await sleep(2);
+      try {
+        await heirUnlock(lockId, user3, user3TokenAccount1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AlreadyUnlocked");
+      }
+    });
+
+    it("cascades to the second heir when the first never claims", async () => {
+      const lockId = await lockWithHeirs(Math.floor(Date.now() / 1000) + 2, twoHeirs());
+      await sleep(7);
+
+      const before = await getAccount(provider.connection, user3TokenAccount1);
+      await heirUnlock(lockId, user3, user3TokenAccount1);
+      const after = await getAccount(provider.connection, user3TokenAccount1);
+      expect(Number(after.amount - before.amount)).to.equal(1_000_000);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+    });
+
+    it("rejects wallets that aren't heirs", async () => {
+      const lockId = await lockWithHeirs(Math.floor(Date.now() / 1000) + 2, [
+        { heir: user2.publicKey, graceOffset: new anchor.BN(1) },
+      ]);
+      await sleep(4);
+
+      try {
+        await heirUnlock(lockId, user3, user3TokenAccount1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("HeirNotEligible");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // STABLE LOCK LAYOUT
+  // ===========================================================================
+  describe("lock layout", () => {
+    it("keeps owner, mint and is_unlocked at their committed offsets", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+      const { data } = (await provider.connection.getAccountInfo(getLockPda(lockId)))!;
+
+      expect(new PublicKey(data.subarray(16, 48)).toBase58()).to.equal(user1.publicKey.toBase58());
+      expect(new PublicKey(data.subarray(48, 80)).toBase58()).to.equal(mint1.toBase58());
+      expect(data[105]).to.equal(0);
+      expect(LockFetcher.OFFSETS.OWNER).to.equal(16);
+      expect(LockFetcher.OFFSETS.MINT).to.equal(48);
+      expect(LockFetcher.OFFSETS.IS_UNLOCKED).to.equal(105);
+    });
+  });
+
+  // ===========================================================================
+  // EMERGENCY RECOVERY
+  // ===========================================================================
+  describe("recover", () => {
+    const MIN_RECOVERY_DELAY = 365 * 86_400;
+
+    const getRecoveryVaultPda = (mint: PublicKey): PublicKey =>
+      PublicKey.findProgramAddressSync([Buffer.from("recovery_vault"), mint.toBuffer()], program.programId)[0];
+
+    const recover = (lockId: number) =>
+      program.methods
+        .recover()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          authority: authority.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc({ commitment: "confirmed" });
+
+    const createRecoverableLock = (unlockTimestamp: number, recoveryAfter: number) =>
+      createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(unlockTimestamp),
+        { ...defaultLockOptions(), recoveryAfter: new anchor.BN(recoveryAfter) }
+      );
+
+    it("rejects a recovery time less than a year after maturity", async () => {
+      const unlockTimestamp = Math.floor(Date.now() / 1000) + 3600;
+      try {
+        await createRecoverableLock(unlockTimestamp, unlockTimestamp + MIN_RECOVERY_DELAY - 1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidRecoveryAfter");
+      }
+    });
+
+    it("rejects recovery of locks that didn't opt in", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+      try {
+        await recover(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("RecoveryDisabled");
+      }
+    });
+
+    describe("timing (test-clock builds only)", () => {
+      before(function () {
+        if (!hasInstruction("set_mock_timestamp")) {
+          this.skip();
+        }
+      });
+
+      // Locks are created against the real clock
+      beforeEach(async () => {
+        await setMockTimestamp(0);
+      });
+
+      after(async () => {
+        if (hasInstruction("set_mock_timestamp")) {
+          await setMockTimestamp(0);
+        }
+      });
+
+      it("only sweeps the lock once recovery_after has passed", async () => {
+        const unlockTimestamp = Math.floor(Date.now() / 1000) + 3600;
+        const recoveryAfter = unlockTimestamp + MIN_RECOVERY_DELAY;
+        const lockId = await createRecoverableLock(unlockTimestamp, recoveryAfter);
+
+        await setMockTimestamp(recoveryAfter - 1);
+        try {
+          await recover(lockId);
+          expect.fail("Should have thrown error");
+        } catch (err: any) {
+          expect(err.error?.errorCode?.code).to.equal("RecoveryTooEarly");
+        }
+
+        await setMockTimestamp(recoveryAfter);
+        const signature = await recover(lockId);
+
+        const events = await getEvents(signature);
+        const recovered = events.find((e) => e.name === "recovered");
+        expect(recovered!.data.lockId.toNumber()).to.equal(lockId);
+        expect(recovered!.data.amount.toNumber()).to.equal(1_000_000);
+
+        const recoveryVault = await getAccount(provider.connection, getRecoveryVaultPda(mint1));
+        expect(Number(recoveryVault.amount)).to.be.at.least(1_000_000);
+        const lock = await program.account.lock.fetch(getLockPda(lockId));
+        expect(lock.isUnlocked).to.equal(true);
+      });
+
+      it("only the authority can recover", async () => {
+        const unlockTimestamp = Math.floor(Date.now() / 1000) + 3600;
+        const recoveryAfter = unlockTimestamp + MIN_RECOVERY_DELAY;
+        const lockId = await createRecoverableLock(unlockTimestamp, recoveryAfter);
+        await setMockTimestamp(recoveryAfter);
+
+        try {
+          await program.methods
+            .recover()
+            .accounts({
+              lock: getLockPda(lockId),
+              vault: getVaultPda(lockId),
+              mint: mint1,
+              authority: user1.publicKey,
+              tokenProgram: TOKEN_PROGRAM_ID,
+            })
+            .signers([user1])
+            .rpc();
+          expect.fail("Should have thrown error");
+        } catch (err: any) {
+          expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+        }
+      });
+    });
+  });
+
+  // ===========================================================================
+  // UNLOCK STATE ORDERING
+  // ===========================================================================
+  describe("unlock re-entry", () => {
+    it("a second unlock of the same lock in one transaction fails with AlreadyUnlocked", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      const unlockIx = () =>
+        program.methods
+          .unlock(null)
+          .accounts({
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .instruction();
+
+      const tx = new Transaction().add(await unlockIx(), await unlockIx());
+      try {
+        await provider.sendAndConfirm(tx, [user1]);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect((err.logs ?? []).join("\n")).to.include("AlreadyUnlocked");
+      }
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(false);
+    });
+  });
+
+  // ===========================================================================
+  // MINT STATS
+  // ===========================================================================
+  describe("mint stats", () => {
+    let mint: PublicKey;
+    let tokenAccount: PublicKey;
+    let maturedLockId: number;
+    let activeLockId: number;
+
+    const lockAccounts = (lockIds: number[]) =>
+      lockIds.map((lockId) => ({ pubkey: getLockPda(lockId), isSigner: false, isWritable: false }));
+
+    const backfill = (lockIds: number[]) =>
+      program.methods
+        .backfillMintStats()
+        .accounts({ mint, authority: authority.publicKey })
+        .remainingAccounts(lockAccounts(lockIds))
+        .rpc();
+
+    const unlock = (lockId: number) =>
+      program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint,
+          ownerTokenAccount: tokenAccount,
+          owner: user2.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user2])
+        .rpc();
+
+    const lock = (amount: number, secondsFromNow: number) =>
+      createLock(
+        user2,
+        tokenAccount,
+        mint,
+        new anchor.BN(amount),
+        new anchor.BN(Math.floor(Date.now() / 1000) + secondsFromNow)
+      );
+
+    before(async () => {
+      mint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+      tokenAccount = await createAssociatedTokenAccount(provider.connection, user2, mint, user2.publicKey);
+      await mintTo(provider.connection, mintAuthority, mint, tokenAccount, mintAuthority, 10_000_000);
+
+      // Locks created before the mint had stats
+      maturedLockId = await lock(1_000_000, 2);
+      activeLockId = await lock(2_000_000, 3600);
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      await unlock(maturedLockId);
+    });
+
+    it("rejects a lock passed twice", async () => {
+      try {
+        await backfill([activeLockId, activeLockId]);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidBatch");
+      }
+    });
+
+    it("only the authority can backfill", async () => {
+      try {
+        await program.methods
+          .backfillMintStats()
+          .accounts({ mint, authority: user1.publicKey })
+          .remainingAccounts(lockAccounts([activeLockId]))
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+
+    it("seeds the stats from the locks that aren't unlocked", async () => {
+      await backfill([maturedLockId, activeLockId]);
+
+      const stats = await program.account.mintStats.fetch(getMintStatsPda(mint));
+      expect(stats.totalLocked.toNumber()).to.equal(2_000_000);
+      expect(stats.activeLocks.toNumber()).to.equal(1);
+    });
+
+    it("can't backfill the same mint twice", async () => {
+      try {
+        await backfill([activeLockId]);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(String(err)).to.include("already in use");
+      }
+    });
+
+    it("tracks later locks and unlocks", async () => {
+      const lockId = await lock(500_000, 2);
+      let stats = await program.account.mintStats.fetch(getMintStatsPda(mint));
+      expect(stats.totalLocked.toNumber()).to.equal(2_500_000);
+      expect(stats.activeLocks.toNumber()).to.equal(2);
+
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      await unlock(lockId);
+      stats = await program.account.mintStats.fetch(getMintStatsPda(mint));
+      expect(stats.totalLocked.toNumber()).to.equal(2_000_000);
+      expect(stats.activeLocks.toNumber()).to.equal(1);
+    });
+  });
+
+  // ===========================================================================
+  // ALL-OR-NOTHING LOCKS
+  // ===========================================================================
+  describe("allow_partial", () => {
+    const createMaturingLock = (allowPartial: boolean) =>
+      createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2),
+        { ...defaultLockOptions(), allowPartial }
+      );
+
+    const partialUnlock = (lockId: number) =>
+      program.methods
+        .partialUnlock(new anchor.BN(400_000))
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    it("allows partial unlocks by default", async () => {
+      const lockId = await createMaturingLock(true);
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      await partialUnlock(lockId);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.allowPartial).to.equal(true);
+      expect(lock.amount.toNumber()).to.equal(600_000);
+    });
+
+    it("forces a full unlock when partial unlocks are disallowed", async () => {
+      const lockId = await createMaturingLock(false);
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      try {
+        await partialUnlock(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("PartialNotAllowed");
+      }
+
+      await program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+    });
+  });
+
+  // ===========================================================================
+  // UNLOCK TIMESTAMP
+  // ===========================================================================
+  describe("unlocked_at", () => {
+    const unlockAccounts = (lockId: number) => ({
+      lock: getLockPda(lockId),
+      vault: getVaultPda(lockId),
+      mint: mint1,
+      ownerTokenAccount: user1TokenAccount1,
+      owner: user1.publicKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    });
+
+    const createMaturingLock = () =>
+      createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+
+    it("is 0 while locked and set by unlock", async () => {
+      const lockId = await createMaturingLock();
+      let lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.unlockedAt.toNumber()).to.equal(0);
+
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      await program.methods.unlock(null).accounts(unlockAccounts(lockId)).signers([user1]).rpc();
+
+      lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.unlockedAt.toNumber()).to.be.at.least(lock.unlockTimestamp.toNumber());
+      expect(lock.unlockedAt.toNumber()).to.be.closeTo(Math.floor(Date.now() / 1000), 30);
+    });
+
+    it("is only set by the partial unlock that empties the lock", async () => {
+      const lockId = await createMaturingLock();
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      await program.methods
+        .partialUnlock(new anchor.BN(400_000))
+        .accounts(unlockAccounts(lockId))
+        .signers([user1])
+        .rpc();
+      let lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.unlockedAt.toNumber()).to.equal(0);
+
+      await program.methods
+        .partialUnlock(new anchor.BN(600_000))
+        .accounts(unlockAccounts(lockId))
+        .signers([user1])
+        .rpc();
+      lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+      expect(lock.unlockedAt.toNumber()).to.be.greaterThan(0);
+    });
+  });
+
+  // ===========================================================================
+  // AMOUNT EVENT
+  // ===========================================================================
+  describe("amount event", () => {
+    it("round-trips a u64::MAX - 1 lock amount", async () => {
+      const amount = BigInt("18446744073709551614");
+      const mint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 0);
+      const tokenAccount = await createAssociatedTokenAccount(provider.connection, user2, mint, user2.publicKey);
+      await mintTo(provider.connection, mintAuthority, mint, tokenAccount, mintAuthority, amount);
+
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+      const signature = await program.methods
+        .lock(new anchor.BN(amount.toString()), unlockTimestamp, null)
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
+          mint,
+          ownerTokenAccount: tokenAccount,
+          owner: user2.publicKey,
+          feeRecipient: FEE_RECIPIENT,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user2])
+        .rpc({ commitment: "confirmed" });
+
+      const events = await getEvents(signature);
+      const locked = events.find((e) => e.name === "amountEvent");
+      expect(locked!.data.lockId.toNumber()).to.equal(lockId);
+      expect(locked!.data.amount.toString()).to.equal(amount.toString());
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.amount.toString()).to.equal(amount.toString());
+    });
+  });
+
+  // ===========================================================================
+  // LOCK STATUS
+  // ===========================================================================
+  describe("lock_status", () => {
+    const lockStatus = (lockId: number): Promise<number> =>
+      program.methods.lockStatus().accounts({ lock: getLockPda(lockId) }).view();
+
+    it("moves from active to matured to unlocked", async () => {
+      const lockId = await createLock(
+        user3,
+        user3TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      expect(await lockStatus(lockId)).to.equal(0);
+
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+      expect(await lockStatus(lockId)).to.equal(1);
+
+      await program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user3TokenAccount1,
+          owner: user3.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user3])
+        .rpc();
+      expect(await lockStatus(lockId)).to.equal(2);
+    });
+
+    it("is frozen while the owner is denylisted", async () => {
+      const lockId = await createLock(
+        user3,
+        user3TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+      await program.methods.addToDenylist(user3.publicKey).accounts({ authority: authority.publicKey }).rpc();
+      await program.methods
+        .setDenylistEnabled(true)
+        .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+        .rpc();
+      try {
+        expect(await lockStatus(lockId)).to.equal(3);
+      } finally {
+        await program.methods
+          .setDenylistEnabled(false)
+          .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+          .rpc();
+        await program.methods.removeFromDenylist(user3.publicKey).accounts({ authority: authority.publicKey }).rpc();
+      }
+      expect(await lockStatus(lockId)).to.equal(0);
+    });
+  });
+
+  // ===========================================================================
+  // UNLOCK WITH SECRET
+  // ===========================================================================
+  describe("unlock_with_secret", () => {
+    const preimage = Buffer.from("gift-code-1234");
+    const secretHash = [
+      ...createHash("sha256")
+        .update(Buffer.concat([preimage, user2.publicKey.toBuffer()]))
+        .digest(),
+    ];
+
+    const unlockWithSecret = (
+      lockId: number,
+      secret: Buffer,
+      claimant = user2,
+      claimantTokenAccount = user2TokenAccount1
+    ) =>
+      program.methods
+        .unlockWithSecret(secret)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          claimantTokenAccount,
+          claimant: claimant.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([claimant])
+        .rpc();
+
+    it("releases a matured lock to the claimant revealing the preimage", async () => {
+      const lockId = await createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2),
+        { ...defaultLockOptions(), secretHash }
+      );
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+
+      try {
+        await unlockWithSecret(lockId, Buffer.from("wrong-code"));
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("WrongSecret");
+      }
+
+      const before = await getAccount(provider.connection, user2TokenAccount1);
+      await unlockWithSecret(lockId, preimage);
+      const after = await getAccount(provider.connection, user2TokenAccount1);
+      expect(Number(after.amount - before.amount)).to.equal(1_000_000);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+    });
+
+    it("rejects locks created without a secret", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+      try {
+        await unlockWithSecret(lockId, Buffer.alloc(0));
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("WrongSecret");
+      }
+    });
+
+    it("rejects another signer replaying a revealed preimage", async () => {
+      const lockId = await createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2),
+        { ...defaultLockOptions(), secretHash }
+      );
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+
+      try {
+        await unlockWithSecret(lockId, preimage, user3, user3TokenAccount1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("WrongSecret");
+      }
+
+      await unlockWithSecret(lockId, preimage);
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+    });
+  });
+
+  // ===========================================================================
+  // STRICT BOUNDARY (only runs against builds with the `test-clock` feature)
+  // ===========================================================================
+  describe("strict_boundary", () => {
+    const setStrictBoundary = (strictBoundary: boolean) =>
+      program.methods
+        .setStrictBoundary(strictBoundary)
+        .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+        .rpc();
+
+    // Lock maturing in 30 days, with the mock clock set exactly to its unlock timestamp
+    const createLockAtBoundary = async (): Promise<number> => {
+      await setMockTimestamp(0);
+      const unlockTimestamp = Math.floor(Date.now() / 1000) + 30 * 86400;
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(unlockTimestamp)
+      );
+      await setMockTimestamp(unlockTimestamp);
+      return lockId;
+    };
+
+    const unlock = (lockId: number) =>
+      program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    before(function () {
+      if (!hasInstruction("set_mock_timestamp")) {
+        this.skip();
+      }
+    });
+
+    after(async () => {
+      if (hasInstruction("set_mock_timestamp")) {
+        await setStrictBoundary(false);
+        await setMockTimestamp(0);
+      }
+    });
+
+    it("by default a lock is unlockable at exactly its unlock timestamp", async () => {
+      const lockId = await createLockAtBoundary();
+      await unlock(lockId);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+    });
+
+    it("when strict, a lock is only unlockable the second after", async () => {
+      await setStrictBoundary(true);
+      const lockId = await createLockAtBoundary();
+
+      try {
+        await unlock(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TooEarly");
+      }
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      await setMockTimestamp(lock.unlockTimestamp.toNumber() + 1);
+      await unlock(lockId);
+
+      const unlocked = await program.account.lock.fetch(getLockPda(lockId));
+      expect(unlocked.isUnlocked).to.equal(true);
+    });
+
+    it("only the authority can change the boundary", async () => {
+      try {
+        await program.methods
+          .setStrictBoundary(true)
+          .accounts({ globalState: globalStatePda, authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // EXTEND BY
+  // ===========================================================================
+  describe("extend_by", () => {
+    const extendBy = async (lockId: number, additionalSeconds: number) => {
+      const lockPda = getLockPda(lockId);
+      const lock = await program.account.lock.fetch(lockPda);
+      await program.methods
+        .extendBy(new anchor.BN(additionalSeconds))
+        .accounts({
+          lock: lockPda,
+          oldExpiryIndex: await getLockExpiryIndexPda(lockPda),
+          newExpiryIndex: getExpiryIndexPda(lock.unlockTimestamp.toNumber() + additionalSeconds),
+          owner: user1.publicKey,
+        })
+        .signers([user1])
+        .rpc();
+    };
+
+    it("adds the delta to the stored unlock timestamp", async () => {
+      const unlockTimestamp = Math.floor(Date.now() / 1000) + 3600;
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(unlockTimestamp)
+      );
+
+      await extendBy(lockId, 2 * 86400);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.unlockTimestamp.toNumber()).to.equal(unlockTimestamp + 2 * 86400);
+    });
+
+    it("rejects a zero or negative delta", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+      for (const additionalSeconds of [0, -60]) {
+        try {
+          await extendBy(lockId, additionalSeconds);
+          expect.fail("Should have thrown error");
+        } catch (err: any) {
+          expect(err.error?.errorCode?.code).to.equal("InvalidExtension");
+        }
+      }
+    });
+  });
+
+  // ===========================================================================
+  // EXTEND ERRORS
+  // ===========================================================================
+  describe("extend errors", () => {
+    it("extend_with_top_up tells an unchanged timestamp from a shorter one", async () => {
+      const unlockTimestamp = Math.floor(Date.now() / 1000) + 3600;
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(unlockTimestamp)
+      );
+      const lockPda = getLockPda(lockId);
+
+      const extendWithTopUp = async (newTimestamp: number) =>
+        program.methods
+          .extendWithTopUp(new anchor.BN(newTimestamp), new anchor.BN(1_000))
+          .accounts({
+            lock: lockPda,
+            vault: getVaultPda(lockId),
+            oldExpiryIndex: await getLockExpiryIndexPda(lockPda),
+            newExpiryIndex: getExpiryIndexPda(newTimestamp),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          })
+          .signers([user1])
+          .rpc();
+
+      for (const [newTimestamp, code] of [
+        [unlockTimestamp, "TimestampUnchanged"],
+        [unlockTimestamp - 1, "CannotShortenTimestamp"],
+      ] as [number, string][]) {
+        try {
+          await extendWithTopUp(newTimestamp);
+          expect.fail("Should have thrown error");
+        } catch (err: any) {
+          expect(err.error?.errorCode?.code).to.equal(code);
+        }
+      }
+    });
+  });
+
+  // ===========================================================================
+  // FEE COLLECTION
+  // ===========================================================================
+  describe("fee collection", () => {
+    it("collects exactly the computed fee", async () => {
+      // Fresh mint, so no per-mint fee override applies
+      const mint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+      const tokenAccount = await createAssociatedTokenAccount(provider.connection, user2, mint, user2.publicKey);
+      await mintTo(provider.connection, mintAuthority, mint, tokenAccount, mintAuthority, 1_000_000);
+
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const before = await provider.connection.getBalance(globalState.feeRecipient);
+      await createLock(
+        user2,
+        tokenAccount,
+        mint,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+      const after = await provider.connection.getBalance(globalState.feeRecipient);
+
+      expect(after - before).to.equal(globalState.feeAmount.toNumber());
+    });
+  });
+
+  // ===========================================================================
+  // GET CONFIG
+  // ===========================================================================
+  describe("get_config", () => {
+    it("returns the live configuration", async () => {
+      const config = await program.methods.getConfig().accounts({}).view();
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+
+      expect(config.feeAmount.toString()).to.equal(globalState.feeAmount.toString());
+      expect(config.feeRecipient.toBase58()).to.equal(globalState.feeRecipient.toBase58());
+      expect(config.authority.toBase58()).to.equal(authority.publicKey.toBase58());
+      expect(config.paused).to.equal(false);
+      expect(config.schemaVersion).to.equal(1);
+    });
+  });
+
+  // ===========================================================================
+  // LOCK TO PROGRAM
+  // ===========================================================================
+  describe("lock_to_program", () => {
+    // Stands in for a governance PDA: a program signs for its PDA through `invoke_signed`, which
+    // passes the same signer check as this keypair signing the transaction
+    const controller = Keypair.generate();
+    let controllerTokenAccount: PublicKey;
+
+    const unlockAs = (lockId: number, signer: Keypair, ownerTokenAccount: PublicKey) =>
+      program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount,
+          owner: signer.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      controllerTokenAccount = await createAssociatedTokenAccount(
+        provider.connection,
+        mintAuthority,
+        mint1,
+        controller.publicKey
+      );
+    });
+
+    it("only the controller can unlock, after maturity", async () => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 2);
+
+      await program.methods
+        .lockToProgram(new anchor.BN(1_000_000), unlockTimestamp, controller.publicKey)
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          feeRecipient: FEE_RECIPIENT,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.owner.toBase58()).to.equal(controller.publicKey.toBase58());
+      expect(lock.beneficiary.toBase58()).to.equal(controller.publicKey.toBase58());
+
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+
+      try {
+        await unlockAs(lockId, user1, user1TokenAccount1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("Unauthorized");
+      }
+
+      await unlockAs(lockId, controller, controllerTokenAccount);
+      const balance = await getAccount(provider.connection, controllerTokenAccount);
+      expect(Number(balance.amount)).to.equal(1_000_000);
+    });
+  });
+
+  // ===========================================================================
+  // AMOUNT OVERFLOW
+  // ===========================================================================
+  describe("amount overflow", () => {
+    it("tops a lock up to exactly u64::MAX", async () => {
+      // A mint's supply is capped at u64::MAX, so the lock can reach but never pass it
+      const max = BigInt("18446744073709551615");
+      const mint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 0);
+      const tokenAccount = await createAssociatedTokenAccount(provider.connection, user2, mint, user2.publicKey);
+      await mintTo(provider.connection, mintAuthority, mint, tokenAccount, mintAuthority, max - BigInt(1));
+
+      const lockId = await createLock(
+        user2,
+        tokenAccount,
+        mint,
+        new anchor.BN((max - BigInt(1)).toString()),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+      await mintTo(provider.connection, mintAuthority, mint, tokenAccount, mintAuthority, 1);
+      await program.methods
+        .topUp(new anchor.BN(1))
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint,
+          ownerTokenAccount: tokenAccount,
+          owner: user2.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user2])
+        .rpc();
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.amount.toString()).to.equal(max.toString());
+    });
+  });
+
+  // ===========================================================================
+  // VAULT SCHEMES
+  // ===========================================================================
+  describe("vault schemes", () => {
+    const getAtaVault = (lockId: number): PublicKey =>
+      getAssociatedTokenAddressSync(mint1, getVaultPda(lockId), true);
+
+    const lockWithAtaVault = async (unlockTimestamp: anchor.BN): Promise<number> => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+
+      await program.methods
+        .lockWithAtaVault(new anchor.BN(1_000_000), unlockTimestamp)
+        .accounts({
+          lockTokens: {
+            globalState: globalStatePda,
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            expiryIndex: getExpiryIndexPda(unlockTimestamp),
+            creationIndex: getCreationIndexPda(),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            feeRecipient: FEE_RECIPIENT,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          },
+          ataVault: getAtaVault(lockId),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+      return lockId;
+    };
+
+    const unlockAtaVault = (lockId: number) =>
+      program.methods
+        .unlockAtaVault()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getAtaVault(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    it("plain locks use the PDA vault", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.vaultScheme).to.equal(0);
+      const vault = await getAccount(provider.connection, getVaultPda(lockId));
+      expect(Number(vault.amount)).to.equal(1_000_000);
+    });
+
+    it("lock_with_ata_vault holds the tokens in the vault PDA's ATA", async () => {
+      const lockId = await lockWithAtaVault(new anchor.BN(Math.floor(Date.now() / 1000) + 3600));
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.vaultScheme).to.equal(1);
+      const vault = await getAccount(provider.connection, getAtaVault(lockId));
+      expect(Number(vault.amount)).to.equal(1_000_000);
+      expect(vault.owner.toBase58()).to.equal(getVaultPda(lockId).toBase58());
+
+      // The unused PDA token account was closed
+      expect(await provider.connection.getAccountInfo(getVaultPda(lockId))).to.equal(null);
+    });
+
+    it("unlock_ata_vault releases the ATA vault after maturity", async () => {
+      const lockId = await lockWithAtaVault(new anchor.BN(Math.floor(Date.now() / 1000) + 2));
+
+      try {
+        await unlockAtaVault(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TooEarly");
+      }
+
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+      const before = await getAccount(provider.connection, user1TokenAccount1);
+      await unlockAtaVault(lockId);
+      const after = await getAccount(provider.connection, user1TokenAccount1);
+      expect(Number(after.amount - before.amount)).to.equal(1_000_000);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+    });
+
+    it("unlock_ata_vault rejects PDA vault locks, even if their vault PDA has an ATA", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      // Anyone can create the ATA of any PDA
+      await createAssociatedTokenAccount(
+        provider.connection,
+        mintAuthority,
+        mint1,
+        getVaultPda(lockId),
+        undefined,
+        TOKEN_PROGRAM_ID,
+        undefined,
+        true
+      );
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+
+      try {
+        await unlockAtaVault(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("WrongVaultScheme");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // FORCE CLOSE
+  // ===========================================================================
+  describe("force_close", () => {
+    const forceClose = (lockId: number) =>
+      program.methods
+        .forceClose()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    it("refuses to close an active lock", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+      try {
+        await forceClose(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("LockStillActive");
+      }
+    });
+
+    it("sweeps dust to the owner and closes the lock and its vault", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+      await program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+      // Dust sent straight to the vault after the unlock
+      await transfer(provider.connection, user1, user1TokenAccount1, getVaultPda(lockId), user1, 5);
+
+      const before = await getAccount(provider.connection, user1TokenAccount1);
+      await forceClose(lockId);
+      const after = await getAccount(provider.connection, user1TokenAccount1);
+
+      expect(Number(after.amount - before.amount)).to.equal(5);
+      expect(await provider.connection.getAccountInfo(getLockPda(lockId))).to.equal(null);
+      expect(await provider.connection.getAccountInfo(getVaultPda(lockId))).to.equal(null);
+    });
+  });
+
+  // ===========================================================================
+  // CREATION INDEX
+  // ===========================================================================
+  describe("creation index", () => {
+    it("records new locks in today's bucket, unaffected by extend", async () => {
+      const unlockTimestamp = Math.floor(Date.now() / 1000) + 3600;
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(unlockTimestamp)
+      );
+      const lockPda = getLockPda(lockId);
+      const lock = await program.account.lock.fetch(lockPda);
+
+      const index = await program.account.creationIndex.fetch(getCreationIndexPda());
+      expect(index.bucket.toNumber()).to.equal(Math.floor(lock.createdAt.toNumber() / 86400));
+      expect(index.lockIds.map((id) => id.toNumber())).to.include(lockId);
+
+      await program.methods
+        .extend(new anchor.BN(unlockTimestamp + 2 * 86400))
+        .accounts({
+          lock: lockPda,
+          oldExpiryIndex: await getLockExpiryIndexPda(lockPda),
+          newExpiryIndex: getExpiryIndexPda(unlockTimestamp + 2 * 86400),
+          owner: user1.publicKey,
+        })
+        .signers([user1])
+        .rpc();
+
+      const after = await program.account.creationIndex.fetch(getCreationIndexPda());
+      expect(after.lockIds.map((id) => id.toNumber())).to.deep.equal(index.lockIds.map((id) => id.toNumber()));
+    });
+
+    it("rejects another day's bucket", async () => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+      try {
+        await program.methods
+          .lock(new anchor.BN(1_000_000), unlockTimestamp, null)
+          .accounts({
+            globalState: globalStatePda,
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            expiryIndex: getExpiryIndexPda(unlockTimestamp),
+            creationIndex: getExpiryIndexPda(unlockTimestamp),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            feeRecipient: FEE_RECIPIENT,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidCreationIndex");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // ASSISTED UNLOCK
+  // ===========================================================================
+  describe("assisted_unlock", () => {
+    const assistedUnlock = (lockId: number, ownerTokenAccount: PublicKey) =>
+      program.methods
+        .assistedUnlock()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount,
+          authority: authority.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc({ commitment: "confirmed" });
+
+    it("only after maturity, only to the owner", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+
+      try {
+        await assistedUnlock(lockId, user1TokenAccount1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TooEarly");
+      }
+
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+
+      // Can't be redirected to anyone else's token account
+      try {
+        await assistedUnlock(lockId, user2TokenAccount1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("ConstraintTokenOwner");
+      }
+
+      const before = await getAccount(provider.connection, user1TokenAccount1);
+      const signature = await assistedUnlock(lockId, user1TokenAccount1);
+      const after = await getAccount(provider.connection, user1TokenAccount1);
+      expect(Number(after.amount - before.amount)).to.equal(1_000_000);
+
+      const events = await getEvents(signature);
+      const assisted = events.find((e) => e.name === "assistedUnlock");
+      expect(assisted!.data.lockId.toNumber()).to.equal(lockId);
+      expect(assisted!.data.owner.toBase58()).to.equal(user1.publicKey.toBase58());
+      expect(assisted!.data.amount.toNumber()).to.equal(1_000_000);
+    });
+
+    it("only the authority can assist", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+      try {
+        await program.methods
+          .assistedUnlock()
+          .accounts({
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            authority: user2.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user2])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // VESTING CURVES
+  // ===========================================================================
+  describe("claim_vested", () => {
+    const lockedAmount = 1_000_000_000;
+    const duration = 1000;
+
+    const claimVested = (lockId: number) =>
+      program.methods
+        .claimVested()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    // Expected vested amount, with the program's integer rounding
+    const expectedVested = (curve: number, total: bigint, elapsed: bigint, d: bigint): bigint => {
+      const remaining = d - elapsed;
+      switch (curve) {
+        case 1:
+          return (total * elapsed) / d;
+        case 2:
+          return total - (((total * remaining) / d) * remaining) / d;
+        default:
+          return (((total * elapsed) / d) * elapsed) / d;
+      }
+    };
+
+    // Create a vesting lock and claim at the midpoint of its schedule, returning the claimed amount
+    const claimAtMidpoint = async (curve: number): Promise<number> => {
+      const now = Math.floor(Date.now() / 1000);
+      await setMockTimestamp(now);
+      const lockId = await createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(lockedAmount),
+        new anchor.BN(now + duration),
+        { ...defaultLockOptions(), vestingCurve: curve }
+      );
+
+      await setMockTimestamp(now + duration / 2);
+      const before = await getAccount(provider.connection, user1TokenAccount1);
+      await claimVested(lockId);
+      const after = await getAccount(provider.connection, user1TokenAccount1);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.vestedClaimed.toString()).to.equal((after.amount - before.amount).toString());
+      return Number(after.amount - before.amount);
+    };
+
+    before(function () {
+      if (!hasInstruction("set_mock_timestamp")) {
+        this.skip();
+      }
+    });
+
+    after(async () => {
+      if (hasInstruction("set_mock_timestamp")) {
+        await setMockTimestamp(0);
+      }
+    });
+
+    it("releases half, three quarters or a quarter at the midpoint", async () => {
+      const total = BigInt(lockedAmount);
+      const half = BigInt(duration / 2);
+      const d = BigInt(duration);
+
+      const linear = await claimAtMidpoint(1);
+      const frontLoaded = await claimAtMidpoint(2);
+      const backLoaded = await claimAtMidpoint(3);
+
+      expect(linear).to.equal(Number(expectedVested(1, total, half, d)));
+      expect(frontLoaded).to.equal(Number(expectedVested(2, total, half, d)));
+      expect(backLoaded).to.equal(Number(expectedVested(3, total, half, d)));
+      expect(linear).to.equal(lockedAmount / 2);
+      expect(frontLoaded).to.equal((lockedAmount * 3) / 4);
+      expect(backLoaded).to.equal(lockedAmount / 4);
+    });
+
+    it("only releases newly vested tokens, and everything at maturity", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      await setMockTimestamp(now);
+      const lockId = await createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(lockedAmount),
+        new anchor.BN(now + duration),
+        { ...defaultLockOptions(), vestingCurve: 1 }
+      );
+
+      await setMockTimestamp(now + duration / 4);
+      await claimVested(lockId);
+      try {
+        await claimVested(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TooEarly");
+      }
+
+      await setMockTimestamp(now + duration);
+      await claimVested(lockId);
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.vestedClaimed.toNumber()).to.equal(lockedAmount);
+      expect(lock.amount.toNumber()).to.equal(0);
+      expect(lock.isUnlocked).to.equal(true);
+    });
+
+    it("rejects locks without a vesting curve and unknown curves", async () => {
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+      const lockId = await createLock(user1, user1TokenAccount1, mint1, new anchor.BN(1_000_000), unlockTimestamp);
+      try {
+        await claimVested(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotVesting");
+      }
+
+      try {
+        await createLockWithOptions(user1, user1TokenAccount1, mint1, new anchor.BN(1_000_000), unlockTimestamp, {
+          ...defaultLockOptions(),
+          vestingCurve: 4,
+        });
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidVestingCurve");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // NEXT UNLOCK TIME
+  // ===========================================================================
+  describe("next_unlock_time", () => {
+    const nextUnlockTime = async (lockId: number): Promise<number> =>
+      (await program.methods.nextUnlockTime().accounts({ lock: getLockPda(lockId) }).view()).toNumber();
+
+    after(async () => {
+      if (hasInstruction("set_mock_timestamp")) {
+        await setMockTimestamp(0);
+      }
+    });
+
+    it("is the unlock timestamp of a plain lock, then 0 once unlocked", async () => {
+      const unlockTimestamp = Math.floor(Date.now() / 1000) + 2;
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(unlockTimestamp)
+      );
+      expect(await nextUnlockTime(lockId)).to.equal(unlockTimestamp);
+
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+      await program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+      expect(await nextUnlockTime(lockId)).to.equal(0);
+    });
+
+    it("is the first unclaimed milestone of a milestone lock", async () => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+      const now = Math.floor(Date.now() / 1000);
+      const milestones = [
+        { timestamp: new anchor.BN(now + 3600), amount: new anchor.BN(1_000) },
+        { timestamp: new anchor.BN(now + 7200), amount: new anchor.BN(1_000) },
+      ];
+
+      await program.methods
+        .lockWithMilestones(new anchor.BN(2_000), milestones)
+        .accounts({
+          lockTokens: {
+            globalState: globalStatePda,
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            expiryIndex: getExpiryIndexPda(milestones[1].timestamp),
+            creationIndex: getCreationIndexPda(),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            feeRecipient: FEE_RECIPIENT,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          },
+          schedule: getMilestoneSchedulePda(lockId),
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+
+      expect(await nextUnlockTime(lockId)).to.equal(now + 3600);
+    });
+
+    it("follows the vesting schedule", async function () {
+      if (!hasInstruction("set_mock_timestamp")) {
+        this.skip();
+      }
+
+      // 1000 tokens vesting linearly over 1000s: one more token per second
+      const now = Math.floor(Date.now() / 1000);
+      await setMockTimestamp(now);
+      const lockId = await createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1000),
+        new anchor.BN(now + 1000),
+        { ...defaultLockOptions(), vestingCurve: 1 }
+      );
+      expect(await nextUnlockTime(lockId)).to.equal(now + 1);
+
+      await setMockTimestamp(now + 100);
+      expect(await nextUnlockTime(lockId)).to.equal(now + 100);
+
+      await program.methods
+        .claimVested()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+      expect(await nextUnlockTime(lockId)).to.equal(now + 101);
+    });
+  });
+
+  // ===========================================================================
+  // CANCEL
+  // ===========================================================================
+  describe("cancel", () => {
+    const feeRecipientTokenAccount = getAssociatedTokenAddressSync(mint1, FEE_RECIPIENT);
+
+    const setCancelFeeBps = (bps: number) =>
+      program.methods
+        .setCancelFeeBps(bps)
+        .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+        .rpc();
+
+    const cancel = (lockId: number) =>
+      program.methods
+        .cancel()
+        .accounts({
+          unlock: {
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          },
+          feeRecipientTokenAccount,
+        })
+        .signers([user1])
+        .rpc();
+
+    const createCancellableLock = (cancellable: boolean) =>
+      createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+        { ...defaultLockOptions(), cancellable }
+      );
+
+    before(async () => {
+      if (!(await provider.connection.getAccountInfo(feeRecipientTokenAccount))) {
+        await createAssociatedTokenAccount(provider.connection, mintAuthority, mint1, FEE_RECIPIENT);
+      }
+      await setCancelFeeBps(250);
+    });
+
+    after(async () => {
+      await setCancelFeeBps(0);
+    });
+
+    it("refunds the owner minus the fee before maturity and empties the vault", async () => {
+      const lockId = await createCancellableLock(true);
+      const ownerBefore = await getAccount(provider.connection, user1TokenAccount1);
+      const feeBefore = await getAccount(provider.connection, feeRecipientTokenAccount);
+
+      await cancel(lockId);
+
+      const ownerAfter = await getAccount(provider.connection, user1TokenAccount1);
+      const feeAfter = await getAccount(provider.connection, feeRecipientTokenAccount);
+      expect(Number(ownerAfter.amount - ownerBefore.amount)).to.equal(975_000);
+      expect(Number(feeAfter.amount - feeBefore.amount)).to.equal(25_000);
+
+      const vault = await getAccount(provider.connection, getVaultPda(lockId));
+      expect(Number(vault.amount)).to.equal(0);
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.amount.toNumber()).to.equal(0);
+      expect(lock.isUnlocked).to.equal(true);
+
+      try {
+        await cancel(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AlreadyUnlocked");
+      }
+    });
+
+    it("rejects locks created without cancellable", async () => {
+      const lockId = await createCancellableLock(false);
+
+      try {
+        await cancel(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotCancellable");
+      }
+    });
+
+    it("fee must stay below 100% and only the authority can set it", async () => {
+      try {
+        await setCancelFeeBps(10_000);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidBps");
+      }
+
+      try {
+        await program.methods
+          .setCancelFeeBps(100)
+          .accounts({ globalState: globalStatePda, authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // BATCH EXTEND
+  // ===========================================================================
+  describe("batch_extend", () => {
+    // Extends the locks and returns the extended count, read from the transaction's return data
+    const batchExtend = async (owner: Keypair, lockIds: number[], newUnlockTimestamp: number): Promise<number> => {
+      const remainingAccounts = [];
+      for (const lockId of lockIds) {
+        remainingAccounts.push(
+          { pubkey: getLockPda(lockId), isWritable: true, isSigner: false },
+          { pubkey: await getLockExpiryIndexPda(getLockPda(lockId)), isWritable: true, isSigner: false }
+        );
+      }
+      const signature = await program.methods
+        .batchExtend(new anchor.BN(newUnlockTimestamp))
+        .accounts({
+          newExpiryIndex: getExpiryIndexPda(newUnlockTimestamp),
+          owner: owner.publicKey,
+        })
+        .remainingAccounts(remainingAccounts)
+        .signers([owner])
+        .rpc({ commitment: "confirmed" });
+
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return Buffer.from(tx!.meta!.returnData!.data[0], "base64").readUInt32LE(0);
+    };
+
+    it("extends earlier locks and skips the ones already unlocking later", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const newUnlockTimestamp = now + 5 * 86400;
+      const earlyLockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(now + 3600)
+      );
+      const lateLockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(now + 10 * 86400)
+      );
+
+      expect(await batchExtend(user1, [earlyLockId, lateLockId], newUnlockTimestamp)).to.equal(1);
+
+      const early = await program.account.lock.fetch(getLockPda(earlyLockId));
+      const late = await program.account.lock.fetch(getLockPda(lateLockId));
+      expect(early.unlockTimestamp.toNumber()).to.equal(newUnlockTimestamp);
+      expect(late.unlockTimestamp.toNumber()).to.equal(now + 10 * 86400);
+
+      const index = await program.account.expiryIndex.fetch(getExpiryIndexPda(newUnlockTimestamp));
+      expect(index.lockIds.map((id) => id.toNumber())).to.include(earlyLockId);
+
+      // Running the same batch again is a no-op
+      expect(await batchExtend(user1, [earlyLockId, lateLockId], newUnlockTimestamp)).to.equal(0);
+    });
+
+    it("rejects locks of another owner", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const ownLockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(now + 3600)
+      );
+      const otherLockId = await createLock(
+        user2,
+        user2TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(now + 3600)
+      );
+
+      try {
+        await batchExtend(user1, [ownLockId, otherLockId], now + 2 * 86400);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("Unauthorized");
+      }
+
+      const own = await program.account.lock.fetch(getLockPda(ownLockId));
+      expect(own.unlockTimestamp.toNumber()).to.equal(now + 3600);
+    });
+  });
+
+  // ===========================================================================
+  // FEE RECIPIENT VALIDATION
+  // ===========================================================================
+  describe("fee recipient validation", () => {
+    const lockPayingFeeTo = async (feeRecipient: PublicKey) => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+      await program.methods
+        .lock(new anchor.BN(1_000_000), unlockTimestamp, null)
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          feeRecipient,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+    };
+
+    after(async () => {
+      await program.methods.setFeeRecipient(FEE_RECIPIENT).accounts({ authority: authority.publicKey }).rpc();
+    });
+
+    it("rejects a program-owned fee recipient", async () => {
+      await program.methods.setFeeRecipient(globalStatePda).accounts({ authority: authority.publicKey }).rpc();
+
+      try {
+        await lockPayingFeeTo(globalStatePda);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("FeeRecipientNotWallet");
+      }
+    });
+
+    it("rejects the fee recipient locking its own tokens", async () => {
+      await program.methods.setFeeRecipient(user1.publicKey).accounts({ authority: authority.publicKey }).rpc();
+
+      try {
+        await lockPayingFeeTo(user1.publicKey);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("OwnerIsFeeRecipient");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // REFERENCE MINT
+  // ===========================================================================
+  describe("reference_mint", () => {
+    const lockWithReferenceMint = async (referenceMint: PublicKey | null): Promise<number> => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+      await program.methods
+        .lock(new anchor.BN(1_000_000), unlockTimestamp, null)
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          feeRecipient: FEE_RECIPIENT,
+          referenceMint,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+
+      return lockId;
+    };
+
+    it("is stored at creation and surfaced by get_lock", async () => {
+      const lockId = await lockWithReferenceMint(mint2);
+
+      const lock = await program.methods.getLock().accounts({ lock: getLockPda(lockId) }).view();
+      expect(lock.referenceMint.toBase58()).to.equal(mint2.toBase58());
+      expect(lock.id.toNumber()).to.equal(lockId);
+      expect(lock.amount.toNumber()).to.equal(1_000_000);
+    });
+
+    it("defaults to none", async () => {
+      const lockId = await lockWithReferenceMint(null);
+
+      const lock = await program.methods.getLock().accounts({ lock: getLockPda(lockId) }).view();
+      expect(lock.referenceMint.toBase58()).to.equal(PublicKey.default.toBase58());
+    });
+
+    it("must be a mint", async () => {
+      try {
+        await lockWithReferenceMint(user1TokenAccount1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AccountDidNotDeserialize");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // UI AMOUNT
+  // ===========================================================================
+  describe("ui_amount", () => {
+    const uiAmount = (lockId: number, mint: PublicKey): Promise<string> =>
+      program.methods.uiAmount().accounts({ lock: getLockPda(lockId), mint }).view();
+
+    const lockOn = (tokenAccount: PublicKey, mint: PublicKey, amount: anchor.BN) =>
+      createLock(user1, tokenAccount, mint, amount, new anchor.BN(Math.floor(Date.now() / 1000) + 3600));
+
+    it("formats a zero-decimal mint as whole tokens", async () => {
+      const mint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 0);
+      const tokenAccount = await createAssociatedTokenAccount(provider.connection, user1, mint, user1.publicKey);
+      await mintTo(provider.connection, mintAuthority, mint, tokenAccount, mintAuthority, 1_000);
+
+      const lockId = await lockOn(tokenAccount, mint, new anchor.BN(250));
+      expect(await uiAmount(lockId, mint)).to.equal("250");
+    });
+
+    it("formats a 6-decimal mint, trimming trailing zeros", async () => {
+      expect(await uiAmount(await lockOn(user1TokenAccount2, mint2, new anchor.BN(1_500_000)), mint2)).to.equal("1.5");
+      expect(await uiAmount(await lockOn(user1TokenAccount2, mint2, new anchor.BN(7)), mint2)).to.equal("0.000007");
+      expect(await uiAmount(await lockOn(user1TokenAccount2, mint2, new anchor.BN(3_000_000)), mint2)).to.equal("3");
+    });
+
+    it("formats a 9-decimal mint", async () => {
+      const lockId = await lockOn(user1TokenAccount1, mint1, new anchor.BN("1234567890123"));
+      expect(await uiAmount(lockId, mint1)).to.equal("1234.567890123");
+    });
+  });
+
+  // ===========================================================================
+  // LOCK MS
+  // ===========================================================================
+  describe("lock_ms", () => {
+    const lockMs = async (unlockTimestampMs: number): Promise<number> => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+
+      await program.methods
+        .lockMs(new anchor.BN(1_000_000), new anchor.BN(unlockTimestampMs))
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          expiryIndex: getExpiryIndexPda(Math.ceil(unlockTimestampMs / 1000)),
+          creationIndex: getCreationIndexPda(),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          feeRecipient: FEE_RECIPIENT,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+
+      return lockId;
+    };
+
+    it("converts milliseconds to seconds, rounding up", async () => {
+      const wholeSecondMs = (Math.floor(Date.now() / 1000) + 3600) * 1000;
+
+      const exact = await program.account.lock.fetch(getLockPda(await lockMs(wholeSecondMs)));
+      expect(exact.unlockTimestamp.toNumber()).to.equal(wholeSecondMs / 1000);
+
+      const rounded = await program.account.lock.fetch(getLockPda(await lockMs(wholeSecondMs + 500)));
+      expect(rounded.unlockTimestamp.toNumber()).to.equal(wholeSecondMs / 1000 + 1);
+    });
+
+    it("rejects a timestamp in seconds", async () => {
+      try {
+        await lockMs(Math.floor(Date.now() / 1000) + 3600);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TimestampNotMilliseconds");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // DISABLED INSTRUCTIONS
+  // ===========================================================================
+  describe("disabled_instructions", () => {
+    const DISABLE_LOCK = 1;
+    const DISABLE_TOP_UP = 2;
+    const DISABLE_EXTEND = 4;
+    const DISABLE_UNLOCK = 8;
+
+    const setDisabledInstructions = (flags: number) =>
+      program.methods
+        .setDisabledInstructions(flags)
+        .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+        .rpc();
+
+    const expectDisabled = async (promise: Promise<unknown>) => {
+      try {
+        await promise;
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InstructionDisabled");
+      }
+    };
+
+    const lockFor = (seconds: number) =>
+      createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + seconds)
+      );
+
+    const topUp = (lockId: number) =>
+      program.methods
+        .topUp(new anchor.BN(1_000))
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    const extendBy = async (lockId: number, seconds: number) => {
+      const lockPda = getLockPda(lockId);
+      const lock = await program.account.lock.fetch(lockPda);
+      return program.methods
+        .extendBy(new anchor.BN(seconds))
+        .accounts({
+          lock: lockPda,
+          oldExpiryIndex: getExpiryIndexPda(lock.unlockTimestamp),
+          newExpiryIndex: getExpiryIndexPda(lock.unlockTimestamp.toNumber() + seconds),
+          owner: user1.publicKey,
+        })
+        .signers([user1])
+        .rpc();
+    };
+
+    const unlock = (lockId: number) =>
+      program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    afterEach(async () => {
+      await setDisabledInstructions(0);
+    });
+
+    it("disables lock creation only", async () => {
+      const lockId = await lockFor(3600);
+      await setDisabledInstructions(DISABLE_LOCK);
+
+      await expectDisabled(lockFor(3600));
+      await topUp(lockId);
+      await extendBy(lockId, 60);
+    });
+
+    it("disables top-ups only", async () => {
+      const lockId = await lockFor(3600);
+      await setDisabledInstructions(DISABLE_TOP_UP);
+
+      await expectDisabled(topUp(lockId));
+      await extendBy(lockId, 60);
+      await lockFor(3600);
+    });
+
+    it("disables extensions only", async () => {
+      const lockId = await lockFor(3600);
+      await setDisabledInstructions(DISABLE_EXTEND);
+
+      await expectDisabled(extendBy(lockId, 60));
+      await topUp(lockId);
+    });
+
+    it("disables unlocks until the unlock bit alone is cleared", async () => {
+      const lockId = await lockFor(2);
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+      await setDisabledInstructions(DISABLE_LOCK | DISABLE_TOP_UP | DISABLE_EXTEND | DISABLE_UNLOCK);
+
+      await expectDisabled(unlock(lockId));
+
+      await setDisabledInstructions(DISABLE_LOCK | DISABLE_TOP_UP | DISABLE_EXTEND);
+      await unlock(lockId);
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+    });
+
+    it("rejects unknown bits and non-authority callers", async () => {
+      try {
+        await setDisabledInstructions(16);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidInstructionFlags");
+      }
+
+      try {
+        await program.methods
+          .setDisabledInstructions(DISABLE_LOCK)
+          .accounts({ globalState: globalStatePda, authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // SALTED VAULTS
+  // ===========================================================================
+  describe("salted vaults", () => {
+    const getSaltedVaultPda = (lockId: number, salt: Buffer): PublicKey =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), new anchor.BN(lockId).toArrayLike(Buffer, "le", 8), salt],
+        program.programId
+      )[0];
+
+    const lockWithSaltedVault = async (unlockTimestamp: anchor.BN, salt: Buffer): Promise<number> => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+
+      await program.methods
+        .lockWithSaltedVault(new anchor.BN(1_000_000), unlockTimestamp, [...salt])
+        .accounts({
+          lockTokens: {
+            globalState: globalStatePda,
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            expiryIndex: getExpiryIndexPda(unlockTimestamp),
+            creationIndex: getCreationIndexPda(),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            feeRecipient: FEE_RECIPIENT,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          },
+          saltedVault: getSaltedVaultPda(lockId, salt),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+      return lockId;
+    };
+
+    const unlockSaltedVault = (lockId: number, vault: PublicKey) =>
+      program.methods
+        .unlockSaltedVault()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault,
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    it("holds the tokens in a vault derived from the owner's salt", async () => {
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+      const salt = Keypair.generate().publicKey.toBuffer();
+      const lockId = await lockWithSaltedVault(unlockTimestamp, salt);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.vaultScheme).to.equal(2);
+      expect(Buffer.from(lock.vaultSalt).equals(salt)).to.equal(true);
+
+      const vault = await getAccount(provider.connection, getSaltedVaultPda(lockId, salt));
+      expect(Number(vault.amount)).to.equal(1_000_000);
+      expect(vault.owner.toBase58()).to.equal(getVaultPda(lockId).toBase58());
+
+      // The unused PDA token account was closed
+      expect(await provider.connection.getAccountInfo(getVaultPda(lockId))).to.equal(null);
+    });
+
+    it("unlock re-derives the vault from the stored salt", async () => {
+      const salt = Keypair.generate().publicKey.toBuffer();
+      const lockId = await lockWithSaltedVault(new anchor.BN(Math.floor(Date.now() / 1000) + 2), salt);
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+
+      // The vault can't be found without the salt
+      const otherVault = getSaltedVaultPda(lockId, Keypair.generate().publicKey.toBuffer());
+      try {
+        await unlockSaltedVault(lockId, otherVault);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AccountNotInitialized");
+      }
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      const before = await getAccount(provider.connection, user1TokenAccount1);
+      await unlockSaltedVault(lockId, getSaltedVaultPda(lockId, Buffer.from(lock.vaultSalt)));
+      const after = await getAccount(provider.connection, user1TokenAccount1);
+      expect(Number(after.amount - before.amount)).to.equal(1_000_000);
+
+      const unlocked = await program.account.lock.fetch(getLockPda(lockId));
+      expect(unlocked.isUnlocked).to.equal(true);
+    });
+  });
+
+  // ===========================================================================
+  // MINT CAP
+  // ===========================================================================
+  describe("mint cap", () => {
+    let mint: PublicKey;
+    let tokenAccount: PublicKey;
+
+    const lockAmount = (amount: number) =>
+      createLock(
+        user1,
+        tokenAccount,
+        mint,
+        new anchor.BN(amount),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+    const topUp = (lockId: number, amount: number) =>
+      program.methods
+        .topUp(new anchor.BN(amount))
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint,
+          ownerTokenAccount: tokenAccount,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    const expectCapExceeded = async (promise: Promise<unknown>) => {
+      try {
+        await promise;
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("MintCapExceeded");
+      }
+    };
+
+    before(async () => {
+      mint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+      tokenAccount = await createAssociatedTokenAccount(provider.connection, user1, mint, user1.publicKey);
+      await mintTo(provider.connection, mintAuthority, mint, tokenAccount, mintAuthority, 10_000_000);
+
+      // The cap is enforced against the mint's stats
+      await program.methods.backfillMintStats().accounts({ mint, authority: authority.publicKey }).rpc();
+      await program.methods
+        .setMintCap(new anchor.BN(1_500_000))
+        .accounts({ mint, authority: authority.publicKey })
+        .rpc();
+    });
+
+    it("rejects locks and top-ups taking the locked total over the cap", async () => {
+      const lockId = await lockAmount(1_000_000);
+      await expectCapExceeded(lockAmount(600_000));
+
+      // Exactly at the cap is fine
+      await topUp(lockId, 500_000);
+      await expectCapExceeded(topUp(lockId, 1));
+
+      const stats = await program.account.mintStats.fetch(getMintStatsPda(mint));
+      expect(stats.totalLocked.toNumber()).to.equal(1_500_000);
+    });
+
+    it("0 removes the cap", async () => {
+      await program.methods
+        .setMintCap(new anchor.BN(0))
+        .accounts({ mint, authority: authority.publicKey })
+        .rpc();
+      await lockAmount(600_000);
+    });
+
+    it("requires the mint's stats and the authority", async () => {
+      const mintWithoutStats = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+      try {
+        await program.methods
+          .setMintCap(new anchor.BN(1))
+          .accounts({ mint: mintWithoutStats, authority: authority.publicKey })
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AccountNotInitialized");
+      }
+
+      try {
+        await program.methods
+          .setMintCap(new anchor.BN(1))
+          .accounts({ mint, authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // ELAPSED SINCE CREATION
+  // ===========================================================================
+  describe("elapsed_since_creation", () => {
+    const elapsedSinceCreation = async (lockId: number): Promise<number> =>
+      (await program.methods.elapsedSinceCreation().accounts({ lock: getLockPda(lockId) }).view()).toNumber();
+
+    after(async () => {
+      if (hasInstruction("set_mock_timestamp")) {
+        await setMockTimestamp(0);
+      }
+    });
+
+    it("counts the seconds since creation by chain time", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+      await new Promise((resolve) => setTimeout(resolve, 2000));
+      const elapsed = await elapsedSinceCreation(lockId);
+      expect(elapsed).to.be.greaterThan(0);
+      expect(elapsed).to.be.lessThan(60);
+    });
+
+    it("clamps to zero when the clock reads before creation", async function () {
+      if (!hasInstruction("set_mock_timestamp")) {
+        this.skip();
+      }
+
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+
+      await setMockTimestamp(lock.createdAt.toNumber() - 100);
+      expect(await elapsedSinceCreation(lockId)).to.equal(0);
+
+      await setMockTimestamp(lock.createdAt.toNumber() + 3 * 86400);
+      expect(await elapsedSinceCreation(lockId)).to.equal(3 * 86400);
+    });
+  });
+
+  // ===========================================================================
+  // MINT MIGRATION
+  // ===========================================================================
+  describe("mint migration", () => {
+    let oldMint: PublicKey;
+    let newMint: PublicKey;
+    let oldTokenAccount: PublicKey;
+    let newTokenAccount: PublicKey;
+    let reserve: PublicKey;
+
+    const getMigrationPda = (mint: PublicKey): PublicKey =>
+      PublicKey.findProgramAddressSync([Buffer.from("migration"), mint.toBuffer()], program.programId)[0];
+
+    const getMigratedVaultPda = (lockId: number, mint: PublicKey): PublicKey =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), new anchor.BN(lockId).toArrayLike(Buffer, "le", 8), mint.toBuffer()],
+        program.programId
+      )[0];
+
+    const lockOldMint = (amount: number, unlockTimestamp: anchor.BN) =>
+      createLock(user1, oldTokenAccount, oldMint, new anchor.BN(amount), unlockTimestamp);
+
+    const migrateMint = (lockId: number, signer: Keypair = authority) =>
+      program.methods
+        .migrateMint()
+        .accounts({
+          migration: getMigrationPda(oldMint),
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          oldMint,
+          newMint,
+          reserve,
+          newVault: getMigratedVaultPda(lockId, newMint),
+          authority: signer.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers(signer === authority ? [] : [signer])
+        .rpc();
+
+    before(async () => {
+      oldMint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+      newMint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+      oldTokenAccount = await createAssociatedTokenAccount(provider.connection, user1, oldMint, user1.publicKey);
+      newTokenAccount = await createAssociatedTokenAccount(provider.connection, user1, newMint, user1.publicKey);
+      await mintTo(provider.connection, mintAuthority, oldMint, oldTokenAccount, mintAuthority, 20_000_000);
+
+      await program.methods
+        .registerMintMigration()
+        .accounts({ oldMint, newMint, authority: authority.publicKey })
+        .rpc();
+
+      // The project funds the swap
+      reserve = await createAccount(
+        provider.connection,
+        mintAuthority,
+        newMint,
+        getMigrationPda(oldMint),
+        Keypair.generate()
+      );
+      await mintTo(provider.connection, mintAuthority, newMint, reserve, mintAuthority, 10_000_000);
+    });
+
+    it("registers a migration between mints of the same decimals only", async () => {
+      const migration = await program.account.mintMigration.fetch(getMigrationPda(oldMint));
+      expect(migration.oldMint.toBase58()).to.equal(oldMint.toBase58());
+      expect(migration.newMint.toBase58()).to.equal(newMint.toBase58());
+
+      const otherMint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+      const nineDecimals = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 9);
+      try {
+        await program.methods
+          .registerMintMigration()
+          .accounts({ oldMint: otherMint, newMint: nineDecimals, authority: authority.publicKey })
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("MigrationDecimalsMismatch");
+      }
+
+      try {
+        await program.methods
+          .registerMintMigration()
+          .accounts({ oldMint: otherMint, newMint: otherMint, authority: authority.publicKey })
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidMigration");
+      }
+
+      try {
+        await program.methods
+          .registerMintMigration()
+          .accounts({ oldMint: otherMint, newMint, authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+
+    it("swaps the locked tokens 1:1 into a vault of the new mint", async () => {
+      const lockId = await lockOldMint(1_000_000, new anchor.BN(Math.floor(Date.now() / 1000) + 3600));
+      const supplyBefore = BigInt((await provider.connection.getTokenSupply(oldMint)).value.amount);
+
+      await migrateMint(lockId);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.mint.toBase58()).to.equal(newMint.toBase58());
+      expect(lock.amount.toNumber()).to.equal(1_000_000);
+      expect(lock.vaultScheme).to.equal(2);
+      expect(Buffer.from(lock.vaultSalt).equals(newMint.toBuffer())).to.equal(true);
+
+      const vault = await getAccount(provider.connection, getMigratedVaultPda(lockId, newMint));
+      expect(Number(vault.amount)).to.equal(1_000_000);
+      expect(vault.owner.toBase58()).to.equal(getVaultPda(lockId).toBase58());
+      expect(Number((await getAccount(provider.connection, reserve)).amount)).to.equal(9_000_000);
+
+      // The old tokens are burned and their vault closed
+      const supplyAfter = BigInt((await provider.connection.getTokenSupply(oldMint)).value.amount);
+      expect(supplyBefore - supplyAfter).to.equal(BigInt(1_000_000));
+      expect(await provider.connection.getAccountInfo(getVaultPda(lockId))).to.equal(null);
+
+      // The lock now holds the new mint, so it can't migrate again
+      try {
+        await migrateMint(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.be.oneOf(["InvalidMint", "AccountNotInitialized"]);
+      }
+    });
+
+    it("unlocks the new tokens once the lock expires", async () => {
+      const lockId = await lockOldMint(1_000_000, new anchor.BN(Math.floor(Date.now() / 1000) + 2));
+      await migrateMint(lockId);
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+
+      const before = await getAccount(provider.connection, newTokenAccount);
+      await program.methods
+        .unlockSaltedVault()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getMigratedVaultPda(lockId, newMint),
+          mint: newMint,
+          ownerTokenAccount: newTokenAccount,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+      const after = await getAccount(provider.connection, newTokenAccount);
+      expect(Number(after.amount - before.amount)).to.equal(1_000_000);
+    });
+
+    it("is authority only and leaves the lock untouched when the reserve runs dry", async () => {
+      const lockId = await lockOldMint(1_000_000, new anchor.BN(Math.floor(Date.now() / 1000) + 3600));
+      try {
+        await migrateMint(lockId, user1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+
+      // Only 8 tokens are left in the reserve
+      const bigLockId = await lockOldMint(9_000_000, new anchor.BN(Math.floor(Date.now() / 1000) + 3600));
+      try {
+        await migrateMint(bigLockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(String(err)).to.match(/insufficient funds|custom program error: 0x1\b/i);
+      }
+
+      const lock = await program.account.lock.fetch(getLockPda(bigLockId));
+      expect(lock.mint.toBase58()).to.equal(oldMint.toBase58());
+      const vault = await getAccount(provider.connection, getVaultPda(bigLockId));
+      expect(Number(vault.amount)).to.equal(9_000_000);
+    });
+
+    it("only migrates locks released by unlock", async () => {
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+      const escrowLockId = await createLockWithOptions(
+        user1,
+        oldTokenAccount,
+        oldMint,
+        new anchor.BN(1_000_000),
+        unlockTimestamp,
+        { ...defaultLockOptions(), arbiter: user3.publicKey, counterparty: user2.publicKey }
+      );
+
+      const heirLockId = (await program.account.globalState.fetch(globalStatePda)).lockCounter.toNumber();
+      await program.methods
+        .lockWithHeirs(new anchor.BN(1_000_000), unlockTimestamp, [
+          { heir: user2.publicKey, graceOffset: new anchor.BN(60) },
+        ])
+        .accounts({
+          lockTokens: {
+            globalState: globalStatePda,
+            lock: getLockPda(heirLockId),
+            vault: getVaultPda(heirLockId),
+            expiryIndex: getExpiryIndexPda(unlockTimestamp),
+            creationIndex: getCreationIndexPda(),
+            mint: oldMint,
+            ownerTokenAccount: oldTokenAccount,
+            owner: user1.publicKey,
+            feeRecipient: FEE_RECIPIENT,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          },
+          heirs: PublicKey.findProgramAddressSync(
+            [Buffer.from("heirs"), new anchor.BN(heirLockId).toArrayLike(Buffer, "le", 8)],
+            program.programId
+          )[0],
+        })
+        .signers([user1])
+        .rpc();
+
+      for (const lockId of [escrowLockId, heirLockId]) {
+        try {
+          await migrateMint(lockId);
+          expect.fail("Should have thrown error");
+        } catch (err: any) {
+          expect(err.error?.errorCode?.code).to.equal("VaultBoundLock");
+        }
+        const lock = await program.account.lock.fetch(getLockPda(lockId));
+        expect(lock.mint.toBase58()).to.equal(oldMint.toBase58());
+      }
+    });
+
+    it("rejects locks holding a deposit", async () => {
+      const lockId = await createLockWithOptions(
+        user1,
+        oldTokenAccount,
+        oldMint,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+        {
+          ...defaultLockOptions(),
+          depositLamports: new anchor.BN(0.01 * LAMPORTS_PER_SOL),
+          claimWindow: new anchor.BN(3600),
+          allowPartial: false,
+        }
+      );
+
+      try {
+        await migrateMint(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("DepositNotSettled");
+      }
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.mint.toBase58()).to.equal(oldMint.toBase58());
+      expect(lock.depositLamports.toNumber()).to.equal(0.01 * LAMPORTS_PER_SOL);
+    });
+
+    it("rejects burn-on-unlock locks", async () => {
+      const lockId = await createLockWithOptions(
+        user1,
+        oldTokenAccount,
+        oldMint,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+        { ...defaultLockOptions(), burnOnUnlock: true, allowPartial: false }
+      );
+
+      try {
+        await migrateMint(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("BurnOnUnlock");
+      }
+      const vault = await getAccount(provider.connection, getVaultPda(lockId));
+      expect(Number(vault.amount)).to.equal(1_000_000);
+    });
+  });
+
+  // ===========================================================================
+  // TRY UNLOCK
+  // ===========================================================================
+  describe("try_unlock", () => {
+    const tryUnlock = async (lockId: number): Promise<boolean> => {
+      const signature = await program.methods
+        .tryUnlock()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc({ commitment: "confirmed" });
+
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return Buffer.from(tx!.meta!.returnData!.data[0], "base64")[0] === 1;
+    };
+
+    it("succeeds without unlocking a lock that isn't matured", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+      expect(await tryUnlock(lockId)).to.equal(false);
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(false);
+    });
+
+    it("unlocks once, then succeeds as a no-op", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+
+      const before = await getAccount(provider.connection, user1TokenAccount1);
+      expect(await tryUnlock(lockId)).to.equal(true);
+      expect(await tryUnlock(lockId)).to.equal(false);
+      const after = await getAccount(provider.connection, user1TokenAccount1);
+      expect(Number(after.amount - before.amount)).to.equal(1_000_000);
+    });
+  });
+
+  // ===========================================================================
+  // UNLOCK DEPOSITS
+  // ===========================================================================
+  describe("unlock deposits", () => {
+    const deposit = 0.01 * LAMPORTS_PER_SOL;
+
+    const lockWithDeposit = (unlockTimestamp: anchor.BN, claimWindow: number) =>
+      createLockWithOptions(user1, user1TokenAccount1, mint1, new anchor.BN(1_000_000), unlockTimestamp, {
+        ...defaultLockOptions(),
+        depositLamports: new anchor.BN(deposit),
+        claimWindow: new anchor.BN(claimWindow),
+        allowPartial: false,
+      });
+
+    const unlockLock = (lockId: number, feeRecipient: PublicKey | null) =>
+      program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          feeRecipient,
+        })
+        .signers([user1])
+        .rpc();
+
+    it("holds the deposit in the lock account", async () => {
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+      const lockId = await lockWithDeposit(unlockTimestamp, 600);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.depositLamports.toNumber()).to.equal(deposit);
+      expect(lock.claimWindow.toNumber()).to.equal(600);
+
+      const info = await provider.connection.getAccountInfo(getLockPda(lockId));
+      const rent = await provider.connection.getMinimumBalanceForRentExemption(info!.data.length);
+      expect(info!.lamports).to.equal(rent + deposit);
+    });
+
+    it("refunds the deposit on a timely unlock", async () => {
+      const lockId = await lockWithDeposit(new anchor.BN(Math.floor(Date.now() / 1000) + 2), 600);
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+
+      const before = await provider.connection.getBalance(user1.publicKey);
+      await unlockLock(lockId, null);
+      const after = await provider.connection.getBalance(user1.publicKey);
+      expect(after - before).to.equal(deposit);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+      expect(lock.depositLamports.toNumber()).to.equal(0);
+    });
+
+    it("forfeits the deposit to the fee recipient on a late unlock", async () => {
+      const lockId = await lockWithDeposit(new anchor.BN(Math.floor(Date.now() / 1000) + 2), 1);
+      await new Promise((resolve) => setTimeout(resolve, 5000));
+
+      try {
+        await unlockLock(lockId, null);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("FeeRecipientMissing");
+      }
+
+      const ownerBefore = await provider.connection.getBalance(user1.publicKey);
+      const recipientBefore = await provider.connection.getBalance(FEE_RECIPIENT);
+      await unlockLock(lockId, FEE_RECIPIENT);
+      expect((await provider.connection.getBalance(FEE_RECIPIENT)) - recipientBefore).to.equal(deposit);
+      expect(await provider.connection.getBalance(user1.publicKey)).to.equal(ownerBefore);
+    });
+
+    it("is only settled by unlock, not by assisted_unlock", async () => {
+      const lockId = await lockWithDeposit(new anchor.BN(Math.floor(Date.now() / 1000) + 2), 600);
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+
+      try {
+        await program.methods
+          .assistedUnlock()
+          .accounts({
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            authority: authority.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("DepositNotSettled");
+      }
+
+      await unlockLock(lockId, null);
+      expect((await program.account.lock.fetch(getLockPda(lockId))).depositLamports.toNumber()).to.equal(0);
+    });
+
+    it("rejects deposits on locks other flows could end", async () => {
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+      for (const options of [
+        { claimWindow: new anchor.BN(0) },
+        { cancellable: true },
+        { vestingCurve: 1 },
+        // Partial withdrawals could drain the lock short of `unlock`
+        { allowPartial: true },
+        { reducible: true },
+      ]) {
+        try {
+          await createLockWithOptions(user1, user1TokenAccount1, mint1, new anchor.BN(1_000_000), unlockTimestamp, {
+            ...defaultLockOptions(),
+            depositLamports: new anchor.BN(deposit),
+            claimWindow: new anchor.BN(600),
+            allowPartial: false,
+            ...options,
+          });
+          expect.fail("Should have thrown error");
+        } catch (err: any) {
+          expect(err.error?.errorCode?.code).to.be.oneOf(["InvalidClaimWindow", "DepositRequiresPlainLock"]);
+        }
+      }
+    });
+  });
+
+  // ===========================================================================
+  // PROGRAM STATS
+  // ===========================================================================
+  describe("program stats", () => {
+    const programStatsPda = PublicKey.findProgramAddressSync([Buffer.from("program_stats")], program.programId)[0];
+
+    it("counts every lock, unlock and fee", async () => {
+      const before = await program.account.programStats.fetch(programStatsPda);
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      expect(before.totalLocksEver.toNumber()).to.equal(globalState.lockCounter.toNumber());
+
+      const recipientBefore = await provider.connection.getBalance(FEE_RECIPIENT);
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      const fee = (await provider.connection.getBalance(FEE_RECIPIENT)) - recipientBefore;
+
+      const locked = await program.account.programStats.fetch(programStatsPda);
+      expect(locked.totalLocksEver.toNumber()).to.equal(before.totalLocksEver.toNumber() + 1);
+      expect(locked.activeLocks.toNumber()).to.equal(before.activeLocks.toNumber() + 1);
+      expect(locked.totalFees.toNumber()).to.equal(before.totalFees.toNumber() + fee);
+
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+      await program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+      const unlocked = await program.account.programStats.fetch(programStatsPda);
+      expect(unlocked.totalLocksEver.toNumber()).to.equal(locked.totalLocksEver.toNumber());
+      expect(unlocked.activeLocks.toNumber()).to.equal(locked.activeLocks.toNumber() - 1);
+      expect(unlocked.totalUnlocks.toNumber()).to.equal(locked.totalUnlocks.toNumber() + 1);
+    });
+  });
+
+  // ===========================================================================
+  // UNLOCK AND STAKE
+  // ===========================================================================
+  describe("unlock_and_stake", () => {
+    // programs/mock_stake, deployed alongside lockfun by `anchor test`
+    const MOCK_STAKE_PROGRAM_ID = new PublicKey("3Q2NyaAvrRK2bPekNirvML8boN4vJFUaohgYKHzCFQSz");
+
+    const setStakingProgram = (stakingProgram: PublicKey) =>
+      program.methods
+        .setStakingProgram(stakingProgram)
+        .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+        .rpc();
+
+    const unlockAndStake = (lockId: number, stakingProgram: PublicKey, pool: PublicKey) =>
+      program.methods
+        .unlockAndStake()
+        .accounts({
+          unlock: {
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          },
+          stakingProgram,
+          staker: user1.publicKey,
+        })
+        .remainingAccounts([{ pubkey: pool, isSigner: false, isWritable: true }])
+        .signers([user1])
+        .rpc();
+
+    const createMaturingLock = async (): Promise<number> => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+      return lockId;
+    };
+
+    after(async () => {
+      await setStakingProgram(PublicKey.default);
+    });
+
+    it("requires a configured staking program", async () => {
+      const lockId = await createMaturingLock();
+      try {
+        await unlockAndStake(lockId, TOKEN_PROGRAM_ID, user2TokenAccount1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidStakingProgram");
+      }
+
+      try {
+        await program.methods
+          .setStakingProgram(MOCK_STAKE_PROGRAM_ID)
+          .accounts({ globalState: globalStatePda, authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+
+    it("moves matured tokens straight from the vault into the staking program", async function () {
+      const stakeProgram = await provider.connection.getAccountInfo(MOCK_STAKE_PROGRAM_ID);
+      if (!stakeProgram?.executable) {
+        this.skip();
+      }
+
+      await setStakingProgram(MOCK_STAKE_PROGRAM_ID);
+      const pool = await createAccount(
+        provider.connection,
+        mintAuthority,
+        mint1,
+        Keypair.generate().publicKey,
+        Keypair.generate()
+      );
+      const lockId = await createMaturingLock();
+
+      const walletBefore = await getAccount(provider.connection, user1TokenAccount1);
+      await unlockAndStake(lockId, MOCK_STAKE_PROGRAM_ID, pool);
+
+      expect(Number((await getAccount(provider.connection, pool)).amount)).to.equal(1_000_000);
+      expect(Number((await getAccount(provider.connection, getVaultPda(lockId))).amount)).to.equal(0);
+      const walletAfter = await getAccount(provider.connection, user1TokenAccount1);
+      expect(walletAfter.amount).to.equal(walletBefore.amount);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+    });
+  });
+
+  // ===========================================================================
+  // LOCK ADDRESS CONSISTENCY
+  // ===========================================================================
+  describe("lock address consistency", () => {
+    const unlockWith = (lock: PublicKey, vault: PublicKey) =>
+      program.methods
+        .unlock(null)
+        .accounts({
+          lock,
+          vault,
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    it("rejects a vault derived from another lock's id", async () => {
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 2);
+      const lockA = await createLock(user1, user1TokenAccount1, mint1, new anchor.BN(1_000_000), unlockTimestamp);
+      const lockB = await createLock(user1, user1TokenAccount1, mint1, new anchor.BN(2_000_000), unlockTimestamp);
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+
+      try {
+        await unlockWith(getLockPda(lockA), getVaultPda(lockB));
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("ConstraintSeeds");
+      }
+
+      // The consistent pairing still works
+      await unlockWith(getLockPda(lockA), getVaultPda(lockA));
+    });
+
+    it("rejects an account that isn't a lock at the lock address", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+      try {
+        await unlockWith(globalStatePda, getVaultPda(lockId));
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AccountDiscriminatorMismatch");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // OWNER MINT TOTAL
+  // ===========================================================================
+  describe("owner_mint_total", () => {
+    let mint: PublicKey;
+    let tokenAccount: PublicKey;
+
+    const ownerMintTotal = async (owner: PublicKey): Promise<number> =>
+      (await program.methods.ownerMintTotal().accounts({ owner, mint }).view()).toNumber();
+
+    before(async () => {
+      mint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+      tokenAccount = await createAssociatedTokenAccount(provider.connection, user1, mint, user1.publicKey);
+      await mintTo(provider.connection, mintAuthority, mint, tokenAccount, mintAuthority, 10_000_000);
+    });
+
+    it("is 0 before the owner's first lock of the mint", async () => {
+      expect(await ownerMintTotal(user1.publicKey)).to.equal(0);
+      expect(await provider.connection.getAccountInfo(getOwnerMintStatsPda(user1.publicKey, mint))).to.equal(null);
+    });
+
+    it("follows locks, top-ups and unlocks of the owner", async () => {
+      const maturing = await createLock(
+        user1,
+        tokenAccount,
+        mint,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      const longLock = await createLock(
+        user1,
+        tokenAccount,
+        mint,
+        new anchor.BN(2_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+      expect(await ownerMintTotal(user1.publicKey)).to.equal(3_000_000);
+      expect(await ownerMintTotal(user2.publicKey)).to.equal(0);
+
+      await program.methods
+        .topUp(new anchor.BN(500_000))
+        .accounts({
+          lock: getLockPda(longLock),
+          vault: getVaultPda(longLock),
+          mint,
+          ownerTokenAccount: tokenAccount,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+      expect(await ownerMintTotal(user1.publicKey)).to.equal(3_500_000);
+
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+      await program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(maturing),
+          vault: getVaultPda(maturing),
+          mint,
+          ownerTokenAccount: tokenAccount,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+      expect(await ownerMintTotal(user1.publicKey)).to.equal(2_500_000);
+
+      const stats = await program.account.ownerMintStats.fetch(getOwnerMintStatsPda(user1.publicKey, mint));
+      expect(stats.owner.toBase58()).to.equal(user1.publicKey.toBase58());
+      expect(stats.mint.toBase58()).to.equal(mint.toBase58());
+    });
+  });
+
+  // ===========================================================================
+  // SUSPICIOUS MINTS
+  // ===========================================================================
+  describe("flag_mint_suspicious", () => {
+    let mint: PublicKey;
+    let tokenAccount: PublicKey;
+
+    const flagMint = (flagged: boolean) =>
+      program.methods
+        .flagMintSuspicious(flagged)
+        .accounts({ mint, authority: authority.publicKey })
+        .rpc();
+
+    before(async () => {
+      mint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+      tokenAccount = await createAssociatedTokenAccount(provider.connection, user1, mint, user1.publicKey);
+      await mintTo(provider.connection, mintAuthority, mint, tokenAccount, mintAuthority, 10_000_000);
+    });
+
+    it("rejects new locks of a flagged mint but keeps unlocks open", async () => {
+      const lockId = await createLock(
+        user1,
+        tokenAccount,
+        mint,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      await flagMint(true);
+
+      try {
+        await createLock(
+          user1,
+          tokenAccount,
+          mint,
+          new anchor.BN(1_000_000),
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+        );
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("MintFlagged");
+      }
+
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+      await program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint,
+          ownerTokenAccount: tokenAccount,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+      // Clearing the flag accepts locks again
+      await flagMint(false);
+      await createLock(
+        user1,
+        tokenAccount,
+        mint,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+    });
+
+    it("is authority only", async () => {
+      try {
+        await program.methods
+          .flagMintSuspicious(true)
+          .accounts({ mint, authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // CLOCK TOLERANCE (boundary tests only run against builds with the `test-clock` feature)
+  // ===========================================================================
+  describe("clock tolerance", () => {
+    const setClockTolerance = (seconds: number) =>
+      program.methods
+        .setClockTolerance(seconds)
+        .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+        .rpc();
+
+    const unlock = (lockId: number) =>
+      program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    after(async () => {
+      await setClockTolerance(0);
+      if (hasInstruction("set_mock_timestamp")) {
+        await setMockTimestamp(0);
+      }
+    });
+
+    it("makes locks unlockable up to the tolerance before their timestamp", async function () {
+      if (!hasInstruction("set_mock_timestamp")) {
+        this.skip();
+      }
+
+      await setMockTimestamp(0);
+      const unlockTimestamp = Math.floor(Date.now() / 1000) + 30 * 86400;
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(unlockTimestamp)
+      );
+      await setClockTolerance(5);
+
+      // One second outside the tolerance
+      await setMockTimestamp(unlockTimestamp - 6);
+      try {
+        await unlock(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TooEarly");
+      }
+
+      // Exactly at the tolerance
+      await setMockTimestamp(unlockTimestamp - 5);
+      await unlock(lockId);
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+    });
+
+    it("is bounded and authority only", async () => {
+      try {
+        await setClockTolerance(301);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidClockTolerance");
+      }
+
+      try {
+        await program.methods
+          .setClockTolerance(5)
+          .accounts({ globalState: globalStatePda, authority: user1.publicKey })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      expect(globalState.clockToleranceSeconds).to.equal(0);
+    });
+  });
+
+  // ===========================================================================
+  // SPLIT LOCK (prepare_vault + finalize_lock)
+  // ===========================================================================
+  describe("prepare_vault and finalize_lock", () => {
+    const prepareVault = async (lockId: number) =>
+      program.methods
+        .prepareVault(new anchor.BN(lockId))
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    const finalizeLock = (lockId: number, amount: anchor.BN, unlockTimestamp: anchor.BN) =>
+      program.methods
+        .finalizeLock(amount, unlockTimestamp)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          feeRecipient: FEE_RECIPIENT,
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+    const nextLockId = async () =>
+      (await program.account.globalState.fetch(globalStatePda)).lockCounter.toNumber();
+
+    it("reserves the id with an inert lock, then locks on finalize", async () => {
+      const lockId = await nextLockId();
+      await prepareVault(lockId);
+
+      expect(await nextLockId()).to.equal(lockId + 1);
+      let lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.owner.toString()).to.equal(user1.publicKey.toString());
+      expect(lock.amount.toNumber()).to.equal(0);
+      expect(lock.isUnlocked).to.equal(true);
+      expect(lock.createdAt.toNumber()).to.equal(0);
+      let vault = await getAccount(provider.connection, getVaultPda(lockId));
+      expect(Number(vault.amount)).to.equal(0);
+
+      // A prepared lock can't be unlocked
+      try {
+        await program.methods
+          .unlock(null)
+          .accounts({
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AlreadyUnlocked");
+      }
+
+      const amount = new anchor.BN(2_000_000);
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+      const feeRecipientBefore = await provider.connection.getBalance(FEE_RECIPIENT);
+      await finalizeLock(lockId, amount, unlockTimestamp);
+
+      lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.amount.toNumber()).to.equal(2_000_000);
+      expect(lock.isUnlocked).to.equal(false);
+      expect(lock.unlockTimestamp.toNumber()).to.equal(unlockTimestamp.toNumber());
+      expect(lock.createdAt.toNumber()).to.be.greaterThan(0);
+      expect(lock.allowPartial).to.equal(true);
+      vault = await getAccount(provider.connection, getVaultPda(lockId));
+      expect(Number(vault.amount)).to.equal(2_000_000);
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      expect((await provider.connection.getBalance(FEE_RECIPIENT)) - feeRecipientBefore).to.equal(
+        globalState.feeAmount.toNumber()
+      );
+
+      // Finalizing again can't overwrite the active lock
+      try {
+        await finalizeLock(lockId, amount, unlockTimestamp);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("LockNotPrepared");
+      }
+    });
+
+    it("only reserves the next id", async () => {
+      const lockId = (await nextLockId()) + 1;
+      try {
+        await prepareVault(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("LockIdMismatch");
+      }
+    });
+
+    it("can't finalize a lock made by lock", async () => {
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        unlockTimestamp
+      );
+
+      try {
+        await finalizeLock(lockId, new anchor.BN(1_000_000), unlockTimestamp);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("LockNotPrepared");
+      }
+    });
+
+    it("lets the owner abandon a prepared lock with force_close", async () => {
+      const lockId = await nextLockId();
+      await prepareVault(lockId);
+
+      await program.methods
+        .forceClose()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+      expect(await provider.connection.getAccountInfo(getLockPda(lockId))).to.be.null;
+      expect(await provider.connection.getAccountInfo(getVaultPda(lockId))).to.be.null;
+    });
+
+    it("won't finalize a prepared vault that already holds tokens", async () => {
+      const lockId = await nextLockId();
+      await prepareVault(lockId);
+      await transfer(provider.connection, user1, user1TokenAccount1, getVaultPda(lockId), user1, 1_000);
+
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+      try {
+        await finalizeLock(lockId, new anchor.BN(1_000_000), unlockTimestamp);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("VaultBalanceMismatch");
+      }
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.isUnlocked).to.equal(true);
+      expect(lock.amount.toNumber()).to.equal(0);
+    });
+  });
+
+  // ===========================================================================
+  // KEEPER BOUNTY
+  // ===========================================================================
+  describe("keeper_unlock", () => {
+    const keeperUnlock = (lockId: number, keeper: Keypair, keeperTokenAccount: PublicKey) =>
+      program.methods
+        .keeperUnlock()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          keeperTokenAccount,
+          keeper: keeper.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([keeper])
+        .rpc();
+
+    it("pays the bounty to a non-owner keeper and the rest to the owner", async () => {
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 2);
+      const lockId = await createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        unlockTimestamp,
+        { ...defaultLockOptions(), bountyBps: 250 }
+      );
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.bountyBps).to.equal(250);
+
+      // Not matured yet
+      try {
+        await keeperUnlock(lockId, user2, user2TokenAccount1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TooEarly");
+      }
+
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      const ownerBefore = (await getAccount(provider.connection, user1TokenAccount1)).amount;
+      const keeperBefore = (await getAccount(provider.connection, user2TokenAccount1)).amount;
+      const sig = await keeperUnlock(lockId, user2, user2TokenAccount1);
+
+      const ownerAfter = (await getAccount(provider.connection, user1TokenAccount1)).amount;
+      const keeperAfter = (await getAccount(provider.connection, user2TokenAccount1)).amount;
+      expect(Number(keeperAfter - keeperBefore)).to.equal(25_000);
+      expect(Number(ownerAfter - ownerBefore)).to.equal(975_000);
+      expect((await program.account.lock.fetch(getLockPda(lockId))).isUnlocked).to.equal(true);
+
+      const event = (await getEvents(sig)).find((e) => e.name === "bountyPaid");
+      expect(event?.data.keeper.toString()).to.equal(user2.publicKey.toString());
+      expect(event?.data.bounty.toNumber()).to.equal(25_000);
+
+      // The bounty is only paid once
+      try {
+        await keeperUnlock(lockId, user2, user2TokenAccount1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AlreadyUnlocked");
+      }
+    });
+
+    it("rejects locks without a bounty and invalid bounties", async () => {
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        unlockTimestamp
+      );
+      try {
+        await keeperUnlock(lockId, user2, user2TokenAccount1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NoBounty");
+      }
+
+      try {
+        await createLockWithOptions(
+          user1,
+          user1TokenAccount1,
+          mint1,
+          new anchor.BN(1_000_000),
+          unlockTimestamp,
+          { ...defaultLockOptions(), bountyBps: 10_001 }
+        );
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidBountyBps");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // LOCK PERCENT
+  // ===========================================================================
+  describe("lock_percent", () => {
+    let mint: PublicKey;
+    let tokenAccount: PublicKey;
+
+    const lockPercent = async (bps: number): Promise<number> => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+      await program.methods
+        .lockPercent(bps, unlockTimestamp)
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
+          mint,
+          ownerTokenAccount: tokenAccount,
+          owner: user2.publicKey,
+          feeRecipient: FEE_RECIPIENT,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user2])
+        .rpc();
+
+      return lockId;
+    };
+
+    before(async () => {
+      mint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+      tokenAccount = await createAssociatedTokenAccount(provider.connection, user2, mint, user2.publicKey);
+      await mintTo(provider.connection, mintAuthority, mint, tokenAccount, mintAuthority, 1_000_001);
+    });
+
+    it("locks a share of the balance, rounded down", async () => {
+      const lockId = await lockPercent(5_000);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.amount.toNumber()).to.equal(500_000);
+      const account = await getAccount(provider.connection, tokenAccount);
+      expect(Number(account.amount)).to.equal(500_001);
+    });
+
+    it("rejects out of range basis points", async () => {
+      for (const bps of [0, 10_001]) {
+        try {
+          await lockPercent(bps);
+          expect.fail("Should have thrown error");
+        } catch (err: any) {
+          expect(err.error?.errorCode?.code).to.equal("InvalidBps");
+        }
+      }
+    });
+
+    it("rejects a share rounding down to zero", async () => {
+      // Empty the account, then leave a single token in it
+      const lockId = await lockPercent(10_000);
+      expect((await program.account.lock.fetch(getLockPda(lockId))).amount.toNumber()).to.equal(500_001);
+      await mintTo(provider.connection, mintAuthority, mint, tokenAccount, mintAuthority, 1);
+
+      try {
+        await lockPercent(9_999);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AmountZero");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // RECOGNIZE DEPOSIT
+  // ===========================================================================
+  describe("recognize_deposit", () => {
+    const recognizeDeposit = (lockId: number) =>
+      program.methods
+        .recognizeDeposit()
+        .accounts({ lock: getLockPda(lockId), vault: getVaultPda(lockId) })
+        .rpc({ commitment: "confirmed" });
+
+    it("adds tokens sent to the vault to the lock, without the owner", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+      const ownerMintStatsPda = getOwnerMintStatsPda(user1.publicKey, mint1);
+      const totalBefore = (await program.account.ownerMintStats.fetch(ownerMintStatsPda)).totalLocked;
+
+      // Rewards land in the vault directly
+      await mintTo(provider.connection, mintAuthority, mint1, getVaultPda(lockId), mintAuthority, 50_000);
+
+      const signature = await recognizeDeposit(lockId);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.amount.toNumber()).to.equal(1_050_000);
+      const totalAfter = (await program.account.ownerMintStats.fetch(ownerMintStatsPda)).totalLocked;
+      expect(totalAfter.sub(totalBefore).toNumber()).to.equal(50_000);
+
+      const recognized = (await getEvents(signature)).find((e) => e.name === "depositRecognized");
+      expect(recognized?.data.oldAmount.toNumber()).to.equal(1_000_000);
+      expect(recognized?.data.newAmount.toNumber()).to.equal(1_050_000);
+    });
+
+    it("rejects a vault holding no more than the lock", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+      try {
+        await recognizeDeposit(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NothingToRecognize");
+      }
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.amount.toNumber()).to.equal(1_000_000);
+    });
+  });
+
+  // ===========================================================================
+  // BURN ON UNLOCK
+  // ===========================================================================
+  describe("burn_on_unlock", () => {
+    const burnOptions = () => ({ ...defaultLockOptions(), allowPartial: false, burnOnUnlock: true });
+
+    it("burns the tokens at unlock, reducing the supply", async () => {
+      const lockId = await createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2),
+        burnOptions()
+      );
+      expect((await program.account.lock.fetch(getLockPda(lockId))).burnOnUnlock).to.equal(true);
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      // Releasing the tokens any other way is refused
+      try {
+        await program.methods
+          .batchUnlock()
+          .accounts({ owner: user1.publicKey, tokenProgram: TOKEN_PROGRAM_ID })
+          .remainingAccounts([
+            { pubkey: getLockPda(lockId), isSigner: false, isWritable: true },
+            { pubkey: getVaultPda(lockId), isSigner: false, isWritable: true },
+            { pubkey: mint1, isSigner: false, isWritable: false },
+            { pubkey: user1TokenAccount1, isSigner: false, isWritable: true },
+            { pubkey: getMintStatsPda(mint1), isSigner: false, isWritable: true },
+            { pubkey: getOwnerMintStatsPda(user1.publicKey, mint1), isSigner: false, isWritable: true },
+          ])
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("BurnOnUnlock");
+      }
+
+      const supplyBefore = (await getMint(provider.connection, mint1)).supply;
+      const ownerBefore = (await getAccount(provider.connection, user1TokenAccount1)).amount;
+
+      const signature = await program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc({ commitment: "confirmed" });
+
+      const supplyAfter = (await getMint(provider.connection, mint1)).supply;
+      expect(Number(supplyBefore - supplyAfter)).to.equal(1_000_000);
+      const ownerAfter = (await getAccount(provider.connection, user1TokenAccount1)).amount;
+      expect(ownerAfter).to.equal(ownerBefore);
+      expect(Number((await getAccount(provider.connection, getVaultPda(lockId))).amount)).to.equal(0);
+      expect((await program.account.lock.fetch(getLockPda(lockId))).isUnlocked).to.equal(true);
+
+      const burned = (await getEvents(signature)).find((e) => e.name === "burned");
+      expect(burned?.data.amount.toNumber()).to.equal(1_000_000);
+    });
+
+    it("is only accepted on plain locks without partial withdrawals", async () => {
+      for (const options of [
+        { ...burnOptions(), allowPartial: true },
+        { ...burnOptions(), reducible: true },
+        { ...burnOptions(), bountyBps: 100 },
+      ]) {
+        try {
+          await createLockWithOptions(
+            user1,
+            user1TokenAccount1,
+            mint1,
+            new anchor.BN(1_000_000),
+            new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+            options
+          );
+          expect.fail("Should have thrown error");
+        } catch (err: any) {
+          expect(err.error?.errorCode?.code).to.equal("BurnRequiresPlainLock");
+        }
+      }
+    });
+  });
+
+  // ===========================================================================
+  // RECEIPT NFT
+  // ===========================================================================
+  describe("receipt NFT", () => {
+    const getReceiptMintPda = (lockId: number): PublicKey =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("receipt"), new anchor.BN(lockId).toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+
+    const lockWithReceipt = async (unlockTimestamp: anchor.BN, withReceiptAccounts = true): Promise<number> => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+      const receiptMint = getReceiptMintPda(lockId);
+
+      await program.methods
+        .lockWithOptions(new anchor.BN(1_000_000), unlockTimestamp, { ...defaultLockOptions(), mintReceipt: true })
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          feeRecipient: FEE_RECIPIENT,
+          receiptMint: withReceiptAccounts ? receiptMint : null,
+          receiptTokenAccount: withReceiptAccounts
+            ? getAssociatedTokenAddressSync(receiptMint, user1.publicKey)
+            : null,
+          associatedTokenProgram: withReceiptAccounts ? ASSOCIATED_TOKEN_PROGRAM_ID : null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+
+      return lockId;
+    };
+
+    it("mints a single receipt to the owner and burns it at unlock", async () => {
+      const lockId = await lockWithReceipt(new anchor.BN(Math.floor(Date.now() / 1000) + 2));
+      const receiptMint = getReceiptMintPda(lockId);
+      const receiptTokenAccount = getAssociatedTokenAddressSync(receiptMint, user1.publicKey);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.receiptMint.toString()).to.equal(receiptMint.toString());
+      let mint = await getMint(provider.connection, receiptMint);
+      expect(mint.decimals).to.equal(0);
+      expect(Number(mint.supply)).to.equal(1);
+      expect(mint.mintAuthority).to.be.null;
+      expect(Number((await getAccount(provider.connection, receiptTokenAccount)).amount)).to.equal(1);
+
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      await program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          receiptMint,
+          receiptTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+      mint = await getMint(provider.connection, receiptMint);
+      expect(Number(mint.supply)).to.equal(0);
+      expect(Number((await getAccount(provider.connection, receiptTokenAccount)).amount)).to.equal(0);
+      expect((await program.account.lock.fetch(getLockPda(lockId))).isUnlocked).to.equal(true);
+    });
+
+    it("burns the receipt when a partial unlock drains the lock", async () => {
+      const lockId = await lockWithReceipt(new anchor.BN(Math.floor(Date.now() / 1000) + 2));
+      const receiptMint = getReceiptMintPda(lockId);
+      const receiptTokenAccount = getAssociatedTokenAddressSync(receiptMint, user1.publicKey);
+      const partialUnlock = (amount: number) =>
+        program.methods
+          .partialUnlock(new anchor.BN(amount))
+          .accounts({
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            receiptMint,
+            receiptTokenAccount,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user1])
+          .rpc();
+
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      // The receipt stays while the lock is still open
+      await partialUnlock(400_000);
+      expect(Number((await getMint(provider.connection, receiptMint)).supply)).to.equal(1);
+
+      await partialUnlock(600_000);
+      expect(Number((await getMint(provider.connection, receiptMint)).supply)).to.equal(0);
+      expect((await program.account.lock.fetch(getLockPda(lockId))).isUnlocked).to.equal(true);
+    });
+
+    it("leaves the receipt of a lock released without the holder, for force_close to burn", async () => {
+      const lockId = await lockWithReceipt(new anchor.BN(Math.floor(Date.now() / 1000) + 2));
+      const receiptMint = getReceiptMintPda(lockId);
+      const receiptTokenAccount = getAssociatedTokenAddressSync(receiptMint, user1.publicKey);
+
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      await program.methods
+        .assistedUnlock()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          authority: authority.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+      // The lock, not the receipt, says whether it was released
+      expect((await program.account.lock.fetch(getLockPda(lockId))).isUnlocked).to.equal(true);
+      expect(Number((await getMint(provider.connection, receiptMint)).supply)).to.equal(1);
+
+      await program.methods
+        .forceClose()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          receiptMint,
+          receiptTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+
+      expect(Number((await getMint(provider.connection, receiptMint)).supply)).to.equal(0);
+      expect(await provider.connection.getAccountInfo(getLockPda(lockId))).to.be.null;
+    });
+
+    it("needs the receipt accounts", async () => {
+      try {
+        await lockWithReceipt(new anchor.BN(Math.floor(Date.now() / 1000) + 3600), false);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("ReceiptAccountsMissing");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // EMPTY LOCK GUARD
+  // ===========================================================================
+  describe("empty lock guard", () => {
+    let drainedLockId: number;
+    let feeMint: PublicKey;
+    let user1FeeTokenAccount: PublicKey;
+    const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+    before(async () => {
+      // A 100% transfer fee leaves the vault empty, and reconciling drains the lock to zero
+      feeMint = await createTransferFeeMint(6, 10_000);
+      user1FeeTokenAccount = await createAssociatedTokenAccount(
+        provider.connection,
+        user1,
+        feeMint,
+        user1.publicKey,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      await mintTo(
+        provider.connection,
+        mintAuthority,
+        feeMint,
+        user1FeeTokenAccount,
+        mintAuthority,
+        10_000_000_000,
+        [],
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      drainedLockId = globalState.lockCounter.toNumber();
+      await program.methods
+        .lock(new anchor.BN(1_000_000), unlockTimestamp, null)
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(drainedLockId),
+          vault: getVaultPda(drainedLockId),
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
+          mint: feeMint,
+          ownerTokenAccount: user1FeeTokenAccount,
+          owner: user1.publicKey,
+          feeRecipient: FEE_RECIPIENT,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+
+      await program.methods
+        .reconcileAmount()
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(drainedLockId),
+          vault: getVaultPda(drainedLockId),
+          authority: authority.publicKey,
+        })
+        .rpc();
+    });
+
+    it("leaves a zero-amount lock that is not marked unlocked", async () => {
+      const lock = await program.account.lock.fetch(getLockPda(drainedLockId));
+      expect(lock.amount.toNumber()).to.equal(0);
+      expect(lock.isUnlocked).to.equal(false);
+    });
+
+    it("rejects extend on an empty lock", async () => {
+      const newTimestamp = unlockTimestamp.addn(86400);
+      try {
+        await program.methods
+          .extend(newTimestamp)
+          .accounts({
+            lock: getLockPda(drainedLockId),
+            oldExpiryIndex: getExpiryIndexPda(unlockTimestamp),
+            newExpiryIndex: getExpiryIndexPda(newTimestamp),
+            owner: user1.publicKey,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("EmptyLock");
+      }
+    });
+
+    it("rejects top_up on an empty lock", async () => {
+      try {
+        await program.methods
+          .topUp(new anchor.BN(1_000_000))
+          .accounts({
+            lock: getLockPda(drainedLockId),
+            vault: getVaultPda(drainedLockId),
+            mint: feeMint,
+            ownerTokenAccount: user1FeeTokenAccount,
+            owner: user1.publicKey,
+            tokenProgram: TOKEN_2022_PROGRAM_ID,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("EmptyLock");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // CHECK INVARIANTS
+  // ===========================================================================
+  describe("check_invariants", () => {
+    const checkInvariants = (lockId: number, vault: PublicKey = getVaultPda(lockId)) =>
+      program.methods
+        .checkInvariants()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault,
+          mint: mint1,
+        })
+        .rpc();
+
+    const newLock = () =>
+      createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+    it("passes on a healthy lock", async () => {
+      const lockId = await newLock();
+      await checkInvariants(lockId);
+    });
+
+    it("fails with VaultMismatch on another lock's vault", async () => {
+      const lockId = await newLock();
+      const otherLockId = await newLock();
+      try {
+        await checkInvariants(lockId, getVaultPda(otherLockId));
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("VaultMismatch");
+      }
+    });
+
+    it("fails with VaultBalanceMismatch after a direct deposit to the vault", async () => {
+      const lockId = await newLock();
+      await transfer(
+        provider.connection,
+        user1,
+        user1TokenAccount1,
+        getVaultPda(lockId),
+        user1,
+        1_000
+      );
+      try {
+        await checkInvariants(lockId);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("VaultBalanceMismatch");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // LOCK CALENDAR
+  // ===========================================================================
+  describe("lock_calendar", () => {
+    const amount = new anchor.BN(1_000_000);
+
+    // Creation index bucket of `now`, which may be a mock timestamp
+    const creationIndexAt = (now: number): PublicKey =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("created"), new anchor.BN(Math.floor(now / 86400)).toArrayLike(Buffer, "le", 8)],
+        program.programId
+      )[0];
+
+    const lockCalendar = async (now: number, months: number, days: number, expectedUnlock: number) => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+      await program.methods
+        .lockCalendar(amount, months, days)
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          expiryIndex: getExpiryIndexPda(expectedUnlock),
+          creationIndex: creationIndexAt(now),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          feeRecipient: FEE_RECIPIENT,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .signers([user1])
+        .rpc();
+      return program.account.lock.fetch(getLockPda(lockId));
+    };
+
+    it("adds calendar days to the chain time", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const lock = await lockCalendar(now, 0, 7, now + 7 * 86400);
+      expect(lock.unlockTimestamp.toNumber() - lock.createdAt.toNumber()).to.equal(7 * 86400);
+    });
+
+    it("rejects an empty duration", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      try {
+        await lockCalendar(now, 0, 0, now);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidCalendarDuration");
+      }
+    });
+
+    describe("with the test clock", () => {
+      before(function () {
+        if (!hasInstruction("set_mock_timestamp")) {
+          this.skip();
+        }
+      });
+
+      after(async () => {
+        if (hasInstruction("set_mock_timestamp")) {
+          await setMockTimestamp(0);
+        }
+      });
+
+      const at = (iso: string) => Math.floor(Date.parse(iso) / 1000);
+
+      it("crosses a year boundary", async () => {
+        const now = at("2023-12-31T12:00:00Z");
+        await setMockTimestamp(now);
+        const lock = await lockCalendar(now, 1, 1, at("2024-02-01T12:00:00Z"));
+        expect(lock.unlockTimestamp.toNumber()).to.equal(at("2024-02-01T12:00:00Z"));
+      });
+
+      it("clamps to February 29 in a leap year", async () => {
+        const now = at("2024-01-31T08:30:00Z");
+        await setMockTimestamp(now);
+        const lock = await lockCalendar(now, 1, 0, at("2024-02-29T08:30:00Z"));
+        expect(lock.unlockTimestamp.toNumber()).to.equal(at("2024-02-29T08:30:00Z"));
+      });
+
+      it("clamps to February 28 in a common year, twelve months later", async () => {
+        const now = at("2024-02-29T00:00:00Z");
+        await setMockTimestamp(now);
+        const lock = await lockCalendar(now, 12, 0, at("2025-02-28T00:00:00Z"));
+        expect(lock.unlockTimestamp.toNumber()).to.equal(at("2025-02-28T00:00:00Z"));
+      });
+    });
+  });
+
+  // ===========================================================================
+  // ERROR CODES
+  // ===========================================================================
+  describe("error codes", () => {
+    const LOCKFUN_ERROR_CODE_BASE = 100_000;
+
+    it("numbers errors contiguously from LOCKFUN_ERROR_CODE_BASE in the IDL", () => {
+      const errors = program.idl.errors!;
+      expect(errors[0].name.toLowerCase()).to.equal("unauthorized");
+      errors.forEach((error, index) => {
+        expect(error.code).to.equal(LOCKFUN_ERROR_CODE_BASE + index);
+      });
+    });
+
+    it("returns the namespaced code on chain", async () => {
+      try {
+        await createLock(
+          user1,
+          user1TokenAccount1,
+          mint1,
+          new anchor.BN(0),
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+        );
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AmountZero");
+        expect(err.error?.errorCode?.number).to.equal(LOCKFUN_ERROR_CODE_BASE + 1);
+      }
+    });
+  });
+
+  // ===========================================================================
+  // LIST ACTIVE LOCKS
+  // ===========================================================================
+  describe("list_active_locks", () => {
+    let firstLockId: number;
+    const lockPdas = (startId: number, count: number) =>
+      Array.from({ length: count }, (_, i) => ({
+        pubkey: getLockPda(startId + i),
+        isSigner: false,
+        isWritable: false,
+      }));
+
+    const listActiveLocks = (startId: number, limit: number, accounts = lockPdas(startId, limit)) =>
+      program.methods.listActiveLocks(new anchor.BN(startId), limit).accounts({}).remainingAccounts(accounts);
+
+    before(async () => {
+      const now = Math.floor(Date.now() / 1000);
+      firstLockId = await createLock(user1, user1TokenAccount1, mint1, new anchor.BN(1_000_000), new anchor.BN(now + 2));
+      await createLock(user1, user1TokenAccount1, mint1, new anchor.BN(2_000_000), new anchor.BN(now + 3600));
+      await createLock(user1, user1TokenAccount1, mint1, new anchor.BN(3_000_000), new anchor.BN(now + 7200));
+
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      await program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(firstLockId),
+          vault: getVaultPda(firstLockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+    });
+
+    it("returns summaries of the active locks of the page only", async () => {
+      const summaries = await listActiveLocks(firstLockId, 3).view();
+
+      expect(summaries.map((summary: any) => summary.id.toNumber())).to.deep.equal([
+        firstLockId + 1,
+        firstLockId + 2,
+      ]);
+      expect(summaries[0].owner.toBase58()).to.equal(user1.publicKey.toBase58());
+      expect(summaries[0].mint.toBase58()).to.equal(mint1.toBase58());
+      expect(summaries[0].amount.toNumber()).to.equal(2_000_000);
+    });
+
+    it("skips ids with no lock account", async () => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const nextId = globalState.lockCounter.toNumber();
+
+      const summaries = await listActiveLocks(nextId - 1, 2).view();
+      expect(summaries.map((summary: any) => summary.id.toNumber())).to.deep.equal([nextId - 1]);
+    });
+
+    it("rejects a limit above MAX_LIST_LIMIT", async () => {
+      try {
+        await listActiveLocks(firstLockId, 11).rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidListLimit");
+      }
+    });
+
+    it("rejects accounts that aren't the lock PDAs of the range", async () => {
+      try {
+        await listActiveLocks(firstLockId, 2, lockPdas(firstLockId + 1, 2)).rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidBatch");
+      }
+    });
+  });
+
+  // ===========================================================================
+  // MAX AMOUNT PER LOCK
+  // ===========================================================================
+  describe("max_amount_per_lock", () => {
+    const cap = 5_000_000;
+
+    const setMaxAmountPerLock = (maxAmount: number, signer?: Keypair) => {
+      const builder = program.methods
+        .setMaxAmountPerLock(new anchor.BN(maxAmount))
+        .accounts({ globalState: globalStatePda, authority: signer ? signer.publicKey : authority.publicKey });
+      return signer ? builder.signers([signer]).rpc() : builder.rpc();
+    };
+
+    const lockAmount = (amount: number) =>
+      createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(amount),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+
+    after(async () => {
+      await setMaxAmountPerLock(0);
+    });
+
+    it("is uncapped by default and only the authority can set it", async () => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      expect(globalState.maxAmountPerLock.toNumber()).to.equal(0);
+
+      try {
+        await setMaxAmountPerLock(cap, user1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+
+    it("rejects locks above the cap and accepts one at the cap", async () => {
+      await setMaxAmountPerLock(cap);
+
+      try {
+        await lockAmount(cap + 1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AmountTooLarge");
+      }
+
+      const lockId = await lockAmount(cap);
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.amount.toNumber()).to.equal(cap);
+    });
+
+    it("rejects top-ups above the cap", async () => {
+      await setMaxAmountPerLock(cap);
+      const lockId = await lockAmount(1_000_000);
+
+      const topUp = (amount: number) =>
+        program.methods
+          .topUp(new anchor.BN(amount))
+          .accounts({
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user1])
+          .rpc();
+
+      try {
+        await topUp(cap + 1);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AmountTooLarge");
+      }
+
+      // The cap bounds each instruction, not the lock's total
+      await topUp(cap);
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.amount.toNumber()).to.equal(1_000_000 + cap);
+    });
+  });
+
+  // ===========================================================================
+  // ROTATE VAULT
+  // ===========================================================================
+  describe("rotate_vault", () => {
+    const getRotatedVaultPda = (lockId: number, oldVault: PublicKey): PublicKey =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), new anchor.BN(lockId).toArrayLike(Buffer, "le", 8), oldVault.toBuffer()],
+        program.programId
+      )[0];
+
+    const rotateVault = (lockId: number, vault: PublicKey, authoritySigner?: Keypair) => {
+      const builder = program.methods.rotateVault().accounts({
+        globalState: globalStatePda,
+        authority: authoritySigner ? authoritySigner.publicKey : authority.publicKey,
+        lock: getLockPda(lockId),
+        owner: user1.publicKey,
+        vault,
+        newVault: getRotatedVaultPda(lockId, vault),
+        mint: mint1,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      });
+      return authoritySigner
+        ? builder.signers([user1, authoritySigner]).rpc()
+        : builder.signers([user1]).rpc({ commitment: "confirmed" });
+    };
+
+    it("requires the authority", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+      try {
+        await rotateVault(lockId, getVaultPda(lockId), user2);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotAuthority");
+      }
+    });
+
+    it("moves the tokens to a fresh vault that unlock then uses", async () => {
+      const amount = 2_000_000;
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(amount),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3)
+      );
+      const oldVault = getVaultPda(lockId);
+      const newVault = getRotatedVaultPda(lockId, oldVault);
+
+      const signature = await rotateVault(lockId, oldVault);
+
+      expect(await provider.connection.getAccountInfo(oldVault)).to.equal(null);
+      const vault = await getAccount(provider.connection, newVault);
+      expect(Number(vault.amount)).to.equal(amount);
+
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.vaultScheme).to.equal(2);
+      expect(Buffer.from(lock.vaultSalt).equals(oldVault.toBuffer())).to.equal(true);
+
+      const events = await getEvents(signature);
+      const rotated = events.find((e) => e.name === "vaultRotated");
+      expect(rotated!.data.oldVault.toBase58()).to.equal(oldVault.toBase58());
+      expect(rotated!.data.newVault.toBase58()).to.equal(newVault.toBase58());
+      expect(rotated!.data.amount.toNumber()).to.equal(amount);
+
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      const before = await getAccount(provider.connection, user1TokenAccount1);
+      await program.methods
+        .unlockSaltedVault()
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: newVault,
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+      const after = await getAccount(provider.connection, user1TokenAccount1);
+      expect(Number(after.amount - before.amount)).to.equal(amount);
+    });
+
+    it("rejects a vault that isn't the lock's", async () => {
+      const lockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+      const otherLockId = await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+      try {
+        await rotateVault(lockId, getVaultPda(otherLockId));
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("VaultMismatch");
+      }
+    });
+
+    it("leaves deposit locks in their vault, where unlock settles them", async () => {
+      const deposit = 0.01 * LAMPORTS_PER_SOL;
+      const lockId = await createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3),
+        {
+          ...defaultLockOptions(),
+          depositLamports: new anchor.BN(deposit),
+          claimWindow: new anchor.BN(600),
+          allowPartial: false,
+        }
+      );
+
+      try {
+        await rotateVault(lockId, getVaultPda(lockId));
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("DepositNotSettled");
+      }
+
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+      const tokensBefore = await getAccount(provider.connection, user1TokenAccount1);
+      const lamportsBefore = await provider.connection.getBalance(user1.publicKey);
+      await program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          feeRecipient: null,
+        })
+        .signers([user1])
+        .rpc();
+      const tokensAfter = await getAccount(provider.connection, user1TokenAccount1);
+      const lamportsAfter = await provider.connection.getBalance(user1.publicKey);
+      expect(Number(tokensAfter.amount - tokensBefore.amount)).to.equal(1_000_000);
+      expect(lamportsAfter - lamportsBefore).to.equal(deposit);
+    });
+
+    it("rejects locks released through the PDA vault", async () => {
+      const lockId = await createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+        { ...defaultLockOptions(), bountyBps: 100 }
+      );
+
+      try {
+        await rotateVault(lockId, getVaultPda(lockId));
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("VaultBoundLock");
+      }
+      const vault = await getAccount(provider.connection, getVaultPda(lockId));
+      expect(Number(vault.amount)).to.equal(1_000_000);
+    });
+  });
+
+  // ===========================================================================
+  // FUTURE MATURITIES
+  // ===========================================================================
+  describe("future_maturities", () => {
+    let mint: PublicKey;
+    let tokenAccount: PublicKey;
+
+    const futureMaturities = async (): Promise<number> =>
+      (await program.methods.futureMaturities().accounts({ mint }).view()).toNumber();
+
+    before(async () => {
+      mint = await createMint(provider.connection, mintAuthority, mintAuthority.publicKey, null, 6);
+      tokenAccount = await createAssociatedTokenAccount(provider.connection, user1, mint, user1.publicKey);
+      await mintTo(provider.connection, mintAuthority, mint, tokenAccount, mintAuthority, 10_000_000);
+    });
+
+    it("fails until the mint's stats exist", async () => {
+      try {
+        await program.methods.futureMaturities().accounts({ mint }).rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("MintStatsNotFound");
+      }
+    });
+
+    it("counts locks until they are unlocked, matured or not", async () => {
+      await program.methods.backfillMintStats().accounts({ mint, authority: authority.publicKey }).rpc();
+      expect(await futureMaturities()).to.equal(0);
+
+      const maturing = await createLock(
+        user1,
+        tokenAccount,
+        mint,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      );
+      await createLock(
+        user1,
+        tokenAccount,
+        mint,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+      expect(await futureMaturities()).to.equal(2);
+
+      // Maturity alone doesn't change the count
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      expect(await futureMaturities()).to.equal(2);
+
+      await program.methods
+        .unlock(null)
+        .accounts({
+          lock: getLockPda(maturing),
+          vault: getVaultPda(maturing),
+          mint,
+          ownerTokenAccount: tokenAccount,
+          owner: user1.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([user1])
+        .rpc();
+      expect(await futureMaturities()).to.equal(1);
+    });
+  });
+
+  describe("global state bump", () => {
+    it("caches the GlobalState bump for later instructions", async () => {
+      const [pda, bump] = PublicKey.findProgramAddressSync([Buffer.from("global_state")], program.programId);
+      expect(pda.toBase58()).to.equal(globalStatePda.toBase58());
+
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      expect(globalState.bump).to.equal(bump);
+
+      // Contexts derive the PDA from the cached bump
+      await program.methods
+        .setMaxAmountPerLock(new anchor.BN(0))
+        .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+        .rpc();
+    });
+  });
+
+  describe("fee vouchers", () => {
+    const voucherSigner = Keypair.generate();
+
+    const setVoucherSigner = (voucherSignerKey: PublicKey) =>
+      program.methods
+        .setVoucherSigner(voucherSignerKey)
+        .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+        .rpc();
+
+    // The voucher and the Ed25519 instruction verifying it, signed by `signer` for user1
+    const signVoucher = (signer: Keypair, expiry: number) => {
+      const expiryBytes = Buffer.alloc(8);
+      expiryBytes.writeBigInt64LE(BigInt(expiry));
+      const message = Buffer.concat([user1.publicKey.toBuffer(), expiryBytes]);
+      const ed25519Ix = Ed25519Program.createInstructionWithPrivateKey({
+        privateKey: signer.secretKey,
+        message,
+      });
+      const signatureOffset = ed25519Ix.data.readUInt16LE(2);
+      const signature = [...ed25519Ix.data.subarray(signatureOffset, signatureOffset + 64)];
+      return { voucher: { expiry: new anchor.BN(expiry), signature }, ed25519Ix };
+    };
+
+    const lockWithVoucher = async (signer: Keypair, expiry: number) => {
+      const { voucher, ed25519Ix } = signVoucher(signer, expiry);
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const lockId = globalState.lockCounter.toNumber();
+      const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+
+      await program.methods
+        .lock(new anchor.BN(1_000_000), unlockTimestamp, voucher)
+        .accounts({
+          globalState: globalStatePda,
+          lock: getLockPda(lockId),
+          vault: getVaultPda(lockId),
+          expiryIndex: getExpiryIndexPda(unlockTimestamp),
+          creationIndex: getCreationIndexPda(),
+          mint: mint1,
+          ownerTokenAccount: user1TokenAccount1,
+          owner: user1.publicKey,
+          feeRecipient: FEE_RECIPIENT,
+          instructionsSysvar: SYSVAR_INSTRUCTIONS_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        })
+        .preInstructions([ed25519Ix])
+        .signers([user1])
+        .rpc();
+      return lockId;
+    };
+
+    before(async () => {
+      await setVoucherSigner(voucherSigner.publicKey);
+    });
+
+    after(async () => {
+      await setVoucherSigner(PublicKey.default);
+    });
+
+    it("waives the lock fee for a valid voucher", async () => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      expect(globalState.voucherSigner.toBase58()).to.equal(voucherSigner.publicKey.toBase58());
+
+      const feeRecipientBalanceBefore = await provider.connection.getBalance(FEE_RECIPIENT);
+      const lockId = await lockWithVoucher(voucherSigner, Math.floor(Date.now() / 1000) + 3600);
+      const feeRecipientBalanceAfter = await provider.connection.getBalance(FEE_RECIPIENT);
+
+      expect(feeRecipientBalanceAfter).to.equal(feeRecipientBalanceBefore);
+      const lock = await program.account.lock.fetch(getLockPda(lockId));
+      expect(lock.amount.toNumber()).to.equal(1_000_000);
+    });
+
+    it("rejects an expired voucher", async () => {
+      try {
+        await lockWithVoucher(voucherSigner, Math.floor(Date.now() / 1000) - 3600);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("FeeVoucherExpired");
+      }
+    });
+
+    it("rejects a voucher not signed by the voucher signer", async () => {
+      try {
+        await lockWithVoucher(Keypair.generate(), Math.floor(Date.now() / 1000) + 3600);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("InvalidFeeVoucher");
+      }
+    });
+  });
+
+  describe("slot locks", () => {
+    const slotLockOptions = (unlockSlot: number) => ({
+      ...defaultLockOptions(),
+      unlockSlot: new anchor.BN(unlockSlot),
+    });
+
+    const waitForSlot = async (slot: number) => {
+      while ((await provider.connection.getSlot()) < slot) {
+        await new Promise((resolve) => setTimeout(resolve, 400));
+      }
+    };
+
+    it("rejects a lock with both an unlock timestamp and an unlock slot", async () => {
+      const slot = await provider.connection.getSlot();
+      try {
+        await createLockWithOptions(
+          user1,
+          user1TokenAccount1,
+          mint1,
+          new anchor.BN(1_000_000),
+          new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+          slotLockOptions(slot + 100)
+        );
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AmbiguousUnlockCondition");
+      }
+    });
+
+    it("unlocks once the unlock slot is reached", async () => {
+      const unlockSlot = (await provider.connection.getSlot()) + 20;
+      const lockId = await createLockWithOptions(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(0),
+        slotLockOptions(unlockSlot)
+      );
+      const lockPda = getLockPda(lockId);
+
+      const lock = await program.account.lock.fetch(lockPda);
+      expect(lock.unlockSlot.toNumber()).to.equal(unlockSlot);
+      expect(lock.unlockTimestamp.toNumber()).to.equal(0);
+
+      const unlock = () =>
+        program.methods
+          .unlock(null)
+          .accounts({
+            lock: lockPda,
+            vault: getVaultPda(lockId),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user1])
+          .rpc();
+
+      try {
+        await unlock();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("TooEarly");
+      }
+
+      // A slot lock can't gain an unlock timestamp
+      const newTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 7200);
+      try {
+        await program.methods
+          .extend(newTimestamp)
+          .accounts({
+            lock: lockPda,
+            oldExpiryIndex: getExpiryIndexPda(0),
+            newExpiryIndex: getExpiryIndexPda(newTimestamp),
+            owner: user1.publicKey,
+          })
+          .signers([user1])
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AmbiguousUnlockCondition");
+      }
+
+      await waitForSlot(unlockSlot + 1);
+      await unlock();
+
+      const lockAfter = await program.account.lock.fetch(lockPda);
+      expect(lockAfter.isUnlocked).to.equal(true);
+    });
+  });
+
+  describe("quote_fee", () => {
+    const quoteFee = async (mint: PublicKey, amount: number): Promise<number> =>
+      (await program.methods.quoteFee(new anchor.BN(amount), null).accounts({ mint }).view()).toNumber();
+
+    // Fee recipient balance change caused by one lock of mint1
+    const feePaidForLock = async (): Promise<number> => {
+      const before = await provider.connection.getBalance(FEE_RECIPIENT);
+      await createLock(
+        user1,
+        user1TokenAccount1,
+        mint1,
+        new anchor.BN(1_000_000),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      );
+      return (await provider.connection.getBalance(FEE_RECIPIENT)) - before;
+    };
+
+    it("quotes the default fee, as charged by lock", async () => {
+      const globalState = await program.account.globalState.fetch(globalStatePda);
+      const quote = await quoteFee(mint1, 1_000_000);
+
+      expect(quote).to.equal(globalState.feeAmount.toNumber());
+      expect(await feePaidForLock()).to.equal(quote);
+    });
+
+    it("quotes the mint's fee override, as charged by lock", async () => {
+      const overrideFee = 5_000_000;
+      await program.methods
+        .setMintFeeOverride(new anchor.BN(overrideFee))
+        .accounts({ mint: mint1, authority: authority.publicKey })
+        .rpc();
+
+      try {
+        const quote = await quoteFee(mint1, 1_000_000);
+        expect(quote).to.equal(overrideFee);
+        expect(await feePaidForLock()).to.equal(quote);
+      } finally {
+        const [feeOverride] = PublicKey.findProgramAddressSync(
+          [Buffer.from("fee_override"), mint1.toBuffer()],
+          program.programId
+        );
+        await program.methods
+          .removeMintFeeOverride()
+          .accounts({ feeOverride, authority: authority.publicKey })
+          .rpc();
+      }
+    });
+
+    it("quotes a voucher-waived fee, as charged by lock", async () => {
+      const voucherSigner = Keypair.generate();
+      const setVoucherSigner = (voucherSignerKey: PublicKey) =>
+        program.methods
+          .setVoucherSigner(voucherSignerKey)
+          .accounts({ globalState: globalStatePda, authority: authority.publicKey })
+          .rpc();
+
+      const expiry = Math.floor(Date.now() / 1000) + 3600;
+      const expiryBytes = Buffer.alloc(8);
+      expiryBytes.writeBigInt64LE(BigInt(expiry));
+      const ed25519Ix = Ed25519Program.createInstructionWithPrivateKey({
+        privateKey: voucherSigner.secretKey,
+        message: Buffer.concat([user1.publicKey.toBuffer(), expiryBytes]),
+      });
+      const signatureOffset = ed25519Ix.data.readUInt16LE(2);
+      const voucher = {
+        expiry: new anchor.BN(expiry),
+        signature: [...ed25519Ix.data.subarray(signatureOffset, signatureOffset + 64)],
+      };
+      const quoteWithVoucher = async (owner: PublicKey): Promise<number> =>
+        (
+          await program.methods
+            .quoteFee(new anchor.BN(1_000_000), voucher)
+            .accounts({ mint: mint1, owner, instructionsSysvar: SYSVAR_INSTRUCTIONS_PUBKEY })
+            .preInstructions([ed25519Ix])
+            .view()
+        ).toNumber();
+
+      await setVoucherSigner(voucherSigner.publicKey);
+      try {
+        expect(await quoteWithVoucher(user1.publicKey)).to.equal(0);
+
+        // The voucher is bound to user1
+        try {
+          await quoteWithVoucher(user2.publicKey);
+          expect.fail("Should have thrown error");
+        } catch (err: any) {
+          expect(String(err)).to.match(/InvalidFeeVoucher/);
+        }
+
+        const lockId = (await program.account.globalState.fetch(globalStatePda)).lockCounter.toNumber();
+        const unlockTimestamp = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+        const before = await provider.connection.getBalance(FEE_RECIPIENT);
+        await program.methods
+          .lock(new anchor.BN(1_000_000), unlockTimestamp, voucher)
+          .accounts({
+            globalState: globalStatePda,
+            lock: getLockPda(lockId),
+            vault: getVaultPda(lockId),
+            expiryIndex: getExpiryIndexPda(unlockTimestamp),
+            creationIndex: getCreationIndexPda(),
+            mint: mint1,
+            ownerTokenAccount: user1TokenAccount1,
+            owner: user1.publicKey,
+            feeRecipient: FEE_RECIPIENT,
+            instructionsSysvar: SYSVAR_INSTRUCTIONS_PUBKEY,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          })
+          .preInstructions([ed25519Ix])
+          .signers([user1])
+          .rpc();
+        expect((await provider.connection.getBalance(FEE_RECIPIENT)) - before).to.equal(0);
+      } finally {
+        await setVoucherSigner(PublicKey.default);
+      }
+    });
+
+    it("rejects amounts lock would reject", async () => {
+      try {
+        await program.methods.quoteFee(new anchor.BN(0), null).accounts({ mint: mint1 }).rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("AmountZero");
+      }
+    });
+  });
+
+  describe("legacy global state migration", () => {
+    it("only migrates a GlobalState still in the legacy layout", async () => {
+      const info = await provider.connection.getAccountInfo(globalStatePda);
+      expect(info!.data.length).to.be.greaterThan(8 + 40);
+
+      try {
+        await program.methods
+          .migrateGlobalState()
+          .accounts({ authority: authority.publicKey })
+          .rpc();
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotLegacyAccount");
+      }
+    });
+  });
+
+  describe("legacy lock migration", () => {
+    // Loaded by Anchor.toml from tests/fixtures: a matured lock of the first deployment, in its
+    // 106-byte layout (fields up to `is_unlocked`), holding 5 tokens of its own mint in its PDA vault
+    const legacyLockId = 1_000_000_000;
+    const legacyMint = new PublicKey("C7SHmrSHPhzhLQnuQoSdkQi4Dv6tM9fxm7B8zeiJH39A");
+    const legacyOwner = Keypair.fromSecretKey(
+      Uint8Array.from(JSON.parse(readFileSync("tests/fixtures/legacy-lock-owner.json", "utf8")))
+    );
+    const lockPda = getLockPda(legacyLockId);
+
+    const migrateLock = (payer: Keypair) =>
+      program.methods
+        .migrateLock()
+        .accounts({ lock: lockPda, mint: legacyMint, payer: payer.publicKey })
+        .signers([payer])
+        .rpc();
+
+    before(async () => {
+      const sig = await provider.connection.requestAirdrop(legacyOwner.publicKey, LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(sig);
+    });
+
+    it("only lets the lock owner or the authority migrate a lock", async () => {
+      try {
+        await migrateLock(user2);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("Unauthorized");
+      }
+    });
+
+    it("grows a legacy lock to the current layout so it unlocks again", async () => {
+      const legacyInfo = await provider.connection.getAccountInfo(lockPda);
+      expect(legacyInfo!.data.length).to.equal(8 + 98);
+
+      await migrateLock(legacyOwner);
+
+      const info = await provider.connection.getAccountInfo(lockPda);
+      expect(info!.data.length).to.be.greaterThan(8 + 98);
+      const lock = await program.account.lock.fetch(lockPda);
+      expect(lock.id.toNumber()).to.equal(legacyLockId);
+      expect(lock.owner.toBase58()).to.equal(legacyOwner.publicKey.toBase58());
+      expect(lock.beneficiary.toBase58()).to.equal(legacyOwner.publicKey.toBase58());
+      expect(lock.mint.toBase58()).to.equal(legacyMint.toBase58());
+      expect(lock.amount.toNumber()).to.equal(5_000_000);
+      expect(lock.unlockTimestamp.toNumber()).to.equal(1_700_000_000);
+      expect(lock.isUnlocked).to.equal(false);
+      expect(lock.allowPartial).to.equal(true);
+      expect(lock.tokenProgram.toBase58()).to.equal(TOKEN_PROGRAM_ID.toBase58());
+
+      try {
+        await migrateLock(legacyOwner);
+        expect.fail("Should have thrown error");
+      } catch (err: any) {
+        expect(err.error?.errorCode?.code).to.equal("NotLegacyAccount");
+      }
+
+      const ownerTokenAccount = await createAssociatedTokenAccount(
+        provider.connection,
+        legacyOwner,
+        legacyMint,
+        legacyOwner.publicKey
+      );
+      await program.methods
+        .unlock(null)
+        .accounts({
+          lock: lockPda,
+          vault: getVaultPda(legacyLockId),
+          mint: legacyMint,
+          ownerTokenAccount,
+          owner: legacyOwner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([legacyOwner])
+        .rpc();
+
+      const account = await getAccount(provider.connection, ownerTokenAccount);
+      expect(Number(account.amount)).to.equal(5_000_000);
+    });
+  });
 });